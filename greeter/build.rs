@@ -0,0 +1,8 @@
+// Zsh's symbols only become available when the module is loaded, so macOS' linker has to be told
+// to leave them undefined.
+fn main() {
+    if std::env::var("CARGO_CFG_TARGET_VENDOR").as_deref() == Ok("apple") {
+        println!("cargo:rustc-cdylib-link-arg=-undefined");
+        println!("cargo:rustc-cdylib-link-arg=dynamic_lookup");
+    }
+}
@@ -14,7 +14,11 @@ impl Greeter {
 
 fn setup() -> Result<Module, Box<dyn std::error::Error>> {
     let module = ModuleBuilder::new(Greeter)
-        .builtin(Greeter::greet_cmd, Builtin::new("greet"))
+        .builtin(
+            Greeter::greet_cmd,
+            Builtin::new("greet").description("Prints a friendly greeting."),
+        )
+        .with_help_builtin()
         .build();
     Ok(module)
 }
@@ -0,0 +1,41 @@
+use zsh_module_test::{zsh_available, ZshTest};
+
+#[test]
+fn greet() {
+    if !zsh_available() {
+        eprintln!("zsh not found, skipping");
+        return;
+    }
+    let zsh = ZshTest::build("greeter", "rgreeter").unwrap();
+    zsh.run("greet")
+        .assert_success()
+        .assert_stdout("Hello, world!\n");
+}
+
+#[test]
+fn help() {
+    if !zsh_available() {
+        eprintln!("zsh not found, skipping");
+        return;
+    }
+    let zsh = ZshTest::build("greeter", "rgreeter").unwrap();
+    zsh.run("rgreeter-help greet")
+        .assert_success()
+        .assert_stdout("greet [arg ...]\n    Prints a friendly greeting.\n");
+    zsh.run("rgreeter-help nope")
+        .assert_status(1)
+        .assert_stderr_contains("no such builtin: nope");
+}
+
+#[test]
+fn zshrc() {
+    if !zsh_available() {
+        eprintln!("zsh not found, skipping");
+        return;
+    }
+    let zsh = ZshTest::build("greeter", "rgreeter").unwrap();
+    std::fs::write(zsh.zdotdir().join(".zshrc"), "hi() { greet }\n").unwrap();
+    zsh.run("hi")
+        .assert_success()
+        .assert_stdout("Hello, world!\n");
+}
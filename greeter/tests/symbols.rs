@@ -0,0 +1,38 @@
+use std::process::Command;
+
+/// Only zsh's entry points should be visible to other modules loaded in the same shell.
+#[test]
+#[cfg(target_os = "linux")]
+fn only_entry_points_are_exported() {
+    let lib = zsh_module_test::build_library("greeter").unwrap();
+    let Ok(out) = Command::new("nm")
+        .args(["--dynamic", "--defined-only", "--format=just-symbols"])
+        .arg(&lib)
+        .output()
+    else {
+        eprintln!("nm not found, skipping");
+        return;
+    };
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let mut symbols: Vec<_> = String::from_utf8(out.stdout)
+        .unwrap()
+        .lines()
+        .map(str::to_owned)
+        .collect();
+    symbols.sort();
+    assert_eq!(
+        symbols,
+        [
+            "boot_",
+            "cleanup_",
+            "enables_",
+            "features_",
+            "finish_",
+            "setup_"
+        ]
+    );
+}
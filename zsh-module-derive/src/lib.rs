@@ -0,0 +1,279 @@
+//! Procedural macros for [`zsh-module`](https://docs.rs/zsh-module). Use them through its
+//! `derive` feature, which re-exports them.
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{
+    meta::ParseNestedMeta, parse_macro_input, spanned::Spanned, Data, DeriveInput, Error, Expr,
+    Fields, GenericArgument, ImplItem, ItemImpl, LitChar, LitStr, PathArguments, Type,
+};
+
+/// Implements `zsh_module::BuiltinArgs` for a struct with named fields.
+///
+/// Fields with `#[arg(short = 'c')]` are the option `-c`:
+///  - `bool` fields are flags, `true` when the option is passed.
+///  - `Option<T>` fields take an argument, and are [`None`] when the option isn't passed.
+///  - Other fields take an argument, and the option is required.
+///
+/// Other fields are the positional arguments, in order:
+///  - `Option<T>` fields are optional, and can't be followed by required ones.
+///  - A `Vec<T>` field takes the remaining arguments, and must be the last one.
+///  - Other fields are required.
+///
+/// Arguments are converted with [`FromStr`](std::str::FromStr).
+#[proc_macro_derive(BuiltinArgs, attributes(arg))]
+pub fn derive_builtin_args(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    builtin_args(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// How a field is filled in.
+enum Kind {
+    Flag(LitChar),
+    Opt { short: LitChar, required: bool },
+    Positional,
+    OptionalPositional,
+    Rest,
+}
+
+/// The type `T` wraps, if `ty` is `wrapper<T>`.
+fn inner_type<'a>(ty: &'a Type, wrapper: &str) -> Option<&'a Type> {
+    let Type::Path(path) = ty else { return None };
+    let segment = path.path.segments.last()?;
+    if segment.ident != wrapper {
+        return None;
+    }
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+    match args.args.first()? {
+        GenericArgument::Type(inner) => Some(inner),
+        _ => None,
+    }
+}
+
+fn is_bool(ty: &Type) -> bool {
+    matches!(ty, Type::Path(path) if path.path.is_ident("bool"))
+}
+
+/// The `short` of the field's `#[arg(...)]`, if any.
+fn short(field: &syn::Field) -> syn::Result<Option<LitChar>> {
+    let mut short = None;
+    for attr in field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("arg"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("short") {
+                let c: LitChar = meta.value()?.parse()?;
+                if !c.value().is_ascii_alphanumeric() {
+                    return Err(Error::new(
+                        c.span(),
+                        "options must be ASCII letters or digits",
+                    ));
+                }
+                short = Some(c);
+                Ok(())
+            } else {
+                Err(meta.error("expected `short = '...'`"))
+            }
+        })?;
+    }
+    Ok(short)
+}
+
+fn builtin_args(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let Data::Struct(data) = &input.data else {
+        return Err(Error::new(
+            Span::call_site(),
+            "BuiltinArgs can only be derived for structs",
+        ));
+    };
+    let Fields::Named(fields) = &data.fields else {
+        return Err(Error::new(
+            data.fields.span(),
+            "BuiltinArgs needs named fields",
+        ));
+    };
+    let mut declare = Vec::new();
+    let mut inits = Vec::new();
+    let (mut minargs, mut maxargs) = (0u32, Some(0u32));
+    for field in &fields.named {
+        let ident = field.ident.as_ref().unwrap();
+        let ty = &field.ty;
+        let kind = match short(field)? {
+            Some(c) if is_bool(ty) => Kind::Flag(c),
+            Some(c) => Kind::Opt {
+                short: c,
+                required: inner_type(ty, "Option").is_none(),
+            },
+            None if maxargs.is_none() => {
+                return Err(Error::new(
+                    ident.span(),
+                    "the Vec field must be the last argument",
+                ));
+            }
+            None if inner_type(ty, "Vec").is_some() => Kind::Rest,
+            None if inner_type(ty, "Option").is_some() => Kind::OptionalPositional,
+            None if maxargs != Some(minargs) => {
+                return Err(Error::new(
+                    ident.span(),
+                    "required arguments can't follow optional ones",
+                ));
+            }
+            None => Kind::Positional,
+        };
+        let name = ident.to_string();
+        let init = match kind {
+            Kind::Flag(c) => {
+                declare.push(quote!(.opt(#c, ::zsh_module::OptKind::Flag)));
+                quote!(opts.flag(#c))
+            }
+            Kind::Opt { short: c, required } => {
+                declare.push(quote!(.opt(#c, ::zsh_module::OptKind::TakesArg)));
+                let flag = format!("-{}", c.value());
+                let value = quote!(opts.arg(#c).map(|value| __derive::parse_arg(#flag, value)).transpose()?);
+                if required {
+                    quote!(#value.ok_or_else(|| __derive::missing(#flag))?)
+                } else {
+                    value
+                }
+            }
+            Kind::Positional => {
+                minargs += 1;
+                maxargs = maxargs.map(|max| max + 1);
+                quote! {
+                    __derive::parse_arg(#name, args.next().ok_or_else(|| __derive::missing(#name))?)?
+                }
+            }
+            Kind::OptionalPositional => {
+                maxargs = maxargs.map(|max| max + 1);
+                quote!(args.next().map(|value| __derive::parse_arg(#name, value)).transpose()?)
+            }
+            Kind::Rest => {
+                maxargs = None;
+                quote! {
+                    args.by_ref()
+                        .map(|value| __derive::parse_arg(#name, value))
+                        .collect::<::zsh_module::ZResult<_>>()?
+                }
+            }
+        };
+        inits.push(quote!(#ident: #init));
+    }
+    let maxargs = match maxargs {
+        Some(max) => quote!(::std::option::Option::Some(#max)),
+        None => quote!(::std::option::Option::None),
+    };
+    let minargs = minargs as i32;
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::zsh_module::BuiltinArgs for #name #ty_generics #where_clause {
+            fn declare(builtin: ::zsh_module::Builtin) -> ::zsh_module::Builtin {
+                builtin #(#declare)* .minargs(#minargs).maxargs(#maxargs)
+            }
+            #[allow(unused_mut, unused_variables)]
+            fn parse(
+                args: &[&str],
+                opts: &::zsh_module::ParsedOpts,
+            ) -> ::zsh_module::ZResult<Self> {
+                use ::zsh_module::__derive;
+                let mut args = args.iter().copied();
+                // Fields are initialized in order, so positional arguments are taken in order
+                ::std::result::Result::Ok(Self { #(#inits,)* })
+            }
+        }
+    })
+}
+
+/// Marks a method of an impl block with `#[builtins]` as a builtin. See `#[builtins]`.
+///
+/// On its own, it does nothing.
+#[proc_macro_attribute]
+pub fn builtin(_: TokenStream, item: TokenStream) -> TokenStream {
+    item
+}
+
+/// Implements `zsh_module::Builtins` for the type of an impl block, registering every method
+/// marked with `#[builtin(...)]` as a builtin.
+///
+/// The builtin is named after the method, unless given a `name = "..."`. Other settings are
+/// the methods of `Builtin` with the same name, like `minargs = 1`, `flags = "v"` or
+/// `plus_flags`. `maxargs = n` is `Some(n)`.
+///
+/// ```ignore
+/// #[builtins]
+/// impl Greeter {
+///     #[builtin(name = "greet", minargs = 1, description = "Greets people")]
+///     fn greet(&mut self, _: &str, args: &[&str], _: Opts) -> MaybeError { .. }
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn builtins(_: TokenStream, item: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(item as ItemImpl);
+    builtins_impl(item)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// A setting of `#[builtin(...)]`, as a call to the method of `Builtin` with the same name.
+fn builtin_setting(meta: ParseNestedMeta) -> syn::Result<TokenStream2> {
+    let method = &meta.path;
+    if !meta.input.peek(syn::Token![=]) {
+        return Ok(quote!(.#method()));
+    }
+    let value: Expr = meta.value()?.parse()?;
+    if method.is_ident("maxargs") {
+        Ok(quote!(.maxargs(::std::option::Option::Some(#value))))
+    } else {
+        Ok(quote!(.#method(#value)))
+    }
+}
+
+fn builtins_impl(mut item: ItemImpl) -> syn::Result<TokenStream2> {
+    let mut builtins = Vec::new();
+    for impl_item in &mut item.items {
+        let ImplItem::Fn(method) = impl_item else {
+            continue;
+        };
+        let Some(index) = method
+            .attrs
+            .iter()
+            .position(|attr| attr.path().is_ident("builtin"))
+        else {
+            continue;
+        };
+        let attr = method.attrs.remove(index);
+        let ident = &method.sig.ident;
+        let mut name = LitStr::new(&ident.to_string(), ident.span());
+        let mut settings = Vec::new();
+        if !matches!(attr.meta, syn::Meta::Path(_)) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("name") {
+                    name = meta.value()?.parse()?;
+                } else {
+                    settings.push(builtin_setting(meta)?);
+                }
+                Ok(())
+            })?;
+        }
+        builtins.push(quote! {
+            .builtin(Self::#ident, ::zsh_module::Builtin::new(#name) #(#settings)*)
+        });
+    }
+    let ty = &item.self_ty;
+    let (impl_generics, _, where_clause) = item.generics.split_for_impl();
+    Ok(quote! {
+        #item
+
+        impl #impl_generics ::zsh_module::Builtins for #ty #where_clause {
+            fn register(builder: ::zsh_module::ModuleBuilder<Self>) -> ::zsh_module::ModuleBuilder<Self> {
+                builder #(#builtins)*
+            }
+        }
+    })
+}
@@ -0,0 +1,214 @@
+//! # Zsh Module Test
+//! A harness for writing integration tests for zsh modules built with `zsh-module`.
+//!
+//! It builds your module's `cdylib`, installs it into a temporary `module_path`, and runs scripts
+//! in a real `zsh` (with an empty, temporary `ZDOTDIR`) that already has your module loaded.
+//!
+//! ## Example
+//! Put this in your module's `tests/` folder:
+//! ```no_run
+//! use zsh_module_test::ZshTest;
+//!
+//! #[test]
+//! fn greets() {
+//!     let zsh = ZshTest::build("greeter", "rgreeter").unwrap();
+//!     zsh.run("greet")
+//!         .assert_success()
+//!         .assert_stdout("Hello, world!\n");
+//! }
+//! ```
+//!
+//! The `zsh` binary is looked up in `$PATH`, unless the `ZSH_MODULE_TEST_ZSH` environment
+//! variable points to another one. Keep in mind that your module must be built against the same
+//! zsh version as the binary used to test it.
+use std::{
+    env,
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// The exit status the test script uses to report that the module failed to load.
+const LOAD_FAILED: i32 = 99;
+
+/// The zsh binary used to run tests.
+fn zsh_binary() -> OsString {
+    env::var_os("ZSH_MODULE_TEST_ZSH").unwrap_or_else(|| "zsh".into())
+}
+
+/// Whether a zsh binary can be found. Useful to skip tests on machines without zsh.
+pub fn zsh_available() -> bool {
+    Command::new(zsh_binary())
+        .args(["-fc", "true"])
+        .output()
+        .map(|out| out.status.success())
+        .unwrap_or(false)
+}
+
+/// Finds the directory cargo builds into, based on where the test executable lives.
+fn target_dir() -> io::Result<PathBuf> {
+    if let Some(dir) = env::var_os("CARGO_TARGET_DIR") {
+        return Ok(dir.into());
+    }
+    // Test executables live in `<target>/<profile>/deps/`
+    let exe = env::current_exe()?;
+    exe.ancestors()
+        .nth(3)
+        .map(Path::to_path_buf)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "couldn't find target dir"))
+}
+
+/// Builds the cargo package `package` (which must have a `cdylib` target) and returns the path to
+/// the shared library.
+///
+/// The package is built in its own target directory, so that it doesn't have to wait on the
+/// `cargo test` invocation that is running the tests.
+pub fn build_library(package: &str) -> io::Result<PathBuf> {
+    let target_dir = target_dir()?.join("zsh-module-test");
+    let status = Command::new(env::var_os("CARGO").unwrap_or_else(|| "cargo".into()))
+        .args(["build", "--package", package, "--target-dir"])
+        .arg(&target_dir)
+        .status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("failed to build {}", package)));
+    }
+    Ok(target_dir.join("debug").join(format!(
+        "{}{}{}",
+        env::consts::DLL_PREFIX,
+        package.replace('-', "_"),
+        env::consts::DLL_SUFFIX
+    )))
+}
+
+/// A temporary directory that gets deleted on drop.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new() -> io::Result<Self> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let path = env::temp_dir().join(format!(
+            "zsh-module-test-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&path)?;
+        Ok(Self(path))
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A zsh environment with a module installed in its `module_path`.
+pub struct ZshTest {
+    module_name: String,
+    module_dir: TempDir,
+    zdotdir: TempDir,
+}
+
+impl ZshTest {
+    /// Builds the cargo package `package` (which must have a `cdylib` target) and installs it as
+    /// the zsh module `module_name`. See [`build_library`].
+    pub fn build(package: &str, module_name: &str) -> io::Result<Self> {
+        Self::with_library(&build_library(package)?, module_name)
+    }
+    /// Installs an already built shared library at `lib` as the zsh module `module_name`.
+    pub fn with_library(lib: &Path, module_name: &str) -> io::Result<Self> {
+        let module_dir = TempDir::new()?;
+        // Zsh always looks for `<name>.so`, regardless of the platform.
+        fs::copy(lib, module_dir.0.join(format!("{}.so", module_name)))?;
+        Ok(Self {
+            module_name: module_name.into(),
+            module_dir,
+            zdotdir: TempDir::new()?,
+        })
+    }
+    /// The temporary `ZDOTDIR` used by the shell. A `.zshrc` put here is sourced before each
+    /// script, once the module is loaded.
+    pub fn zdotdir(&self) -> &Path {
+        &self.zdotdir.0
+    }
+    /// Runs `script` in a new non-interactive zsh that has the module loaded, after the `.zshrc`
+    /// of [`Self::zdotdir`], if there's one.
+    ///
+    /// Panics if the module fails to load.
+    pub fn run(&self, script: &str) -> Output {
+        // `-f` keeps the system's startup files out, and non-interactive shells never read
+        // `.zshrc`, so it's sourced by hand
+        let prelude = format!(
+            "module_path=({} $module_path)\nzmodload {} || exit {}\n\
+             [[ -f $ZDOTDIR/.zshrc ]] && source $ZDOTDIR/.zshrc\n",
+            self.module_dir.0.display(),
+            self.module_name,
+            LOAD_FAILED
+        );
+        let out = Command::new(zsh_binary())
+            .arg("-f")
+            .arg("-c")
+            .arg(prelude + script)
+            .env("ZDOTDIR", self.zdotdir())
+            .output()
+            .expect("failed to run zsh");
+        let output = Output {
+            status: out.status.code().unwrap_or(-1),
+            stdout: String::from_utf8_lossy(&out.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&out.stderr).into_owned(),
+        };
+        if output.status == LOAD_FAILED {
+            panic!(
+                "failed to load module {}:\n{}",
+                self.module_name, output.stderr
+            );
+        }
+        output
+    }
+}
+
+/// The result of running a script with [`ZshTest::run`].
+#[derive(Debug, Clone)]
+pub struct Output {
+    /// The exit status of the script, or `-1` if zsh was killed by a signal.
+    pub status: i32,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl Output {
+    /// Asserts the script exited with status `0`.
+    #[track_caller]
+    pub fn assert_success(&self) -> &Self {
+        self.assert_status(0)
+    }
+    /// Asserts the script exited with `status`.
+    #[track_caller]
+    pub fn assert_status(&self, status: i32) -> &Self {
+        assert_eq!(
+            self.status, status,
+            "unexpected exit status\nstdout:\n{}\nstderr:\n{}",
+            self.stdout, self.stderr
+        );
+        self
+    }
+    /// Asserts the script printed exactly `stdout`.
+    #[track_caller]
+    pub fn assert_stdout(&self, stdout: &str) -> &Self {
+        assert_eq!(self.stdout, stdout, "stderr:\n{}", self.stderr);
+        self
+    }
+    /// Asserts the script's error output contains `text`.
+    #[track_caller]
+    pub fn assert_stderr_contains(&self, text: &str) -> &Self {
+        assert!(
+            self.stderr.contains(text),
+            "{:?} not found in stderr:\n{}",
+            text,
+            self.stderr
+        );
+        self
+    }
+}
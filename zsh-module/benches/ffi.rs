@@ -0,0 +1,113 @@
+//! Benchmarks for the paths between zsh and Rust code that modules hit the most.
+//!
+//! These run against an embedded zsh, so they need the `testing` feature and a shared `libzsh`:
+//! ```sh
+//! ZSH_SYS_LIB_DIR=/path/to/zsh/lib cargo bench -p zsh-module --features testing
+//! ```
+use std::{
+    ffi::{c_char, CStr, CString},
+    sync::Once,
+};
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use zsh_module::{testing, zsh, Builtin, MaybeError, ModuleBuilder, Opts};
+use zsh_sys as zsys;
+
+const ARRAY_LEN: usize = 10_000;
+
+fn noop(_: &mut (), _: &str, args: &[&str], _: Opts) -> MaybeError {
+    black_box(args);
+    Ok(())
+}
+
+/// Initializes zsh and loads a module with a `bench_noop` builtin.
+fn setup() -> testing::ZshGuard {
+    static LOAD: Once = Once::new();
+    let guard = testing::init_embedded_zsh();
+    LOAD.call_once(|| {
+        let module = ModuleBuilder::new(())
+            .builtin(noop, Builtin::new("bench_noop").flags("ab"))
+            .build();
+        testing::load_module("bench", module).unwrap();
+    });
+    guard
+}
+
+fn eval(script: &str) {
+    zsh::eval_simple(script).unwrap()
+}
+
+fn builtin_dispatch(c: &mut Criterion) {
+    let _zsh = setup();
+    let mut group = c.benchmark_group("builtin dispatch");
+    // The cost of running any command at all, to compare the others against.
+    group.bench_function("zsh builtin", |b| b.iter(|| eval(":")));
+    group.bench_function("no args", |b| b.iter(|| eval("bench_noop")));
+    group.bench_function("flags", |b| b.iter(|| eval("bench_noop -a -b")));
+    let many_args = format!("bench_noop {}", vec!["arg"; 1000].join(" "));
+    group.bench_function("1000 args", |b| b.iter(|| eval(&many_args)));
+    group.finish();
+}
+
+fn params(c: &mut Criterion) {
+    let _zsh = setup();
+    let mut group = c.benchmark_group("params");
+    eval("bench_scalar=value");
+    group.bench_function("get scalar", |b| {
+        b.iter(|| unsafe {
+            let value = zsys::getsparam(c"bench_scalar".as_ptr() as *mut _);
+            CStr::from_ptr(black_box(value)).to_str().unwrap().len()
+        })
+    });
+    group.bench_function("set scalar", |b| {
+        b.iter(|| unsafe {
+            zsys::setsparam(
+                c"bench_scalar".as_ptr() as *mut _,
+                zsys::ztrdup(c"value".as_ptr()),
+            )
+        })
+    });
+    group.finish();
+}
+
+fn large_array(c: &mut Criterion) {
+    let _zsh = setup();
+    let mut group = c.benchmark_group("large array");
+    let items: Vec<String> = (0..ARRAY_LEN).map(|i| format!("item{}", i)).collect();
+    let script = format!("bench_array=({})", items.join(" "));
+    group.bench_function("assign from script", |b| b.iter(|| eval(&script)));
+    group.bench_function("assign from rust", |b| {
+        b.iter_batched(
+            || {
+                // `setaparam` takes ownership of the array and its strings
+                let array =
+                    unsafe { zsys::zalloc((items.len() + 1) * std::mem::size_of::<*mut c_char>()) }
+                        as *mut *mut c_char;
+                for (i, item) in items.iter().enumerate() {
+                    let item = CString::new(item.as_str()).unwrap();
+                    unsafe { *array.add(i) = zsys::ztrdup(item.as_ptr()) };
+                }
+                unsafe { *array.add(items.len()) = std::ptr::null_mut() };
+                array
+            },
+            |array| unsafe { zsys::setaparam(c"bench_array".as_ptr() as *mut _, array) },
+            BatchSize::SmallInput,
+        )
+    });
+    eval(&script);
+    group.bench_function("iterate", |b| {
+        b.iter(|| unsafe {
+            let mut ptr = zsys::getaparam(c"bench_array".as_ptr() as *mut _);
+            let mut total = 0;
+            while !(*ptr).is_null() {
+                total += CStr::from_ptr(*ptr).to_str().unwrap().len();
+                ptr = ptr.add(1);
+            }
+            total
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, builtin_dispatch, params, large_array);
+criterion_main!(benches);
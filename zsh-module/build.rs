@@ -0,0 +1,14 @@
+use std::env;
+
+// Re-emits the zsh version cfg flags (e.g. `zsh_5_9`) detected by `zsh-sys`, so that APIs can be
+// gated on the version of zsh being built against.
+fn main() {
+    let known = env::var("DEP_ZSH_KNOWN_CFGS").expect("zsh-sys didn't report its known cfgs");
+    for cfg in known.split(',').filter(|cfg| !cfg.is_empty()) {
+        println!("cargo:rustc-check-cfg=cfg({})", cfg);
+    }
+    let enabled = env::var("DEP_ZSH_CFGS").expect("zsh-sys didn't report the zsh version");
+    for cfg in enabled.split(',').filter(|cfg| !cfg.is_empty()) {
+        println!("cargo:rustc-cfg={}", cfg);
+    }
+}
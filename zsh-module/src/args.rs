@@ -0,0 +1,178 @@
+use std::{
+    borrow::Cow,
+    ffi::{CStr, OsStr},
+    fmt::Display,
+    str::FromStr,
+};
+
+use crate::{validate, Builtin, CStrArray, ConversionError, ParsedOpts, ZError, ZResult};
+
+/// How the arguments of a builtin are turned into strings. See [`Builtin::decoding`].
+///
+/// Zsh passes arguments metafied, and doesn't require them to be valid UTF-8. The raw bytes are
+/// always available through [`Args::bytes`].
+///
+/// [`Builtin::decoding`]: crate::Builtin::decoding
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Decoding {
+    /// Unmetafied, and the builtin fails if any of them isn't valid UTF-8.
+    Strict,
+    /// Unmetafied, with invalid UTF-8 replaced by `U+FFFD`.
+    #[default]
+    Lossy,
+    /// As zsh passes them, still metafied, and the builtin fails if any of them isn't valid
+    /// UTF-8.
+    Metafied,
+}
+
+/// The arguments of a builtin, decoded as its [`Decoding`] says. Handlers get them with
+/// [`CmdContext::raw_args`](crate::CmdContext::raw_args).
+#[derive(Clone, Copy)]
+pub struct Args<'a> {
+    array: CStrArray<'a>,
+    decoding: Decoding,
+}
+
+impl<'a> Args<'a> {
+    pub fn new(array: CStrArray<'a>, decoding: Decoding) -> Self {
+        Self { array, decoding }
+    }
+    pub fn len(&self) -> usize {
+        self.array.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.array.is_empty()
+    }
+    /// The argument at `index` as zsh passed it, metafied.
+    pub fn bytes(&self, index: usize) -> Option<&'a [u8]> {
+        self.array.get(index).map(CStr::to_bytes)
+    }
+    /// The argument at `index`, unmetafied but not decoded.
+    pub fn unmetafied(&self, index: usize) -> Option<Cow<'a, [u8]>> {
+        self.array.get_bytes(index)
+    }
+    /// The argument at `index`, unmetafied, for builtins taking paths. Unlike [`Self::get`], it
+    /// works whatever the [`Decoding`], and with names that aren't UTF-8.
+    pub fn os_str(&self, index: usize) -> Option<Cow<'a, OsStr>> {
+        self.array.get_os_str(index)
+    }
+    /// The argument at `index`, decoded.
+    pub fn get(&self, index: usize) -> Option<ZResult<Cow<'a, str>>> {
+        let invalid = || {
+            ZError::from(ConversionError::InvalidUtf8 {
+                what: format!("argument {}", index + 1),
+            })
+        };
+        let decoded = match self.decoding {
+            Decoding::Metafied => std::str::from_utf8(self.bytes(index)?)
+                .map(Cow::Borrowed)
+                .map_err(|_| invalid()),
+            Decoding::Strict => match self.unmetafied(index)? {
+                Cow::Borrowed(bytes) => std::str::from_utf8(bytes)
+                    .map(Cow::Borrowed)
+                    .map_err(|_| invalid()),
+                Cow::Owned(bytes) => String::from_utf8(bytes)
+                    .map(Cow::Owned)
+                    .map_err(|_| invalid()),
+            },
+            Decoding::Lossy => Ok(match self.unmetafied(index)? {
+                Cow::Borrowed(bytes) => String::from_utf8_lossy(bytes),
+                Cow::Owned(bytes) => Cow::Owned(String::from_utf8_lossy(&bytes).into_owned()),
+            }),
+        };
+        Some(decoded)
+    }
+    /// The arguments from `index` on, e.g. to hand them to a subcommand. Empty if `index` is out
+    /// of bounds.
+    ///
+    /// ```no_run
+    /// # fn run(args: zsh_module::Args) -> zsh_module::MaybeError {
+    /// match args.get(0).transpose()?.as_deref() {
+    ///     Some("add") => { /* add(args.rest(1)) */ }
+    ///     _ => return Err(zsh_module::ZError::Usage("usage: mycmd add ...".into()).into()),
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn rest(&self, index: usize) -> Self {
+        Self {
+            array: self.array.rest(index),
+            decoding: self.decoding,
+        }
+    }
+    /// Every argument, decoded. Fails on the first one that can't be.
+    pub fn decode_all(&self) -> ZResult<Vec<Cow<'a, str>>> {
+        (0..self.len()).filter_map(|i| self.get(i)).collect()
+    }
+}
+
+/// The arguments and options of a builtin as a struct. See [`ModuleBuilder::builtin_args`].
+///
+/// With the `derive` feature, it can be derived with `#[derive(BuiltinArgs)]`.
+///
+/// [`ModuleBuilder::builtin_args`]: crate::ModuleBuilder::builtin_args
+pub trait BuiltinArgs: Sized {
+    /// Declares the options and argument counts the struct needs on `builtin`.
+    fn declare(builtin: Builtin) -> Builtin;
+    /// Builds the struct out of the builtin's arguments and options.
+    fn parse(args: &[&str], opts: &ParsedOpts) -> ZResult<Self>;
+}
+
+/// Converts the argument `name` (or the argument of the option `name`), for derived
+/// [`BuiltinArgs`].
+#[doc(hidden)]
+pub fn parse_arg<T>(name: &str, value: &str) -> ZResult<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    validate::arg(name, value, |value: &str| {
+        value.parse::<T>().map_err(|e| e.to_string())
+    })
+}
+
+#[doc(hidden)]
+pub fn missing(name: &str) -> ZError {
+    ZError::Usage(format!("missing {}", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{c_char, CString};
+
+    use super::*;
+
+    fn with_args(args: &[&[u8]], f: impl FnOnce(CStrArray)) {
+        let strings: Vec<CString> = args.iter().map(|arg| CString::new(*arg).unwrap()).collect();
+        let raw: Vec<*mut c_char> = strings
+            .iter()
+            .map(|s| s.as_ptr() as *mut _)
+            .chain([std::ptr::null_mut()])
+            .collect();
+        f(unsafe { CStrArray::from_raw(raw.as_ptr()) })
+    }
+
+    #[test]
+    fn decodings() {
+        // `ő` is `\xc5\x91`, metafied as `\xc5\x83\xb1`
+        with_args(&[b"a", b"\xc5\x83\xb1", b"\xff"], |array| {
+            let strict = Args::new(array, Decoding::Strict);
+            assert_eq!(strict.get(1).unwrap().unwrap(), "ő");
+            assert_eq!(
+                strict.decode_all().unwrap_err().to_string(),
+                "conversion error: argument 3 is not valid UTF-8"
+            );
+            let lossy = Args::new(array, Decoding::Lossy);
+            assert_eq!(lossy.decode_all().unwrap(), ["a", "ő", "\u{fffd}"]);
+            let metafied = Args::new(array, Decoding::Metafied);
+            assert!(metafied.get(1).unwrap().is_err());
+            assert_eq!(metafied.bytes(2), Some(&b"\xff"[..]));
+            assert_eq!(strict.os_str(1).as_deref(), Some(OsStr::new("ő")));
+            assert_eq!(metafied.get(3).map(|_| ()), None);
+            let rest = lossy.rest(1);
+            assert_eq!(rest.decode_all().unwrap(), ["ő", "\u{fffd}"]);
+            assert_eq!(rest.rest(1).get(0).unwrap().unwrap(), "\u{fffd}");
+            assert!(lossy.rest(4).is_empty());
+        });
+    }
+}
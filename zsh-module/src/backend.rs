@@ -0,0 +1,119 @@
+//! The boundary between this crate and zsh's internals.
+//!
+//! Functionality that only needs zsh for side effects (e.g. printing warnings) goes through
+//! [`Backend`] instead of calling [`zsh_sys`] directly, so that it can run against the in-memory
+//! fake in [`crate::mock`] when unit testing.
+use std::{
+    ffi::{c_int, CStr},
+    sync::OnceLock,
+};
+
+use zsh_sys as zsys;
+
+use crate::{metafy_heap, unmetafy, zalloc, CStrArrayBuf};
+
+pub(crate) trait Backend {
+    fn warn(msg: &CStr);
+    fn warn_named(cmd: &CStr, msg: &CStr);
+    fn error(msg: &CStr);
+    fn error_named(cmd: &CStr, msg: &CStr);
+    /// Whether zsh would drop messages instead of printing them, e.g. because an error was
+    /// already reported.
+    fn messages_suppressed() -> bool;
+    /// Whether the user interrupted the shell, e.g. with `^C`.
+    fn interrupted() -> bool;
+    /// The amount of terminal columns taken by `c`.
+    fn char_width(c: char) -> usize;
+    /// The value of an integer parameter, or 0 if it isn't set.
+    fn get_int_param(name: &CStr) -> i64;
+    fn set_int_param(name: &CStr, value: i64);
+    /// The (unmetafied) value of a scalar parameter, or [`None`] if it isn't set.
+    fn get_str_param(name: &CStr) -> Option<Vec<u8>>;
+    fn set_str_param(name: &CStr, value: &[u8]);
+    /// The (unmetafied) elements of an array parameter, or [`None`] if it isn't set.
+    fn get_array_param(name: &CStr) -> Option<Vec<Vec<u8>>>;
+    /// Returns whether zsh accepted the value.
+    fn set_array_param(name: &CStr, values: &[&[u8]]) -> bool;
+}
+
+/// The real zsh.
+#[cfg_attr(any(test, feature = "mock"), allow(dead_code))]
+pub(crate) struct Zsh;
+
+impl Backend for Zsh {
+    fn warn(msg: &CStr) {
+        unsafe { zsys::zwarn(msg.as_ptr()) }
+    }
+    fn warn_named(cmd: &CStr, msg: &CStr) {
+        unsafe { zsys::zwarnnam(cmd.as_ptr(), msg.as_ptr()) }
+    }
+    fn error(msg: &CStr) {
+        unsafe { zsys::zerr(msg.as_ptr()) }
+    }
+    fn error_named(cmd: &CStr, msg: &CStr) {
+        unsafe { zsys::zerrnam(cmd.as_ptr(), msg.as_ptr()) }
+    }
+    fn messages_suppressed() -> bool {
+        unsafe { zsys::errflag != 0 || zsys::noerrs != 0 }
+    }
+    fn interrupted() -> bool {
+        unsafe { zsys::errflag & zsys::ERRFLAG_INT as c_int != 0 }
+    }
+    fn char_width(c: char) -> usize {
+        type ZWcWidth = unsafe extern "C" fn(zsys::wint_t) -> c_int;
+        // Looked up once, since this runs for every character. The symbol is part of the shell,
+        // so it doesn't come and go
+        static ZWCWIDTH: OnceLock<Option<ZWcWidth>> = OnceLock::new();
+        // Only there with multibyte support. Without it, zsh takes every byte for a column
+        let zwcwidth =
+            ZWCWIDTH.get_or_init(|| unsafe { crate::capabilities::function("zwcwidth") });
+        match zwcwidth {
+            Some(zwcwidth) => unsafe { zwcwidth(c as zsys::wint_t) }.max(0) as usize,
+            None => c.len_utf8(),
+        }
+    }
+    fn get_int_param(name: &CStr) -> i64 {
+        unsafe { zsys::getiparam(name.as_ptr() as *mut _) as i64 }
+    }
+    fn set_int_param(name: &CStr, value: i64) {
+        unsafe { zsys::setiparam(name.as_ptr() as *mut _, value as zsys::zlong) };
+    }
+    fn get_str_param(name: &CStr) -> Option<Vec<u8>> {
+        let value = unsafe { zsys::getsparam(name.as_ptr() as *mut _) };
+        (!value.is_null()).then(|| unmetafy(unsafe { CStr::from_ptr(value) }.to_bytes()))
+    }
+    fn set_str_param(name: &CStr, value: &[u8]) {
+        // Zsh takes ownership of the value
+        unsafe {
+            zsys::setsparam(
+                name.as_ptr() as *mut _,
+                zalloc::hand_over(zalloc::ztrdup(metafy_heap(value))),
+            )
+        };
+    }
+    fn get_array_param(name: &CStr) -> Option<Vec<Vec<u8>>> {
+        let mut ptr = unsafe { zsys::getaparam(name.as_ptr() as *mut _) };
+        if ptr.is_null() {
+            return None;
+        }
+        let mut values = Vec::new();
+        unsafe {
+            while !(*ptr).is_null() {
+                values.push(unmetafy(CStr::from_ptr(*ptr).to_bytes()));
+                ptr = ptr.add(1);
+            }
+        }
+        Some(values)
+    }
+    fn set_array_param(name: &CStr, values: &[&[u8]]) -> bool {
+        // Zsh takes ownership of the array and its elements
+        let array: CStrArrayBuf = values.iter().collect();
+        unsafe { !zsys::setaparam(name.as_ptr() as *mut _, array.into_raw()).is_null() }
+    }
+}
+
+#[cfg(not(any(test, feature = "mock")))]
+pub(crate) type Current = Zsh;
+
+#[cfg(any(test, feature = "mock"))]
+pub(crate) type Current = crate::mock::Mock;
@@ -0,0 +1,88 @@
+//! Runtime checks for zsh functions and variables that may be missing.
+//!
+//! Calling a zsh function directly makes the module fail to load on shells that don't have it,
+//! e.g. older releases, or shells where the module providing it (like `zsh/zle`) isn't loaded.
+//! Looking it up at runtime instead lets the module load anyway, and only disable what needs it.
+//!
+//! # Example
+//! ```no_run
+//! use std::ffi::c_char;
+//!
+//! use zsh_module::capabilities;
+//!
+//! type ZleGetLine = unsafe extern "C" fn(*mut i32, *mut i32) -> *mut c_char;
+//!
+//! match unsafe { capabilities::function::<ZleGetLine>("zlegetline") } {
+//!     Some(zlegetline) => { /* ... */ }
+//!     None => zsh_module::warn!("ZLE isn't loaded, completion is disabled"),
+//! }
+//! ```
+use std::{collections::HashMap, ffi::c_void, sync::Mutex};
+
+use crate::to_cstr;
+
+/// Addresses of the symbols found so far.
+static RESOLVED: Mutex<Option<HashMap<String, usize>>> = Mutex::new(None);
+
+/// The address of the symbol `name`, if the shell (or any library or module it loaded) has it.
+///
+/// Symbols that were found are cached. Missing ones are looked up again every time, since the
+/// module providing them may have been loaded since.
+pub fn lookup(name: &str) -> Option<*mut c_void> {
+    let mut resolved = RESOLVED.lock().unwrap();
+    let resolved = resolved.get_or_insert_with(HashMap::new);
+    if let Some(&addr) = resolved.get(name) {
+        return Some(addr as *mut c_void);
+    }
+    let cname = to_cstr(name);
+    let addr = unsafe { libc::dlsym(libc::RTLD_DEFAULT, cname.as_ptr()) };
+    if addr.is_null() {
+        return None;
+    }
+    resolved.insert(name.to_string(), addr as usize);
+    Some(addr)
+}
+
+/// Whether the symbol `name` exists. See [`lookup`].
+pub fn has(name: &str) -> bool {
+    lookup(name).is_some()
+}
+
+/// The function `name`, as an `F`. See [`lookup`].
+///
+/// # Safety
+/// `F` must be a function pointer type matching the function's signature.
+pub unsafe fn function<F: Copy>(name: &str) -> Option<F> {
+    assert_eq!(
+        std::mem::size_of::<F>(),
+        std::mem::size_of::<*mut c_void>(),
+        "F must be a function pointer"
+    );
+    lookup(name).map(|addr| std::mem::transmute_copy(&addr))
+}
+
+/// Whether the line editor (`zsh/zle`) is loaded.
+pub fn zle() -> bool {
+    has("zlegetline")
+}
+
+/// Whether zsh was built with multibyte support, so it knows how wide characters are.
+pub fn multibyte() -> bool {
+    has("zwcwidth")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::c_char;
+
+    use super::*;
+
+    #[test]
+    fn lookups() {
+        assert!(has("strlen"));
+        assert!(!has("zsh_module_no_such_symbol"));
+        let strlen =
+            unsafe { function::<unsafe extern "C" fn(*const c_char) -> usize>("strlen") }.unwrap();
+        assert_eq!(unsafe { strlen(c"four".as_ptr()) }, 4);
+    }
+}
@@ -0,0 +1,174 @@
+//! Writing completers in Rust: a builtin called from a completion function can read the word
+//! being completed and add matches with a [`CompletionContext`], instead of printing zsh script
+//! for the function to evaluate.
+//!
+//! # Example
+//! ```no_run
+//! use zsh_module::{completion::CompletionContext, Builtin, MaybeError, ModuleBuilder};
+//!
+//! // Called by a completion function, e.g. `compdef '_mymod-complete' mycmd`
+//! fn complete(_: &mut (), _: &str, _: &[&str], _: zsh_module::Opts) -> MaybeError {
+//!     let mut ctx = CompletionContext::current().ok_or("not completing")?;
+//!     ctx.group("colors").explanation("color");
+//!     ctx.add_described(&[("red", "warm"), ("blue", "cold")])?;
+//!     Ok(())
+//! }
+//!
+//! let module = ModuleBuilder::new(())
+//!     .builtin(complete, Builtin::new("_mymod-complete"))
+//!     .build();
+//! ```
+use crate::{
+    backend::{Backend, Current},
+    zsh, ZResult,
+};
+
+const MATCHES: &str = "_zsh_module_compadd_matches";
+const DISPLAYS: &str = "_zsh_module_compadd_displays";
+const GROUP: &str = "_zsh_module_compadd_group";
+const EXPLANATION: &str = "_zsh_module_compadd_explanation";
+
+/// The state of the completion system, and options for the matches added to it.
+#[derive(Debug, Clone)]
+pub struct CompletionContext {
+    words: Vec<String>,
+    current: usize,
+    prefix: String,
+    suffix: String,
+    group: Option<String>,
+    explanation: Option<String>,
+}
+
+fn str_param(name: &std::ffi::CStr) -> String {
+    Current::get_str_param(name)
+        .map(|value| String::from_utf8_lossy(&value).into_owned())
+        .unwrap_or_default()
+}
+
+impl CompletionContext {
+    /// The context of the running completion, or [`None`] if nothing is being completed.
+    pub fn current() -> Option<Self> {
+        let words = Current::get_array_param(c"words")?
+            .into_iter()
+            .map(|word| String::from_utf8_lossy(&word).into_owned())
+            .collect();
+        let current = usize::try_from(Current::get_int_param(c"CURRENT")).ok()?;
+        Some(Self {
+            words,
+            current: current.checked_sub(1)?,
+            prefix: str_param(c"PREFIX"),
+            suffix: str_param(c"SUFFIX"),
+            group: None,
+            explanation: None,
+        })
+    }
+    /// The words of the command line (`$words`).
+    pub fn words(&self) -> &[String] {
+        &self.words
+    }
+    /// The index of the word being completed in [`Self::words`].
+    pub fn current_index(&self) -> usize {
+        self.current
+    }
+    /// The word being completed.
+    pub fn current_word(&self) -> &str {
+        self.words.get(self.current).map_or("", String::as_str)
+    }
+    /// The part of the current word before the cursor (`$PREFIX`).
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+    /// The part of the current word after the cursor (`$SUFFIX`).
+    pub fn suffix(&self) -> &str {
+        &self.suffix
+    }
+    /// Puts the matches added from now on in the group `name`, listed separately from others
+    /// (`compadd -J`).
+    pub fn group(&mut self, name: &str) -> &mut Self {
+        self.group = Some(name.into());
+        self
+    }
+    /// Shows `explanation` above the matches added from now on (`compadd -X`).
+    pub fn explanation(&mut self, explanation: &str) -> &mut Self {
+        self.explanation = Some(explanation.into());
+        self
+    }
+    /// Adds `matches`, keeping those that match the current word. Returns whether any did.
+    pub fn add_matches(&self, matches: &[&str]) -> ZResult<bool> {
+        self.compadd(matches, None)
+    }
+    /// Adds matches listed with a description, each given as `(match, description)`. Returns
+    /// whether any matched the current word.
+    pub fn add_described(&self, matches: &[(&str, &str)]) -> ZResult<bool> {
+        let width = matches.iter().map(|(m, _)| m.chars().count()).max();
+        let displays: Vec<String> = matches
+            .iter()
+            .map(|(m, desc)| format!("{:<1$}  -- {2}", m, width.unwrap_or(0), desc))
+            .collect();
+        let names: Vec<&str> = matches.iter().map(|(m, _)| *m).collect();
+        self.compadd(&names, Some(&displays))
+    }
+    /// The `compadd` call, which finds its arguments in parameters.
+    fn script(&self, described: bool) -> String {
+        let mut script = String::from("compadd");
+        if self.group.is_some() {
+            script += &format!(" -J \"${}\"", GROUP);
+        }
+        if self.explanation.is_some() {
+            script += &format!(" -X \"${}\"", EXPLANATION);
+        }
+        if described {
+            script += &format!(" -l -d {}", DISPLAYS);
+        }
+        script + &format!(" -a {}", MATCHES)
+    }
+    fn compadd(&self, matches: &[&str], displays: Option<&[String]>) -> ZResult<bool> {
+        // Passing everything through parameters saves quoting it
+        let set_array = |name: &str, values: &[&str]| {
+            let values: Vec<&[u8]> = values.iter().map(|v| v.as_bytes()).collect();
+            Current::set_array_param(&crate::to_cstr(name), &values);
+        };
+        set_array(MATCHES, matches);
+        if let Some(displays) = displays {
+            let displays: Vec<&str> = displays.iter().map(String::as_str).collect();
+            set_array(DISPLAYS, &displays);
+        }
+        if let Some(group) = &self.group {
+            Current::set_str_param(&crate::to_cstr(GROUP), group.as_bytes());
+        }
+        if let Some(explanation) = &self.explanation {
+            Current::set_str_param(&crate::to_cstr(EXPLANATION), explanation.as_bytes());
+        }
+        let status = zsh::eval_with_status(&self.script(displays.is_some()));
+        let _ = zsh::eval_simple(&format!(
+            "unset {} {} {} {}",
+            MATCHES, DISPLAYS, GROUP, EXPLANATION
+        ));
+        Ok(status? == 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock;
+
+    #[test]
+    fn context_from_parameters() {
+        assert!(CompletionContext::current().is_none());
+        mock::set_array_param("words", &["git", "che"]);
+        mock::set_param("CURRENT", "2");
+        mock::set_param("PREFIX", "ch");
+        mock::set_param("SUFFIX", "e");
+        let mut ctx = CompletionContext::current().unwrap();
+        assert_eq!(ctx.current_word(), "che");
+        assert_eq!((ctx.prefix(), ctx.suffix()), ("ch", "e"));
+        assert_eq!(ctx.script(false), "compadd -a _zsh_module_compadd_matches");
+        ctx.group("commands").explanation("command");
+        assert_eq!(
+            ctx.script(true),
+            "compadd -J \"$_zsh_module_compadd_group\" -X \"$_zsh_module_compadd_explanation\" \
+             -l -d _zsh_module_compadd_displays -a _zsh_module_compadd_matches"
+        );
+    }
+}
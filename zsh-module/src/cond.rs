@@ -0,0 +1,57 @@
+//! Conditions usable in `[[ ... ]]`, like `[[ -newer a b ]]` or `[[ a -newer b ]]`. See
+//! [`ModuleBuilder::condition`](crate::ModuleBuilder::condition).
+use std::ffi::CString;
+
+use crate::{extensions::State, to_cstr};
+
+/// The handler of a condition, given its (expanded) arguments.
+pub(crate) type CondFn = Box<dyn FnMut(&mut State, &[&str]) -> bool>;
+
+/// Properties of a condition.
+pub struct Condition {
+    pub(crate) name: CString,
+    pub(crate) infix: bool,
+    pub(crate) minargs: i32,
+    pub(crate) maxargs: i32,
+}
+
+impl Condition {
+    /// Creates a condition written before its arguments, like `[[ -name a b ]]`, taking any
+    /// amount of them.
+    ///
+    /// `name` is given without the leading `-`.
+    pub fn prefix(name: &str) -> Self {
+        Self {
+            name: to_cstr(name),
+            infix: false,
+            minargs: 0,
+            maxargs: -1,
+        }
+    }
+    /// Creates a condition written between its two arguments, like `[[ a -name b ]]`.
+    ///
+    /// `name` is given without the leading `-`.
+    pub fn infix(name: &str) -> Self {
+        Self {
+            name: to_cstr(name),
+            infix: true,
+            minargs: 2,
+            maxargs: 2,
+        }
+    }
+    /// Sets the minimum amount of arguments of a prefix condition. Zsh refuses to evaluate it with
+    /// fewer.
+    pub fn minargs(mut self, value: i32) -> Self {
+        if !self.infix {
+            self.minargs = value;
+        }
+        self
+    }
+    /// Sets the maximum amount of arguments of a prefix condition, [`None`] meaning any.
+    pub fn maxargs(mut self, value: Option<u32>) -> Self {
+        if !self.infix {
+            self.maxargs = value.map(|i| i as i32).unwrap_or(-1);
+        }
+        self
+    }
+}
@@ -0,0 +1,166 @@
+use std::{
+    any::Any,
+    fs::File,
+    io::{self, Read, Write},
+    marker::PhantomData,
+    mem::ManuallyDrop,
+    os::fd::{FromRawFd, RawFd},
+};
+
+use crate::{
+    backend::{Backend, Current},
+    extensions::State,
+    Args, Feature, Opts,
+};
+
+/// Everything a builtin registered with
+/// [`ModuleBuilder::builtin_with_context`](crate::ModuleBuilder::builtin_with_context) is given.
+///
+/// New capabilities are added here as methods, without changing the signature of handlers.
+pub struct CmdContext<'a, A> {
+    state: &'a mut State,
+    _data: PhantomData<A>,
+    name: &'a str,
+    args: &'a [&'a str],
+    opts: Opts,
+}
+
+impl<'a, A: Any> CmdContext<'a, A> {
+    pub(crate) fn new(
+        state: &'a mut State,
+        name: &'a str,
+        args: &'a [&'a str],
+        opts: Opts,
+    ) -> Self {
+        Self {
+            state,
+            _data: PhantomData,
+            name,
+            args,
+            opts,
+        }
+    }
+    /// The name the builtin was called with.
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+    pub fn args(&self) -> &'a [&'a str] {
+        self.args
+    }
+    /// The arguments as zsh passed them, e.g. to get at ones that aren't valid UTF-8 with
+    /// [`Args::os_str`]. [`Self::args`] are these, decoded as [`Builtin::decoding`] says.
+    ///
+    /// `None` when the handler isn't called by zsh, e.g. in tests.
+    ///
+    /// [`Builtin::decoding`]: crate::Builtin::decoding
+    pub fn raw_args(&self) -> Option<Args<'_>> {
+        self.state.raw_args
+    }
+    pub fn opts(&self) -> &Opts {
+        &self.opts
+    }
+    /// The module's user data.
+    pub fn data(&self) -> &A {
+        self.state.user_data.downcast_ref().unwrap()
+    }
+    pub fn data_mut(&mut self) -> &mut A {
+        self.state.data()
+    }
+    /// The value of type `T` added to the module with [`Module::insert`](crate::Module::insert),
+    /// if any.
+    pub fn get<T: Any>(&self) -> Option<&T> {
+        self.state.extensions.get()
+    }
+    pub fn get_mut<T: Any>(&mut self) -> Option<&mut T> {
+        self.state.extensions.get_mut()
+    }
+    /// The builtin's standard input.
+    ///
+    /// Unlike [`std::io::stdin`], it isn't buffered, so it doesn't read ahead input meant for the
+    /// commands run after the builtin.
+    pub fn stdin(&self) -> Fd {
+        Fd::new(0)
+    }
+    /// The builtin's standard output.
+    ///
+    /// Unlike [`std::io::stdout`], it isn't buffered, so what's written to it shows up before the
+    /// output of the commands run after the builtin.
+    pub fn stdout(&self) -> Fd {
+        Fd::new(1)
+    }
+    /// The features of the module, like `zmodload -lF <module>` lists them.
+    pub fn features(&self) -> &[Feature] {
+        &self.state.features
+    }
+    /// Enables or disables the feature `feature` of the module, like
+    /// `zmodload -F <module> +b:name` does. Features are named like `b:name` for builtins,
+    /// `c:name` for conditions, `f:name` for math functions and `p:name` for parameters.
+    ///
+    /// The change happens once the builtin returns, since the module can't change while it runs.
+    pub fn set_feature_enabled(&mut self, feature: &str, enabled: bool) {
+        self.state
+            .feature_changes
+            .push((feature.to_string(), enabled));
+    }
+    /// Runs `cb` whenever `fd` becomes readable, once the builtin returns. See
+    /// [`Module::watch_fd`](crate::Module::watch_fd).
+    #[cfg(feature = "watch")]
+    pub fn watch_fd<F>(&mut self, fd: RawFd, cb: F)
+    where
+        F: 'static + FnMut(&mut A, RawFd, crate::watch::FdEvent) -> bool,
+    {
+        self.state.watch_fd(fd, cb)
+    }
+    /// Stops watching `fd`, once the builtin returns. Returns whether it was watched.
+    #[cfg(feature = "watch")]
+    pub fn unwatch_fd(&mut self, fd: RawFd) -> bool {
+        self.state.unwatch(fd)
+    }
+    /// A queue other threads can send closures through, to run them on the shell's thread. See
+    /// [`Module::main_thread_queue`](crate::Module::main_thread_queue).
+    #[cfg(feature = "watch")]
+    pub fn main_thread_queue(&self) -> crate::ZResult<crate::main_thread::MainThreadQueue<A>> {
+        self.state.main_thread_queue()
+    }
+    /// Runs `fut` on a background runtime, and `then` with its output back on the shell's
+    /// thread. See [`Module::spawn`](crate::Module::spawn).
+    #[cfg(feature = "async")]
+    pub fn spawn<F, C>(&mut self, fut: F, then: C) -> crate::ZResult<()>
+    where
+        F: std::future::Future + Send + 'static,
+        F::Output: Send,
+        C: 'static + FnOnce(&mut A, F::Output),
+    {
+        self.state.spawn(fut, then)
+    }
+    /// Whether the user interrupted the shell (e.g. with `^C`) since the builtin started. Builtins
+    /// that run for long should check it regularly, and return when it's set.
+    pub fn interrupted(&self) -> bool {
+        Current::interrupted()
+    }
+}
+
+/// An unbuffered file descriptor, which isn't closed when dropped. See [`CmdContext::stdin`],
+/// [`CmdContext::stdout`] and [`zsh::io`](crate::zsh::io).
+pub struct Fd(ManuallyDrop<File>);
+
+impl Fd {
+    pub(crate) fn new(fd: RawFd) -> Self {
+        Self(ManuallyDrop::new(unsafe { File::from_raw_fd(fd) }))
+    }
+}
+
+impl Read for Fd {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl Write for Fd {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
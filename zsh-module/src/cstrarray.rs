@@ -0,0 +1,337 @@
+use std::{
+    borrow::Cow,
+    ffi::{CStr, OsStr},
+    iter::FusedIterator,
+    marker::PhantomData,
+    os::{
+        raw::c_char,
+        unix::ffi::{OsStrExt, OsStringExt},
+    },
+};
+
+use crate::{metafy_heap, unmetafy, zalloc, ZError, ZResult, META};
+
+/// A view of a null-terminated array of C strings, like the arguments zsh passes to builtins.
+///
+/// The strings are metafied, just like zsh keeps them.
+#[derive(Clone, Copy)]
+pub struct CStrArray<'a> {
+    raw: *const *mut c_char,
+    len: usize,
+    _marker: PhantomData<&'a CStr>,
+}
+
+impl<'a> CStrArray<'a> {
+    /// Wraps a null-terminated array of C strings.
+    ///
+    /// # Safety
+    /// `raw` must point to a null-terminated array of valid C strings, which outlive `'a` and
+    /// aren't changed meanwhile.
+    pub unsafe fn from_raw(raw: *const *mut c_char) -> Self {
+        let mut len = 0;
+        while !(*raw.add(len)).is_null() {
+            len += 1;
+        }
+        Self {
+            raw,
+            len,
+            _marker: PhantomData,
+        }
+    }
+    /// The raw array, to pass to functions of [`zsh_sys`] this crate doesn't wrap.
+    pub fn as_raw(&self) -> *const *mut c_char {
+        self.raw
+    }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// The string at `index`, if it's in bounds.
+    pub fn get(&self, index: usize) -> Option<&'a CStr> {
+        (index < self.len).then(|| unsafe { CStr::from_ptr(*self.raw.add(index)) })
+    }
+    /// The string at `index`, unmetafied: the bytes it stands for, which may not be UTF-8. Only
+    /// copied if it had to be unmetafied.
+    pub fn get_bytes(&self, index: usize) -> Option<Cow<'a, [u8]>> {
+        let bytes = self.get(index)?.to_bytes();
+        Some(if bytes.contains(&META) {
+            Cow::Owned(unmetafy(bytes))
+        } else {
+            Cow::Borrowed(bytes)
+        })
+    }
+    /// The string at `index`, unmetafied, e.g. to use it as a path without losing names that
+    /// aren't UTF-8.
+    pub fn get_os_str(&self, index: usize) -> Option<Cow<'a, OsStr>> {
+        Some(match self.get_bytes(index)? {
+            Cow::Borrowed(bytes) => Cow::Borrowed(OsStr::from_bytes(bytes)),
+            Cow::Owned(bytes) => Cow::Owned(std::ffi::OsString::from_vec(bytes)),
+        })
+    }
+    pub fn iter(&self) -> CStrArrayIter<'a> {
+        CStrArrayIter {
+            array: *self,
+            front: 0,
+            back: self.len,
+        }
+    }
+    /// The strings from `index` on, e.g. the arguments left to a subcommand. Empty if `index` is
+    /// out of bounds.
+    ///
+    /// Only the end of an array can be sliced off, since it has to stay null-terminated.
+    pub fn rest(&self, index: usize) -> Self {
+        let index = index.min(self.len);
+        Self {
+            raw: unsafe { self.raw.add(index) },
+            len: self.len - index,
+            _marker: PhantomData,
+        }
+    }
+    /// Every string, e.g. to match on them as a slice.
+    pub fn to_vec(&self) -> Vec<&'a CStr> {
+        self.iter().collect()
+    }
+}
+
+impl<'a> IntoIterator for CStrArray<'a> {
+    type Item = &'a CStr;
+    type IntoIter = CStrArrayIter<'a>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// The strings of a [`CStrArray`], in order. See [`CStrArray::iter`].
+#[derive(Clone)]
+pub struct CStrArrayIter<'a> {
+    array: CStrArray<'a>,
+    /// The strings left are the ones in `front..back`.
+    front: usize,
+    back: usize,
+}
+
+impl<'a> Iterator for CStrArrayIter<'a> {
+    type Item = &'a CStr;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+        self.front += 1;
+        self.array.get(self.front - 1)
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.back - self.front;
+        (len, Some(len))
+    }
+}
+
+impl DoubleEndedIterator for CStrArrayIter<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+        self.back -= 1;
+        self.array.get(self.back)
+    }
+}
+
+impl ExactSizeIterator for CStrArrayIter<'_> {}
+
+impl FusedIterator for CStrArrayIter<'_> {}
+
+/// A view of a null-terminated array of C strings whose elements can be replaced, e.g. for
+/// builtins that rewrite their arguments before handing them to another command.
+///
+/// Replacements are allocated on zsh's heap, so they're freed along with the arguments of a
+/// builtin when it returns.
+pub struct CStrArrayMut<'a> {
+    raw: *mut *mut c_char,
+    len: usize,
+    _marker: PhantomData<&'a mut CStr>,
+}
+
+impl<'a> CStrArrayMut<'a> {
+    /// Wraps a null-terminated array of C strings.
+    ///
+    /// # Safety
+    /// `raw` must point to a null-terminated array of valid C strings, which outlive `'a` and
+    /// aren't accessed otherwise meanwhile. Strings in it may be replaced without being freed, so
+    /// they shouldn't be owned by the array (they usually live on zsh's heap).
+    pub unsafe fn from_raw(raw: *mut *mut c_char) -> Self {
+        let len = CStrArray::from_raw(raw).len();
+        Self {
+            raw,
+            len,
+            _marker: PhantomData,
+        }
+    }
+    /// The raw array, to pass to functions of [`zsh_sys`] this crate doesn't wrap.
+    pub fn as_raw(&mut self) -> *mut *mut c_char {
+        self.raw
+    }
+    /// A read-only view of the array.
+    pub fn as_array(&self) -> CStrArray<'_> {
+        CStrArray {
+            raw: self.raw,
+            len: self.len,
+            _marker: PhantomData,
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// The string at `index`, if it's in bounds.
+    pub fn get(&self, index: usize) -> Option<&CStr> {
+        self.as_array().get(index)
+    }
+    /// Replaces the string at `index` with (a metafied copy of) `value`.
+    ///
+    /// Fails if `index` is out of bounds, since the array can't grow.
+    pub fn set(&mut self, index: usize, value: impl AsRef<[u8]>) -> ZResult<()> {
+        if index >= self.len {
+            return Err(ZError::InvalidArgument {
+                name: "index".into(),
+                msg: format!("{} is out of bounds for {} elements", index, self.len),
+            });
+        }
+        unsafe { *self.raw.add(index) = metafy_heap(value.as_ref()) };
+        Ok(())
+    }
+}
+
+/// An owned null-terminated array of C strings, allocated like zsh allocates the arrays it keeps,
+/// e.g. the values of array parameters. It's freed when dropped, unless it's given to zsh with
+/// [`Self::into_raw`].
+///
+/// The strings are metafied on the way in, so they may contain any bytes.
+///
+/// # Example
+/// ```no_run
+/// use zsh_module::CStrArrayBuf;
+///
+/// let dirs: CStrArrayBuf = ["/usr/bin", "/bin"].into_iter().collect();
+/// // `setaparam` takes ownership of the array
+/// unsafe { zsh_module::zsh_sys::setaparam(c"_mymod_dirs".as_ptr() as *mut _, dirs.into_raw()) };
+/// ```
+pub struct CStrArrayBuf {
+    raw: *mut *mut c_char,
+    len: usize,
+}
+
+impl CStrArrayBuf {
+    /// A read-only view of the array.
+    pub fn as_array(&self) -> CStrArray<'_> {
+        CStrArray {
+            raw: self.raw,
+            len: self.len,
+            _marker: PhantomData,
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// Gives up ownership of the array, to hand it to zsh (e.g. to `setaparam`, or as the value
+    /// of an array parameter's getter), which frees it with `freearray`.
+    pub fn into_raw(self) -> *mut *mut c_char {
+        let raw = self.raw;
+        std::mem::forget(self);
+        unsafe { zalloc::hand_over_array(raw) }
+    }
+}
+
+impl<S: AsRef<[u8]>> FromIterator<S> for CStrArrayBuf {
+    fn from_iter<I: IntoIterator<Item = S>>(iter: I) -> Self {
+        let strings: Vec<*mut c_char> = iter
+            .into_iter()
+            .map(|s| unsafe { zalloc::ztrdup(metafy_heap(s.as_ref())) })
+            .collect();
+        let len = strings.len();
+        unsafe {
+            let raw =
+                zalloc::zalloc((len + 1) * std::mem::size_of::<*mut c_char>()) as *mut *mut c_char;
+            std::ptr::copy_nonoverlapping(strings.as_ptr(), raw, len);
+            *raw.add(len) = std::ptr::null_mut();
+            Self { raw, len }
+        }
+    }
+}
+
+impl Drop for CStrArrayBuf {
+    fn drop(&mut self) {
+        unsafe { zalloc::freearray(self.raw) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+
+    use super::*;
+
+    /// A null-terminated array of pointers to `strings`, like zsh's.
+    fn raw(strings: &[CString]) -> Vec<*mut c_char> {
+        strings
+            .iter()
+            .map(|s| s.as_ptr() as *mut _)
+            .chain([std::ptr::null_mut()])
+            .collect()
+    }
+
+    #[test]
+    fn views() {
+        let strings = [c"cmd", c"-v", c"file"].map(CString::from);
+        let mut raw = raw(&strings);
+        let array = unsafe { CStrArrayMut::from_raw(raw.as_mut_ptr()) };
+        assert_eq!(array.len(), 3);
+        assert_eq!(array.get(2), Some(c"file"));
+        assert_eq!(array.get(3), None);
+        assert_eq!(
+            array.as_array().iter().collect::<Vec<_>>(),
+            [c"cmd", c"-v", c"file"]
+        );
+    }
+
+    #[test]
+    fn iteration() {
+        let strings = [c"git", c"remote", c"add", c"origin"].map(CString::from);
+        let raw = raw(&strings);
+        let array = unsafe { CStrArray::from_raw(raw.as_ptr()) };
+        let mut iter = array.iter();
+        assert_eq!(iter.len(), 4);
+        assert_eq!(iter.next_back(), Some(c"origin"));
+        assert_eq!(iter.next(), Some(c"git"));
+        assert_eq!(iter.len(), 2);
+        assert_eq!(iter.rev().collect::<Vec<_>>(), [c"add", c"remote"]);
+
+        let rest = array.rest(2);
+        assert_eq!(rest.to_vec(), [c"add", c"origin"]);
+        assert_eq!(unsafe { CStrArray::from_raw(rest.as_raw()) }.len(), 2);
+        assert!(array.rest(5).is_empty());
+        assert_eq!(array.get_os_str(3).as_deref(), Some(OsStr::new("origin")));
+    }
+
+    #[test]
+    fn unmetafied() {
+        // Zsh metafies the bytes it uses internally, like the meta byte itself, as `\x83` followed
+        // by the byte xor 32
+        let strings = [c"plain", c"\x83\xa3.txt"].map(CString::from);
+        let raw = raw(&strings);
+        let array = unsafe { CStrArray::from_raw(raw.as_ptr()) };
+        assert!(matches!(array.get_bytes(0), Some(Cow::Borrowed(b"plain"))));
+        assert_eq!(array.get_bytes(1).as_deref(), Some(&b"\x83.txt"[..]));
+        assert_eq!(
+            array.get_os_str(1).as_deref(),
+            Some(OsStr::from_bytes(b"\x83.txt"))
+        );
+        assert_eq!(array.get_os_str(2), None);
+        assert_eq!(array.rest(5).iter().next(), None);
+    }
+}
@@ -0,0 +1,117 @@
+use std::{
+    ffi::{c_char, CStr, CString},
+    fmt,
+    ops::Deref,
+};
+
+use crate::{unmetafy, ConversionError, ZResult, META};
+
+/// Whether zsh escapes `byte` when metafying: null bytes, and the ones it uses internally as
+/// tokens (`Meta` through `Marker`).
+fn is_meta(byte: u8) -> bool {
+    byte == 0 || (META..=0xa2).contains(&byte)
+}
+
+/// An owned, metafied C string, the form zsh stores strings in.
+///
+/// Strings are metafied when created, and unmetafied by [`MetaCString::unmetafied`] and
+/// [`MetaCString::into_string`], so non-ASCII text (and even null bytes) survive the trip through
+/// zsh unchanged.
+///
+/// # Example
+/// ```
+/// use zsh_module::MetaCString;
+///
+/// let meta = MetaCString::new("ő");
+/// assert_eq!(meta.to_bytes(), b"\xc5\x83\xb1");
+/// assert_eq!(meta.into_string().unwrap(), "ő");
+/// ```
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct MetaCString(CString);
+
+impl MetaCString {
+    /// Metafies `bytes`.
+    pub fn new(bytes: impl AsRef<[u8]>) -> Self {
+        let bytes = bytes.as_ref();
+        let mut meta = Vec::with_capacity(bytes.len() + 1);
+        for &byte in bytes {
+            if is_meta(byte) {
+                meta.extend([META, byte ^ 32]);
+            } else {
+                meta.push(byte);
+            }
+        }
+        Self(CString::new(meta).unwrap())
+    }
+    /// Copies a string zsh already metafied.
+    pub fn from_metafied(meta: &CStr) -> Self {
+        Self(meta.to_owned())
+    }
+    pub fn as_c_str(&self) -> &CStr {
+        &self.0
+    }
+    pub fn as_ptr(&self) -> *const c_char {
+        self.0.as_ptr()
+    }
+    /// The original bytes.
+    pub fn unmetafied(&self) -> Vec<u8> {
+        unmetafy(self.0.to_bytes())
+    }
+    /// The original string, failing if it isn't valid UTF-8.
+    pub fn into_string(self) -> ZResult<String> {
+        String::from_utf8(self.unmetafied()).map_err(|_| {
+            ConversionError::InvalidUtf8 {
+                what: "string".into(),
+            }
+            .into()
+        })
+    }
+}
+
+impl Deref for MetaCString {
+    type Target = CStr;
+    fn deref(&self) -> &CStr {
+        &self.0
+    }
+}
+
+impl From<&str> for MetaCString {
+    fn from(value: &str) -> Self {
+        Self::new(value)
+    }
+}
+
+impl From<String> for MetaCString {
+    fn from(value: String) -> Self {
+        Self::new(value)
+    }
+}
+
+impl fmt::Debug for MetaCString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", String::from_utf8_lossy(&self.unmetafied()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        for string in ["plain", "ő", "日本語", "🦀", "a\0b", "\u{83}"] {
+            let meta = MetaCString::new(string);
+            assert!(!meta.to_bytes().contains(&0));
+            assert_eq!(meta.clone().into_string().unwrap(), string);
+            assert_eq!(MetaCString::from_metafied(&meta), meta);
+        }
+        assert_eq!(MetaCString::new("plain").to_bytes(), b"plain");
+        assert_eq!(
+            MetaCString::new(b"\xff")
+                .into_string()
+                .unwrap_err()
+                .to_string(),
+            "conversion error: string is not valid UTF-8"
+        );
+    }
+}
@@ -0,0 +1,202 @@
+use std::any::Any;
+
+use crate::{AnyError, Cmd, MaybeError, Opts, ZError};
+
+type SubcommandFn<A> = Box<dyn FnMut(&mut A, &str, &[&str], Opts) -> MaybeError>;
+
+struct Subcommand<A> {
+    name: String,
+    description: String,
+    handler: SubcommandFn<A>,
+}
+
+/// A builtin with git-style subcommands, like `mymod sync` or `mymod status`, which runs the
+/// handler of the subcommand given as its first argument.
+///
+/// It also has a `help` subcommand, listing the others with their description, and suggests the
+/// closest subcommand when given an unknown one.
+///
+/// # Example
+/// ```no_run
+/// use zsh_module::{Builtin, Dispatcher, MaybeError, ModuleBuilder, Opts};
+///
+/// fn sync(_: &mut (), _: &str, args: &[&str], _: Opts) -> MaybeError {
+///     println!("syncing {:?}", args);
+///     Ok(())
+/// }
+///
+/// let mymod = Dispatcher::new()
+///     .subcommand("sync", "Syncs the given repos", sync)
+///     .subcommand("status", "Shows what changed", |_: &mut (), _: &str, _: &[&str], _: Opts| {
+///         println!("nothing changed");
+///         Ok::<_, std::io::Error>(())
+///     });
+/// let module = ModuleBuilder::new(())
+///     .builtin(mymod.into_handler(), Builtin::new("mymod"))
+///     .build();
+/// ```
+pub struct Dispatcher<A> {
+    subcommands: Vec<Subcommand<A>>,
+}
+
+impl<A: Any> Default for Dispatcher<A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A: Any> Dispatcher<A> {
+    pub fn new() -> Self {
+        Self {
+            subcommands: Vec::new(),
+        }
+    }
+    /// Adds the subcommand `name`, shown by `help` with `description`. Its handler is given the
+    /// subcommand's name and the arguments after it.
+    pub fn subcommand<E>(mut self, name: &str, description: &str, mut cb: impl Cmd<A, E>) -> Self
+    where
+        E: Into<AnyError>,
+    {
+        self.subcommands.push(Subcommand {
+            name: name.to_string(),
+            description: description.to_string(),
+            handler: Box::new(move |data: &mut A, name: &str, args: &[&str], opts: Opts| {
+                cb(data, name, args, opts).map_err(E::into)
+            }),
+        });
+        self
+    }
+    /// Turns the dispatcher into the handler of a builtin, for [`ModuleBuilder::builtin`].
+    ///
+    /// [`ModuleBuilder::builtin`]: crate::ModuleBuilder::builtin
+    pub fn into_handler(mut self) -> impl Cmd<A, AnyError> {
+        move |data: &mut A, name: &str, args: &[&str], opts: Opts| -> MaybeError {
+            let Some((&subcommand, args)) = args.split_first() else {
+                return Err(
+                    ZError::Usage(format!("missing subcommand\n{}", self.help(name))).into(),
+                );
+            };
+            if let Some(sub) = self
+                .subcommands
+                .iter_mut()
+                .find(|sub| sub.name == subcommand)
+            {
+                return (sub.handler)(data, subcommand, args, opts);
+            }
+            if subcommand == "help" {
+                crate::zprint!("{}", self.help(name));
+                return Ok(());
+            }
+            let mut msg = format!("unknown subcommand: {}", subcommand);
+            if let Some(suggestion) = self.suggestion(subcommand) {
+                msg += &format!(" (did you mean {}?)", suggestion);
+            }
+            Err(ZError::Usage(format!("{}\nsee `{} help`", msg, name)).into())
+        }
+    }
+    /// The output of the `help` subcommand.
+    fn help(&self, builtin: &str) -> String {
+        let width = self.subcommands.iter().map(|sub| sub.name.len()).max();
+        let width = width.unwrap_or(0).max("help".len());
+        let mut help = format!(
+            "usage: {} <subcommand> [arg ...]\n\nSubcommands:\n",
+            builtin
+        );
+        let help_line = ("help", "Lists the subcommands");
+        for (name, description) in self
+            .subcommands
+            .iter()
+            .map(|sub| (sub.name.as_str(), sub.description.as_str()))
+            .chain([help_line])
+        {
+            help += &format!("  {:<2$}  {}\n", name, description, width);
+        }
+        help
+    }
+    /// The subcommand closest to `typo`, if one is close enough to be what was meant.
+    fn suggestion(&self, typo: &str) -> Option<&str> {
+        self.subcommands
+            .iter()
+            .map(|sub| sub.name.as_str())
+            .chain(["help"])
+            .map(|name| (distance(name, typo), name))
+            .filter(|&(distance, name)| distance <= (name.chars().count() / 3).max(1))
+            .min()
+            .map(|(_, name)| name)
+    }
+}
+
+/// The Levenshtein distance between `a` and `b`.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockOpts;
+
+    #[test]
+    fn subcommands() {
+        let dispatcher = Dispatcher::new()
+            .subcommand(
+                "status",
+                "Shows what changed",
+                |data: &mut Vec<String>, name: &str, args: &[&str], _: Opts| -> MaybeError {
+                    data.push(name.to_string());
+                    data.extend(args.iter().map(|arg| arg.to_string()));
+                    Ok(())
+                },
+            )
+            .subcommand(
+                "sync",
+                "Syncs",
+                |_: &mut Vec<String>, _: &str, _: &[&str], _: Opts| Err::<(), _>("offline"),
+            );
+        assert_eq!(
+            dispatcher.help("mymod"),
+            "usage: mymod <subcommand> [arg ...]\n\nSubcommands:\n  \
+             status  Shows what changed\n  \
+             sync    Syncs\n  \
+             help    Lists the subcommands\n"
+        );
+        let mut handler = dispatcher.into_handler();
+        let mut data = Vec::new();
+        let mut run = |args: &[&str]| {
+            handler(&mut data, "mymod", args, MockOpts::new().build()).map_err(|e| e.to_string())
+        };
+        assert_eq!(run(&["status", "-s"]), Ok(()));
+        assert_eq!(run(&["sync"]), Err("offline".into()));
+        assert_eq!(
+            run(&["stauts"]),
+            Err("unknown subcommand: stauts (did you mean status?)\nsee `mymod help`".into())
+        );
+        assert_eq!(
+            run(&["frobnicate"]),
+            Err("unknown subcommand: frobnicate\nsee `mymod help`".into())
+        );
+        assert!(run(&[])
+            .unwrap_err()
+            .starts_with("missing subcommand\nusage: mymod"));
+        assert_eq!(data, ["status", "-s"]);
+    }
+
+    #[test]
+    fn distances() {
+        assert_eq!(distance("status", "status"), 0);
+        assert_eq!(distance("status", "stauts"), 2);
+        assert_eq!(distance("sync", "syn"), 1);
+        assert_eq!(distance("", "abc"), 3);
+    }
+}
@@ -0,0 +1,282 @@
+use std::{fmt, io};
+
+/// Errors produced while interacting with zsh's internals.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ZError {
+    /// Zsh flagged an error (set its `errflag`) while running the operation. Zsh already printed
+    /// why, so a builtin failing with it doesn't print anything more.
+    Zsh,
+    /// A value couldn't be converted between its zsh and Rust representations.
+    Conversion(ConversionError),
+    /// The operation can't be performed in the shell's current state.
+    Unavailable(&'static str),
+    /// A builtin was called with options or arguments it doesn't accept.
+    Usage(String),
+    /// The argument `name` of a builtin was invalid. See [`crate::validate`].
+    InvalidArgument { name: String, msg: String },
+    /// A system call failed. See [`Self::errno`].
+    Io(io::Error),
+    /// Any other error, e.g. made from a message with [`From`].
+    Other(String),
+    /// Makes a builtin return this status without printing anything, e.g. for a `grep`-like
+    /// builtin to return 1 when nothing matched, or a `test`-like one to return 2.
+    Status(i32),
+    /// `error` made the builtin `builtin` fail. The errors of builtins are given their name
+    /// before the module's wrappers (see [`ModuleBuilder::wrap_builtins`]) get them.
+    ///
+    /// [`ModuleBuilder::wrap_builtins`]: crate::ModuleBuilder::wrap_builtins
+    InBuiltin { builtin: String, error: Box<ZError> },
+    /// `error`, with where it was made. Errors made with [`From`] get one, with the `backtrace`
+    /// feature.
+    #[cfg(feature = "backtrace")]
+    Backtraced {
+        error: Box<ZError>,
+        backtrace: std::backtrace::Backtrace,
+    },
+}
+
+/// Why a value couldn't be converted. See [`ZError::Conversion`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConversionError {
+    /// `what` isn't valid UTF-8.
+    InvalidUtf8 { what: String },
+    /// `what` doesn't fit in the type it's converted to.
+    OutOfRange { what: String },
+    /// The parameter `name` isn't of the type `expected`, like `scalar`.
+    WrongType {
+        name: String,
+        expected: &'static str,
+    },
+    /// `input` doesn't match the format it's parsed with.
+    NoMatch { input: String },
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidUtf8 { what } => write!(f, "{} is not valid UTF-8", what),
+            Self::OutOfRange { what } => write!(f, "{} is out of range", what),
+            Self::WrongType { name, expected } => write!(f, "{} is not a {}", name, expected),
+            Self::NoMatch { input } => write!(f, "format not matched: {}", input),
+        }
+    }
+}
+
+impl ZError {
+    /// The status a builtin failing with this error returns: the one of [`Self::Status`], 2 for
+    /// usage errors, like most commands, and 1 otherwise.
+    pub fn exit_code(&self) -> i32 {
+        match self.inner() {
+            Self::Status(status) => *status,
+            Self::Usage(_) | Self::InvalidArgument { .. } => 2,
+            _ => 1,
+        }
+    }
+    /// The `errno` of the system call that failed, if that's the error.
+    pub fn errno(&self) -> Option<i32> {
+        match self.inner() {
+            Self::Io(e) => e.raw_os_error(),
+            _ => None,
+        }
+    }
+    /// The error, without the builtin name and backtrace it was given. See
+    /// [`Self::InBuiltin`].
+    ///
+    /// # Example
+    /// ```
+    /// use zsh_module::ZError;
+    ///
+    /// let error = ZError::Usage("usage: greet name".into()).in_builtin("greet");
+    /// assert!(matches!(error.inner(), ZError::Usage(_)));
+    /// assert_eq!(error.to_string(), "greet: usage: greet name");
+    /// ```
+    pub fn inner(&self) -> &ZError {
+        match self {
+            Self::InBuiltin { error, .. } => error.inner(),
+            #[cfg(feature = "backtrace")]
+            Self::Backtraced { error, .. } => error.inner(),
+            error => error,
+        }
+    }
+    /// Says that the error made the builtin `builtin` fail, unless it already says which.
+    pub fn in_builtin(self, builtin: &str) -> Self {
+        if self.builtin().is_some() {
+            return self;
+        }
+        Self::InBuiltin {
+            builtin: builtin.to_string(),
+            error: Box::new(self),
+        }
+    }
+    /// The builtin the error made fail, if known.
+    pub fn builtin(&self) -> Option<&str> {
+        match self {
+            Self::InBuiltin { builtin, .. } => Some(builtin),
+            #[cfg(feature = "backtrace")]
+            Self::Backtraced { error, .. } => error.builtin(),
+            _ => None,
+        }
+    }
+    /// Where the error was made, if it was made with [`From`] and the `backtrace` feature is
+    /// enabled. Like [`std::backtrace::Backtrace::capture`], it's empty unless `RUST_BACKTRACE`
+    /// or `RUST_LIB_BACKTRACE` is set.
+    #[cfg(feature = "backtrace")]
+    pub fn backtrace(&self) -> Option<&std::backtrace::Backtrace> {
+        match self {
+            Self::InBuiltin { error, .. } => error.backtrace(),
+            Self::Backtraced { backtrace, .. } => Some(backtrace),
+            _ => None,
+        }
+    }
+    /// Gives `error` a backtrace, with the `backtrace` feature.
+    fn traced(error: Self) -> Self {
+        #[cfg(feature = "backtrace")]
+        return Self::Backtraced {
+            error: Box::new(error),
+            backtrace: std::backtrace::Backtrace::capture(),
+        };
+        #[cfg(not(feature = "backtrace"))]
+        error
+    }
+}
+
+impl fmt::Display for ZError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Zsh => write!(f, "zsh reported an error"),
+            Self::Conversion(e) => write!(f, "conversion error: {}", e),
+            Self::Unavailable(msg) => write!(f, "{}", msg),
+            Self::Usage(msg) => write!(f, "{}", msg),
+            Self::InvalidArgument { name, msg } => write!(f, "{}: {}", name, msg),
+            Self::Io(e) => write!(f, "{}", e),
+            Self::Other(msg) => write!(f, "{}", msg),
+            Self::Status(status) => write!(f, "exit status {}", status),
+            Self::InBuiltin { builtin, error } => write!(f, "{}: {}", builtin, error),
+            #[cfg(feature = "backtrace")]
+            Self::Backtraced { error, .. } => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for ZError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::InBuiltin { error, .. } => Some(error),
+            #[cfg(feature = "backtrace")]
+            Self::Backtraced { error, .. } => error.source(),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ZError {
+    fn from(e: io::Error) -> Self {
+        Self::traced(Self::Io(e))
+    }
+}
+
+impl From<ConversionError> for ZError {
+    fn from(e: ConversionError) -> Self {
+        Self::traced(Self::Conversion(e))
+    }
+}
+
+/// What builtins print when they're called wrong, like zsh's own do. Only the first line counts,
+/// so a message can go on with the usage.
+const USAGE_PREFIXES: &[&str] = &[
+    "usage:",
+    "bad option:",
+    "not enough arguments",
+    "too many arguments",
+    "missing argument",
+];
+
+/// Messages are [`ZError::Usage`] when their first line reads like zsh's usage errors (`usage:`,
+/// `bad option:`, `not enough arguments`, `too many arguments` or `missing argument`), and
+/// [`ZError::Other`] otherwise. Other usage errors are made with [`ZError::Usage`] directly.
+impl From<String> for ZError {
+    fn from(msg: String) -> Self {
+        let first = msg.lines().next().unwrap_or_default();
+        Self::traced(if USAGE_PREFIXES.iter().any(|p| first.starts_with(p)) {
+            Self::Usage(msg)
+        } else {
+            Self::Other(msg)
+        })
+    }
+}
+
+impl From<&str> for ZError {
+    fn from(msg: &str) -> Self {
+        msg.to_string().into()
+    }
+}
+
+impl From<std::str::Utf8Error> for ZError {
+    fn from(_: std::str::Utf8Error) -> Self {
+        ConversionError::InvalidUtf8 {
+            what: "string".into(),
+        }
+        .into()
+    }
+}
+
+impl From<std::string::FromUtf8Error> for ZError {
+    fn from(_: std::string::FromUtf8Error) -> Self {
+        ConversionError::InvalidUtf8 {
+            what: "string".into(),
+        }
+        .into()
+    }
+}
+
+/// A [`Result`] whose error is a [`ZError`].
+pub type ZResult<T> = Result<T, ZError>;
+
+/// A [`MaybeError`][crate::MaybeError] whose error is a [`ZError`].
+pub type MaybeZError = ZResult<()>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_and_exit_codes() {
+        let usage = ZError::from("usage: greet name");
+        assert!(matches!(usage.inner(), ZError::Usage(_)));
+        assert_eq!(usage.exit_code(), 2);
+        let usage = ZError::from("not enough arguments\nusage: greet name");
+        assert_eq!(usage.exit_code(), 2);
+        // Only the start of the first line counts
+        let other = ZError::from("greet: see usage: below");
+        assert!(matches!(other.inner(), ZError::Other(_)));
+        let other = ZError::from(format!("{} not found", "foo"));
+        assert_eq!(other.to_string(), "foo not found");
+        assert_eq!(other.exit_code(), 1);
+        let io = ZError::from(io::Error::from_raw_os_error(libc::ENOENT));
+        assert_eq!(io.errno(), Some(libc::ENOENT));
+        assert_eq!(other.errno(), None);
+        assert_eq!(ZError::Status(3).exit_code(), 3);
+    }
+
+    #[test]
+    fn builtin_names() {
+        let error = ZError::from(io::Error::from_raw_os_error(libc::ENOENT)).in_builtin("cat");
+        assert_eq!(error.builtin(), Some("cat"));
+        assert_eq!(error.errno(), Some(libc::ENOENT));
+        assert!(error.to_string().starts_with("cat: "));
+        // The innermost builtin is the one that failed
+        assert_eq!(error.in_builtin("outer").builtin(), Some("cat"));
+        let conversion = ZError::from(ConversionError::WrongType {
+            name: "path".into(),
+            expected: "scalar",
+        });
+        assert_eq!(
+            conversion.to_string(),
+            "conversion error: path is not a scalar"
+        );
+        assert_eq!(conversion.builtin(), None);
+    }
+}
@@ -1,27 +1,78 @@
 use std::{
-    ffi::{c_char, c_int, CStr},
-    sync::atomic::AtomicBool,
+    borrow::Cow,
+    cell::RefCell,
+    collections::VecDeque,
+    ffi::{c_char, c_int, CStr, CString},
+    panic::AssertUnwindSafe,
+    rc::Rc,
 };
 
-use crate::{log, options::Opts, to_cstr, AnyError, MaybeError, Module};
+use crate::{
+    extensions::State, log, math::Number, metafy_heap, options::Opts, paramdef::ParamFns, AnyError,
+    Args, CStrArray, Feature, FunctionWrapper, MaybeError, MetaCString, Module, Reentry, ZError,
+};
 
 use parking_lot::Mutex;
 use zsh_sys as zsys;
 
 struct ModuleHolder {
     module: Mutex<Option<Module>>,
-    panicked: AtomicBool,
+    /// Calls to wait until the running builtin returns. See [`Reentry::Queue`].
+    queue: Mutex<VecDeque<QueuedCall>>,
 }
 
 impl ModuleHolder {
     const fn empty() -> Self {
         Self {
             module: parking_lot::const_mutex(None),
-            panicked: AtomicBool::new(false),
+            queue: parking_lot::const_mutex(VecDeque::new()),
         }
     }
 }
 
+/// A copy of a builtin call, since zsh frees its arguments once the builtin returns.
+struct QueuedCall {
+    name: CString,
+    args: Vec<CString>,
+    opts: zsys::options,
+    opt_args: Vec<CString>,
+}
+
+impl QueuedCall {
+    unsafe fn new(name: &CStr, args: CStrArray, opts: *mut zsys::options) -> Self {
+        let opts = *opts;
+        let opt_args = (0..opts.argscount as usize)
+            .map(|i| CStr::from_ptr(*opts.args.add(i)).to_owned())
+            .collect();
+        Self {
+            name: name.to_owned(),
+            args: args.iter().map(CStr::to_owned).collect(),
+            opts,
+            opt_args,
+        }
+    }
+    fn run(mut self) -> i32 {
+        let mut opt_args: Vec<*mut c_char> = self
+            .opt_args
+            .iter()
+            .map(|arg| arg.as_ptr() as *mut _)
+            .collect();
+        self.opts.args = opt_args.as_mut_ptr();
+        let mut args: Vec<*mut c_char> = self
+            .args
+            .iter()
+            .map(|arg| arg.as_ptr() as *mut _)
+            .chain([std::ptr::null_mut()])
+            .collect();
+        builtin_callback(
+            self.name.as_ptr() as *mut _,
+            args.as_mut_ptr(),
+            &mut self.opts,
+            Reentry::Refuse as i32,
+        )
+    }
+}
+
 // This struct is neither of them, but since it isn't exposed to user code
 // and it isn't given to any threads, this should be safe.
 unsafe impl Sync for ModuleHolder {}
@@ -29,74 +80,342 @@ unsafe impl Send for ModuleHolder {}
 
 static MODULE: ModuleHolder = ModuleHolder::empty();
 
-unsafe fn strings_from_ptr<'a>(mut ptr: *const *const c_char) -> Vec<&'a str> {
-    let mut vec = Vec::with_capacity(2);
-    loop {
-        if (*ptr).is_null() {
-            break vec;
-        }
-        vec.push(CStr::from_ptr(*ptr).to_str().expect("Failed to parse arg"));
-        ptr = ptr.add(1);
-    }
-}
-
 extern "C" fn builtin_callback(
     name: *mut c_char,
     args: *mut *mut c_char,
     opts: *mut zsys::options,
-    _: i32,
+    reentry: i32,
 ) -> i32 {
     handle_panic(|| {
-        let args = unsafe { strings_from_ptr(std::mem::transmute(args)) };
+        let args = unsafe { CStrArray::from_raw(args) };
         let name = unsafe { CStr::from_ptr(name) };
-        let opts = unsafe { Opts::from_raw(opts) };
 
-        let mut module = get_mod();
+        // Another builtin of this module is running, and called this one (e.g. through `eval`)
+        let Some(module) = MODULE.module.try_lock() else {
+            if reentry == Reentry::Queue as i32 {
+                let call = unsafe { QueuedCall::new(name, args, opts) };
+                MODULE.queue.lock().push_back(call);
+                return 0;
+            }
+            log::error_named(
+                name,
+                "can't run while another builtin of its module is running",
+            );
+            return 1;
+        };
+        let mut module =
+            parking_lot::MutexGuard::map(module, |opt| opt.as_mut().expect("No module set"));
         let Module {
             bintable,
-            user_data,
+            state,
+            decodings,
             ..
         } = &mut *module;
-        let bin = bintable.get_mut(name).expect("Failed to find binary name");
-        match bin(
-            &mut **user_data,
-            name.to_str().expect("Failed to parse binary name"),
-            &args,
-            opts,
-        ) {
+        // Closures sent from other threads run first, so the builtin sees what they did
+        #[cfg(feature = "watch")]
+        crate::main_thread::run_queued(state);
+        let decoding = decodings.get(name).copied().unwrap_or_default();
+        let raw_args = Args::new(args, decoding);
+        // Zsh frees the arguments once the builtin returns, and the state forgets them before
+        state.raw_args = Some(unsafe { std::mem::transmute::<Args<'_>, Args<'static>>(raw_args) });
+        let result = raw_args
+            .decode_all()
+            .map_err(AnyError::from)
+            .and_then(|args| {
+                let args: Vec<&str> = args.iter().map(AsRef::as_ref).collect();
+                let bin = bintable.get_mut(name).expect("Failed to find binary name");
+                bin(
+                    state,
+                    name.to_str().expect("Failed to parse binary name"),
+                    &args,
+                    unsafe { Opts::from_raw(opts) },
+                )
+            });
+        state.raw_args = None;
+        let status = match result {
             Ok(()) => 0,
-            Err(e) => {
-                let msg = to_cstr(e.to_string());
-                log::error_named(name, msg);
-                1
-            }
+            Err(e) => match e.downcast_ref::<ZError>() {
+                // Zsh already printed why, or there's nothing to print
+                Some(error) if matches!(error.inner(), ZError::Zsh | ZError::Status(_)) => {
+                    error.exit_code()
+                }
+                Some(error) => {
+                    let builtin = error.builtin().map_or(Cow::Borrowed(name), |builtin| {
+                        Cow::Owned(crate::to_cstr(builtin))
+                    });
+                    log::error_named(&*builtin, format_args!("{}", error.inner()));
+                    #[cfg(feature = "backtrace")]
+                    if let Some(backtrace) = error.backtrace() {
+                        if backtrace.status() == std::backtrace::BacktraceStatus::Captured {
+                            use std::io::Write;
+                            let _ = write!(crate::zsh::io::stderr(), "{}", backtrace);
+                        }
+                    }
+                    error.exit_code()
+                }
+                None => {
+                    log::error_named(name, format_args!("{}", e));
+                    1
+                }
+            },
+        };
+        let changes = std::mem::take(&mut module.state.feature_changes);
+        if !changes.is_empty() {
+            change_features(&mut module, name, changes);
         }
+        #[cfg(feature = "watch")]
+        let watch_script = {
+            let changes = std::mem::take(&mut module.state.watch_changes);
+            crate::watch::script(module.name.unwrap(), &changes)
+        };
+        drop(module);
+        // Zle can only be told once the lock is released, like for hooks
+        #[cfg(feature = "watch")]
+        if !watch_script.is_empty() {
+            let _ = crate::zsh::eval_simple(&watch_script);
+        }
+        loop {
+            let Some(call) = MODULE.queue.lock().pop_front() else {
+                break;
+            };
+            call.run();
+        }
+        status
     })
     .unwrap_or(65)
 }
 
+/// Enables or disables features of the module, as asked by the builtin `builtin`.
+fn change_features(module: &mut Module, builtin: &CStr, changes: Vec<(String, bool)>) {
+    let names = module.features.names();
+    unsafe {
+        let enables = zsys::getfeatureenables(module.raw, &mut *module.features);
+        for (feature, enabled) in changes {
+            match names.iter().position(|name| *name == feature) {
+                Some(i) => *enables.add(i) = enabled as c_int,
+                None => log::error_named(builtin, format_args!("no such feature: {}", feature)),
+            }
+        }
+        zsys::setfeatureenables(module.raw, &mut *module.features, enables);
+    }
+    list_features(module);
+}
+
+/// Updates the features builtins see, once they might have changed.
+fn list_features(module: &mut Module) {
+    let names = module.features.names();
+    let enables = unsafe { zsys::getfeatureenables(module.raw, &mut *module.features) };
+    module.state.features = names
+        .iter()
+        .enumerate()
+        .filter_map(|(i, name)| Feature::parse(name, unsafe { *enables.add(i) } != 0))
+        .collect();
+}
+
+extern "C" fn math_callback(
+    name: *mut c_char,
+    argc: c_int,
+    argv: *mut zsys::mnumber,
+    _id: c_int,
+) -> zsys::mnumber {
+    handle_panic(|| {
+        let name = unsafe { CStr::from_ptr(name) };
+        let args: Vec<Number> = (0..argc as usize)
+            .map(|i| Number::from_raw(unsafe { &*argv.add(i) }))
+            .collect();
+        let Some(module) = MODULE.module.try_lock() else {
+            log::error_named(
+                name,
+                "can't run while another builtin of its module is running",
+            );
+            return Number::Integer(0);
+        };
+        let mut module =
+            parking_lot::MutexGuard::map(module, |opt| opt.as_mut().expect("No module set"));
+        let Module {
+            mathtable, state, ..
+        } = &mut *module;
+        let func = mathtable
+            .get_mut(name)
+            .expect("Failed to find math function name");
+        // Reporting the error also makes zsh abort the arithmetic expression
+        func(state, &args).unwrap_or_else(|e| {
+            log::error_named(name, format_args!("{}", e));
+            Number::Integer(0)
+        })
+    })
+    .unwrap_or(Number::Integer(0))
+    .into_raw()
+}
+
+extern "C" fn cond_callback(args: *mut *mut c_char, id: c_int) -> c_int {
+    handle_panic(|| {
+        // The arguments are only expanded when the condition asks for them
+        let len = unsafe { CStrArray::from_raw(args) }.len();
+        let args: Vec<MetaCString> = (0..len)
+            .map(|i| unsafe {
+                MetaCString::from_metafied(CStr::from_ptr(zsys::cond_str(args, i as c_int, 0)))
+            })
+            .collect();
+        let Some(module) = MODULE.module.try_lock() else {
+            log::error("a condition can't run while another builtin of its module is running");
+            return 0;
+        };
+        let mut module =
+            parking_lot::MutexGuard::map(module, |opt| opt.as_mut().expect("No module set"));
+        let Module {
+            condtable, state, ..
+        } = &mut *module;
+        let (name, cb) = &mut condtable[id as usize];
+        let args: Vec<String> = match args.into_iter().map(MetaCString::into_string).collect() {
+            Ok(args) => args,
+            Err(e) => {
+                log::error_named(&**name, format_args!("{}", e));
+                return 0;
+            }
+        };
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        cb(state, &args) as c_int
+    })
+    .unwrap_or(0)
+}
+
+/// The get/set/unset functions of every special parameter of the module, which find its handlers
+/// by its name.
+static PARAM_GSU: zsys::gsu_scalar = zsys::gsu_scalar {
+    getfn: Some(param_get),
+    setfn: Some(param_set),
+    unsetfn: Some(param_unset),
+};
+
+/// Runs `f` with the handlers of the special parameter `pm`, unless the module is busy.
+fn with_param<R>(
+    pm: zsys::Param,
+    f: impl FnOnce(&CStr, &mut ParamFns, &mut State) -> R,
+) -> Option<R> {
+    let name = unsafe { CStr::from_ptr((*pm).node.nam) };
+    let Some(module) = MODULE.module.try_lock() else {
+        log::error_named(
+            name,
+            "can't be used while a builtin of its module is running",
+        );
+        return None;
+    };
+    let mut module =
+        parking_lot::MutexGuard::map(module, |opt| opt.as_mut().expect("No module set"));
+    let Module {
+        paramtable, state, ..
+    } = &mut *module;
+    let fns = paramtable
+        .get_mut(name)
+        .expect("Failed to find parameter name");
+    Some(f(name, fns, state))
+}
+
+extern "C" fn param_get(pm: zsys::Param) -> *mut c_char {
+    let value = handle_panic(|| with_param(pm, |_, fns, state| (fns.get)(state)))
+        .flatten()
+        .unwrap_or_default();
+    // Zsh expects the value on its heap
+    metafy_heap(value.as_bytes())
+}
+
+extern "C" fn param_set(pm: zsys::Param, value: *mut c_char) {
+    if value.is_null() {
+        return;
+    }
+    // Zsh hands the value over
+    let decoded = MetaCString::from_metafied(unsafe { CStr::from_ptr(value) })
+        .into_string()
+        .map_err(|e| e.to_string());
+    unsafe { zsys::zsfree(value) };
+    handle_panic(|| {
+        with_param(pm, |name, fns, state| {
+            let result = decoded
+                .map_err(AnyError::from)
+                .and_then(|value| match &mut fns.set {
+                    Some(set) => set(state, &value),
+                    None => Err("read-only variable".into()),
+                });
+            if let Err(e) = result {
+                log::error_named(name, format_args!("{}", e));
+            }
+        })
+    });
+}
+
+extern "C" fn param_unset(pm: zsys::Param, _exp: c_int) {
+    handle_panic(|| {
+        with_param(pm, |_, fns, state| {
+            if let Some(unset) = &mut fns.unset {
+                unset(state)
+            }
+        })
+    });
+}
+
 pub fn set_mod(mut module: Module, name: &'static str) {
+    module.add_help_builtin(name);
+    module.add_hook_builtins(name);
     for x in module.features.get_binaries() {
         x.handlerfunc = Some(builtin_callback)
     }
+    for x in module.features.get_mathfuncs() {
+        x.nfunc = Some(math_callback)
+    }
+    for x in module.features.get_conddefs() {
+        x.handler = Some(cond_callback)
+    }
+    for x in module.features.get_paramdefs() {
+        x.gsu = &PARAM_GSU as *const zsys::gsu_scalar as *const _
+    }
     module.name = Some(name);
     *MODULE.module.lock() = Some(module);
 }
 
+/// Drops the module, freeing its features and running the destructor of its user data.
 fn drop_mod() {
-    if !panicked() {
-        MODULE.module.lock().take();
+    let module = MODULE.module.lock().take();
+    // After a panic, the user data may be in a state its destructor doesn't expect
+    if std::panic::catch_unwind(AssertUnwindSafe(|| drop(module))).is_err() {
+        log::error("the module panicked while being unloaded");
     }
 }
 
-fn get_mod() -> parking_lot::MappedMutexGuard<'static, Module> {
+pub(crate) fn get_mod() -> parking_lot::MappedMutexGuard<'static, Module> {
     parking_lot::MutexGuard::map(MODULE.module.lock(), |opt| {
         opt.as_mut().expect("No module set")
     })
 }
 
-fn panicked() -> bool {
-    MODULE.panicked.load(std::sync::atomic::Ordering::Acquire)
+/// The `major.minor` part of a zsh version string.
+fn release(version: &[u8]) -> &[u8] {
+    let mut dots = version.iter().enumerate().filter(|(_, c)| **c == b'.');
+    let end = dots.nth(1).map(|(i, _)| i).unwrap_or(version.len());
+    &version[..end]
+}
+
+/// Checks that the running zsh is the same release as the one the module was built against, since
+/// the layout of its structs may differ between releases.
+pub fn check_version(module_name: &str) -> bool {
+    let built_for = CStr::from_bytes_with_nul(zsys::ZSH_VERSION)
+        .unwrap()
+        .to_bytes();
+    let running = unsafe { zsys::getsparam(c"ZSH_VERSION".as_ptr() as *mut _) };
+    if running.is_null() {
+        return true;
+    }
+    let running = unsafe { CStr::from_ptr(running) }.to_bytes();
+    if release(running) == release(built_for) {
+        return true;
+    }
+    crate::error!(
+        "{}: built for zsh {}, but this is zsh {}",
+        module_name,
+        String::from_utf8_lossy(built_for),
+        String::from_utf8_lossy(running)
+    );
+    false
 }
 
 pub fn handle_maybe_error<E>(error: MaybeError<E>) -> i32
@@ -122,9 +441,6 @@ where
         Ok(ret) => Some(ret),
         Err(err) => {
             let name = get_mod().name.unwrap();
-            MODULE
-                .panicked
-                .store(true, std::sync::atomic::Ordering::Release);
             if let Some(msg) = err.downcast_ref::<&str>() {
                 crate::error!("{:?} Panic: {}", name, msg);
             } else if let Some(msg) = err.downcast_ref::<String>() {
@@ -146,14 +462,21 @@ pub mod ffi {
 #[macro_export]
 /// Exports a `setup` function to be called when the module needs to be set up.
 /// You need to specify your module's loadable name
+///
+/// Only the entry points zsh looks for (`setup_`, `boot_`, `features_`, `enables_`, `cleanup_`
+/// and `finish_`) are exported from the library, and the macro adds nothing else to your crate's
+/// namespace, so several modules written in Rust can be loaded into the same shell. Rust already
+/// hides every symbol of a `cdylib` but its `#[no_mangle]` items, and these entry points are the
+/// only ones the macro defines, so no version script is needed.
 macro_rules! export_module {
     ($module_name:ident, $setupfn:ident) => {
-        #[doc(hidden)]
-        static MOD_NAME: &'static str = stringify!($module_name);
-
         #[no_mangle]
         #[doc(hidden)]
         extern "C" fn setup_(_: $crate::export_module::ffi::Module) -> i32 {
+            const MOD_NAME: &str = stringify!($module_name);
+            if !$crate::export_module::check_version(MOD_NAME) {
+                return 1;
+            }
             $crate::export_module::handle_panic(|| {
                 let res = $setupfn().map(|module|
                     $crate::export_module::set_mod(module, MOD_NAME)
@@ -163,6 +486,7 @@ macro_rules! export_module {
             .unwrap_or(65)
         }
 
+        #[doc(hidden)]
         mod _zsh_private_glue {
             use ::std::ffi::{ c_char, c_int };
             $crate::export_module!(@fn boot_(module: $crate::export_module::ffi::Module));
@@ -174,7 +498,6 @@ macro_rules! export_module {
     };
     (@fn $name:ident ($($arg:ident : $type:ty),*)) => {
         #[no_mangle]
-        #[doc(hidden)]
         extern "C" fn $name($($arg: $type),*) -> i32 {
             $crate::export_module::$name($($arg),*)
         }
@@ -198,10 +521,103 @@ macro_rules! mod_fn {
     };
 }
 
+thread_local! {
+    /// The module's function wrappers. They're kept apart from the module, since the functions
+    /// they run may call its builtins.
+    static FUNCTION_WRAPPERS: RefCell<Vec<Rc<dyn FunctionWrapper>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Hands the module's function wrappers to zsh, if any.
+fn add_function_wrappers(raw: zsys::Module) {
+    let mut module = get_mod();
+    if module.function_wrappers.is_empty() {
+        return;
+    }
+    FUNCTION_WRAPPERS.set(module.function_wrappers.clone());
+    let funcwrap = module.funcwrap.insert(Box::new(zsys::funcwrap {
+        next: std::ptr::null_mut(),
+        flags: 0,
+        handler: Some(function_wrapper_callback),
+        module: std::ptr::null_mut(),
+    }));
+    unsafe { zsys::addwrapper(raw, &mut **funcwrap) };
+}
+
+/// Runs a shell function inside of the module's wrappers, the first one being the outermost.
+extern "C" fn function_wrapper_callback(
+    prog: zsys::Eprog,
+    wrap: zsys::FuncWrap,
+    name: *mut c_char,
+) -> c_int {
+    let wrappers = FUNCTION_WRAPPERS.with_borrow(Clone::clone);
+    let fname =
+        String::from_utf8_lossy(&crate::unmetafy(unsafe { CStr::from_ptr(name) }.to_bytes()))
+            .into_owned();
+    let mut run: Box<dyn FnMut() -> i32> = Box::new(|| unsafe {
+        // Runs the next wrapper, if any, or the function
+        zsys::runshfunc(prog, wrap, name);
+        zsys::lastval as i32
+    });
+    for wrapper in wrappers.iter().rev() {
+        let (mut inner, fname) = (run, fname.as_str());
+        run = Box::new(move || {
+            let mut status = None;
+            wrapper(fname, &mut || *status.insert(inner()));
+            status.unwrap_or(unsafe { zsys::lastval as i32 })
+        });
+    }
+    // Not `handle_panic`, which locks the module, since a builtin of the module may be running
+    if std::panic::catch_unwind(AssertUnwindSafe(run)).is_err() {
+        crate::error!("a function wrapper panicked");
+    }
+    // Zsh runs the next wrapper when this one returns non-zero, so this returns 0 even if the
+    // function wasn't run
+    0
+}
+
 mod_fn!(
     fn boot_(_mod) try {
-        // zsys::addwrapper()
-        Ok::<_, std::convert::Infallible>(())
+        crate::zsh::set_shell_thread();
+        // Zsh doesn't clean up after a module which failed to boot, so nothing is installed
+        // until the callbacks succeed
+        let booted = {
+            // Like builtins, the callbacks hold the lock, so builtins they run refuse to
+            let mut module = get_mod();
+            let Module { on_boot, state, .. } = &mut *module;
+            on_boot.iter_mut().try_for_each(|boot| boot(state))
+        };
+        if booted.is_err() {
+            crate::zsh::timers::clear();
+        }
+        booted.and_then(|()| {
+            let script = {
+                #[cfg_attr(not(feature = "watch"), allow(unused_mut))]
+                let mut module = get_mod();
+                let name = module.name.unwrap();
+                if let Some(exports) = &module.exports {
+                    exports.publish(name);
+                }
+                #[allow(unused_mut)]
+                let mut script = module.hooks_install_script(name);
+                #[cfg(feature = "watch")]
+                {
+                    let changes = std::mem::take(&mut module.state.watch_changes);
+                    script += &crate::watch::script(name, &changes);
+                }
+                script
+            };
+            // The lock can't be held here, in case the script ends up calling a builtin of the
+            // module
+            if !script.is_empty() {
+                if let Err(e) = crate::zsh::eval_simple(&script) {
+                    let _ = crate::zsh::eval_simple(&teardown(_mod));
+                    return Err(AnyError::from(e));
+                }
+            }
+            // Last, since it can't fail
+            add_function_wrappers(_mod);
+            Ok(())
+        })
     }
 );
 
@@ -217,14 +633,66 @@ mod_fn!(
     fn enables_(mod_, enables_ptr: *mut *mut c_int) {
         let mut module = get_mod();
         unsafe {
-            zsys::handlefeatures(mod_, &mut *module.features, enables_ptr)
+            // The first features to be set are those `zmodload` enables on load
+            if !enables_ptr.is_null() && !(*enables_ptr).is_null() && module.raw.is_null() {
+                module.raw = mod_;
+                let names = module.features.names();
+                let enables = std::slice::from_raw_parts_mut(*enables_ptr, names.len());
+                // Unless features were picked, like with `zmodload -F`, everything is enabled.
+                // Zsh doesn't say which, so picking every feature looks the same
+                if enables.iter().all(|&enabled| enabled != 0) {
+                    for (enabled, name) in enables.iter_mut().zip(&names) {
+                        if module.disabled.contains(name) {
+                            *enabled = 0;
+                        }
+                    }
+                }
+            }
+            let status = zsys::handlefeatures(mod_, &mut *module.features, enables_ptr);
+            if !module.raw.is_null() {
+                list_features(&mut module);
+            }
+            status
         }
     }
 );
 
+/// Undoes what [`boot_`] did, returning the script removing the hooks, which must run once the
+/// lock is released.
+fn teardown(raw: zsys::Module) -> String {
+    let mut module = get_mod();
+    let Module {
+        on_cleanup, state, ..
+    } = &mut *module;
+    on_cleanup
+        .iter_mut()
+        .rev()
+        .for_each(|cleanup| cleanup(state));
+    crate::zsh::timers::clear();
+    if let Some(mut funcwrap) = module.funcwrap.take() {
+        unsafe { zsys::deletewrapper(raw, &mut *funcwrap) };
+    }
+    FUNCTION_WRAPPERS.take();
+    let name = module.name.unwrap();
+    if module.exports.is_some() {
+        let param = crate::interop::param_name(name);
+        unsafe { zsys::unsetparam(param.as_ptr() as *mut _) };
+    }
+    module.hooks_uninstall_script(name)
+}
+
 // Called when cleaning the module up.
 mod_fn!(
     fn cleanup_(_mod) {
+        if get_mod().forbid_unload {
+            log::error_named(get_mod().name.unwrap(), "this module can't be unloaded");
+            return 1;
+        }
+        let script = teardown(_mod);
+        if !script.is_empty() {
+            // The hooks are gone either way once the builtins are
+            let _ = crate::zsh::eval_simple(&script);
+        }
         let mut module = get_mod();
         unsafe {
             zsys::setfeatureenables(_mod, &mut *module.features, std::ptr::null_mut())
@@ -239,3 +707,15 @@ mod_fn!(
         Ok::<(), std::convert::Infallible>(())
     }
 );
+
+#[cfg(test)]
+mod tests {
+    use super::release;
+
+    #[test]
+    fn release_ignores_patch_and_suffix() {
+        assert_eq!(release(b"5.9"), b"5.9");
+        assert_eq!(release(b"5.9.0.1-dev"), b"5.9");
+        assert_eq!(release(b"5.8.1"), b"5.8");
+    }
+}
@@ -0,0 +1,111 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+};
+
+use crate::Feature;
+
+/// Values of any type, at most one of each. See [`Module::insert`](crate::Module::insert).
+#[derive(Default)]
+pub(crate) struct Extensions(HashMap<TypeId, Box<dyn Any>>);
+
+impl Extensions {
+    /// Returns the value of the same type it replaced, if any.
+    pub(crate) fn insert<T: Any>(&mut self, value: T) -> Option<T> {
+        self.0
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .map(|old| *old.downcast().unwrap())
+    }
+    pub(crate) fn get<T: Any>(&self) -> Option<&T> {
+        self.0.get(&TypeId::of::<T>())?.downcast_ref()
+    }
+    pub(crate) fn get_mut<T: Any>(&mut self) -> Option<&mut T> {
+        self.0.get_mut(&TypeId::of::<T>())?.downcast_mut()
+    }
+}
+
+/// The state of a module builtins are given, besides their arguments.
+pub(crate) struct State {
+    pub(crate) user_data: Box<dyn Any>,
+    pub(crate) extensions: Extensions,
+    /// Features to enable or disable once the running builtin returns. See
+    /// [`CmdContext::set_feature_enabled`](crate::CmdContext::set_feature_enabled).
+    pub(crate) feature_changes: Vec<(String, bool)>,
+    /// The features of the module, once it's loaded.
+    pub(crate) features: Vec<Feature>,
+    /// The arguments of the running builtin, as zsh passed them. Only set while it runs, which
+    /// is what their lifetime really is.
+    pub(crate) raw_args: Option<crate::Args<'static>>,
+    /// The callbacks of the fds the module watches. See [`crate::watch`].
+    #[cfg(feature = "watch")]
+    pub(crate) watchers: HashMap<std::os::fd::RawFd, crate::watch::WatchFn>,
+    /// Fds to watch or stop watching once the running builtin returns.
+    #[cfg(feature = "watch")]
+    pub(crate) watch_changes: Vec<(std::os::fd::RawFd, bool)>,
+    /// Closures other threads run on the shell's thread, unless the socket to wait for them
+    /// couldn't be made. See [`crate::main_thread`].
+    #[cfg(feature = "watch")]
+    pub(crate) queue: Option<crate::main_thread::Queue>,
+    /// The futures spawned by the module.
+    #[cfg(feature = "async")]
+    pub(crate) tasks: crate::tasks::Tasks,
+}
+
+impl State {
+    pub(crate) fn new(user_data: Box<dyn Any>) -> Self {
+        Self {
+            user_data,
+            extensions: Extensions::default(),
+            feature_changes: Vec::new(),
+            features: Vec::new(),
+            raw_args: None,
+            #[cfg(feature = "watch")]
+            watchers: HashMap::new(),
+            #[cfg(feature = "watch")]
+            watch_changes: Vec::new(),
+            #[cfg(feature = "watch")]
+            queue: None,
+            #[cfg(feature = "async")]
+            tasks: Default::default(),
+        }
+    }
+    /// The user data, which must be an `A`.
+    pub(crate) fn data<A: Any>(&mut self) -> &mut A {
+        self.user_data.downcast_mut().unwrap()
+    }
+    #[cfg(feature = "watch")]
+    pub(crate) fn watch_fd<A, F>(&mut self, fd: std::os::fd::RawFd, mut cb: F)
+    where
+        A: Any,
+        F: 'static + FnMut(&mut A, std::os::fd::RawFd, crate::watch::FdEvent) -> bool,
+    {
+        self.watch(
+            fd,
+            Box::new(move |state, fd, event| cb(state.data(), fd, event)),
+        );
+    }
+    #[cfg(feature = "async")]
+    pub(crate) fn spawn<A, F, C>(&mut self, fut: F, then: C) -> crate::ZResult<()>
+    where
+        A: Any,
+        F: std::future::Future + Send + 'static,
+        F::Output: Send,
+        C: 'static + FnOnce(&mut A, F::Output),
+    {
+        let queue = self.main_thread_queue()?;
+        let then = Box::new(move |state: &mut State, output: Box<dyn Any + Send>| {
+            then(state.data(), *output.downcast().unwrap())
+        });
+        Ok(self.tasks.spawn(queue, fut, then)?)
+    }
+    #[cfg(feature = "watch")]
+    pub(crate) fn main_thread_queue<A>(
+        &self,
+    ) -> crate::ZResult<crate::main_thread::MainThreadQueue<A>> {
+        let queue = self
+            .queue
+            .as_ref()
+            .ok_or(crate::ZError::Unavailable("can't wake the shell up"))?;
+        Ok(queue.handle())
+    }
+}
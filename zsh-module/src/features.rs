@@ -1,7 +1,75 @@
-use std::ptr::slice_from_raw_parts_mut;
+use std::{fmt, ptr::slice_from_raw_parts_mut};
 
 use zsh_sys as zsys;
 
+/// What a [`Feature`] of a module is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeatureKind {
+    Builtin,
+    /// A condition like `[[ -op arg ]]`.
+    Condition,
+    /// A condition like `[[ arg -op arg ]]`.
+    InfixCondition,
+    MathFunc,
+    Param,
+}
+
+impl FeatureKind {
+    const ALL: [Self; 5] = [
+        Self::Builtin,
+        Self::Condition,
+        Self::InfixCondition,
+        Self::MathFunc,
+        Self::Param,
+    ];
+    /// The prefix of the names of features of this kind.
+    fn prefix(self) -> &'static str {
+        match self {
+            Self::Builtin => "b:",
+            Self::Condition => "c:",
+            Self::InfixCondition => "C:",
+            Self::MathFunc => "f:",
+            Self::Param => "p:",
+        }
+    }
+}
+
+/// A feature of a module, as listed by `zmodload -lF <module>`. See
+/// [`Module::features`](crate::Module::features).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Feature {
+    pub kind: FeatureKind,
+    /// The name of the builtin, condition, function or parameter.
+    pub name: String,
+    pub enabled: bool,
+}
+
+impl Feature {
+    /// Parses a feature name like `b:greet`.
+    pub(crate) fn parse(id: &str, enabled: bool) -> Option<Self> {
+        FeatureKind::ALL.into_iter().find_map(|kind| {
+            let name = id.strip_prefix(kind.prefix())?;
+            Some(Self {
+                kind,
+                name: name.to_string(),
+                enabled,
+            })
+        })
+    }
+    /// The name zsh knows the feature by, like `b:greet`, as given to `zmodload -F`.
+    pub fn id(&self) -> String {
+        format!("{}{}", self.kind.prefix(), self.name)
+    }
+}
+
+/// Shows the feature like `zmodload -lF` does, e.g. `+b:greet` when it's enabled.
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.enabled { '+' } else { '-' };
+        write!(f, "{}{}", sign, self.id())
+    }
+}
+
 pub(crate) struct Features {
     pub raw: zsys::features,
 }
@@ -38,9 +106,60 @@ impl Features {
         unsafe { std::mem::MaybeUninit::zeroed().assume_init() }
     }
     feature_list_method!(binaries, get_binaries, zsys::builtin, bn_list, bn_size);
-    /* feature_list_method!(conddef, zsys::conddef, cd_list, cd_size);
-    feature_list_method!(mathfuncs, zsys::mathfunc, mf_list, mf_size);
-    feature_list_method!(paramdefs, zsys::paramdef, pd_list, pd_size); */
+    /// Adds a builtin to the ones already registered.
+    pub fn push_binary(&mut self, binary: zsys::builtin) {
+        let mut list = if self.bn_list.is_null() {
+            Vec::new()
+        } else {
+            let old = slice_from_raw_parts_mut(self.bn_list, self.bn_size as usize);
+            unsafe { Box::from_raw(old) }.into_vec()
+        };
+        list.push(binary);
+        let mem = Box::leak(list.into_boxed_slice());
+        self.raw.bn_list = mem.as_mut_ptr();
+        self.raw.bn_size = mem.len() as i32;
+    }
+    feature_list_method!(mathfuncs, get_mathfuncs, zsys::mathfunc, mf_list, mf_size);
+    feature_list_method!(conddefs, get_conddefs, zsys::conddef, cd_list, cd_size);
+    feature_list_method!(paramdefs, get_paramdefs, zsys::paramdef, pd_list, pd_size);
+    /// The names of the features, like `b:greet`, in the order zsh enables them.
+    pub fn names(&self) -> Vec<String> {
+        /// The names in a list, given the prefix of each entry.
+        unsafe fn list<T>(data: *const T, len: i32, f: impl Fn(&T) -> String) -> Vec<String> {
+            if data.is_null() {
+                return Vec::new();
+            }
+            std::slice::from_raw_parts(data, len as usize)
+                .iter()
+                .map(f)
+                .collect()
+        }
+        let name = |prefix: &str, name: *const std::ffi::c_char| {
+            let name = unsafe { std::ffi::CStr::from_ptr(name) };
+            format!("{}{}", prefix, name.to_string_lossy())
+        };
+        unsafe {
+            let mut names = list(self.bn_list, self.bn_size, |b| {
+                name(FeatureKind::Builtin.prefix(), b.node.nam)
+            });
+            names.extend(list(self.cd_list, self.cd_size, |c| {
+                let infix = c.flags as u32 & zsys::CONDF_INFIX != 0;
+                let kind = if infix {
+                    FeatureKind::InfixCondition
+                } else {
+                    FeatureKind::Condition
+                };
+                name(kind.prefix(), c.name)
+            }));
+            names.extend(list(self.mf_list, self.mf_size, |f| {
+                name(FeatureKind::MathFunc.prefix(), f.name)
+            }));
+            names.extend(list(self.pd_list, self.pd_size, |p| {
+                name(FeatureKind::Param.prefix(), p.name)
+            }));
+            names
+        }
+    }
 }
 
 unsafe fn free_list<T: std::fmt::Debug>(data: *mut T, len: i32) {
@@ -62,3 +181,19 @@ impl std::ops::Drop for Features {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn feature_names() {
+        let feature = Feature::parse("C:eq", true).unwrap();
+        assert_eq!(feature.kind, FeatureKind::InfixCondition);
+        assert_eq!(feature.name, "eq");
+        assert_eq!(feature.to_string(), "+C:eq");
+        let feature = Feature::parse("p:MYMOD_STATE", false).unwrap();
+        assert_eq!(feature.to_string(), "-p:MYMOD_STATE");
+        assert_eq!(Feature::parse("x:what", true), None);
+    }
+}
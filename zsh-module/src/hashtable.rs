@@ -1,8 +1,33 @@
-use std::os::raw::c_char;
+use std::{
+    cell::RefCell,
+    ffi::CStr,
+    os::raw::{c_char, c_int},
+};
 
 use zsh_sys as zsys;
 
-use crate::to_cstr;
+use crate::{to_cstr, MetaCString};
+
+thread_local! {
+    /// The entries collected by [`HashTable::entries`] so far.
+    static ENTRIES: RefCell<Vec<(MetaCString, MetaCString)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Copies the key and value of `node`, an element of an associative array.
+unsafe extern "C" fn collect_entry(node: zsys::HashNode, _flags: c_int) {
+    if (*node).flags as u32 & zsys::PM_UNSET != 0 {
+        return;
+    }
+    let param = node as zsys::Param;
+    let value = (*(*param).gsu.s).getfn.unwrap()(param);
+    let key = MetaCString::from_metafied(CStr::from_ptr((*node).nam));
+    let value = if value.is_null() {
+        MetaCString::new("")
+    } else {
+        MetaCString::from_metafied(CStr::from_ptr(value))
+    };
+    ENTRIES.with_borrow_mut(|entries| entries.push((key, value)));
+}
 
 /// A wrapper around Zsh's hashtable implementation
 pub struct HashTable {
@@ -10,9 +35,17 @@ pub struct HashTable {
 }
 
 impl HashTable {
-    pub(crate) unsafe fn from_raw(raw: zsys::HashTable) -> Self {
+    /// Wraps one of zsh's hash tables, e.g. [`zsys::paramtab`].
+    ///
+    /// # Safety
+    /// `raw` must point to a valid hash table, which outlives the returned value.
+    pub unsafe fn from_raw(raw: zsys::HashTable) -> Self {
         Self { raw }
     }
+    /// The raw hash table, to pass to functions of [`zsh_sys`] this crate doesn't wrap.
+    pub fn as_raw(&self) -> zsys::HashTable {
+        self.raw
+    }
     pub(crate) fn get(&self, name: &str) -> zsys::HashNode {
         let name = to_cstr(name);
         unsafe { self.raw_get(name.as_ptr()) }
@@ -21,6 +54,38 @@ impl HashTable {
         let name = to_cstr(name);
         unsafe { self.raw_remove(name.as_ptr()) }
     }
+    /// Every node in the table, in no particular order.
+    pub(crate) fn nodes(&self) -> impl Iterator<Item = zsys::HashNode> + '_ {
+        let table = unsafe { &*self.raw };
+        (0..table.hsize as usize).flat_map(move |i| {
+            let mut node = unsafe { *table.nodes.add(i) };
+            std::iter::from_fn(move || {
+                let current = node;
+                if current.is_null() {
+                    return None;
+                }
+                node = unsafe { (*current).next };
+                Some(current)
+            })
+        })
+    }
+    /// The keys and values of an associative array, in no particular order. See
+    /// [`ParamValue::HashTable`](crate::zsh::ParamValue::HashTable).
+    ///
+    /// Special associative arrays (like `$commands` or `$history`) make their entries up as
+    /// they're listed, so they're copied instead of borrowed.
+    pub fn entries(&self) -> Vec<(MetaCString, MetaCString)> {
+        let outer = ENTRIES.take();
+        match unsafe { (*self.raw).scantab } {
+            Some(scantab) => unsafe { scantab(self.raw, Some(collect_entry), 0) },
+            None => {
+                for node in self.nodes() {
+                    unsafe { collect_entry(node, 0) }
+                }
+            }
+        }
+        ENTRIES.replace(outer)
+    }
     pub(crate) unsafe fn raw_get(&self, name: *const c_char) -> zsys::HashNode {
         zsys::gethashnode(self.raw, name)
     }
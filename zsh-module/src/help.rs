@@ -0,0 +1,154 @@
+//! Documentation of a module's builtins, shown by the builtin added with
+//! [`ModuleBuilder::with_help_builtin`](crate::ModuleBuilder::with_help_builtin).
+use std::fmt::Write;
+
+use crate::Builtin;
+
+/// What the help builtin knows about one of the module's builtins.
+#[derive(Debug, Clone)]
+pub(crate) struct BuiltinHelp {
+    pub name: String,
+    pub usage: String,
+    pub description: Option<String>,
+    pub examples: Vec<String>,
+    pub deprecated: Option<String>,
+}
+
+impl BuiltinHelp {
+    pub fn new(builtin: &Builtin) -> Self {
+        let name = builtin.name.to_string_lossy().into_owned();
+        let flags = builtin
+            .flags
+            .as_ref()
+            .map(|flags| flags.to_string_lossy())
+            .unwrap_or_default();
+        Self {
+            usage: usage(&name, &flags, builtin.minargs, builtin.maxargs),
+            name,
+            description: builtin.description.clone(),
+            examples: builtin.examples.clone(),
+            deprecated: builtin.deprecated.clone(),
+        }
+    }
+    /// The usage line, followed by the description and examples.
+    fn block(&self) -> String {
+        let mut out = format!("{}\n", self.usage);
+        if let Some(note) = &self.deprecated {
+            writeln!(out, "    Deprecated: {}", note).unwrap();
+        }
+        if let Some(description) = &self.description {
+            for line in description.lines() {
+                writeln!(out, "    {}", line).unwrap();
+            }
+        }
+        if !self.examples.is_empty() {
+            writeln!(out, "\n    Examples:").unwrap();
+            for example in &self.examples {
+                writeln!(out, "      {}", example).unwrap();
+            }
+        }
+        out
+    }
+}
+
+/// Builds a usage line like `cmd [-ab] [-o arg] arg [arg ...]` out of the builtin's `optstr`
+/// (where a `:` after a flag means it takes an argument) and argument counts.
+pub(crate) fn usage(name: &str, flags: &str, minargs: i32, maxargs: i32) -> String {
+    let mut usage = name.to_string();
+    let mut switches = String::new();
+    let mut with_args = Vec::new();
+    let mut chars = flags.chars().peekable();
+    while let Some(c) = chars.next() {
+        if !c.is_ascii_alphanumeric() {
+            continue;
+        }
+        if chars.peek() == Some(&':') {
+            while chars.next_if(|c| *c == ':' || *c == '%').is_some() {}
+            with_args.push(c);
+        } else {
+            switches.push(c);
+        }
+    }
+    if !switches.is_empty() {
+        write!(usage, " [-{}]", switches).unwrap();
+    }
+    for c in with_args {
+        write!(usage, " [-{} arg]", c).unwrap();
+    }
+    let minargs = minargs.max(0);
+    for _ in 0..minargs {
+        usage.push_str(" arg");
+    }
+    if maxargs < 0 {
+        usage.push_str(" [arg ...]");
+    } else {
+        for _ in minargs..maxargs {
+            usage.push_str(" [arg]");
+        }
+    }
+    usage
+}
+
+/// Formats the help of every builtin in `entries`, or only the one called `only`.
+pub(crate) fn render(module_name: &str, entries: &[BuiltinHelp], only: Option<&str>) -> String {
+    let blocks: Vec<String> = entries
+        .iter()
+        .filter(|entry| only.is_none_or(|name| name == entry.name))
+        .map(BuiltinHelp::block)
+        .collect();
+    match only {
+        Some(_) => blocks.join("\n"),
+        None => format!(
+            "Builtins provided by {}:\n\n{}",
+            module_name,
+            blocks.join("\n")
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn usage_lines() {
+        assert_eq!(usage("greet", "", 0, -1), "greet [arg ...]");
+        assert_eq!(usage("greet", "", 0, 0), "greet");
+        assert_eq!(
+            usage("zpty", "wo:dL", 1, 2),
+            "zpty [-wdL] [-o arg] arg [arg]"
+        );
+        assert_eq!(usage("x", "t:%", 0, 1), "x [-t arg] [arg]");
+    }
+
+    #[test]
+    fn renders_everything_or_one() {
+        let entries = [
+            BuiltinHelp::new(
+                &Builtin::new("greet")
+                    .maxargs(Some(1))
+                    .description("Greets someone.")
+                    .example("greet world"),
+            ),
+            BuiltinHelp::new(
+                &Builtin::new("bye")
+                    .maxargs(Some(0))
+                    .deprecated("just leave"),
+            ),
+        ];
+        assert_eq!(
+            render("rgreeter", &entries, None),
+            "Builtins provided by rgreeter:\n\
+             \n\
+             greet [arg]\n    Greets someone.\n\
+             \n    Examples:\n      greet world\n\
+             \n\
+             bye\n    Deprecated: just leave\n"
+        );
+        assert_eq!(
+            render("rgreeter", &entries, Some("bye")),
+            "bye\n    Deprecated: just leave\n"
+        );
+        assert_eq!(render("rgreeter", &entries, Some("nope")), "");
+    }
+}
@@ -0,0 +1,148 @@
+//! Letting other modules call into this one, and calling into them.
+//!
+//! A module publishes functions with [`ModuleBuilder::export_fn`]. While it's loaded, the address
+//! of a [`Table`] listing them is stored in the integer parameter `zsh_module_interop_<module>`,
+//! which any module can read, including ones written in C:
+//! ```c
+//! struct entry { const char *name; void *func; };
+//! struct table { uint32_t version; size_t len; const struct entry *entries; };
+//!
+//! struct table *t = (struct table *)getiparam("zsh_module_interop_greeter");
+//! ```
+//!
+//! # Example
+//! ```no_run
+//! use zsh_module::{interop, ModuleBuilder};
+//!
+//! extern "C" fn double(x: i32) -> i32 {
+//!     x * 2
+//! }
+//!
+//! // In the `greeter` module
+//! let module = ModuleBuilder::new(())
+//!     .export_fn("double", double as *const _)
+//!     .build();
+//!
+//! // In another module
+//! if let Some(func) = unsafe { interop::lookup("greeter", "double") } {
+//!     let double: extern "C" fn(i32) -> i32 = unsafe { std::mem::transmute(func) };
+//!     assert_eq!(double(21), 42);
+//! }
+//! ```
+//!
+//! [`ModuleBuilder::export_fn`]: crate::ModuleBuilder::export_fn
+use std::ffi::{c_char, c_void, CStr, CString};
+
+use crate::{
+    backend::{Backend, Current},
+    to_cstr,
+};
+
+/// The version of [`Table`]'s layout. Tables with another version are ignored.
+pub const VERSION: u32 = 1;
+
+/// The functions published by a module.
+#[repr(C)]
+#[derive(Debug)]
+pub struct Table {
+    pub version: u32,
+    pub len: usize,
+    pub entries: *const Entry,
+}
+
+/// A function published by a module.
+#[repr(C)]
+#[derive(Debug)]
+pub struct Entry {
+    /// Its name, which is valid UTF-8.
+    pub name: *const c_char,
+    pub func: *const c_void,
+}
+
+/// A [`Table`] and what it points to.
+pub(crate) struct Exports {
+    // Only pointed to
+    #[allow(dead_code)]
+    names: Vec<CString>,
+    #[allow(dead_code)]
+    entries: Vec<Entry>,
+    table: Box<Table>,
+}
+
+impl Exports {
+    pub(crate) fn new(funcs: &[(&'static str, *const c_void)]) -> Self {
+        let names: Vec<CString> = funcs.iter().map(|(name, _)| to_cstr(*name)).collect();
+        let entries: Vec<Entry> = names
+            .iter()
+            .zip(funcs)
+            .map(|(name, (_, func))| Entry {
+                name: name.as_ptr(),
+                func: *func,
+            })
+            .collect();
+        let table = Box::new(Table {
+            version: VERSION,
+            len: entries.len(),
+            entries: entries.as_ptr(),
+        });
+        Self {
+            names,
+            entries,
+            table,
+        }
+    }
+    /// Stores the address of the table in the module's parameter.
+    #[cfg_attr(not(feature = "export_module"), allow(dead_code))]
+    pub(crate) fn publish(&self, module_name: &str) {
+        let addr = &*self.table as *const Table as i64;
+        Current::set_int_param(&param_name(module_name), addr);
+    }
+}
+
+/// The parameter holding the address of `module_name`'s [`Table`].
+pub(crate) fn param_name(module_name: &str) -> CString {
+    to_cstr(format!("zsh_module_interop_{}", module_name))
+}
+
+/// Finds the function named `name` published by the module `module_name`, if it's loaded and
+/// published one.
+///
+/// # Safety
+/// The parameter holding the table must not have been tampered with, since its value is
+/// dereferenced as is.
+pub unsafe fn lookup(module_name: &str, name: &str) -> Option<*const c_void> {
+    let addr = Current::get_int_param(&param_name(module_name));
+    if addr == 0 {
+        return None;
+    }
+    let table = &*(addr as *const Table);
+    if table.version != VERSION {
+        return None;
+    }
+    std::slice::from_raw_parts(table.entries, table.len)
+        .iter()
+        .find(|entry| CStr::from_ptr(entry.name).to_bytes() == name.as_bytes())
+        .map(|entry| entry.func)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock;
+
+    extern "C" fn double(x: i32) -> i32 {
+        x * 2
+    }
+
+    #[test]
+    fn published_functions_can_be_looked_up() {
+        assert!(unsafe { lookup("mymod", "double") }.is_none());
+        let exports = Exports::new(&[("double", double as *const c_void)]);
+        exports.publish("mymod");
+        assert!(mock::param("zsh_module_interop_mymod").is_some());
+        assert!(unsafe { lookup("mymod", "triple") }.is_none());
+        let func = unsafe { lookup("mymod", "double") }.unwrap();
+        let func: extern "C" fn(i32) -> i32 = unsafe { std::mem::transmute(func) };
+        assert_eq!(func(21), 42);
+    }
+}
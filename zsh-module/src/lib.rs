@@ -9,6 +9,17 @@
 //! crate-type = ["cdylib"]
 //! ```
 //!
+//! On macOS, the linker refuses to leave zsh's symbols undefined, which they must be until the
+//! module is loaded. Add a `build.rs` to your crate to allow it:
+//! ```ignore
+//! fn main() {
+//!     if std::env::var("CARGO_CFG_TARGET_VENDOR").as_deref() == Ok("apple") {
+//!         println!("cargo:rustc-cdylib-link-arg=-undefined");
+//!         println!("cargo:rustc-cdylib-link-arg=dynamic_lookup");
+//!     }
+//! }
+//! ```
+//!
 //! ## Boilerplate
 //! On your `lib.rs`, you need to put a [`export_module!`] macro call, alongside a `setup` function
 //! (can be called whatever you want):
@@ -58,6 +69,47 @@
 //! }
 //! ```
 //!
+//! ## Cargo features
+//! Bigger subsystems are behind cargo features, all of which are enabled by default. A small
+//! module can disable the default features and only pick the ones it needs:
+//! ```toml
+//! [dependencies]
+//! zsh-module = { version = "0.3", default-features = false, features = ["export_module"] }
+//! ```
+//! - `export_module`: The [`export_module!`] macro. Needed by every module.
+//! - `zle`: The [`zle`] module, to interact with the line editor.
+//! - `completion`: The [`completion`] module, to write completers in Rust.
+//! - `prompt`: Prompt expansion, through [`zsh::expand_prompt`].
+//! - `net`: The [`net`] module, to make sockets zsh knows about.
+//! - `pty`: The [`pty`] module, to run commands in pseudo-terminals.
+//!
+//! `derive` (not enabled by default) adds `#[derive(BuiltinArgs)]`, to parse the arguments of a
+//! builtin into a struct, and `#[builtins]`, to register methods as builtins. See
+//! [`ModuleBuilder::builtin_args`] and [`ModuleBuilder::builtins`].
+//!
+//! `log` (not enabled by default) prints the messages of libraries using the `log` crate. See
+//! `log::init_log_crate`.
+//!
+//! `watch` (not enabled by default) adds the `watch` module, to run callbacks when file
+//! descriptors become readable, and the `main_thread` module, to run closures sent by other
+//! threads on the shell's thread. See `Module::watch_fd` and `Module::main_thread_queue`.
+//!
+//! `async` (not enabled by default) runs futures on a background `tokio` runtime, and their
+//! callbacks back on the shell's thread. See `Module::spawn`.
+//!
+//! `tracing` (not enabled by default) adds the [`tracing`](crate::tracing) module, printing the
+//! events of `tracing` through zsh.
+//!
+//! `serde` (not enabled by default) implements `Serialize` and `Deserialize` for the data read
+//! from zsh, like [`zsh::history::Entry`], [`zsh::jobs::Job`] or [`zsh::commands::Command`], e.g.
+//! to send it to other programs.
+//!
+//! `backtrace` (not enabled by default) captures where the errors made with [`From`] come from,
+//! and prints it when they make a builtin fail. See `ZError::backtrace`.
+//!
+//! And, for development only, `hot_reload` (not enabled by default) reloads the module when it's
+//! rebuilt. See `ModuleBuilder::hot_reload`.
+//!
 //! ## Installing
 //! When your module is ready, copy your shared library to your distribution's zsh module folder
 //! and name it whatever you want, the only requirement is that it ends with your platforms's
@@ -76,22 +128,88 @@
 use std::{
     any::Any,
     borrow::Cow,
-    collections::HashMap,
+    collections::VecDeque,
+    collections::{HashMap, HashSet},
     error::Error,
-    ffi::{c_char, CStr, CString},
+    ffi::{c_char, c_int, c_void, CStr, CString},
+    rc::Rc,
+    time::{Duration, Instant},
 };
 
 use features::Features;
+pub use features::{Feature, FeatureKind};
+use help::BuiltinHelp;
 
-pub use options::Opts;
+pub use options::{OptKind, OptState, Opts, ParsedOpts};
 use zsh_sys as zsys;
+/// The raw bindings to zsh, for functions this crate doesn't wrap yet. See e.g.
+/// [`Opts::as_raw`].
+pub use zsh_sys;
 
+mod args;
+mod backend;
+pub mod capabilities;
+#[cfg(feature = "completion")]
+pub mod completion;
+pub mod cond;
+mod context;
+mod cstrarray;
+mod cstring;
+mod dispatch;
+mod error;
+mod extensions;
 mod features;
 mod hashtable;
+mod help;
+pub mod interop;
 pub mod log;
+#[cfg(feature = "watch")]
+pub mod main_thread;
+pub mod math;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+#[cfg(feature = "net")]
+pub mod net;
 mod options;
+pub mod paramdef;
+pub mod parseopts;
+#[cfg(feature = "pty")]
+pub mod pty;
+#[cfg(feature = "hot_reload")]
+mod reload;
+#[cfg(feature = "async")]
+mod tasks;
+pub mod terminal;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "tracing")]
+pub mod tracing;
+pub mod validate;
+#[cfg(feature = "watch")]
+pub mod watch;
+pub mod zalloc;
+#[cfg(feature = "zle")]
+pub mod zle;
 pub mod zsh;
 
+pub use args::{Args, BuiltinArgs, Decoding};
+#[cfg(feature = "derive")]
+pub use zsh_module_derive::{builtin, builtins, BuiltinArgs};
+// Lets the tests use `#[derive(BuiltinArgs)]`, whose code names the crate
+#[cfg(all(test, feature = "derive"))]
+extern crate self as zsh_module;
+
+/// Used by the code `#[derive(BuiltinArgs)]` generates.
+#[doc(hidden)]
+pub mod __derive {
+    pub use crate::args::{missing, parse_arg};
+}
+pub use context::{CmdContext, Fd};
+pub use cstrarray::{CStrArray, CStrArrayBuf, CStrArrayIter, CStrArrayMut};
+pub use cstring::MetaCString;
+pub use dispatch::Dispatcher;
+pub use error::{ConversionError, MaybeZError, ZError, ZResult};
+use extensions::State;
 pub use hashtable::HashTable;
 
 /// A box error type for easier error handling.
@@ -104,7 +222,7 @@ pub type AnyError = Box<dyn Error>;
 /// You can (and should) replace the default error type `E` with your own [`Error`].
 pub type MaybeError<E = AnyError> = Result<(), E>;
 
-trait AnyCmd = Cmd<dyn Any, AnyError>;
+trait AnyCmd = Cmd<State, AnyError>;
 
 /// This trait corresponds to the function signature of a zsh builtin command handler.
 ///
@@ -125,10 +243,93 @@ trait AnyCmd = Cmd<dyn Any, AnyError>;
 pub trait Cmd<A: Any + ?Sized, E: Into<AnyError>> =
     'static + FnMut(&mut A, &str, &[&str], Opts) -> MaybeError<E>;
 
+/// A type whose methods are builtins, registered all at once with [`ModuleBuilder::builtins`].
+///
+/// With the `derive` feature, it's implemented by putting `#[builtins]` on an impl block, and
+/// `#[builtin(...)]` on the methods to register.
+pub trait Builtins: Any + Sized {
+    /// Registers the builtins on `builder`.
+    fn register(builder: ModuleBuilder<Self>) -> ModuleBuilder<Self>;
+}
+
+/// A function run around the builtins of a module, given the builtin's name, its arguments and a
+/// function that runs the builtin. See [`ModuleBuilder::wrap_builtins`].
+pub trait Wrapper = 'static + Fn(&str, &[&str], &mut dyn FnMut() -> MaybeError) -> MaybeError;
+
+/// A function run around every shell function called while the module is loaded, given the
+/// function's name and a function that runs it and returns its exit status. See
+/// [`ModuleBuilder::wrap_functions`].
+pub trait FunctionWrapper = 'static + Fn(&str, &mut dyn FnMut() -> i32);
+
+/// Makes `cmd` run inside of `wrappers`, the first one being the outermost.
+fn wrap_cmd(wrappers: &[Rc<dyn Wrapper>], cmd: Box<dyn AnyCmd>) -> Box<dyn AnyCmd> {
+    wrappers.iter().rev().fold(cmd, |mut cmd, wrapper| {
+        let wrapper = wrapper.clone();
+        Box::new(move |state: &mut State, name, args, opts: Opts| {
+            wrapper(name, args, &mut || cmd(state, name, args, opts.clone()))
+        })
+    })
+}
+
+/// Turns the errors of `cmd` into their status, so they aren't printed.
+/// Says which builtin failed in its errors, when they're [`ZError`]s. See [`ZError::InBuiltin`].
+fn named_errors(mut cmd: Box<dyn AnyCmd>) -> impl AnyCmd {
+    move |state: &mut State, name: &str, args: &[&str], opts: Opts| {
+        cmd(state, name, args, opts).map_err(|e| match e.downcast::<ZError>() {
+            Ok(e) => Box::new(e.in_builtin(name)) as AnyError,
+            Err(e) => e,
+        })
+    }
+}
+
+fn quiet(mut cmd: Box<dyn AnyCmd>) -> impl AnyCmd {
+    move |state: &mut State, name: &str, args: &[&str], opts: Opts| {
+        cmd(state, name, args, opts).map_err(|e| -> AnyError {
+            let status = e.downcast_ref::<ZError>().map_or(1, ZError::exit_code);
+            Box::new(ZError::Status(status))
+        })
+    }
+}
+
 pub(crate) fn to_cstr(string: impl Into<Vec<u8>>) -> CString {
     CString::new(string).expect("Strings should not contain a null byte!")
 }
 
+/// The byte zsh uses to escape special characters in metafied strings.
+pub(crate) const META: u8 = 0x83;
+
+/// Copies `bytes` into zsh's heap, metafying them on the way.
+///
+/// Zsh internally stores strings in a "metafied" form, in which null bytes and other special
+/// characters are escaped, so anything handed to zsh needs to be metafied first.
+pub(crate) fn metafy_heap(bytes: &[u8]) -> *mut c_char {
+    unsafe {
+        let meta = zsys::metafy(
+            bytes.as_ptr() as *mut _,
+            bytes.len() as c_int,
+            zsys::META_HEAPDUP as c_int,
+        );
+        zalloc::record_heap(CStr::from_ptr(meta).to_bytes_with_nul().len());
+        meta
+    }
+}
+
+/// Reverses zsh's metafication. See [`metafy_heap`].
+pub(crate) fn unmetafy(bytes: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut iter = bytes.iter();
+    while let Some(&byte) = iter.next() {
+        if byte == META {
+            if let Some(&next) = iter.next() {
+                out.push(next ^ 32);
+            }
+        } else {
+            out.push(byte);
+        }
+    }
+    out
+}
+
 /// Represents any type that can be represented as a C String. You shouldn't
 /// need to implement this yourself as the most commonly used `string`-y types
 /// already have this implemented.
@@ -166,6 +367,15 @@ macro_rules! impl_tocstring {
 
 impl_tocstring!(Vec<u8>, &[u8], &str, String);
 
+impl ToCString for std::fmt::Arguments<'_> {
+    fn into_cstr<'a>(self) -> Cow<'a, CStr>
+    where
+        Self: 'a,
+    {
+        Cow::Owned(to_cstr(self.to_string()))
+    }
+}
+
 impl ToCString for &CStr {
     fn into_cstr<'a>(self) -> Cow<'a, CStr>
     where
@@ -193,6 +403,255 @@ impl ToCString for *mut c_char {
     }
 }
 
+/// Where the options of a builtin end and its arguments start.
+///
+/// Zsh parses the options of a builtin before calling it, stopping at the first argument that
+/// doesn't start with a `-`. Everything after that is passed to the builtin verbatim, even if it
+/// looks like an option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EndOfOptions {
+    /// `--` or `-` also end the options and are dropped, so `cmd -a -- -b` gets `-b` as an
+    /// argument. This is what most zsh builtins do.
+    ///
+    /// Builtins without [flags](Builtin::flags) only drop a leading `--`.
+    #[default]
+    DoubleDash,
+    /// Like [`EndOfOptions::DoubleDash`], but a lone `-` is passed as an argument, like in `cd -`.
+    DashIsArgument,
+    /// Options also end at the first argument with a flag the builtin doesn't know of, which is
+    /// passed as an argument, like in `echo -x`. `--` still ends them too.
+    FirstUnknown,
+    /// Options aren't parsed at all: every argument is passed verbatim, including `--`.
+    Verbatim,
+}
+
+impl EndOfOptions {
+    /// The `BINF_*` flags that make zsh's option parser behave like this.
+    fn binf(self) -> c_int {
+        (match self {
+            Self::DoubleDash => 0,
+            // Otherwise, zsh drops a leading `--` even without options
+            Self::Verbatim => zsys::BINF_HANDLES_OPTS,
+            Self::DashIsArgument => zsys::BINF_SKIPDASH,
+            Self::FirstUnknown => zsys::BINF_SKIPINVALID | zsys::BINF_DASHDASHVALID,
+        }) as c_int
+    }
+}
+
+/// What happens when a builtin is called while another builtin of the same module is running, e.g.
+/// when a builtin runs code with [`zsh::eval_simple`] that calls itself, or triggers a hook that
+/// does.
+///
+/// Builtins of a module never run while another one of them is running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(i32)]
+pub enum Reentry {
+    /// The call fails with an error.
+    #[default]
+    Refuse,
+    /// The call succeeds right away, but the builtin only runs once the running one returns. Its
+    /// exit status is lost.
+    Queue,
+}
+
+/// What to do with a line that's about to be saved in the history. See
+/// [`ModuleBuilder::history_filter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(i32)]
+pub enum HistoryAction {
+    /// Save the line.
+    #[default]
+    Keep = 0,
+    /// Don't save the line at all.
+    Skip = 1,
+    /// Keep the line in the history list, but don't write it to `$HISTFILE`.
+    Stop = 2,
+}
+
+/// The hook functions a module can add to with [`ModuleBuilder::hook`]. See "Hook Functions" in
+/// `zshmisc(1)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hook {
+    /// After the current directory changed.
+    Chpwd,
+    /// Before a prompt, every `$PERIOD` seconds.
+    Periodic,
+    /// Before each prompt.
+    Precmd,
+    /// After a command line is read, before it runs. Given the line as typed, followed by two
+    /// expanded forms of it.
+    Preexec,
+    /// When the shell exits.
+    ZshExit,
+}
+
+impl Hook {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Chpwd => "chpwd",
+            Self::Periodic => "periodic",
+            Self::Precmd => "precmd",
+            Self::Preexec => "preexec",
+            Self::ZshExit => "zshexit",
+        }
+    }
+}
+
+/// The signals a module can trap with [`ModuleBuilder::trap`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Signal {
+    /// `SIGINT`, usually sent by `^C`.
+    Int,
+    /// `SIGALRM`, also sent when `$TMOUT` runs out.
+    Alrm,
+    /// `SIGCONT`, when the shell is resumed after being stopped.
+    Cont,
+    /// `SIGUSR1`.
+    Usr1,
+    /// `SIGUSR2`.
+    Usr2,
+    /// `SIGWINCH`, when the terminal is resized.
+    Winch,
+}
+
+impl Signal {
+    /// The name of the function trapping the signal.
+    fn trap_name(self) -> &'static str {
+        match self {
+            Self::Int => "TRAPINT",
+            Self::Alrm => "TRAPALRM",
+            Self::Cont => "TRAPCONT",
+            Self::Usr1 => "TRAPUSR1",
+            Self::Usr2 => "TRAPUSR2",
+            Self::Winch => "TRAPWINCH",
+        }
+    }
+    /// The name `trap` lists the signal by.
+    fn name(self) -> &'static str {
+        &self.trap_name()[4..]
+    }
+}
+
+/// A hook implemented by a builtin of the module, named `<module>-<hook>`, which a shell function
+/// named `_<module>_<hook>` calls.
+struct ShellHook {
+    hook: &'static str,
+    kind: HookKind,
+    cb: Box<dyn AnyCmd>,
+}
+
+enum HookKind {
+    /// One of the hook functions in "Hook Functions" in `zshmisc(1)`.
+    Function,
+    /// A ZLE widget, which wraps the widget of the same name (which may be the user's own) and
+    /// runs it unless the builtin fails. It's put back when the module is unloaded.
+    #[cfg_attr(not(feature = "zle"), allow(dead_code))]
+    Widget,
+    /// One of the special widgets in "Special Widgets" in `zshzle(1)`, added with
+    /// `add-zle-hook-widget`.
+    #[cfg_attr(not(feature = "zle"), allow(dead_code))]
+    ZleHook,
+    /// Reloads the module with `<module>-reload`, a function, when the builtin succeeds. Runs
+    /// from the `precmd` hook, under its own name so it doesn't clash with [`Hook::Precmd`].
+    #[cfg_attr(not(feature = "hot_reload"), allow(dead_code))]
+    Reload,
+    /// A function zsh calls by name, like `command_not_found_handler`. One the user already
+    /// defined is run when the builtin returns 127, and is put back when the module is unloaded.
+    Handler,
+    /// A function trapping a signal, which runs the trap the user had already set afterwards.
+    /// Otherwise, it returns the status zsh would have behaved as if the signal wasn't trapped.
+    Trap(Signal),
+    /// The widget zle runs for watched fds, see [`watch`]. The fds are watched separately.
+    #[cfg_attr(not(feature = "watch"), allow(dead_code))]
+    FdWatch,
+}
+
+impl ShellHook {
+    fn install_script(&self, module_name: &str) -> String {
+        let (m, h) = (module_name, self.hook);
+        match self.kind {
+            HookKind::Function => {
+                format!("_{m}_{h}() {{ builtin {m}-{h} \"$@\" }}\n{h}_functions+=(_{m}_{h})\n")
+            }
+            HookKind::Widget => format!(
+                "zle -A {h} _{m}_orig_{h}\n\
+                 _{m}_{h}() {{ builtin {m}-{h} \"$@\" && zle _{m}_orig_{h} -- \"$@\" }}\n\
+                 zle -N {h} _{m}_{h}\n"
+            ),
+            HookKind::ZleHook => format!(
+                "_{m}_{h}() {{ builtin {m}-{h} }}\nautoload -Uz add-zle-hook-widget\n\
+                 add-zle-hook-widget {h} _{m}_{h}\n"
+            ),
+            HookKind::Handler => format!(
+                "if (( $+functions[{h}] )); then functions -c {h} _{m}_{h}; fi\n\
+                 {h}() {{\n\
+                 builtin {m}-{h} \"$@\"\n\
+                 local ret=$?\n\
+                 if (( ret == 127 && $+functions[_{m}_{h}] )); then _{m}_{h} \"$@\"; return; fi\n\
+                 return ret\n\
+                 }}\n"
+            ),
+            // A builtin can't unload the library its own code is in, so this is done by a function
+            HookKind::Reload => format!(
+                "{m}-reload() {{ zmodload -u {m} && zmodload {m} }}\n\
+                 _{m}_{h}() {{ builtin {m}-{h} && {m}-reload }}\nprecmd_functions+=(_{m}_{h})\n"
+            ),
+            // A trap set with `trap` is turned into a function, as if it was a `TRAPNAL` function
+            HookKind::Trap(signal) => format!(
+                "() {{\n\
+                 if (( $+functions[{h}] )); then functions -c {h} _{m}_{h}\n\
+                 else\n\
+                 local trap=${{(M)${{(f)\"$(trap)\"}}:#* {name}}}\n\
+                 if [[ -n $trap ]]; then eval \"set -- ${{trap#trap -- }}\"; functions[_{m}_{h}]=$1; fi\n\
+                 fi\n\
+                 }}\n\
+                 {h}() {{\n\
+                 builtin {m}-{h} \"$@\"\n\
+                 if (( $+functions[_{m}_{h}] )); then _{m}_{h} \"$@\"; return; fi\n\
+                 return {status}\n\
+                 }}\n",
+                name = signal.name(),
+                // Interrupting the running command, like an untrapped `^C` would
+                status = if signal == Signal::Int { "$(( 128 + $1 ))" } else { "0" },
+            ),
+            HookKind::FdWatch => format!(
+                "_{m}_{h}() {{ builtin {m}-{h} \"$@\" }}\nif [[ -o zle ]]; then zle -N _{m}_{h}; fi\n"
+            ),
+        }
+    }
+    fn uninstall_script(&self, module_name: &str) -> String {
+        let (m, h) = (module_name, self.hook);
+        match self.kind {
+            // `<module>-reload` is kept, to load the module again if reloading failed
+            HookKind::Function => {
+                format!("{h}_functions=(${{{h}_functions:#_{m}_{h}}})\nunfunction _{m}_{h}\n")
+            }
+            HookKind::Reload => {
+                format!("precmd_functions=(${{precmd_functions:#_{m}_{h}}})\nunfunction _{m}_{h}\n")
+            }
+            HookKind::Widget => {
+                format!("zle -A _{m}_orig_{h} {h}\nzle -D _{m}_orig_{h}\nunfunction _{m}_{h}\n")
+            }
+            HookKind::ZleHook => {
+                format!("add-zle-hook-widget -d {h} _{m}_{h}\nunfunction _{m}_{h}\n")
+            }
+            HookKind::Handler | HookKind::Trap(_) => format!(
+                "unfunction {h}\n\
+                 if (( $+functions[_{m}_{h}] )); then\n\
+                 functions -c _{m}_{h} {h}\nunfunction _{m}_{h}\n\
+                 fi\n"
+            ),
+            HookKind::FdWatch => {
+                format!("if [[ -o zle ]]; then zle -D _{m}_{h}; fi\nunfunction _{m}_{h}\n")
+            }
+        }
+    }
+}
+
+/// Builds the usage of a builtin, given its name. See [`Builtin::usage`].
+type UsageFn = Box<dyn Fn(&str) -> String>;
+
 /// Properties of a zsh builtin command.
 ///
 /// Any chages will reflect on the behaviour of the builtin
@@ -201,6 +660,17 @@ pub struct Builtin {
     maxargs: i32,
     flags: Option<CString>,
     name: CString,
+    description: Option<String>,
+    examples: Vec<String>,
+    deprecated: Option<String>,
+    end_of_options: EndOfOptions,
+    plus_flags: bool,
+    usage: Option<UsageFn>,
+    reentry: Reentry,
+    rate_limit: Option<(usize, Duration)>,
+    decoding: Decoding,
+    disabled_by_default: bool,
+    quiet_errors: bool,
 }
 
 impl Builtin {
@@ -214,6 +684,17 @@ impl Builtin {
             maxargs: -1,
             flags: None,
             name: to_cstr(name),
+            description: None,
+            examples: Vec::new(),
+            deprecated: None,
+            end_of_options: EndOfOptions::default(),
+            plus_flags: false,
+            usage: None,
+            reentry: Reentry::default(),
+            rate_limit: None,
+            decoding: Decoding::default(),
+            disabled_by_default: false,
+            quiet_errors: false,
         }
     }
     /// Sets the minimum amount of arguments allowed by the builtin
@@ -231,9 +712,154 @@ impl Builtin {
         self.flags = Some(to_cstr(value));
         self
     }
+    /// Declares the option `-c`, adding it to the builtin's [flags](Self::flags) and its usage
+    /// line. Zsh rejects options the builtin doesn't declare, and ones missing their argument.
+    ///
+    /// Handlers registered with [`ModuleBuilder::builtin_parsed`] get the options as
+    /// [`ParsedOpts`].
+    ///
+    /// # Example
+    /// ```
+    /// use zsh_module::{Builtin, OptKind};
+    ///
+    /// // usage: mycmd [-v] [-f arg] [arg ...]
+    /// let mycmd = Builtin::new("mycmd")
+    ///     .opt('v', OptKind::Flag)
+    ///     .opt('f', OptKind::TakesArg);
+    /// ```
+    pub fn opt(mut self, c: char, kind: OptKind) -> Self {
+        let mut flags = self.flags.map(CString::into_bytes).unwrap_or_default();
+        flags.extend(kind.optstr(c).into_bytes());
+        self.flags = Some(to_cstr(flags));
+        self
+    }
+    /// Allows the builtin's flags to also be set with a `+`, like `setopt +o`. See
+    /// [`Opts::state`].
+    pub fn plus_flags(mut self) -> Self {
+        self.plus_flags = true;
+        self
+    }
+    /// Sets where the builtin's options end. See [`EndOfOptions`].
+    ///
+    /// # Example
+    /// ```
+    /// use zsh_module::{Builtin, EndOfOptions};
+    ///
+    /// // Like `echo`, `say -n -x` prints `-x` without a newline
+    /// let say = Builtin::new("say")
+    ///     .flags("n")
+    ///     .end_of_options(EndOfOptions::FirstUnknown);
+    /// ```
+    pub fn end_of_options(mut self, value: EndOfOptions) -> Self {
+        self.end_of_options = value;
+        self
+    }
+    /// Sets the usage shown when the builtin is called with too few or too many arguments, given
+    /// the builtin's name.
+    ///
+    /// By default, a usage line generated from the builtin's flags and argument counts is shown,
+    /// e.g. `usage: greet [-v] name [name ...]`.
+    ///
+    /// # Example
+    /// ```
+    /// use zsh_module::Builtin;
+    ///
+    /// let greet = Builtin::new("greet")
+    ///     .minargs(1)
+    ///     .usage(|name| format!("usage: {} <name>...\nGreets everyone named.", name));
+    /// ```
+    pub fn usage(mut self, cb: impl 'static + Fn(&str) -> String) -> Self {
+        self.usage = Some(Box::new(cb));
+        self
+    }
+    /// Sets what happens when the builtin is called while another builtin of the module is running.
+    /// See [`Reentry`].
+    pub fn on_reentry(mut self, value: Reentry) -> Self {
+        self.reentry = value;
+        self
+    }
+    /// Sets how the arguments of the builtin are decoded before it gets them. See [`Decoding`].
+    ///
+    /// They're decoded lossily unless this says otherwise, so arguments that aren't valid UTF-8
+    /// don't make the builtin (or a hook, like [`ModuleBuilder::history_filter`]'s) fail.
+    pub fn decoding(mut self, value: Decoding) -> Self {
+        self.decoding = value;
+        self
+    }
+    /// Makes the builtin fail when it's called more than `calls` times within `period`, e.g. to
+    /// keep an expensive builtin run from a hook from piling up.
+    ///
+    /// # Example
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use zsh_module::Builtin;
+    ///
+    /// let sync = Builtin::new("sync-history").rate_limit(1, Duration::from_secs(5));
+    /// ```
+    pub fn rate_limit(mut self, calls: usize, period: Duration) -> Self {
+        self.rate_limit = Some((calls, period));
+        self
+    }
+    /// Sets what the builtin does, shown by the module's help builtin.
+    ///
+    /// See [`ModuleBuilder::with_help_builtin`].
+    pub fn description(mut self, value: &str) -> Self {
+        self.description = Some(value.to_string());
+        self
+    }
+    /// Adds an example of how to use the builtin, shown by the module's help builtin.
+    ///
+    /// See [`ModuleBuilder::with_help_builtin`].
+    pub fn example(mut self, value: &str) -> Self {
+        self.examples.push(value.to_string());
+        self
+    }
+    /// Marks the builtin as deprecated. The first time it's used in a shell, a warning with
+    /// `note` is printed (e.g. to point users to its replacement). It's also marked as deprecated
+    /// by the module's help builtin.
+    ///
+    /// # Example
+    /// ```
+    /// use zsh_module::Builtin;
+    ///
+    /// let old = Builtin::new("greet-all").deprecated("use `greet` instead");
+    /// ```
+    pub fn deprecated(mut self, note: &str) -> Self {
+        self.deprecated = Some(note.to_string());
+        self
+    }
+    /// Makes the builtin fail without printing its errors, only returning their
+    /// [status](ZError::exit_code), e.g. for predicates and probes, which fail often.
+    ///
+    /// # Example
+    /// ```
+    /// use zsh_module::Builtin;
+    ///
+    /// // Used like `if is-git-repo; then ...`
+    /// let probe = Builtin::new("is-git-repo").quiet_errors();
+    /// ```
+    pub fn quiet_errors(mut self) -> Self {
+        self.quiet_errors = true;
+        self
+    }
+    /// Leaves the builtin out when the module is loaded with `zmodload <module>`, so it's only
+    /// there when asked for, with `zmodload -F <module> +b:<name>`.
+    ///
+    /// Zsh hands the module the same enables for `zmodload <module>` and for a `zmodload -F`
+    /// naming every one of its features, so the latter leaves the builtin out too. Ask for it
+    /// once the module is loaded (`zmodload -F <module> +b:<name>` again) in that case.
+    ///
+    /// See [`CmdContext::set_feature_enabled`] to enable it from another builtin.
+    pub fn disabled_by_default(mut self) -> Self {
+        self.disabled_by_default = true;
+        self
+    }
 }
 
 type Bintable = HashMap<Box<CStr>, Box<dyn AnyCmd>>;
+type BootFn = Box<dyn FnMut(&mut State) -> MaybeError<AnyError>>;
+type CleanupFn = Box<dyn FnMut(&mut State)>;
 
 /// Allows you to build a [`Module`]
 pub struct ModuleBuilder<A> {
@@ -241,6 +867,22 @@ pub struct ModuleBuilder<A> {
     binaries: Vec<zsys::builtin>,
     bintable: Bintable,
     strings: Vec<Box<CStr>>,
+    help: Vec<BuiltinHelp>,
+    help_builtin: bool,
+    wrappers: Vec<Rc<dyn Wrapper>>,
+    function_wrappers: Vec<Rc<dyn FunctionWrapper>>,
+    hooks: Vec<ShellHook>,
+    exports: Vec<(&'static str, *const c_void)>,
+    decodings: HashMap<Box<CStr>, Decoding>,
+    mathfuncs: Vec<zsys::mathfunc>,
+    mathtable: HashMap<Box<CStr>, math::MathFn>,
+    conddefs: Vec<zsys::conddef>,
+    condtable: Vec<(Box<CStr>, cond::CondFn)>,
+    paramdefs: Vec<zsys::paramdef>,
+    paramtable: HashMap<Box<CStr>, paramdef::ParamFns>,
+    on_boot: Vec<BootFn>,
+    on_cleanup: Vec<CleanupFn>,
+    disabled: HashSet<String>,
 }
 
 impl<A> ModuleBuilder<A>
@@ -254,6 +896,22 @@ where
             binaries: vec![],
             bintable: HashMap::new(),
             strings: Vec::with_capacity(8),
+            help: Vec::new(),
+            help_builtin: false,
+            wrappers: Vec::new(),
+            function_wrappers: Vec::new(),
+            hooks: Vec::new(),
+            exports: Vec::new(),
+            decodings: HashMap::new(),
+            mathfuncs: Vec::new(),
+            mathtable: HashMap::new(),
+            conddefs: Vec::new(),
+            condtable: Vec::new(),
+            paramdefs: Vec::new(),
+            paramtable: HashMap::new(),
+            on_boot: Vec::new(),
+            on_cleanup: Vec::new(),
+            disabled: HashSet::new(),
         }
     }
     /// Registers a new builtin command
@@ -262,20 +920,228 @@ where
         E: Into<Box<dyn Error>>,
         C: Cmd<A, E>,
     {
+        self.add_handler(
+            move |state: &mut State, name: &str, args: &[&str], opts: Opts| {
+                cb(state.data::<A>(), name, args, opts)
+            },
+            builtin,
+        )
+    }
+    fn add_handler<E, C>(mut self, mut cb: C, builtin: Builtin) -> Self
+    where
+        E: Into<Box<dyn Error>>,
+        C: Cmd<State, E>,
+    {
+        let help = BuiltinHelp::new(&builtin);
+        let (minargs, maxargs) = (builtin.minargs, builtin.maxargs);
+        let usage: UsageFn = builtin.usage.unwrap_or_else(|| {
+            let line = format!("usage: {}", help.usage);
+            Box::new(move |_| line.clone())
+        });
+        self.help.push(help);
+        let mut deprecated = builtin.deprecated;
+        let (flags, end_of_options) = match builtin.end_of_options {
+            // Zsh doesn't parse options at all without an optstr
+            EndOfOptions::Verbatim => (None, EndOfOptions::Verbatim),
+            other => (builtin.flags, other),
+        };
+        let rate_limit = builtin.rate_limit;
+        let mut calls = VecDeque::new();
         let closure: Box<dyn AnyCmd> = Box::new(
-            move |data: &mut (dyn Any + 'static), name, args, opts| -> MaybeError<AnyError> {
-                cb(data.downcast_mut::<A>().unwrap(), name, args, opts).map_err(E::into)
+            move |state: &mut State, name, args: &[&str], opts| -> MaybeError<AnyError> {
+                // Only warn once per shell
+                if let Some(note) = deprecated.take() {
+                    log::warn_named(name, format!("deprecated: {}", note));
+                }
+                if let Some((max, period)) = rate_limit {
+                    let now = Instant::now();
+                    while calls.front().is_some_and(|&call| now - call >= period) {
+                        calls.pop_front();
+                    }
+                    if calls.len() >= max {
+                        return Err(format!(
+                            "called too often (at most {} times every {:?})",
+                            max, period
+                        )
+                        .into());
+                    }
+                    calls.push_back(now);
+                }
+                // The argument counts are checked here rather than by zsh, to show the usage
+                let problem = if (args.len() as i32) < minargs {
+                    "not enough arguments"
+                } else if maxargs >= 0 && args.len() as i32 > maxargs {
+                    "too many arguments"
+                } else {
+                    return cb(state, name, args, opts).map_err(E::into);
+                };
+                Err(ZError::Usage(format!("{}\n{}", problem, usage(name))).into())
             },
         );
-        self.add_builtin(
-            builtin.name,
-            builtin.minargs,
-            builtin.maxargs,
-            builtin.flags,
-            closure,
+        let closure: Box<dyn AnyCmd> = Box::new(named_errors(closure));
+        let closure: Box<dyn AnyCmd> = if builtin.quiet_errors {
+            Box::new(quiet(closure))
+        } else {
+            closure
+        };
+        let mut binf = end_of_options.binf();
+        if builtin.plus_flags {
+            binf |= zsys::BINF_PLUSOPTS as c_int;
+        }
+        if builtin.disabled_by_default {
+            let feature = format!("b:{}", builtin.name.to_string_lossy());
+            self.disabled.insert(feature);
+        }
+        let name = builtin.name.into_boxed_c_str();
+        self.decodings.insert(name.clone(), builtin.decoding);
+        self.add_builtin(name, binf, builtin.reentry, flags, closure)
+    }
+    /// Registers a new builtin command, whose handler is given a [`CmdContext`] instead of separate
+    /// arguments.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::io::Write;
+    ///
+    /// use zsh_module::{Builtin, CmdContext, MaybeError, ModuleBuilder};
+    ///
+    /// fn count(ctx: &mut CmdContext<u64>) -> MaybeError {
+    ///     *ctx.data_mut() += 1;
+    ///     writeln!(ctx.stdout(), "{} was called {} times", ctx.name(), ctx.data())?;
+    ///     Ok(())
+    /// }
+    ///
+    /// let module = ModuleBuilder::new(0u64)
+    ///     .builtin_with_context(count, Builtin::new("count"))
+    ///     .build();
+    /// ```
+    pub fn builtin_with_context<E, C>(self, mut cb: C, builtin: Builtin) -> Self
+    where
+        E: Into<Box<dyn Error>>,
+        C: 'static + FnMut(&mut CmdContext<A>) -> MaybeError<E>,
+    {
+        self.add_handler(
+            move |state: &mut State, name: &str, args: &[&str], opts: Opts| {
+                cb(&mut CmdContext::new(state, name, args, opts))
+            },
+            builtin,
         )
     }
-    fn hold_cstring(&mut self, value: impl Into<Vec<u8>>) -> *mut i8 {
+    /// Registers every builtin of the user data. See [`Builtins`].
+    ///
+    /// # Example
+    /// ```no_run
+    /// # #[cfg(feature = "derive")] {
+    /// use zsh_module::{builtin, builtins, MaybeError, ModuleBuilder, Opts};
+    ///
+    /// struct Greeter;
+    ///
+    /// #[builtins]
+    /// impl Greeter {
+    ///     #[builtin(name = "greet", minargs = 1, description = "Greets everyone named")]
+    ///     fn greet(&mut self, _: &str, args: &[&str], _: Opts) -> MaybeError {
+    ///         println!("Hello, {}!", args.join(" and "));
+    ///         Ok(())
+    ///     }
+    ///     #[builtin]
+    ///     fn wave(&mut self, _: &str, _: &[&str], _: Opts) -> MaybeError {
+    ///         println!("o/");
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// let module = ModuleBuilder::new(Greeter).builtins().build();
+    /// # }
+    /// ```
+    pub fn builtins(self) -> Self
+    where
+        A: Builtins,
+    {
+        A::register(self)
+    }
+    /// Registers a new builtin command, whose handler is given its options as [`ParsedOpts`].
+    /// Declare them with [`Builtin::opt`].
+    ///
+    /// # Example
+    /// ```no_run
+    /// use zsh_module::{validate::as_int, Builtin, MaybeError, ModuleBuilder, OptKind, ParsedOpts};
+    ///
+    /// fn repeat(_: &mut (), _: &str, args: &[&str], opts: ParsedOpts) -> MaybeError {
+    ///     let times: usize = opts.value('n', as_int())?.unwrap_or(1);
+    ///     let sep = if opts.flag('l') { "\n" } else { " " };
+    ///     println!("{}", vec![args.join(" "); times].join(sep));
+    ///     Ok(())
+    /// }
+    ///
+    /// let module = ModuleBuilder::new(())
+    ///     .builtin_parsed(
+    ///         repeat,
+    ///         Builtin::new("repeat")
+    ///             .opt('n', OptKind::TakesArg)
+    ///             .opt('l', OptKind::Flag),
+    ///     )
+    ///     .build();
+    /// ```
+    pub fn builtin_parsed<E, C>(self, mut cb: C, builtin: Builtin) -> Self
+    where
+        E: Into<Box<dyn Error>>,
+        C: 'static + FnMut(&mut A, &str, &[&str], ParsedOpts) -> MaybeError<E>,
+    {
+        self.builtin(
+            move |data: &mut A, name: &str, args: &[&str], opts: Opts| {
+                cb(data, name, args, ParsedOpts::new(opts))
+            },
+            builtin,
+        )
+    }
+    /// Registers a new builtin command, whose handler is given its arguments and options parsed as
+    /// `T`. The options and argument counts `T` needs are added to `builtin`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # #[cfg(feature = "derive")] {
+    /// use zsh_module::{Builtin, BuiltinArgs, MaybeZError, ModuleBuilder};
+    ///
+    /// // usage: greet [-l] [-t arg] arg [arg]
+    /// #[derive(BuiltinArgs)]
+    /// struct GreetArgs {
+    ///     #[arg(short = 'l')]
+    ///     loud: bool,
+    ///     #[arg(short = 't')]
+    ///     times: Option<u32>,
+    ///     name: String,
+    ///     greeting: Option<String>,
+    /// }
+    ///
+    /// fn greet(_: &mut (), args: GreetArgs) -> MaybeZError {
+    ///     let greeting = args.greeting.as_deref().unwrap_or("Hello");
+    ///     for _ in 0..args.times.unwrap_or(1) {
+    ///         let line = format!("{}, {}!", greeting, args.name);
+    ///         println!("{}", if args.loud { line.to_uppercase() } else { line });
+    ///     }
+    ///     Ok(())
+    /// }
+    ///
+    /// let module = ModuleBuilder::new(())
+    ///     .builtin_args(greet, Builtin::new("greet"))
+    ///     .build();
+    /// # }
+    /// ```
+    pub fn builtin_args<T, E, C>(self, mut cb: C, builtin: Builtin) -> Self
+    where
+        T: BuiltinArgs,
+        E: Into<Box<dyn Error>>,
+        C: 'static + FnMut(&mut A, T) -> MaybeError<E>,
+    {
+        self.builtin_parsed(
+            move |data: &mut A, _: &str, args: &[&str], opts: ParsedOpts| -> MaybeError {
+                let parsed = T::parse(args, &opts)?;
+                cb(data, parsed).map_err(E::into)
+            },
+            T::declare(builtin),
+        )
+    }
+    fn hold_cstring(&mut self, value: impl Into<Vec<u8>>) -> *mut c_char {
         let value = to_cstr(value).into_boxed_c_str();
         let ptr = value.as_ptr();
         self.strings.push(value);
@@ -283,13 +1149,12 @@ where
     }
     fn add_builtin(
         mut self,
-        name: CString,
-        minargs: i32,
-        maxargs: i32,
+        name: Box<CStr>,
+        binf: c_int,
+        reentry: Reentry,
         options: Option<CString>,
         cb: Box<dyn AnyCmd + 'static>,
     ) -> Self {
-        let name = name.into_boxed_c_str();
         let flags = match options {
             Some(flags) => self.hold_cstring(flags),
             None => std::ptr::null_mut(),
@@ -298,14 +1163,16 @@ where
             node: zsys::hashnode {
                 next: std::ptr::null_mut(),
                 nam: name.as_ptr() as *mut _,
-                // !TODO: add flags param
-                flags: 0,
+                flags: binf,
             },
             // The handler function will be set later by the zsh module glue
             handlerfunc: None,
-            minargs,
-            maxargs,
-            funcid: 0,
+            // Checked by the handler instead, see `ModuleBuilder::builtin`
+            minargs: 0,
+            maxargs: -1,
+            // Given back to the handler, which is the only way to know about it before locking the
+            // module
+            funcid: reentry as c_int,
             optstr: flags,
             defopts: std::ptr::null_mut(),
         };
@@ -313,6 +1180,565 @@ where
         self.bintable.insert(name, cb);
         self
     }
+    /// Adds a `<module>-help` builtin, which lists every builtin of the module with its usage,
+    /// description and examples. Given the name of a builtin, it only shows that one.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use zsh_module::{Builtin, MaybeError, ModuleBuilder, Opts};
+    ///
+    /// fn greet(_: &mut (), _: &str, args: &[&str], _: Opts) -> MaybeError {
+    ///     println!("Hello, {}!", args.first().unwrap_or(&"world"));
+    ///     Ok(())
+    /// }
+    ///
+    /// let module = ModuleBuilder::new(())
+    ///     .builtin(
+    ///         greet,
+    ///         Builtin::new("greet")
+    ///             .maxargs(Some(1))
+    ///             .description("Greets someone, or the whole world.")
+    ///             .example("greet zsh"),
+    ///     )
+    ///     .with_help_builtin()
+    ///     .build();
+    /// ```
+    /// If the module is loaded as `greeter`, running `greeter-help` prints:
+    /// ```text
+    /// Builtins provided by greeter:
+    ///
+    /// greet [arg]
+    ///     Greets someone, or the whole world.
+    ///
+    ///     Examples:
+    ///       greet zsh
+    /// ```
+    pub fn with_help_builtin(mut self) -> Self {
+        self.help_builtin = true;
+        self
+    }
+    /// Runs `wrapper` around every builtin of the module, e.g. to time or log them, check
+    /// permissions or translate errors. It's given the builtin's name, its arguments and a function
+    /// that runs the builtin, which it may skip.
+    ///
+    /// When called more than once, the first wrapper runs outermost.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::time::Instant;
+    ///
+    /// use zsh_module::{Builtin, MaybeError, ModuleBuilder, Opts};
+    ///
+    /// fn greet(_: &mut (), _: &str, _: &[&str], _: Opts) -> MaybeError {
+    ///     println!("Hello, world!");
+    ///     Ok(())
+    /// }
+    ///
+    /// let module = ModuleBuilder::new(())
+    ///     .builtin(greet, Builtin::new("greet"))
+    ///     .wrap_builtins(|name, _args, run| {
+    ///         let start = Instant::now();
+    ///         let result = run();
+    ///         eprintln!("{} took {:?}", name, start.elapsed());
+    ///         result
+    ///     })
+    ///     .build();
+    /// ```
+    pub fn wrap_builtins(mut self, wrapper: impl Wrapper) -> Self {
+        self.wrappers.push(Rc::new(wrapper));
+        self
+    }
+    /// Runs `wrapper` around every shell function called while the module is loaded, e.g. to
+    /// profile or audit them, like `zsh/zprof` does. It's given the function's name and a function
+    /// that runs it, which it must call for the shell function to run at all.
+    ///
+    /// This uses zsh's wrapper mechanism, which only covers shell functions: builtins and
+    /// external commands aren't wrapped. When called more than once, the first wrapper runs
+    /// outermost. Zsh refuses to unload the module while a wrapped function runs.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::time::Instant;
+    ///
+    /// use zsh_module::ModuleBuilder;
+    ///
+    /// let module = ModuleBuilder::new(())
+    ///     .wrap_functions(|name, run| {
+    ///         let start = Instant::now();
+    ///         let status = run();
+    ///         eprintln!("{} returned {} after {:?}", name, status, start.elapsed());
+    ///     })
+    ///     .build();
+    /// ```
+    pub fn wrap_functions(mut self, wrapper: impl FunctionWrapper) -> Self {
+        self.function_wrappers.push(Rc::new(wrapper));
+        self
+    }
+    /// Runs `filter` on every line before it's saved in the history, e.g. to drop lines
+    /// containing secrets. It's given the line without its trailing newline, and decides what
+    /// happens to it like a `zshaddhistory` function would.
+    ///
+    /// This adds a `<module>-zshaddhistory` builtin, and a function calling it to
+    /// `$zshaddhistory_functions` while the module is loaded.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use zsh_module::{HistoryAction, ModuleBuilder};
+    ///
+    /// let module = ModuleBuilder::new(())
+    ///     .history_filter(|_, line| {
+    ///         if line.contains("PASSWORD=") {
+    ///             HistoryAction::Skip
+    ///         } else {
+    ///             HistoryAction::Keep
+    ///         }
+    ///     })
+    ///     .build();
+    /// ```
+    pub fn history_filter<F>(mut self, mut filter: F) -> Self
+    where
+        F: 'static + FnMut(&mut A, &str) -> HistoryAction,
+    {
+        let cb: Box<dyn AnyCmd> = Box::new(
+            move |state: &mut State, _: &str, args: &[&str], _| -> MaybeError {
+                let line = args.first().copied().unwrap_or_default();
+                let line = line.strip_suffix('\n').unwrap_or(line);
+                match filter(state.data::<A>(), line) {
+                    HistoryAction::Keep => Ok(()),
+                    action => Err(Box::new(ZError::Status(action as i32))),
+                }
+            },
+        );
+        self.hooks.push(ShellHook {
+            hook: "zshaddhistory",
+            kind: HookKind::Function,
+            cb,
+        });
+        self
+    }
+    /// Runs `cb` on `hook`, with the arguments zsh passes to hook functions. Hooks added more than
+    /// once run in the order they were added.
+    ///
+    /// This adds a `<module>-<hook>` builtin, and a function calling it to `$<hook>_functions`
+    /// while the module is loaded.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use zsh_module::{Hook, ModuleBuilder};
+    ///
+    /// let module = ModuleBuilder::new(0usize)
+    ///     .hook(Hook::Preexec, |commands, _args| {
+    ///         *commands += 1;
+    ///         Ok(())
+    ///     })
+    ///     .hook(Hook::ZshExit, |commands, _args| {
+    ///         println!("{} commands run", commands);
+    ///         Ok(())
+    ///     })
+    ///     .build();
+    /// ```
+    pub fn hook<F>(mut self, hook: Hook, mut cb: F) -> Self
+    where
+        F: 'static + FnMut(&mut A, &[&str]) -> MaybeError,
+    {
+        let mut cb: Box<dyn AnyCmd> = Box::new(
+            move |state: &mut State, _: &str, args: &[&str], _| -> MaybeError {
+                cb(state.data::<A>(), args)
+            },
+        );
+        let hook = hook.name();
+        let existing = self
+            .hooks
+            .iter()
+            .position(|h| h.hook == hook && matches!(h.kind, HookKind::Function));
+        if let Some(i) = existing {
+            let mut first = self.hooks.remove(i).cb;
+            cb = Box::new(move |state: &mut State, name, args, opts: Opts| {
+                first(state, name, args, opts.clone())?;
+                cb(state, name, args, opts)
+            });
+        }
+        self.hooks.push(ShellHook {
+            hook,
+            kind: HookKind::Function,
+            cb,
+        });
+        self
+    }
+    /// Runs `cb` when the user accepts a line (by replacing the `accept-line` widget), before it's
+    /// run. It's given the edit buffer, which it may rewrite, e.g. to expand abbreviations or fix
+    /// typos, and decides whether the line is accepted.
+    ///
+    /// This adds a `<module>-accept-line` builtin, only meant to be called by the widget. Requires
+    /// the `zle` feature.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use zsh_module::{zle::AcceptAction, ModuleBuilder};
+    ///
+    /// let module = ModuleBuilder::new(())
+    ///     .accept_line(|_, buffer| {
+    ///         if let Some(rest) = buffer.strip_prefix("gti ") {
+    ///             *buffer = format!("git {}", rest);
+    ///         }
+    ///         AcceptAction::Accept
+    ///     })
+    ///     .build();
+    /// ```
+    #[cfg(feature = "zle")]
+    pub fn accept_line<F>(mut self, mut cb: F) -> Self
+    where
+        F: 'static + FnMut(&mut A, &mut String) -> zle::AcceptAction,
+    {
+        let cb: Box<dyn AnyCmd> = Box::new(
+            move |state: &mut State, _: &str, _: &[&str], _| -> MaybeError {
+                let data = state.data::<A>();
+                match zle::filter_buffer(|buffer| cb(data, buffer)) {
+                    zle::AcceptAction::Accept => Ok(()),
+                    zle::AcceptAction::KeepEditing => Err(Box::new(ZError::Status(1))),
+                }
+            },
+        );
+        self.hooks.push(ShellHook {
+            hook: "accept-line",
+            kind: HookKind::Widget,
+            cb,
+        });
+        self
+    }
+    /// Runs `handler` when a command isn't found, instead of `command_not_found_handler`. It's
+    /// given the command and its arguments, and either handles it, returning its exit status, or
+    /// declines with [`None`], in which case the previous `command_not_found_handler` runs, if
+    /// any. Otherwise, zsh reports that the command wasn't found.
+    ///
+    /// Zsh runs the handler in the subshell it forked to run the command, so the handler only
+    /// gets to read the user data: changes wouldn't be seen by the shell.
+    ///
+    /// This adds a `<module>-command_not_found_handler` builtin, only meant to be called by the
+    /// handler.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use zsh_module::ModuleBuilder;
+    ///
+    /// let module = ModuleBuilder::new(())
+    ///     .command_not_found(|_, cmd, _args| {
+    ///         if cmd == "sl" {
+    ///             println!("Did you mean `ls`?");
+    ///             return Some(1);
+    ///         }
+    ///         None
+    ///     })
+    ///     .build();
+    /// ```
+    pub fn command_not_found<F>(mut self, handler: F) -> Self
+    where
+        F: 'static + Fn(&A, &str, &[&str]) -> Option<i32>,
+    {
+        let cb: Box<dyn AnyCmd> = Box::new(
+            move |state: &mut State, _: &str, args: &[&str], _| -> MaybeError {
+                let Some((cmd, args)) = args.split_first() else {
+                    return Err(Box::new(ZError::Status(127)));
+                };
+                // Zsh reports the command as not found when its handler returns 127
+                match handler(&*state.data::<A>(), cmd, args).unwrap_or(127) {
+                    0 => Ok(()),
+                    status => Err(Box::new(ZError::Status(status))),
+                }
+            },
+        );
+        self.hooks.push(ShellHook {
+            hook: "command_not_found_handler",
+            kind: HookKind::Handler,
+            cb,
+        });
+        self
+    }
+    /// Runs `cb` when the shell receives `signal`, e.g. to react to the terminal being resized, or
+    /// to cancel background work on `^C`. Traps the user set, before the module was loaded, still
+    /// run afterwards. Signals trapped more than once run their callbacks in the order they were
+    /// added.
+    ///
+    /// This adds a `<module>-TRAP<signal>` builtin, and a `TRAP<signal>` function calling it while
+    /// the module is loaded. Like other traps, it runs between commands.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use zsh_module::{ModuleBuilder, Signal};
+    ///
+    /// let module = ModuleBuilder::new(0usize)
+    ///     .trap(Signal::Winch, |resizes| *resizes += 1)
+    ///     .build();
+    /// ```
+    pub fn trap<F>(mut self, signal: Signal, mut cb: F) -> Self
+    where
+        F: 'static + FnMut(&mut A),
+    {
+        let mut cb: Box<dyn AnyCmd> = Box::new(
+            move |state: &mut State, _: &str, _: &[&str], _| -> MaybeError {
+                cb(state.data::<A>());
+                Ok(())
+            },
+        );
+        let existing = self
+            .hooks
+            .iter()
+            .position(|h| matches!(h.kind, HookKind::Trap(s) if s == signal));
+        if let Some(i) = existing {
+            let mut first = self.hooks.remove(i).cb;
+            cb = Box::new(move |state: &mut State, name, args, opts: Opts| {
+                first(state, name, args, opts.clone())?;
+                cb(state, name, args, opts)
+            });
+        }
+        self.hooks.push(ShellHook {
+            hook: signal.trap_name(),
+            kind: HookKind::Trap(signal),
+            cb,
+        });
+        self
+    }
+    /// Reloads the module when its library is rebuilt, before showing the next prompt, to try
+    /// changes without restarting the shell. It can also be reloaded with `<module>-reload`.
+    /// Requires the `hot_reload` feature, which is only meant for development.
+    ///
+    /// Reloading unloads the module completely, so its user data is lost. The new library is only
+    /// loaded if the old one could be unloaded, which glibc refuses for libraries that registered
+    /// thread-local destructors.
+    ///
+    /// This adds a `<module>-hot-reload` builtin, only meant to be called by the hook.
+    #[cfg(feature = "hot_reload")]
+    pub fn hot_reload(mut self) -> Self {
+        let mut watcher = reload::Watcher::new(reload::library_path());
+        let cb: Box<dyn AnyCmd> =
+            Box::new(move |_: &mut State, _: &str, _: &[&str], _| -> MaybeError {
+                if watcher.changed() {
+                    Ok(())
+                } else {
+                    Err(Box::new(ZError::Status(1)))
+                }
+            });
+        self.hooks.push(ShellHook {
+            hook: "hot-reload",
+            kind: HookKind::Reload,
+            cb,
+        });
+        self
+    }
+    /// Highlights the edit buffer with `highlighter` whenever it changes, adding its spans to
+    /// `$region_highlight` alongside those of other plugins.
+    ///
+    /// This adds a `<module>-zle-line-pre-redraw` builtin, only meant to be called by the hook
+    /// widget. Requires the `zle` feature.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use zsh_module::{zle::Span, ModuleBuilder};
+    ///
+    /// // Underlines the command
+    /// let module = ModuleBuilder::new(())
+    ///     .highlighter(|buffer: &str| {
+    ///         let end = buffer.find(' ').unwrap_or(buffer.len());
+    ///         vec![Span::new(0..end, "underline")]
+    ///     })
+    ///     .build();
+    /// ```
+    #[cfg(feature = "zle")]
+    pub fn highlighter(mut self, highlighter: impl zle::Highlighter + 'static) -> Self {
+        let mut highlighting = zle::Highlighting::new(highlighter);
+        let cb: Box<dyn AnyCmd> = Box::new(
+            move |_: &mut State, name: &str, _: &[&str], _| -> MaybeError {
+                highlighting.refresh(name);
+                Ok(())
+            },
+        );
+        self.hooks.push(ShellHook {
+            hook: "zle-line-pre-redraw",
+            kind: HookKind::ZleHook,
+            cb,
+        });
+        self
+    }
+    /// Registers a function callable from arithmetic, like `$(( clamp(x, 0, 10) ))`. It's given
+    /// its arguments already evaluated, and may return an integer or a float.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use zsh_module::{math::MathFunc, ModuleBuilder};
+    ///
+    /// let module = ModuleBuilder::new(())
+    ///     .mathfunc(
+    ///         |_, args| {
+    ///             let (x, lo, hi) = (args[0].as_f64(), args[1].as_f64(), args[2].as_f64());
+    ///             if lo > hi {
+    ///                 return Err("empty range");
+    ///             }
+    ///             Ok(x.clamp(lo, hi))
+    ///         },
+    ///         MathFunc::new("clamp").minargs(3).maxargs(Some(3)),
+    ///     )
+    ///     .build();
+    /// ```
+    pub fn mathfunc<E, R, F>(mut self, mut cb: F, func: math::MathFunc) -> Self
+    where
+        E: Into<AnyError>,
+        R: Into<math::Number>,
+        F: 'static + FnMut(&mut A, &[math::Number]) -> Result<R, E>,
+    {
+        let name = func.name.into_boxed_c_str();
+        self.mathfuncs.push(zsys::mathfunc {
+            next: std::ptr::null_mut(),
+            name: name.as_ptr() as *mut _,
+            flags: 0,
+            // The handler function will be set later by the zsh module glue
+            nfunc: None,
+            sfunc: None,
+            module: std::ptr::null_mut(),
+            // Unlike for builtins, zsh checks the argument counts itself
+            minargs: func.minargs,
+            maxargs: func.maxargs,
+            funcid: 0,
+        });
+        let cb: math::MathFn = Box::new(move |state: &mut State, args: &[math::Number]| {
+            cb(state.data::<A>(), args)
+                .map(Into::into)
+                .map_err(Into::into)
+        });
+        self.mathtable.insert(name, cb);
+        self
+    }
+    /// Registers a condition usable in `[[ ... ]]`. It's given its arguments, expanded, and tells
+    /// whether the condition holds.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use zsh_module::{cond::Condition, ModuleBuilder};
+    ///
+    /// // [[ -ascii $x ]] and [[ $a -prefix-of $b ]]
+    /// let module = ModuleBuilder::new(())
+    ///     .condition(
+    ///         |_, args| args.iter().all(|arg| arg.is_ascii()),
+    ///         Condition::prefix("ascii").minargs(1),
+    ///     )
+    ///     .condition(
+    ///         |_, args| args[1].starts_with(args[0]),
+    ///         Condition::infix("prefix-of"),
+    ///     )
+    ///     .build();
+    /// ```
+    pub fn condition<F>(mut self, mut cb: F, cond: cond::Condition) -> Self
+    where
+        F: 'static + FnMut(&mut A, &[&str]) -> bool,
+    {
+        let name = cond.name.into_boxed_c_str();
+        self.conddefs.push(zsys::conddef {
+            next: std::ptr::null_mut(),
+            name: name.as_ptr() as *mut _,
+            flags: if cond.infix {
+                zsys::CONDF_INFIX as c_int
+            } else {
+                0
+            },
+            // The handler function will be set later by the zsh module glue
+            handler: None,
+            min: cond.minargs,
+            max: cond.maxargs,
+            // The handler isn't given the name, so it finds the condition by its index instead
+            condid: self.condtable.len() as c_int,
+            module: std::ptr::null_mut(),
+        });
+        let cb: cond::CondFn =
+            Box::new(move |state: &mut State, args: &[&str]| cb(state.data::<A>(), args));
+        self.condtable.push((name, cb));
+        self
+    }
+    /// Adds a special parameter, whose value is computed by the module whenever it's read, e.g. to
+    /// expose part of the user data. See [`paramdef::SpecialParam`].
+    ///
+    /// # Example
+    /// ```no_run
+    /// use zsh_module::{paramdef::SpecialParam, ModuleBuilder};
+    ///
+    /// struct Counter {
+    ///     count: u64,
+    /// }
+    ///
+    /// let module = ModuleBuilder::new(Counter { count: 0 })
+    ///     .param(
+    ///         SpecialParam::new("COUNTER", |counter: &mut Counter| {
+    ///             counter.count += 1;
+    ///             counter.count.to_string()
+    ///         })
+    ///         .set(|counter, value| {
+    ///             counter.count = value.parse()?;
+    ///             Ok::<(), std::num::ParseIntError>(())
+    ///         }),
+    ///     )
+    ///     .build();
+    /// ```
+    pub fn param(mut self, param: paramdef::SpecialParam<A>) -> Self {
+        let name = param.name.into_boxed_c_str();
+        let mut flags = zsys::PM_SCALAR | zsys::PM_SPECIAL | zsys::PM_HIDE;
+        if param.fns.set.is_none() {
+            flags |= zsys::PM_READONLY;
+        }
+        self.paramdefs.push(zsys::paramdef {
+            name: name.as_ptr() as *mut _,
+            flags: flags as c_int,
+            var: std::ptr::null_mut(),
+            // The get/set/unset functions will be set later by the zsh module glue
+            gsu: std::ptr::null(),
+            getnfn: None,
+            scantfn: None,
+            pm: std::ptr::null_mut(),
+        });
+        self.paramtable.insert(name, param.fns);
+        self
+    }
+    /// Publishes `func` as `name` for other modules to call. See [`interop`].
+    ///
+    /// `func` should be an `extern "C" fn`, so that modules written in any language can call it.
+    pub fn export_fn(mut self, name: &'static str, func: *const c_void) -> Self {
+        self.exports.push((name, func));
+        self
+    }
+    /// Runs `cb` when the module is loaded, once its builtins are available, e.g. to set
+    /// parameters up or start a background task. If it fails, the module isn't loaded.
+    ///
+    /// The hooks are only installed once every callback succeeded, so they don't fire yet.
+    ///
+    /// When called more than once, the callbacks run in the order they were added, until one
+    /// fails.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use zsh_module::{zsh, ModuleBuilder};
+    ///
+    /// let module = ModuleBuilder::new(())
+    ///     .on_boot(|_| zsh::eval_simple("typeset -g MYMOD_LOADED=1"))
+    ///     .on_cleanup(|_| {
+    ///         let _ = zsh::unset("MYMOD_LOADED");
+    ///     })
+    ///     .build();
+    /// ```
+    pub fn on_boot<E>(mut self, mut cb: impl 'static + FnMut(&mut A) -> MaybeError<E>) -> Self
+    where
+        E: Into<AnyError>,
+    {
+        self.on_boot.push(Box::new(move |state: &mut State| {
+            cb(state.data::<A>()).map_err(E::into)
+        }));
+        self
+    }
+    /// Runs `cb` when the module is unloaded (e.g. by `zmodload -u`), while its builtins are still
+    /// available, to undo what [`Self::on_boot`] did.
+    ///
+    /// When called more than once, the callbacks run in the reverse order they were added.
+    pub fn on_cleanup(mut self, mut cb: impl 'static + FnMut(&mut A)) -> Self {
+        self.on_cleanup
+            .push(Box::new(move |state: &mut State| cb(state.data::<A>())));
+        self
+    }
     /// Creates a new module, ready to be used.
     pub fn build(self) -> Module {
         Module::new(self)
@@ -321,27 +1747,911 @@ where
 
 /// Hooks into the Zsh module system and connects it to your `User Data`.
 pub struct Module {
-    user_data: Box<dyn Any>,
+    state: State,
     features: Features,
     bintable: Bintable,
     #[allow(dead_code)]
     strings: Vec<Box<CStr>>,
     name: Option<&'static str>,
+    help: Vec<BuiltinHelp>,
+    help_builtin: bool,
+    wrappers: Vec<Rc<dyn Wrapper>>,
+    #[cfg_attr(not(feature = "export_module"), allow(dead_code))]
+    function_wrappers: Vec<Rc<dyn FunctionWrapper>>,
+    /// Given to zsh while the module is loaded, if it wraps functions.
+    #[cfg_attr(not(feature = "export_module"), allow(dead_code))]
+    funcwrap: Option<Box<zsys::funcwrap>>,
+    hooks: Vec<ShellHook>,
+    #[cfg_attr(not(feature = "export_module"), allow(dead_code))]
+    exports: Option<interop::Exports>,
+    /// How the arguments of each builtin are decoded. Builtins missing from it use the default.
+    #[cfg_attr(not(feature = "export_module"), allow(dead_code))]
+    decodings: HashMap<Box<CStr>, Decoding>,
+    #[cfg_attr(not(feature = "export_module"), allow(dead_code))]
+    mathtable: HashMap<Box<CStr>, math::MathFn>,
+    /// The conditions, by their `condid`.
+    #[cfg_attr(not(feature = "export_module"), allow(dead_code))]
+    condtable: Vec<(Box<CStr>, cond::CondFn)>,
+    #[cfg_attr(not(feature = "export_module"), allow(dead_code))]
+    paramtable: HashMap<Box<CStr>, paramdef::ParamFns>,
+    #[cfg_attr(not(feature = "export_module"), allow(dead_code))]
+    on_boot: Vec<BootFn>,
+    #[cfg_attr(not(feature = "export_module"), allow(dead_code))]
+    on_cleanup: Vec<CleanupFn>,
+    #[cfg_attr(not(feature = "export_module"), allow(dead_code))]
+    forbid_unload: bool,
+    /// The features left out when every feature is enabled, which happens on load.
+    #[cfg_attr(not(feature = "export_module"), allow(dead_code))]
+    disabled: HashSet<String>,
+    /// The module as zsh knows it, once its features were first enabled.
+    #[cfg_attr(not(feature = "export_module"), allow(dead_code))]
+    raw: zsys::Module,
 }
 
 impl Module {
+    /// Adds `value` to the module, for builtins to get with [`CmdContext::get`]. This lets
+    /// parts of a module keep their own state, instead of sharing a single user data type.
+    ///
+    /// There's at most one value of each type, so this returns the one `value` replaced, if any.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::collections::HashMap;
+    ///
+    /// use zsh_module::{Builtin, CmdContext, MaybeError, ModuleBuilder};
+    ///
+    /// #[derive(Default)]
+    /// struct Cache(HashMap<String, String>);
+    ///
+    /// fn lookup(ctx: &mut CmdContext<()>) -> MaybeError {
+    ///     let key = ctx.args()[0];
+    ///     let cache = ctx.get_mut::<Cache>().unwrap();
+    ///     let value = cache.0.entry(key.into()).or_insert_with(|| key.to_uppercase());
+    ///     println!("{}", value);
+    ///     Ok(())
+    /// }
+    ///
+    /// let mut module = ModuleBuilder::new(())
+    ///     .builtin_with_context(lookup, Builtin::new("lookup").minargs(1))
+    ///     .build();
+    /// module.insert(Cache::default());
+    /// ```
+    pub fn insert<T: Any>(&mut self, value: T) -> Option<T> {
+        self.state.extensions.insert(value)
+    }
+    /// Makes `zmodload -u` fail for this module, which then stays loaded until the shell exits,
+    /// e.g. for modules that started threads or handed pointers to zsh that can't be taken back.
+    ///
+    /// Otherwise, unloading the module removes its hooks, runs the callbacks of
+    /// [`ModuleBuilder::on_cleanup`] and drops the user data, even after a builtin panicked.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use zsh_module::ModuleBuilder;
+    ///
+    /// let mut module = ModuleBuilder::new(()).build();
+    /// module.forbid_unload();
+    /// ```
+    pub fn forbid_unload(&mut self) {
+        self.forbid_unload = true;
+    }
+    /// The features of the module, like `zmodload -lF <module>` lists them, e.g. to document
+    /// them. Until the module is loaded, they're enabled unless they're disabled by default.
+    ///
+    /// See [`CmdContext::features`] for builtins.
+    pub fn features(&self) -> Vec<Feature> {
+        if !self.raw.is_null() {
+            return self.state.features.clone();
+        }
+        self.features
+            .names()
+            .iter()
+            .filter_map(|name| Feature::parse(name, !self.disabled.contains(name)))
+            .collect()
+    }
+    /// Sets whether the feature `feature` (e.g. `b:name` for a builtin, or `p:NAME` for a
+    /// parameter) is enabled when the module is loaded with `zmodload <module>`. Every feature is
+    /// by default.
+    ///
+    /// Fails if the module has no such feature.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use zsh_module::{paramdef::SpecialParam, ModuleBuilder};
+    ///
+    /// let mut module = ModuleBuilder::new(())
+    ///     .param(SpecialParam::new("MYMOD_DEBUG", |_| "internal state".into()))
+    ///     .build();
+    /// module.set_feature_enabled("p:MYMOD_DEBUG", false)?;
+    /// # Ok::<(), zsh_module::ZError>(())
+    /// ```
+    pub fn set_feature_enabled(&mut self, feature: &str, enabled: bool) -> ZResult<()> {
+        if !self.features.names().iter().any(|name| name == feature) {
+            return Err(ZError::InvalidArgument {
+                name: feature.to_string(),
+                msg: "no such feature".to_string(),
+            });
+        }
+        if enabled {
+            self.disabled.remove(feature);
+        } else {
+            self.disabled.insert(feature.to_string());
+        }
+        Ok(())
+    }
+    /// The value of type `T` added with [`Self::insert`], if any.
+    pub fn get<T: Any>(&self) -> Option<&T> {
+        self.state.extensions.get()
+    }
+    pub fn get_mut<T: Any>(&mut self) -> Option<&mut T> {
+        self.state.extensions.get_mut()
+    }
+    /// Runs `cb` whenever `fd` becomes readable, with the user data (which must be an `A`), until
+    /// it returns `false` or the fd is closed. Watching an fd again replaces its callback.
+    /// Requires the `watch` feature.
+    ///
+    /// Zsh only watches fds at the prompt, see [`watch`] for the details. The fd must stay open
+    /// while it's watched: zle stops watching fds that were closed, but they may be reused.
+    ///
+    /// See [`CmdContext::watch_fd`] for builtins.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::{io::Read, os::unix::net::UnixStream, os::fd::AsRawFd};
+    ///
+    /// use zsh_module::ModuleBuilder;
+    ///
+    /// let mut daemon = UnixStream::connect("/run/user/1000/mydaemon.sock").unwrap();
+    /// let fd = daemon.as_raw_fd();
+    /// let mut module = ModuleBuilder::new(Vec::<u8>::new()).build();
+    /// module.watch_fd(fd, move |received: &mut Vec<u8>, _, _| {
+    ///     let mut buf = [0; 1024];
+    ///     match daemon.read(&mut buf) {
+    ///         Ok(n) if n > 0 => {
+    ///             received.extend_from_slice(&buf[..n]);
+    ///             true
+    ///         }
+    ///         _ => false,
+    ///     }
+    /// });
+    /// ```
+    #[cfg(feature = "watch")]
+    pub fn watch_fd<A, F>(&mut self, fd: std::os::fd::RawFd, cb: F)
+    where
+        A: Any,
+        F: 'static + FnMut(&mut A, std::os::fd::RawFd, watch::FdEvent) -> bool,
+    {
+        self.state.watch_fd(fd, cb)
+    }
+    /// A queue other threads can send closures through, to run them on the shell's thread with
+    /// the user data (which must be an `A`). Requires the `watch` feature.
+    ///
+    /// See [`main_thread`] for an example, and [`CmdContext::main_thread_queue`] for builtins.
+    #[cfg(feature = "watch")]
+    pub fn main_thread_queue<A: Any>(&self) -> ZResult<main_thread::MainThreadQueue<A>> {
+        self.state.main_thread_queue()
+    }
+    /// Runs `fut` on a background `tokio` runtime, e.g. to do network I/O without blocking the
+    /// prompt, and `then` with its output and the user data (which must be an `A`) back on the
+    /// shell's thread. Requires the `async` feature.
+    ///
+    /// `then` runs while the line editor waits for input, as a widget, so it may use
+    /// [`zle`](crate::zle) (e.g. to redraw the prompt). Tasks finishing while a command runs are
+    /// handled once the next prompt shows up, and never in scripts, which don't use the line
+    /// editor. Unloading the module cancels the tasks that didn't finish yet.
+    ///
+    /// See [`CmdContext::spawn`] for builtins.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use zsh_module::ModuleBuilder;
+    ///
+    /// let mut module = ModuleBuilder::new(String::new()).build();
+    /// module.spawn(
+    ///     async { std::fs::read_to_string("/etc/hostname").unwrap_or_default() },
+    ///     |hostname: &mut String, name| *hostname = name,
+    /// )?;
+    /// # Ok::<(), zsh_module::ZError>(())
+    /// ```
+    #[cfg(feature = "async")]
+    pub fn spawn<A, F, C>(&mut self, fut: F, then: C) -> ZResult<()>
+    where
+        A: Any,
+        F: std::future::Future + Send + 'static,
+        F::Output: Send,
+        C: 'static + FnOnce(&mut A, F::Output),
+    {
+        self.state.spawn(fut, then)
+    }
     fn new<A: Any + 'static>(desc: ModuleBuilder<A>) -> Self {
-        let features = Features::empty().binaries(desc.binaries.into());
+        let features = Features::empty()
+            .binaries(desc.binaries.into())
+            .mathfuncs(desc.mathfuncs.into())
+            .conddefs(desc.conddefs.into())
+            .paramdefs(desc.paramdefs.into());
+        let bintable = desc
+            .bintable
+            .into_iter()
+            .map(|(name, cmd)| (name, wrap_cmd(&desc.wrappers, cmd)))
+            .collect();
+        #[allow(unused_mut)]
+        let (mut state, mut hooks) = (State::new(Box::new(desc.user_data)), desc.hooks);
+        #[cfg(feature = "watch")]
+        hooks.push(ShellHook {
+            hook: "fd",
+            kind: HookKind::FdWatch,
+            cb: Box::new(|state: &mut State, _: &str, args: &[&str], _| {
+                watch::dispatch(state, args)
+            }),
+        });
+        #[cfg(feature = "watch")]
+        {
+            state.queue = main_thread::Queue::new().ok();
+            if let Some(fd) = state.queue.as_ref().map(main_thread::Queue::fd) {
+                state.watch(
+                    fd,
+                    Box::new(|state, _, _| {
+                        main_thread::run_queued(state);
+                        true
+                    }),
+                );
+            }
+            // Zle only watches fds while it waits for input, and builtins run the queue before
+            // anything else, so a `precmd` hook covers the prompts that follow other commands
+            let precmd = Hook::Precmd.name();
+            let hooked = hooks
+                .iter()
+                .any(|h| h.hook == precmd && matches!(h.kind, HookKind::Function));
+            if !hooked {
+                hooks.push(ShellHook {
+                    hook: precmd,
+                    kind: HookKind::Function,
+                    cb: Box::new(|_: &mut State, _: &str, _: &[&str], _| Ok(())),
+                });
+            }
+        }
         Self {
-            user_data: Box::new(desc.user_data),
+            state,
             features,
-            bintable: desc.bintable,
+            bintable,
             strings: desc.strings,
             name: None,
+            help: desc.help,
+            help_builtin: desc.help_builtin,
+            wrappers: desc.wrappers,
+            function_wrappers: desc.function_wrappers,
+            funcwrap: None,
+            hooks,
+            exports: (!desc.exports.is_empty()).then(|| interop::Exports::new(&desc.exports)),
+            decodings: desc.decodings,
+            mathtable: desc.mathtable,
+            condtable: desc.condtable,
+            paramtable: desc.paramtable,
+            on_boot: desc.on_boot,
+            on_cleanup: desc.on_cleanup,
+            forbid_unload: false,
+            disabled: desc.disabled,
+            raw: std::ptr::null_mut(),
+        }
+    }
+    /// Registers the `<module>-help` builtin, if it was asked for. This can only be done once the
+    /// module's name is known.
+    #[cfg_attr(not(feature = "export_module"), allow(dead_code))]
+    fn add_help_builtin(&mut self, module_name: &'static str) {
+        if !self.help_builtin {
+            return;
+        }
+        let name = to_cstr(format!("{}-help", module_name)).into_boxed_c_str();
+        let help = std::mem::take(&mut self.help);
+        let cb: Box<dyn AnyCmd> = Box::new(
+            move |_: &mut State, _: &str, args: &[&str], _| -> MaybeError {
+                let text = help::render(module_name, &help, args.first().copied());
+                if text.is_empty() {
+                    return Err(format!("no such builtin: {}", args[0]).into());
+                }
+                crate::zprint!("{}", text);
+                Ok(())
+            },
+        );
+        self.features.push_binary(zsys::builtin {
+            node: zsys::hashnode {
+                next: std::ptr::null_mut(),
+                nam: name.as_ptr() as *mut _,
+                flags: 0,
+            },
+            handlerfunc: None,
+            minargs: 0,
+            maxargs: 1,
+            funcid: 0,
+            optstr: std::ptr::null_mut(),
+            defopts: std::ptr::null_mut(),
+        });
+        self.bintable.insert(name, wrap_cmd(&self.wrappers, cb));
+    }
+    /// Registers the builtins behind the module's hooks. This can only be done once the module's
+    /// name is known.
+    #[cfg_attr(not(feature = "export_module"), allow(dead_code))]
+    fn add_hook_builtins(&mut self, module_name: &str) {
+        for hook in &mut self.hooks {
+            let name = to_cstr(format!("{}-{}", module_name, hook.hook)).into_boxed_c_str();
+            self.features.push_binary(zsys::builtin {
+                node: zsys::hashnode {
+                    next: std::ptr::null_mut(),
+                    nam: name.as_ptr() as *mut _,
+                    flags: 0,
+                },
+                handlerfunc: None,
+                minargs: 0,
+                maxargs: -1,
+                funcid: Reentry::Refuse as c_int,
+                optstr: std::ptr::null_mut(),
+                defopts: std::ptr::null_mut(),
+            });
+            let cb = std::mem::replace(&mut hook.cb, Box::new(|_, _, _, _| Ok(())));
+            self.bintable.insert(name, wrap_cmd(&self.wrappers, cb));
+        }
+    }
+    /// The script adding the module's hook functions, which call its hook builtins.
+    #[cfg_attr(not(feature = "export_module"), allow(dead_code))]
+    fn hooks_install_script(&self, module_name: &str) -> String {
+        self.hooks
+            .iter()
+            .map(|hook| hook.install_script(module_name))
+            .collect()
+    }
+    /// The script undoing [`Self::hooks_install_script`].
+    #[cfg_attr(not(feature = "export_module"), allow(dead_code))]
+    fn hooks_uninstall_script(&self, module_name: &str) -> String {
+        #[allow(unused_mut)]
+        let mut script = String::new();
+        #[cfg(feature = "watch")]
+        {
+            let unwatch: Vec<_> = self.state.watchers.keys().map(|&fd| (fd, false)).collect();
+            script += &watch::script(module_name, &unwatch);
         }
+        self.hooks.iter().fold(script, |script, hook| {
+            script + &hook.uninstall_script(module_name)
+        })
     }
 }
 
 #[cfg(feature = "export_module")]
 #[doc(hidden)]
 pub mod export_module;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{self, Message, MockOpts};
+
+    fn noop(_: &mut (), _: &str, _: &[&str], _: Opts) -> MaybeError {
+        Ok(())
+    }
+
+    fn call(module: &mut Module, name: &str) {
+        call_with(module, name, &[]);
+    }
+
+    fn call_with(module: &mut Module, name: &str, args: &[&str]) {
+        try_call(module, name, args).unwrap();
+    }
+
+    fn try_call(module: &mut Module, name: &str, args: &[&str]) -> MaybeError {
+        let cmd = module.bintable.get_mut(to_cstr(name).as_c_str()).unwrap();
+        cmd(&mut module.state, name, args, MockOpts::new().build())
+    }
+
+    /// The status zsh would get from the builtin.
+    fn status(module: &mut Module, name: &str, args: &[&str]) -> i32 {
+        match try_call(module, name, args) {
+            Ok(()) => 0,
+            Err(e) => e.downcast_ref::<ZError>().map_or(1, ZError::exit_code),
+        }
+    }
+
+    fn record(data: &mut Vec<String>, _: &str, args: &[&str], _: Opts) -> MaybeError {
+        data.extend(args.iter().map(|arg| arg.to_string()));
+        Ok(())
+    }
+
+    fn recorded(module: &mut Module) -> Vec<String> {
+        std::mem::take(module.state.data::<Vec<String>>())
+    }
+
+    #[test]
+    fn history_filter_statuses() {
+        let mut module = ModuleBuilder::new(Vec::<String>::new())
+            .history_filter(|seen, line| {
+                seen.push(line.to_string());
+                match line {
+                    "secret" => HistoryAction::Skip,
+                    "local" => HistoryAction::Stop,
+                    _ => HistoryAction::Keep,
+                }
+            })
+            .build();
+        module.add_hook_builtins("mymod");
+        let hook = "mymod-zshaddhistory";
+        assert_eq!(status(&mut module, hook, &["ls\n"]), 0);
+        assert_eq!(status(&mut module, hook, &["secret\n"]), 1);
+        assert_eq!(status(&mut module, hook, &["local\n"]), 2);
+        assert_eq!(recorded(&mut module), ["ls", "secret", "local"]);
+        assert!(module
+            .hooks_install_script("mymod")
+            .contains("zshaddhistory_functions+=(_mymod_zshaddhistory)"));
+    }
+
+    #[cfg(feature = "watch")]
+    #[test]
+    fn queue_runs_before_prompts() {
+        let script = ModuleBuilder::new(()).build().hooks_install_script("mymod");
+        assert!(script.contains("precmd_functions+=(_mymod_precmd)"));
+        // The user's own precmd hook is enough
+        let script = ModuleBuilder::new(Vec::<String>::new())
+            .hook(Hook::Precmd, |_, _| Ok(()))
+            .build()
+            .hooks_install_script("mymod");
+        assert_eq!(script.matches("precmd_functions+=").count(), 1);
+    }
+
+    #[test]
+    fn hooks_run_in_order() {
+        let mut module = ModuleBuilder::new(Vec::<String>::new())
+            .hook(Hook::Preexec, |seen, args| {
+                seen.push(format!("first {}", args[0]));
+                Ok(())
+            })
+            .hook(Hook::Chpwd, |seen, _| {
+                seen.push("chpwd".into());
+                Ok(())
+            })
+            .hook(Hook::Preexec, |seen, args| {
+                seen.push(format!("second {}", args[0]));
+                Ok(())
+            })
+            .build();
+        module.add_hook_builtins("mymod");
+        call_with(&mut module, "mymod-preexec", &["ls"]);
+        call(&mut module, "mymod-chpwd");
+        assert_eq!(recorded(&mut module), ["first ls", "second ls", "chpwd"]);
+        let script = module.hooks_install_script("mymod");
+        assert_eq!(
+            script
+                .matches("preexec_functions+=(_mymod_preexec)")
+                .count(),
+            1
+        );
+        assert!(script.contains("chpwd_functions+=(_mymod_chpwd)"));
+    }
+
+    #[test]
+    fn traps_chain() {
+        let mut module = ModuleBuilder::new(Vec::<String>::new())
+            .trap(Signal::Int, |seen| seen.push("first".into()))
+            .trap(Signal::Winch, |seen| seen.push("winch".into()))
+            .trap(Signal::Int, |seen| seen.push("second".into()))
+            .build();
+        module.add_hook_builtins("mymod");
+        call_with(&mut module, "mymod-TRAPINT", &["2"]);
+        call_with(&mut module, "mymod-TRAPWINCH", &["28"]);
+        assert_eq!(recorded(&mut module), ["first", "second", "winch"]);
+        let script = module.hooks_install_script("mymod");
+        assert_eq!(script.matches("\nTRAPINT() {").count(), 1);
+        assert!(script.contains(":#* INT}"));
+        assert!(script.contains("return $(( 128 + $1 ))"));
+        assert!(script.contains("_mymod_TRAPWINCH \"$@\"; return; fi\nreturn 0\n"));
+    }
+
+    #[test]
+    #[cfg(feature = "zle")]
+    fn accept_line_chains() {
+        let module = ModuleBuilder::new(())
+            .accept_line(|_, _| zle::AcceptAction::Accept)
+            .build();
+        let install = module.hooks_install_script("mymod");
+        assert!(install.starts_with("zle -A accept-line _mymod_orig_accept-line\n"));
+        assert!(install.contains("&& zle _mymod_orig_accept-line -- \"$@\""));
+        let uninstall = module.hooks_uninstall_script("mymod");
+        assert!(uninstall.contains("zle -A _mymod_orig_accept-line accept-line\n"));
+    }
+
+    #[test]
+    fn mathfunc_results() {
+        let mut module = ModuleBuilder::new(0)
+            .mathfunc(
+                |calls: &mut i32, args| {
+                    *calls += 1;
+                    match args {
+                        [math::Number::Integer(i)] => Ok(math::Number::from(i * 2)),
+                        [x] => Ok((x.as_f64() * 2.0).into()),
+                        _ => Err("expected one argument"),
+                    }
+                },
+                math::MathFunc::new("double"),
+            )
+            .build();
+        let double = module.mathtable.get_mut(c"double").unwrap();
+        let state = &mut module.state;
+        assert_eq!(
+            double(state, &[3.into()]).unwrap(),
+            math::Number::Integer(6)
+        );
+        assert_eq!(
+            double(state, &[1.5.into()]).unwrap(),
+            math::Number::Float(3.0)
+        );
+        assert!(double(state, &[]).is_err());
+        assert_eq!(*module.state.data::<i32>(), 3);
+    }
+
+    #[test]
+    fn conditions_are_found_by_condid() {
+        let mut module = ModuleBuilder::new(())
+            .condition(|_, args| args.len() == 1, cond::Condition::prefix("one"))
+            .condition(
+                |_, args| args[0] == args[1],
+                cond::Condition::infix("same").minargs(0),
+            )
+            .build();
+        let defs = module.features.get_conddefs();
+        assert_eq!((defs[0].flags, defs[0].condid), (0, 0));
+        assert_eq!(
+            (defs[1].flags, defs[1].min, defs[1].condid),
+            (zsys::CONDF_INFIX as c_int, 2, 1)
+        );
+        let (name, same) = &mut module.condtable[1];
+        assert_eq!(name.as_ref(), c"same");
+        assert!(same(&mut module.state, &["a", "a"]));
+        assert!(!same(&mut module.state, &["a", "b"]));
+    }
+
+    #[test]
+    fn special_params_without_setter_are_readonly() {
+        let mut module = ModuleBuilder::new(3)
+            .param(paramdef::SpecialParam::new("GET", |n: &mut i32| {
+                n.to_string()
+            }))
+            .param(
+                paramdef::SpecialParam::new("SET", |n: &mut i32| n.to_string()).set(|n, value| {
+                    *n = value.parse()?;
+                    Ok::<(), std::num::ParseIntError>(())
+                }),
+            )
+            .build();
+        let defs = module.features.get_paramdefs();
+        assert_ne!(defs[0].flags as u32 & zsys::PM_READONLY, 0);
+        assert_eq!(defs[1].flags as u32 & zsys::PM_READONLY, 0);
+        let set = module.paramtable.get_mut(c"SET").unwrap();
+        set.set.as_mut().unwrap()(&mut module.state, "5").unwrap();
+        assert!(set.set.as_mut().unwrap()(&mut module.state, "five").is_err());
+        let get = module.paramtable.get_mut(c"GET").unwrap();
+        assert_eq!((get.get)(&mut module.state), "5");
+    }
+
+    #[test]
+    fn command_not_found_declines_with_127() {
+        let mut module = ModuleBuilder::new(())
+            .command_not_found(|_, cmd, args| (cmd == "sl").then_some(args.len() as i32))
+            .build();
+        module.add_hook_builtins("mymod");
+        let hook = "mymod-command_not_found_handler";
+        assert_eq!(status(&mut module, hook, &["sl"]), 0);
+        assert_eq!(status(&mut module, hook, &["sl", "-a", "-l"]), 2);
+        assert_eq!(status(&mut module, hook, &["gti", "status"]), 127);
+    }
+
+    #[test]
+    fn context_builtins() {
+        let mut module = ModuleBuilder::new(Vec::<String>::new())
+            .builtin_with_context(
+                |ctx: &mut CmdContext<Vec<String>>| -> MaybeError {
+                    let name = ctx.name().to_string();
+                    let args = ctx.args();
+                    ctx.data_mut().push(name);
+                    ctx.data_mut()
+                        .extend(args.iter().map(|arg| arg.to_string()));
+                    assert!(!ctx.interrupted());
+                    Ok(())
+                },
+                Builtin::new("ctx"),
+            )
+            .build();
+        call_with(&mut module, "ctx", &["a", "b"]);
+        assert_eq!(recorded(&mut module), ["ctx", "a", "b"]);
+    }
+
+    #[test]
+    fn extensions() {
+        struct Counter(u32);
+        let mut module = ModuleBuilder::new(())
+            .builtin_with_context(
+                |ctx: &mut CmdContext<()>| -> MaybeError {
+                    ctx.get_mut::<Counter>().ok_or("no counter")?.0 += 1;
+                    Ok(())
+                },
+                Builtin::new("count"),
+            )
+            .build();
+        assert!(module.insert(Counter(0)).is_none());
+        call(&mut module, "count");
+        call(&mut module, "count");
+        assert_eq!(module.get::<Counter>().unwrap().0, 2);
+        assert_eq!(module.insert(Counter(5)).unwrap().0, 2);
+        assert!(module.get::<String>().is_none());
+    }
+
+    #[test]
+    fn deprecated_builtins_warn_once() {
+        let mut module = ModuleBuilder::new(())
+            .builtin(noop, Builtin::new("old").deprecated("use `new` instead"))
+            .builtin(noop, Builtin::new("new"))
+            .build();
+        call(&mut module, "old");
+        call(&mut module, "old");
+        call(&mut module, "new");
+        assert_eq!(
+            mock::take_messages(),
+            vec![Message::Warning {
+                cmd: Some("old".into()),
+                msg: "deprecated: use `new` instead".into()
+            }]
+        );
+    }
+
+    #[test]
+    fn dashdash_is_left_to_zsh() {
+        let mut module = ModuleBuilder::new(Vec::<String>::new())
+            .builtin(record, Builtin::new("plain"))
+            .build();
+        // What zsh passes on is what the builtin gets
+        call_with(&mut module, "plain", &["--", "-x"]);
+        assert_eq!(recorded(&mut module), ["--", "-x"]);
+    }
+
+    #[test]
+    fn end_of_options_flags() {
+        let module = ModuleBuilder::new(())
+            .builtin(noop, Builtin::new("a").flags("x"))
+            .builtin(
+                noop,
+                Builtin::new("b")
+                    .flags("x")
+                    .end_of_options(EndOfOptions::FirstUnknown),
+            )
+            .builtin(
+                noop,
+                Builtin::new("c")
+                    .flags("x")
+                    .end_of_options(EndOfOptions::Verbatim),
+            );
+        let binaries = &module.binaries;
+        assert_eq!(binaries[0].node.flags, 0);
+        assert!(!binaries[0].optstr.is_null());
+        assert_eq!(
+            binaries[1].node.flags as u32,
+            zsys::BINF_SKIPINVALID | zsys::BINF_DASHDASHVALID
+        );
+        assert!(binaries[2].optstr.is_null());
+        assert_eq!(binaries[2].node.flags as u32, zsys::BINF_HANDLES_OPTS);
+    }
+
+    #[test]
+    fn parsed_builtins() {
+        let mut module = ModuleBuilder::new(Vec::<String>::new()).builtin_parsed(
+            |data: &mut Vec<String>, _: &str, _: &[&str], opts: ParsedOpts| -> MaybeError {
+                let count: u32 = opts.value('n', validate::as_int())?.unwrap_or(1);
+                data.push(format!("{} {}", opts.flag('v'), count));
+                Ok(())
+            },
+            Builtin::new("p")
+                .flags("q")
+                .opt('v', OptKind::Flag)
+                .opt('n', OptKind::TakesArg),
+        );
+        let optstr = unsafe { CStr::from_ptr(module.binaries[0].optstr) };
+        assert_eq!(optstr, c"qvn:");
+        assert_eq!(module.help[0].usage, "p [-qv] [-n arg] [arg ...]");
+        let cmd = module.bintable.get_mut(c"p").unwrap();
+        let mut state = State::new(Box::new(Vec::<String>::new()));
+        let opts = MockOpts::new().minus('v').arg('n', "3").build();
+        cmd(&mut state, "p", &[], opts).unwrap();
+        let opts = MockOpts::new().arg('n', "x").build();
+        assert!(cmd(&mut state, "p", &[], opts).is_err());
+        assert_eq!(*state.data::<Vec<String>>(), ["true 3"]);
+    }
+
+    #[test]
+    fn quiet_errors() {
+        let fail = |_: &mut (), _: &str, args: &[&str], _: Opts| -> MaybeZError {
+            match args {
+                [] => Err(ZError::Usage("usage: probe arg".into())),
+                _ => Err(ZError::Other("not found".into())),
+            }
+        };
+        let mut module = ModuleBuilder::new(())
+            .builtin(fail, Builtin::new("probe").quiet_errors())
+            .build();
+        for (args, expected) in [(&[][..], 2), (&["x"][..], 1)] {
+            let e = try_call(&mut module, "probe", args).unwrap_err();
+            match e.downcast_ref::<ZError>() {
+                Some(ZError::Status(status)) => assert_eq!(*status, expected),
+                _ => panic!("{} would be printed", e),
+            }
+        }
+    }
+
+    #[test]
+    fn features_disabled_by_default() {
+        let noop = |_: &mut (), _: &str, _: &[&str], _: Opts| -> MaybeError { Ok(()) };
+        let mut module = ModuleBuilder::new(())
+            .builtin(noop, Builtin::new("on"))
+            .builtin(noop, Builtin::new("off").disabled_by_default())
+            .build();
+        let mut names = module.features.names();
+        names.sort();
+        assert_eq!(names, ["b:off", "b:on"]);
+        assert!(module.disabled.contains("b:off"));
+        module.set_feature_enabled("b:off", true).unwrap();
+        module.set_feature_enabled("b:on", false).unwrap();
+        assert_eq!(module.disabled, HashSet::from(["b:on".to_string()]));
+        assert!(module.set_feature_enabled("b:missing", false).is_err());
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn derived_builtin_args() {
+        #[derive(BuiltinArgs, Debug, PartialEq)]
+        struct Copy {
+            #[arg(short = 'v')]
+            verbose: bool,
+            #[arg(short = 'm')]
+            mode: Option<u32>,
+            from: String,
+            to: Option<String>,
+            rest: Vec<i64>,
+        }
+        let builtin = Copy::declare(Builtin::new("cp"));
+        assert_eq!(
+            help::BuiltinHelp::new(&builtin).usage,
+            "cp [-v] [-m arg] arg [arg ...]"
+        );
+        let opts = ParsedOpts::new(MockOpts::new().arg('m', "644").build());
+        assert_eq!(
+            Copy::parse(&["a", "b", "1", "2"], &opts).unwrap(),
+            Copy {
+                verbose: false,
+                mode: Some(644),
+                from: "a".into(),
+                to: Some("b".into()),
+                rest: vec![1, 2],
+            }
+        );
+        let err = Copy::parse(&["a", "b", "x"], &opts).unwrap_err();
+        assert_eq!(err.to_string(), "rest: invalid digit found in string");
+        assert!(Copy::parse(&[], &opts).is_err());
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn builtins_attribute() {
+        #[derive(Default)]
+        struct Counter(Vec<String>);
+        #[builtins]
+        impl Counter {
+            #[builtin(name = "count-add", minargs = 1, maxargs = 2, flags = "v")]
+            fn add(&mut self, _: &str, args: &[&str], _: Opts) -> MaybeError {
+                self.0.extend(args.iter().map(|arg| arg.to_string()));
+                Ok(())
+            }
+            #[builtin]
+            fn reset(&mut self, _: &str, _: &[&str], _: Opts) -> MaybeError {
+                self.0.clear();
+                Ok(())
+            }
+        }
+        let mut module = ModuleBuilder::new(Counter::default()).builtins().build();
+        let usages: Vec<_> = module.help.iter().map(|help| help.usage.as_str()).collect();
+        assert_eq!(usages, ["count-add [-v] arg [arg]", "reset [arg ...]"]);
+        call_with(&mut module, "count-add", &["a"]);
+        assert_eq!(module.state.data::<Counter>().0, ["a"]);
+        call(&mut module, "reset");
+        assert!(module.state.data::<Counter>().0.is_empty());
+    }
+
+    #[test]
+    fn arg_counts_show_usage() {
+        let mut module = ModuleBuilder::new(())
+            .builtin(
+                noop,
+                Builtin::new("one").flags("v").minargs(1).maxargs(Some(1)),
+            )
+            .builtin(
+                noop,
+                Builtin::new("custom")
+                    .maxargs(Some(0))
+                    .usage(|name| format!("{} takes nothing", name)),
+            );
+        let mut run = |name: &str, args: &[&str]| {
+            let cmd = module.bintable.get_mut(to_cstr(name).as_c_str()).unwrap();
+            cmd(
+                &mut State::new(Box::new(())),
+                name,
+                args,
+                MockOpts::new().build(),
+            )
+            .map_err(|e| e.to_string())
+        };
+        assert_eq!(
+            run("one", &[]),
+            Err("one: not enough arguments\nusage: one [-v] arg".into())
+        );
+        assert_eq!(run("one", &["a"]), Ok(()));
+        assert_eq!(
+            run("one", &["a", "b"]),
+            Err("one: too many arguments\nusage: one [-v] arg".into())
+        );
+        assert_eq!(
+            run("custom", &["a"]),
+            Err("custom: too many arguments\ncustom takes nothing".into())
+        );
+    }
+
+    #[test]
+    fn wrappers_run_around_builtins() {
+        let log = Rc::new(std::cell::RefCell::new(Vec::new()));
+        let (outer, inner) = (log.clone(), log.clone());
+        let mut module = ModuleBuilder::new(Vec::<String>::new())
+            .builtin(record, Builtin::new("run"))
+            .builtin(
+                |_: &mut Vec<String>, _: &str, _: &[&str], _: Opts| -> MaybeError {
+                    Err("raw".into())
+                },
+                Builtin::new("fail"),
+            )
+            .wrap_builtins(move |name, args, run| {
+                outer
+                    .borrow_mut()
+                    .push(format!("outer {} {:?}", name, args));
+                run().map_err(|e| format!("translated {}", e).into())
+            })
+            .wrap_builtins(move |name, _, run| {
+                inner.borrow_mut().push(format!("inner {}", name));
+                if name == "run" {
+                    run()
+                } else {
+                    Err("denied".into())
+                }
+            })
+            .build();
+        call_with(&mut module, "run", &["a"]);
+        assert_eq!(recorded(&mut module), ["a"]);
+        let result = try_call(&mut module, "fail", &[]);
+        assert_eq!(result.unwrap_err().to_string(), "translated denied");
+        assert_eq!(
+            *log.borrow(),
+            [
+                "outer run [\"a\"]",
+                "inner run",
+                "outer fail []",
+                "inner fail"
+            ]
+        );
+    }
+
+    #[test]
+    fn rate_limits() {
+        let mut module = ModuleBuilder::new(())
+            .builtin(
+                noop,
+                Builtin::new("limited").rate_limit(2, Duration::from_secs(3600)),
+            )
+            .build();
+        let mut run = || try_call(&mut module, "limited", &[]).map_err(|e| e.to_string());
+        assert!(run().is_ok());
+        assert!(run().is_ok());
+        assert_eq!(
+            run(),
+            Err("called too often (at most 2 times every 3600s)".into())
+        );
+    }
+}
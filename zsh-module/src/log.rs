@@ -1,38 +1,154 @@
 //! Zsh native log functions. This module contains high level interfaces to the zsh log functions.
+//!
+//! Besides warnings and errors, which are always printed, messages can be logged at a [`Level`]
+//! with [`info!`](crate::info!), [`debug!`](crate::debug!) and [`trace!`](crate::trace!), which
+//! are only printed when the user asks for them, e.g. with a parameter. See [`level_param`].
+//!
+//! With the `log` feature, messages of libraries using the `log` crate can be printed too. See
+//! `init_log_crate`.
 
 use std::{
-    borrow::Cow,
-    ffi::{c_char, CStr, CString},
+    ffi::CString,
+    fmt,
+    str::FromStr,
+    sync::atomic::{AtomicU8, Ordering},
 };
 
-use zsh_sys as zsys;
+use parking_lot::Mutex;
 
-use crate::ToCString;
+use crate::{
+    backend::{Backend, Current},
+    to_cstr, ToCString,
+};
+
+/// How important a message is, from the most to the least.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Level {
+    Error = 1,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    const ALL: [Self; 5] = [
+        Self::Error,
+        Self::Warn,
+        Self::Info,
+        Self::Debug,
+        Self::Trace,
+    ];
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Error => "error",
+            Self::Warn => "warn",
+            Self::Info => "info",
+            Self::Debug => "debug",
+            Self::Trace => "trace",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Parses a level name like `debug`, in any case, or its number, from 1 for `error` to 5 for
+/// `trace`.
+impl FromStr for Level {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|level| {
+                s.eq_ignore_ascii_case(&level.to_string()) || s == (*level as u8).to_string()
+            })
+            .ok_or_else(|| format!("invalid log level: {}", s))
+    }
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Warn as u8);
+static LEVEL_PARAM: Mutex<Option<CString>> = parking_lot::const_mutex(None);
+
+/// Sets the least important messages printed. By default, only warnings and errors are.
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Lets the user pick the least important messages printed with the parameter `name`, e.g.
+/// `MYMOD_DEBUG=debug`. It takes the name of a level or its number (see [`Level::from_str`]),
+/// and overrides [`set_level`] while it's set to one.
+///
+/// # Example
+/// ```no_run
+/// zsh_module::log::level_param("MYMOD_DEBUG");
+/// // Only printed when `MYMOD_DEBUG` is `debug` or `trace`
+/// zsh_module::debug!("loaded {} entries", 42);
+/// ```
+pub fn level_param(name: &str) {
+    *LEVEL_PARAM.lock() = Some(to_cstr(name));
+}
+
+/// The least important messages printed.
+pub fn level() -> Level {
+    let from_param = LEVEL_PARAM.lock().as_deref().and_then(|param| {
+        let value = Current::get_str_param(param)?;
+        String::from_utf8(value).ok()?.parse().ok()
+    });
+    from_param.unwrap_or_else(|| Level::ALL[LEVEL.load(Ordering::Relaxed) as usize - 1])
+}
+
+/// Whether messages at `level` are printed.
+pub fn enabled(level: Level) -> bool {
+    level <= self::level()
+}
+
+/// Prints `msg` if messages at `level` are. Errors and warnings are printed like [`error`] and
+/// [`warn`], and others as warnings starting with their level, like `debug: msg`. See
+/// [`crate::debug!`].
+pub fn log(level: Level, msg: impl fmt::Display) {
+    if !enabled(level) {
+        return;
+    }
+    match level {
+        Level::Error => error(msg.to_string()),
+        Level::Warn => warn(msg.to_string()),
+        _ => warn(format!("{}: {}", level, msg)),
+    }
+}
+
+// Messages are only converted (and formatted, for the macros) once it's known zsh will print them
 
 /// Prints out a warning message from the command `cmd`. See [`crate::warn_named!`]
 pub fn warn_named(cmd: impl ToCString, msg: impl ToCString) {
-    let cmd_c = cmd.into_cstr();
-    let msg_c = msg.into_cstr();
-    unsafe { zsys::zwarnnam(cmd_c.as_ptr(), msg_c.as_ptr()) }
+    if !Current::messages_suppressed() {
+        Current::warn_named(&cmd.into_cstr(), &msg.into_cstr())
+    }
 }
 
 /// Prints out a warning message. See [`crate::warn!`]
 pub fn warn(msg: impl ToCString) {
-    let msg_c = msg.into_cstr();
-    unsafe { zsys::zwarn(msg_c.as_ptr()) }
+    if !Current::messages_suppressed() {
+        Current::warn(&msg.into_cstr())
+    }
 }
 
 /// Prints out an error message. See [`crate::error!`]
 pub fn error(msg: impl ToCString) {
-    let msg_c = msg.into_cstr();
-    unsafe { zsys::zerr(msg_c.as_ptr()) }
+    if Current::messages_suppressed() {
+        // Still flags the error, which zsh does even when it doesn't print it
+        Current::error(c"")
+    } else {
+        Current::error(&msg.into_cstr())
+    }
 }
 
 /// Prints out an error message from the command `cmd`. See [`crate::error_named!`]
 pub fn error_named(cmd: impl ToCString, msg: impl ToCString) {
-    let cmd = cmd.into_cstr();
-    let msg = msg.into_cstr();
-    unsafe { zsys::zerrnam(cmd.as_ptr(), msg.as_ptr()) }
+    if !Current::messages_suppressed() {
+        Current::error_named(&cmd.into_cstr(), &msg.into_cstr())
+    }
 }
 
 #[macro_export]
@@ -49,7 +165,7 @@ pub fn error_named(cmd: impl ToCString, msg: impl ToCString) {
 /// ```
 macro_rules! warn_named {
     ($cmd:expr, $msg:expr $(,$val:expr)*) => {
-       $crate::log::warn_named($cmd, format!($msg, $($val),*))
+       $crate::log::warn_named($cmd, format_args!($msg, $($val),*))
     };
 }
 
@@ -69,7 +185,7 @@ macro_rules! warn_named {
 /// ```
 macro_rules! error_named {
     ($cmd:expr, $msg:expr $(,$val:expr)*) => {
-       $crate::log::error_named($cmd, format!($msg, $($val),*))
+       $crate::log::error_named($cmd, format_args!($msg, $($val),*))
     };
 }
 
@@ -85,7 +201,7 @@ macro_rules! error_named {
 #[macro_export]
 macro_rules! warn {
     ($msg:expr $(,$val:expr)*) => {
-       $crate::log::warn(format!($msg, $($val),*))
+       $crate::log::warn(format_args!($msg, $($val),*))
     };
 }
 
@@ -102,6 +218,157 @@ macro_rules! warn {
 #[macro_export]
 macro_rules! error {
     ($msg:expr $(,$val:expr)*) => {
-       $crate::log::error(format!($msg, $($val),*))
+       $crate::log::error(format_args!($msg, $($val),*))
+    };
+}
+
+/// Logs a message at [`Level::Info`], like [`println!`]. See [`log::log`](crate::log::log).
+#[macro_export]
+macro_rules! info {
+    ($msg:expr $(,$val:expr)*) => {
+       $crate::log::log($crate::log::Level::Info, format_args!($msg, $($val),*))
     };
 }
+
+/// Logs a message at [`Level::Debug`], like [`println!`]. See [`log::log`](crate::log::log).
+///
+/// # Example
+/// ```no_run
+/// let path = "/tmp/cache";
+/// zsh_module::debug!("reading the cache from {}", path);
+/// ```
+#[macro_export]
+macro_rules! debug {
+    ($msg:expr $(,$val:expr)*) => {
+       $crate::log::log($crate::log::Level::Debug, format_args!($msg, $($val),*))
+    };
+}
+
+/// Logs a message at [`Level::Trace`], like [`println!`]. See [`log::log`](crate::log::log).
+#[macro_export]
+macro_rules! trace {
+    ($msg:expr $(,$val:expr)*) => {
+       $crate::log::log($crate::log::Level::Trace, format_args!($msg, $($val),*))
+    };
+}
+
+#[cfg(feature = "log")]
+mod facade {
+    use std::{sync::OnceLock, thread::ThreadId};
+
+    use super::Level;
+
+    /// The thread of the shell, the only one that may print.
+    static SHELL_THREAD: OnceLock<ThreadId> = OnceLock::new();
+
+    struct Logger;
+
+    impl From<log::Level> for Level {
+        fn from(level: log::Level) -> Self {
+            match level {
+                log::Level::Error => Self::Error,
+                log::Level::Warn => Self::Warn,
+                log::Level::Info => Self::Info,
+                log::Level::Debug => Self::Debug,
+                log::Level::Trace => Self::Trace,
+            }
+        }
+    }
+
+    impl log::Log for Logger {
+        fn enabled(&self, metadata: &log::Metadata) -> bool {
+            SHELL_THREAD.get() == Some(&std::thread::current().id())
+                && super::enabled(metadata.level().into())
+        }
+        fn log(&self, record: &log::Record) {
+            if self.enabled(record.metadata()) {
+                super::log(record.level().into(), record.args());
+            }
+        }
+        fn flush(&self) {}
+    }
+
+    /// Prints the messages logged with the `log` crate (e.g. by libraries) like [`super::log`]
+    /// does. Requires the `log` feature.
+    ///
+    /// It must be called from the shell's thread, e.g. in the module's setup: messages logged
+    /// from other threads are dropped, since zsh can't print them.
+    pub fn init_log_crate() -> Result<(), log::SetLoggerError> {
+        SHELL_THREAD.get_or_init(|| std::thread::current().id());
+        log::set_logger(&Logger)?;
+        // Which messages are printed is decided for each message
+        log::set_max_level(log::LevelFilter::Trace);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "log")]
+pub use facade::init_log_crate;
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, fmt};
+
+    use super::*;
+    use crate::mock::{self, Message};
+
+    #[test]
+    fn messages_are_formatted() {
+        crate::warn!("{} apples", 3);
+        crate::error_named!("eat", "no more {}", "apples");
+        assert_eq!(
+            mock::take_messages(),
+            vec![
+                Message::Warning {
+                    cmd: None,
+                    msg: "3 apples".into()
+                },
+                Message::Error {
+                    cmd: Some("eat".into()),
+                    msg: "no more apples".into()
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn levels() {
+        assert_eq!("DEBUG".parse(), Ok(Level::Debug));
+        assert_eq!("5".parse(), Ok(Level::Trace));
+        assert!("verbose".parse::<Level>().is_err());
+        mock::take_messages();
+        crate::debug!("hidden");
+        level_param("_zsh_module_test_log_level");
+        mock::set_param("_zsh_module_test_log_level", "debug");
+        crate::debug!("{} entries", 2);
+        crate::trace!("hidden");
+        *LEVEL_PARAM.lock() = None;
+        assert_eq!(
+            mock::take_messages(),
+            vec![Message::Warning {
+                cmd: None,
+                msg: "debug: 2 entries".into()
+            }]
+        );
+    }
+
+    #[test]
+    fn suppressed_messages_are_not_formatted() {
+        struct Counted<'a>(&'a Cell<usize>);
+        impl fmt::Display for Counted<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.set(self.0.get() + 1);
+                write!(f, "counted")
+            }
+        }
+        let formatted = Cell::new(0);
+        mock::suppress_messages(true);
+        crate::warn!("{}", Counted(&formatted));
+        crate::error_named!("cmd", "{}", Counted(&formatted));
+        assert_eq!(formatted.get(), 0);
+        mock::suppress_messages(false);
+        crate::warn!("{}", Counted(&formatted));
+        assert_eq!(formatted.get(), 1);
+        assert_eq!(mock::take_messages().len(), 1);
+    }
+}
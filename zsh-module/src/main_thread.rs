@@ -0,0 +1,182 @@
+//! Running code on the shell's thread from other threads. Requires the `watch` feature.
+//!
+//! Zsh isn't thread safe: its parameters, options, `eval` and everything else in
+//! [`zsh`](crate::zsh) may only be used from the thread running the shell. Types tied to the
+//! shell, like [`Param`](crate::zsh::Param) or [`CmdContext`](crate::CmdContext), aren't
+//! [`Send`], so they can't be handed to other threads:
+//! ```compile_fail
+//! let mut path = zsh_module::zsh::Param::find("PATH").unwrap();
+//! std::thread::spawn(move || path.set("/bin"));
+//! ```
+//! The functions of [`zsh`](crate::zsh) and [`zle`](crate::zle) check which thread they're
+//! called from, and panic when it isn't the shell's.
+//!
+//! Instead, background threads send closures through a [`MainThreadQueue`], which the module
+//! runs on the shell's thread: while the line editor waits for input, like the callbacks of
+//! [`Module::watch_fd`](crate::Module::watch_fd), before each prompt, and before any of the
+//! module's builtins runs.
+//!
+//! # Example
+//! ```no_run
+//! use zsh_module::{zsh::ParamBuilder, ModuleBuilder};
+//!
+//! let module = ModuleBuilder::new(()).build();
+//! let queue = module.main_thread_queue::<()>()?;
+//! std::thread::spawn(move || {
+//!     let weather = "sunny".to_string();
+//!     queue.push(move |_| {
+//!         let _ = ParamBuilder::new("WEATHER").scalar(&weather).build();
+//!     });
+//! });
+//! # Ok::<(), zsh_module::ZError>(())
+//! ```
+use std::{
+    any::Any,
+    io,
+    marker::PhantomData,
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+        unix::net::UnixStream,
+    },
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+};
+
+use crate::extensions::State;
+
+/// A closure to run on the shell's thread.
+pub(crate) type Job = Box<dyn FnOnce(&mut State) + Send>;
+
+/// Sends closures to run on the shell's thread, given the module's user data (an `A`). See
+/// [`Module::main_thread_queue`](crate::Module::main_thread_queue).
+///
+/// It can be cloned and sent to any thread. Closures pushed once the module is unloaded are
+/// dropped.
+pub struct MainThreadQueue<A> {
+    sender: Sender<Job>,
+    /// Written to after every closure, to wake the shell up.
+    wake: Arc<OwnedFd>,
+    _data: PhantomData<fn(&mut A)>,
+}
+
+impl<A> Clone for MainThreadQueue<A> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+            wake: self.wake.clone(),
+            _data: PhantomData,
+        }
+    }
+}
+
+impl<A: Any> MainThreadQueue<A> {
+    /// Runs `f` on the shell's thread. Returns `false` if the module was unloaded, in which case
+    /// it never runs.
+    pub fn push(&self, f: impl FnOnce(&mut A) + Send + 'static) -> bool {
+        self.push_job(Box::new(move |state| f(state.data())))
+    }
+    pub(crate) fn push_job(&self, job: Job) -> bool {
+        if self.sender.send(job).is_err() {
+            return false;
+        }
+        // A socket rather than a pipe, so writing after the module is gone fails instead of
+        // killing the shell with `SIGPIPE`
+        unsafe {
+            libc::send(
+                self.wake.as_raw_fd(),
+                b"x".as_ptr().cast(),
+                1,
+                libc::MSG_NOSIGNAL,
+            )
+        };
+        true
+    }
+}
+
+/// The receiving end of the queues of a module.
+pub(crate) struct Queue {
+    sender: Sender<Job>,
+    receiver: Receiver<Job>,
+    read: OwnedFd,
+    wake: Arc<OwnedFd>,
+}
+
+impl Queue {
+    pub(crate) fn new() -> io::Result<Self> {
+        let (read, wake) = UnixStream::pair()?;
+        let (sender, receiver) = mpsc::channel();
+        Ok(Self {
+            sender,
+            receiver,
+            read: move_up(read.into())?,
+            wake: Arc::new(move_up(wake.into())?),
+        })
+    }
+    /// The fd the shell watches.
+    pub(crate) fn fd(&self) -> RawFd {
+        self.read.as_raw_fd()
+    }
+    pub(crate) fn handle<A>(&self) -> MainThreadQueue<A> {
+        MainThreadQueue {
+            sender: self.sender.clone(),
+            wake: self.wake.clone(),
+            _data: PhantomData,
+        }
+    }
+    /// Takes the closures pushed so far.
+    fn take(&mut self) -> Vec<Job> {
+        let mut buf = [0u8; 64];
+        while unsafe { libc::read(self.fd(), buf.as_mut_ptr().cast(), buf.len()) } > 0 {}
+        self.receiver.try_iter().collect()
+    }
+}
+
+/// Runs the closures pushed so far. They're taken out first, since they're given the state the
+/// queue is in.
+pub(crate) fn run_queued(state: &mut State) {
+    let Some(queue) = &mut state.queue else {
+        return;
+    };
+    for job in queue.take() {
+        job(state);
+    }
+}
+
+/// Moves `fd` above 10, like zsh does for the fds it opens itself, so it doesn't get in the way
+/// of the user's redirections. It doesn't leak into commands, and doesn't block.
+fn move_up(fd: OwnedFd) -> io::Result<OwnedFd> {
+    let new = unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_DUPFD_CLOEXEC, 10) };
+    if new == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    unsafe { libc::fcntl(new, libc::F_SETFL, libc::O_NONBLOCK) };
+    Ok(unsafe { OwnedFd::from_raw_fd(new) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn closures_run_on_the_shell_thread() {
+        let mut state = State::new(Box::new(Vec::<i32>::new()));
+        let queue = Queue::new().unwrap();
+        assert!(queue.fd() >= 10);
+        let handle = queue.handle::<Vec<i32>>();
+        state.queue = Some(queue);
+        std::thread::spawn(move || {
+            for n in [1, 2] {
+                assert!(handle.push(move |seen| seen.push(n)));
+            }
+        })
+        .join()
+        .unwrap();
+        run_queued(&mut state);
+        assert_eq!(*state.data::<Vec<i32>>(), [1, 2]);
+
+        let handle = state.queue.take().unwrap().handle::<Vec<i32>>();
+        assert!(!handle.push(|seen| seen.push(3)));
+    }
+}
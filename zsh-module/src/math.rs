@@ -0,0 +1,132 @@
+//! Functions callable from arithmetic, like `$(( hypot(3, 4) ))`. See
+//! [`ModuleBuilder::mathfunc`](crate::ModuleBuilder::mathfunc).
+use std::{
+    ffi::{c_int, CString},
+    fmt,
+};
+
+use zsh_sys as zsys;
+
+use crate::{extensions::State, to_cstr, AnyError};
+
+/// A number in zsh arithmetic, which is either an integer or a float.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Number {
+    Integer(i64),
+    Float(f64),
+}
+
+impl Number {
+    /// The number as an integer, truncating floats like zsh does.
+    pub fn as_i64(self) -> i64 {
+        match self {
+            Self::Integer(i) => i,
+            Self::Float(f) => f as i64,
+        }
+    }
+    pub fn as_f64(self) -> f64 {
+        match self {
+            Self::Integer(i) => i as f64,
+            Self::Float(f) => f,
+        }
+    }
+    pub(crate) fn from_raw(raw: &zsys::mnumber) -> Self {
+        if raw.type_ as u32 & zsys::MN_FLOAT != 0 {
+            Self::Float(unsafe { raw.u.d })
+        } else {
+            Self::Integer(unsafe { raw.u.l })
+        }
+    }
+    pub(crate) fn into_raw(self) -> zsys::mnumber {
+        match self {
+            Self::Integer(i) => zsys::mnumber {
+                u: zsys::mnumber__bindgen_ty_1 {
+                    l: i as zsys::zlong,
+                },
+                type_: zsys::MN_INTEGER as c_int,
+            },
+            Self::Float(f) => zsys::mnumber {
+                u: zsys::mnumber__bindgen_ty_1 { d: f },
+                type_: zsys::MN_FLOAT as c_int,
+            },
+        }
+    }
+}
+
+impl From<i64> for Number {
+    fn from(value: i64) -> Self {
+        Self::Integer(value)
+    }
+}
+
+impl From<i32> for Number {
+    fn from(value: i32) -> Self {
+        Self::Integer(value.into())
+    }
+}
+
+impl From<f64> for Number {
+    fn from(value: f64) -> Self {
+        Self::Float(value)
+    }
+}
+
+impl From<bool> for Number {
+    fn from(value: bool) -> Self {
+        Self::Integer(value.into())
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Integer(i) => i.fmt(f),
+            Self::Float(x) => x.fmt(f),
+        }
+    }
+}
+
+/// The handler of a math function, given its arguments.
+pub(crate) type MathFn = Box<dyn FnMut(&mut State, &[Number]) -> Result<Number, AnyError>>;
+
+/// Properties of a math function.
+pub struct MathFunc {
+    pub(crate) name: CString,
+    pub(crate) minargs: i32,
+    pub(crate) maxargs: i32,
+}
+
+impl MathFunc {
+    /// Creates a math function taking any amount of arguments.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: to_cstr(name),
+            minargs: 0,
+            maxargs: -1,
+        }
+    }
+    /// Sets the minimum amount of arguments. Zsh refuses to call the function with fewer.
+    pub fn minargs(mut self, value: i32) -> Self {
+        self.minargs = value;
+        self
+    }
+    /// Sets the maximum amount of arguments, [`None`] meaning any.
+    pub fn maxargs(mut self, value: Option<u32>) -> Self {
+        self.maxargs = value.map(|i| i as i32).unwrap_or(-1);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raw_round_trip() {
+        for number in [Number::Integer(-3), Number::Float(0.5)] {
+            assert_eq!(Number::from_raw(&number.into_raw()), number);
+        }
+        assert_eq!(Number::Float(2.9).as_i64(), 2);
+        assert_eq!(Number::from(true), Number::Integer(1));
+    }
+}
@@ -0,0 +1,218 @@
+//! An in-memory stand-in for zsh, used to unit test modules on machines without zsh.
+//!
+//! This module is only available with the `mock` feature. While it is enabled, functions that only
+//! talk to zsh for their side effects don't reach zsh at all:
+//!  - Messages printed through [`crate::log`] are recorded and can be inspected with
+//!    [`take_messages`], unless they're suppressed with [`suppress_messages`].
+//!  - Text widths (e.g. in [`crate::terminal::format_columns`]) are computed without zsh's
+//!    character tables: every printable character takes one column.
+//!  - Shell parameters (e.g. `OPTIND`, set by [`crate::parseopts::getopts`]) are kept in a table
+//!    that can be inspected with [`param`] and [`set_param`], or [`array_param`] and
+//!    [`set_array_param`] for arrays. Tied parameters (like `path` and `PATH`) aren't kept in sync.
+//!
+//! [`MockOpts`] can be used to build the [`Opts`] given to builtin handlers.
+//!
+//! # Example
+//! ```
+//! use zsh_module::mock::{self, Message, MockOpts};
+//! use zsh_module::OptState;
+//!
+//! let opts = MockOpts::new().minus('v').arg('o', "out.txt").build();
+//! assert_eq!(opts.state(b'v' as _), Some(OptState::Minus));
+//! assert_eq!(opts.get_arg(b'o' as _), Some("out.txt"));
+//!
+//! zsh_module::warn_named!("mycmd", "careful!");
+//! assert_eq!(
+//!     mock::take_messages(),
+//!     vec![Message::Warning { cmd: Some("mycmd".into()), msg: "careful!".into() }]
+//! );
+//! ```
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    ffi::{c_char, CStr, CString},
+};
+
+use zsh_sys as zsys;
+
+use crate::{backend::Backend, Opts};
+
+/// A message that would have been printed by zsh.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Warning { cmd: Option<String>, msg: String },
+    Error { cmd: Option<String>, msg: String },
+}
+
+thread_local! {
+    static MESSAGES: RefCell<Vec<Message>> = const { RefCell::new(Vec::new()) };
+    static SUPPRESSED: Cell<bool> = const { Cell::new(false) };
+    static PARAMS: RefCell<HashMap<String, String>> = RefCell::new(HashMap::new());
+    static ARRAYS: RefCell<HashMap<String, Vec<String>>> = RefCell::new(HashMap::new());
+}
+
+/// Returns (and forgets) every message logged so far on the current thread.
+pub fn take_messages() -> Vec<Message> {
+    MESSAGES.with(|messages| messages.take())
+}
+
+/// Drops messages logged on the current thread instead of recording them, like zsh does while
+/// `noerrs` is set.
+pub fn suppress_messages(suppressed: bool) {
+    SUPPRESSED.with(|cell| cell.set(suppressed))
+}
+
+/// The value of a shell parameter set on the current thread, if any.
+pub fn param(name: &str) -> Option<String> {
+    PARAMS.with(|params| params.borrow().get(name).cloned())
+}
+
+/// Sets a shell parameter on the current thread, as if it was set by a script.
+pub fn set_param(name: &str, value: &str) {
+    PARAMS.with(|params| {
+        params
+            .borrow_mut()
+            .insert(name.to_string(), value.to_string())
+    });
+}
+
+/// The value of an array parameter set on the current thread, if any.
+pub fn array_param(name: &str) -> Option<Vec<String>> {
+    ARRAYS.with(|arrays| arrays.borrow().get(name).cloned())
+}
+
+/// Sets an array parameter on the current thread, as if it was set by a script.
+pub fn set_array_param(name: &str, values: &[&str]) {
+    ARRAYS.with(|arrays| {
+        arrays.borrow_mut().insert(
+            name.to_string(),
+            values.iter().map(|value| value.to_string()).collect(),
+        )
+    });
+}
+
+fn record(message: Message) {
+    if SUPPRESSED.with(Cell::get) {
+        return;
+    }
+    MESSAGES.with(|messages| messages.borrow_mut().push(message))
+}
+
+fn lossy(cstr: &CStr) -> String {
+    cstr.to_string_lossy().into_owned()
+}
+
+pub(crate) struct Mock;
+
+impl Backend for Mock {
+    fn warn(msg: &CStr) {
+        record(Message::Warning {
+            cmd: None,
+            msg: lossy(msg),
+        })
+    }
+    fn warn_named(cmd: &CStr, msg: &CStr) {
+        record(Message::Warning {
+            cmd: Some(lossy(cmd)),
+            msg: lossy(msg),
+        })
+    }
+    fn error(msg: &CStr) {
+        record(Message::Error {
+            cmd: None,
+            msg: lossy(msg),
+        })
+    }
+    fn error_named(cmd: &CStr, msg: &CStr) {
+        record(Message::Error {
+            cmd: Some(lossy(cmd)),
+            msg: lossy(msg),
+        })
+    }
+    fn messages_suppressed() -> bool {
+        SUPPRESSED.with(Cell::get)
+    }
+    fn interrupted() -> bool {
+        false
+    }
+    fn char_width(c: char) -> usize {
+        if c.is_control() {
+            0
+        } else {
+            1
+        }
+    }
+    fn get_int_param(name: &CStr) -> i64 {
+        param(&lossy(name))
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(0)
+    }
+    fn set_int_param(name: &CStr, value: i64) {
+        set_param(&lossy(name), &value.to_string())
+    }
+    fn get_str_param(name: &CStr) -> Option<Vec<u8>> {
+        param(&lossy(name)).map(String::into_bytes)
+    }
+    fn set_str_param(name: &CStr, value: &[u8]) {
+        set_param(&lossy(name), &String::from_utf8_lossy(value))
+    }
+    fn get_array_param(name: &CStr) -> Option<Vec<Vec<u8>>> {
+        array_param(&lossy(name)).map(|values| values.into_iter().map(String::into_bytes).collect())
+    }
+    fn set_array_param(name: &CStr, values: &[&[u8]]) -> bool {
+        let values: Vec<_> = values.iter().map(|v| String::from_utf8_lossy(v)).collect();
+        let values: Vec<&str> = values.iter().map(|v| v.as_ref()).collect();
+        set_array_param(&lossy(name), &values);
+        true
+    }
+}
+
+/// Builds [`Opts`] without a running shell, as if they were parsed by zsh.
+pub struct MockOpts {
+    raw: zsys::options,
+    args: Vec<CString>,
+}
+
+impl Default for MockOpts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockOpts {
+    /// Creates a set of options where no option is set.
+    pub fn new() -> Self {
+        Self {
+            raw: unsafe { std::mem::zeroed() },
+            args: Vec::new(),
+        }
+    }
+    /// Sets the option as if it was passed as `-c`.
+    pub fn minus(mut self, c: char) -> Self {
+        self.raw.ind[c as usize] |= 1;
+        self
+    }
+    /// Sets the option as if it was passed as `+c`.
+    pub fn plus(mut self, c: char) -> Self {
+        self.raw.ind[c as usize] |= 2;
+        self
+    }
+    /// Sets the option as if it was passed as `-c value`.
+    pub fn arg(mut self, c: char, value: &str) -> Self {
+        self.args.push(crate::to_cstr(value));
+        // Zsh stores the (1-based) index of the argument above the minus/plus bits
+        self.raw.ind[c as usize] = (self.args.len() << 2) as _;
+        self.minus(c)
+    }
+    /// Creates the [`Opts`].
+    ///
+    /// The memory backing them is leaked, so this should only be used in tests.
+    pub fn build(self) -> Opts {
+        let args: Vec<*mut c_char> = self.args.into_iter().map(CString::into_raw).collect();
+        let mut raw = self.raw;
+        raw.argscount = args.len() as _;
+        raw.argsalloc = args.len() as _;
+        raw.args = args.leak().as_mut_ptr();
+        unsafe { Opts::from_raw(Box::leak(Box::new(raw))) }
+    }
+}
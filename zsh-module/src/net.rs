@@ -0,0 +1,155 @@
+//! TCP and Unix domain sockets that integrate with the shell, similar to what the `zsh/net/tcp`
+//! and `zsh/net/socket` modules provide.
+//!
+//! Every socket opened through this module is registered in zsh's file descriptor table, so the
+//! shell won't clobber it and users can redirect to it like any other fd:
+//! ```zsh
+//! print "PING" >&$fd
+//! ```
+//!
+//! # Example
+//! ```no_run
+//! use std::io::Write;
+//!
+//! let mut stream = zsh_module::net::tcp_connect("127.0.0.1:6379").unwrap();
+//! stream.write_all(b"PING\r\n").unwrap();
+//! println!("Connected on fd {}", stream.fd());
+//! ```
+use std::{
+    io,
+    mem::ManuallyDrop,
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    os::unix::{
+        io::{AsRawFd, FromRawFd, IntoRawFd, RawFd},
+        net::{UnixListener, UnixStream},
+    },
+    path::Path,
+};
+
+use zsh_sys as zsys;
+
+/// A socket whose file descriptor is registered in zsh's file descriptor table.
+///
+/// The socket derefs to the wrapped standard library type (e.g. [`TcpStream`]), so it can be used
+/// just like one. Dropping it closes the fd and removes it from zsh's table.
+pub struct Socket<T: AsRawFd + IntoRawFd> {
+    inner: ManuallyDrop<T>,
+}
+
+impl<T: AsRawFd + IntoRawFd + FromRawFd> Socket<T> {
+    /// Hands `socket` over to zsh. Like zsh does, the fd is moved above 10 so that it doesn't
+    /// get in the way of the user's redirections.
+    pub fn new(socket: T) -> io::Result<Self> {
+        let fd = unsafe { zsys::movefd(socket.into_raw_fd()) };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        unsafe {
+            libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+            zsys::addmodulefd(fd, zsys::FDT_EXTERNAL as i32);
+        }
+        Ok(Self {
+            inner: ManuallyDrop::new(unsafe { T::from_raw_fd(fd) }),
+        })
+    }
+}
+
+impl<T: AsRawFd + IntoRawFd> Socket<T> {
+    /// The file descriptor number, as seen by the shell.
+    pub fn fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl<T: AsRawFd + IntoRawFd> std::ops::Deref for Socket<T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl<T: AsRawFd + IntoRawFd> std::ops::DerefMut for Socket<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
+impl<T: AsRawFd + IntoRawFd> AsRawFd for Socket<T> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd()
+    }
+}
+
+impl<T: AsRawFd + IntoRawFd> Drop for Socket<T> {
+    fn drop(&mut self) {
+        // Let zsh close the fd, so that its fd table stays consistent.
+        let inner = unsafe { ManuallyDrop::take(&mut self.inner) };
+        unsafe { zsys::zclose(inner.into_raw_fd()) };
+    }
+}
+
+impl io::Read for Socket<TcpStream> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl io::Write for Socket<TcpStream> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl io::Read for Socket<UnixStream> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl io::Write for Socket<UnixStream> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Socket<TcpListener> {
+    /// Accepts a new incoming connection, like `ztcp -a`.
+    pub fn accept(&self) -> io::Result<Socket<TcpStream>> {
+        let (stream, _) = self.inner.accept()?;
+        Socket::new(stream)
+    }
+}
+
+impl Socket<UnixListener> {
+    /// Accepts a new incoming connection, like `zsocket -a`.
+    pub fn accept(&self) -> io::Result<Socket<UnixStream>> {
+        let (stream, _) = self.inner.accept()?;
+        Socket::new(stream)
+    }
+}
+
+/// Opens a TCP connection to `addr`, like `ztcp host port`.
+pub fn tcp_connect(addr: impl ToSocketAddrs) -> io::Result<Socket<TcpStream>> {
+    Socket::new(TcpStream::connect(addr)?)
+}
+
+/// Listens for TCP connections on `addr`, like `ztcp -l port`.
+pub fn tcp_listen(addr: impl ToSocketAddrs) -> io::Result<Socket<TcpListener>> {
+    Socket::new(TcpListener::bind(addr)?)
+}
+
+/// Opens a connection to the Unix domain socket at `path`, like `zsocket path`.
+pub fn unix_connect(path: impl AsRef<Path>) -> io::Result<Socket<UnixStream>> {
+    Socket::new(UnixStream::connect(path)?)
+}
+
+/// Listens for connections on a Unix domain socket at `path`, like `zsocket -l path`.
+pub fn unix_listen(path: impl AsRef<Path>) -> io::Result<Socket<UnixListener>> {
+    Socket::new(UnixListener::bind(path)?)
+}
@@ -2,27 +2,95 @@ use std::{ffi::CStr, os::raw::c_char};
 
 use zsh_sys as zsys;
 
+use crate::{
+    validate::{self, Validator},
+    ZResult,
+};
+
+/// How an option was passed to a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptState {
+    /// Passed as `-o`.
+    Minus,
+    /// Passed as `+o`. Only builtins with [`plus_flags`](crate::Builtin::plus_flags) accept these.
+    Plus,
+    /// Passed as both `-o` and `+o`.
+    Both,
+}
+
 /// Represents all the options passed to a command.
+#[derive(Clone)]
 pub struct Opts {
     raw: zsys::Options,
 }
 
 impl Opts {
-    pub(crate) unsafe fn from_raw(raw: zsys::Options) -> Self {
+    /// Wraps the options zsh passes to a builtin's handler.
+    ///
+    /// # Safety
+    /// `raw` must point to valid options, which outlive the returned value.
+    pub unsafe fn from_raw(raw: zsys::Options) -> Self {
         Self { raw }
     }
+    /// The raw options, to pass to functions of [`zsh_sys`] this crate doesn't wrap.
+    pub fn as_raw(&self) -> zsys::Options {
+        self.raw
+    }
+    /// The raw state of the option `c`.
+    ///
+    /// `c_char` is signed on some platforms and unsigned on others, so it's reinterpreted as a
+    /// byte first. Characters zsh doesn't have room for are never set.
+    fn ind(&self, c: c_char) -> u8 {
+        unsafe { (*self.raw).ind.get(c as u8 as usize).copied().unwrap_or(0) }
+    }
     // Taken from `zsh.h`
     // Let's hope Zsh does not change the implementation of these:
 
+    /// How the option was set, if it was.
+    /// E.g:
+    /// ```zsh
+    /// command -o # Returns Some(OptState::Minus)
+    /// command +o # Returns Some(OptState::Plus)
+    /// command -o +o # Returns Some(OptState::Both)
+    /// command # Returns None
+    /// ```
+    pub fn state(&self, c: c_char) -> Option<OptState> {
+        match self.ind(c) & 3 {
+            1 => Some(OptState::Minus),
+            2 => Some(OptState::Plus),
+            3 => Some(OptState::Both),
+            _ => None,
+        }
+    }
+    /// Iterates over the options that were set, in alphabetical order, with how they were set.
+    ///
+    /// # Example
+    /// ```
+    /// use zsh_module::{OptState, Opts};
+    ///
+    /// // `toggle -a +b` enables `a` and disables `b`
+    /// fn toggle(opts: &Opts, enabled: &mut Vec<char>) {
+    ///     for (c, state) in opts.iter() {
+    ///         match state {
+    ///             OptState::Minus => enabled.push(c),
+    ///             OptState::Plus => enabled.retain(|&e| e != c),
+    ///             OptState::Both => {}
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn iter(&self) -> impl Iterator<Item = (char, OptState)> + '_ {
+        (0..128u8).filter_map(|c| Some((c as char, self.state(c as c_char)?)))
+    }
     /// Whether the option was set using a minus.
     /// E.g:
     /// ```zsh
     /// command +o # Returns false
     /// command -o # Returns true.
     /// ```
+    #[deprecated(note = "use `Opts::state` instead")]
     pub fn is_minus(&self, c: c_char) -> bool {
-        let expr = unsafe { (*self.raw).ind[c as usize] & 1 };
-        expr != 0
+        self.ind(c) & 1 != 0
     }
     /// Whether the option was set using a plus.
     /// E.g:
@@ -30,9 +98,9 @@ impl Opts {
     /// command +o # Returns true
     /// command -o # Returns false
     /// ```
+    #[deprecated(note = "use `Opts::state` instead")]
     pub fn is_plus(&self, c: c_char) -> bool {
-        let expr = unsafe { (*self.raw).ind[c as usize] & 2 };
-        expr != 0
+        self.ind(c) & 2 != 0
     }
     /// Whether the option was set.
     /// E.g:
@@ -42,7 +110,7 @@ impl Opts {
     /// command # Returns false
     /// ```
     pub fn is_set(&self, c: c_char) -> bool {
-        unsafe { (*self.raw).ind[c as usize] != 0 }
+        self.ind(c) != 0
     }
     /// Returns the argument passed with the option, if any.
     /// E.g:
@@ -56,7 +124,7 @@ impl Opts {
         unsafe {
             let args =
                 std::ptr::slice_from_raw_parts((*self.raw).args, (*self.raw).argscount as usize);
-            let opt = (*self.raw).ind[c as usize];
+            let opt = self.ind(c);
             if opt > 3 {
                 CStr::from_ptr((*args)[(opt >> 2) as usize - 1])
                     .to_str()
@@ -67,3 +135,147 @@ impl Opts {
         }
     }
 }
+
+/// What an option declared with [`Builtin::opt`](crate::Builtin::opt) takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptKind {
+    /// Nothing, like `-v`.
+    Flag,
+    /// An argument, either attached or as the next argument, like `-ofile` or `-o file`.
+    TakesArg,
+}
+
+impl OptKind {
+    /// The option in a builtin's `optstr`.
+    pub(crate) fn optstr(self, c: char) -> String {
+        match self {
+            Self::Flag => c.to_string(),
+            Self::TakesArg => format!("{}:", c),
+        }
+    }
+}
+
+/// The options passed to a builtin registered with
+/// [`ModuleBuilder::builtin_parsed`](crate::ModuleBuilder::builtin_parsed), already checked by
+/// zsh against the ones it declares with [`Builtin::opt`](crate::Builtin::opt).
+///
+/// Options are given as `char`s; ones zsh can't have (i.e. non-ASCII) are never set.
+#[derive(Clone)]
+pub struct ParsedOpts {
+    opts: Opts,
+}
+
+impl ParsedOpts {
+    pub fn new(opts: Opts) -> Self {
+        Self { opts }
+    }
+    fn c_char(c: char) -> Option<c_char> {
+        c.is_ascii().then_some(c as u8 as c_char)
+    }
+    /// Whether the option `-c` was passed.
+    pub fn flag(&self, c: char) -> bool {
+        Self::c_char(c).is_some_and(|c| self.opts.is_set(c))
+    }
+    /// The argument of the option `-c`, if it was passed.
+    pub fn arg(&self, c: char) -> Option<&str> {
+        self.opts.get_arg(Self::c_char(c)?)
+    }
+    /// The argument of the option `-c`, checked and converted with `validator`. Failures name the
+    /// option, like [`validate::arg`] does.
+    ///
+    /// # Example
+    /// ```
+    /// use zsh_module::{validate::as_int, MaybeError, ParsedOpts};
+    ///
+    /// fn head(opts: &ParsedOpts) -> MaybeError {
+    ///     let lines: u32 = opts.value('n', as_int())?.unwrap_or(10);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn value<V: Validator>(&self, c: char, validator: V) -> ZResult<Option<V::Output>> {
+        self.arg(c)
+            .map(|value| validate::arg(&format!("-{}", c), value, validator))
+            .transpose()
+    }
+    /// The options as zsh passed them.
+    pub fn opts(&self) -> &Opts {
+        &self.opts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OptState;
+    use crate::mock::MockOpts;
+
+    #[test]
+    #[allow(deprecated)]
+    fn plus_and_minus() {
+        let opts = MockOpts::new().minus('a').plus('b').build();
+        assert!(opts.is_minus(b'a' as _) && !opts.is_plus(b'a' as _));
+        assert!(opts.is_plus(b'b' as _) && !opts.is_minus(b'b' as _));
+        assert!(opts.is_set(b'a' as _) && opts.is_set(b'b' as _));
+        assert!(!opts.is_set(b'c' as _));
+    }
+
+    #[test]
+    fn args() {
+        let opts = MockOpts::new()
+            .arg('o', "out")
+            .minus('v')
+            .arg('i', "in")
+            .build();
+        assert_eq!(opts.get_arg(b'o' as _), Some("out"));
+        assert_eq!(opts.get_arg(b'i' as _), Some("in"));
+        assert_eq!(opts.get_arg(b'v' as _), None);
+        assert_eq!(opts.state(b'o' as _), Some(OptState::Minus));
+    }
+
+    #[test]
+    fn states() {
+        let opts = MockOpts::new()
+            .minus('a')
+            .plus('b')
+            .minus('c')
+            .plus('c')
+            .build();
+        assert_eq!(opts.state(b'a' as _), Some(OptState::Minus));
+        assert_eq!(opts.state(b'b' as _), Some(OptState::Plus));
+        assert_eq!(opts.state(b'c' as _), Some(OptState::Both));
+        assert_eq!(opts.state(b'd' as _), None);
+        assert_eq!(
+            opts.iter().collect::<Vec<_>>(),
+            [
+                ('a', OptState::Minus),
+                ('b', OptState::Plus),
+                ('c', OptState::Both)
+            ]
+        );
+    }
+
+    #[test]
+    fn non_ascii_options_are_never_set() {
+        let opts = MockOpts::new().minus('a').build();
+        // Negative where `c_char` is signed, past zsh's table where it's unsigned
+        for c in [0x80u8, 0xe9, 0xff] {
+            assert!(!opts.is_set(c as _));
+            assert_eq!(opts.state(c as _), None);
+            assert_eq!(opts.get_arg(c as _), None);
+        }
+    }
+
+    #[test]
+    fn parsed_opts() {
+        use super::ParsedOpts;
+        use crate::validate::as_int;
+
+        let opts = ParsedOpts::new(MockOpts::new().minus('v').arg('n', "x").build());
+        assert!(opts.flag('v') && !opts.flag('q') && !opts.flag('é'));
+        assert_eq!(opts.arg('n'), Some("x"));
+        assert_eq!(
+            opts.value('n', as_int::<u32>()).unwrap_err().to_string(),
+            "-n: expected an integer (got `x`)"
+        );
+        assert_eq!(opts.value('m', as_int::<u32>()).unwrap(), None);
+    }
+}
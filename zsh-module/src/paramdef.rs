@@ -0,0 +1,57 @@
+//! Special parameters whose value comes from the module, like `$MYMOD_STATE`. See
+//! [`ModuleBuilder::param`](crate::ModuleBuilder::param).
+use std::{any::Any, ffi::CString};
+
+use crate::{extensions::State, to_cstr, AnyError, MaybeError};
+
+type GetFn = Box<dyn FnMut(&mut State) -> String>;
+type SetFn = Box<dyn FnMut(&mut State, &str) -> MaybeError<AnyError>>;
+type UnsetFn = Box<dyn FnMut(&mut State)>;
+
+/// The handlers of a special parameter, once its user data type is erased.
+pub(crate) struct ParamFns {
+    pub(crate) get: GetFn,
+    pub(crate) set: Option<SetFn>,
+    pub(crate) unset: Option<UnsetFn>,
+}
+
+/// A scalar special parameter, whose value is computed by the module every time it's read.
+///
+/// It's read-only unless it has a [setter](Self::set).
+pub struct SpecialParam<A> {
+    pub(crate) name: CString,
+    pub(crate) fns: ParamFns,
+    _data: std::marker::PhantomData<A>,
+}
+
+impl<A: Any> SpecialParam<A> {
+    /// Creates a parameter named `name`, whose value is returned by `get`.
+    pub fn new(name: &str, mut get: impl 'static + FnMut(&mut A) -> String) -> Self {
+        Self {
+            name: to_cstr(name),
+            fns: ParamFns {
+                get: Box::new(move |state: &mut State| get(state.data::<A>())),
+                set: None,
+                unset: None,
+            },
+            _data: std::marker::PhantomData,
+        }
+    }
+    /// Lets the parameter be assigned, running `set` with the new value. If it fails, the
+    /// assignment fails with its error.
+    pub fn set<E>(mut self, mut set: impl 'static + FnMut(&mut A, &str) -> MaybeError<E>) -> Self
+    where
+        E: Into<AnyError>,
+    {
+        self.fns.set = Some(Box::new(move |state: &mut State, value: &str| {
+            set(state.data::<A>(), value).map_err(E::into)
+        }));
+        self
+    }
+    /// Runs `unset` when the parameter is unset. Special parameters stay defined when unset, so
+    /// this should reset the value instead.
+    pub fn unset(mut self, mut unset: impl 'static + FnMut(&mut A)) -> Self {
+        self.fns.unset = Some(Box::new(move |state: &mut State| unset(state.data::<A>())));
+        self
+    }
+}
@@ -0,0 +1,525 @@
+//! A parser for options in the style of zsh's `zparseopts`.
+//!
+//! Zsh parses the options of a builtin before calling it (see [`Builtin::flags`]), but only
+//! single letter flags that come before any argument. Builtins that want long options, or options
+//! mixed with their arguments, can leave [`Builtin::flags`] unset and parse their arguments with
+//! [`ParseOpts`] instead.
+//!
+//! Options are described with the same specs as `zparseopts`:
+//!  - `name`: An option without arguments, given as `-name`.
+//!  - `name:`: An option with a mandatory argument, either in the same word (`-namearg`) or the
+//!    next one (`-name arg`).
+//!  - `name:-`: An option with a mandatory argument in the same word.
+//!  - `name::`: An option with an optional argument in the same word.
+//!  - `name+`: (before any `:`) The option may be given more than once, and every occurrence is
+//!    kept. Otherwise, only the last one is.
+//!  - `name=key`: (at the end) The option is stored as `-key` instead, to give an option several
+//!    names.
+//!
+//! Long options are written like `-long` in the spec to be given as `--long`, like in zsh.
+//!
+//! # Example
+//! ```
+//! use zsh_module::parseopts::ParseOpts;
+//!
+//! let parser = ParseOpts::new(&["v+", "o:", "-output:=o", "-help=h", "h"])
+//!     .unwrap()
+//!     .extract();
+//! let parsed = parser
+//!     .parse(&["file", "-vv", "--output", "out.txt", "other", "--", "-v"])
+//!     .unwrap();
+//! assert_eq!(parsed.count("-v"), 2);
+//! assert_eq!(parsed.get("-o"), Some("out.txt"));
+//! assert!(!parsed.contains("-h"));
+//! assert_eq!(parsed.rest, ["file", "other", "-v"]);
+//! ```
+//!
+//! For builtins replacing shell functions that use `getopts`, [`getopts`] parses one option at a
+//! time, keeping track of where it is in `OPTIND` like `getopts` does.
+//!
+//! [`Builtin::flags`]: crate::Builtin::flags
+use std::collections::HashMap;
+
+use parking_lot::Mutex;
+
+use crate::{
+    backend::{Backend, Current},
+    validate::{self, Validator},
+    ZError, ZResult,
+};
+
+/// How an option takes its argument.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArgKind {
+    None,
+    /// `name:`
+    Mandatory,
+    /// `name:-`
+    SameWord,
+    /// `name::`
+    Optional,
+}
+
+#[derive(Debug, Clone)]
+struct Spec {
+    name: String,
+    key: String,
+    repeat: bool,
+    arg: ArgKind,
+}
+
+impl Spec {
+    fn parse(full: &str) -> ZResult<Self> {
+        let (spec, key) = match full.split_once('=') {
+            Some((spec, key)) => (spec, Some(key)),
+            None => (full, None),
+        };
+        let (spec, arg) = if let Some(spec) = spec.strip_suffix("::") {
+            (spec, ArgKind::Optional)
+        } else if let Some(spec) = spec.strip_suffix(":-") {
+            (spec, ArgKind::SameWord)
+        } else if let Some(spec) = spec.strip_suffix(':') {
+            (spec, ArgKind::Mandatory)
+        } else {
+            (spec, ArgKind::None)
+        };
+        let (name, repeat) = match spec.strip_suffix('+') {
+            Some(name) => (name, true),
+            None => (spec, false),
+        };
+        if name.is_empty() || key == Some("") {
+            return Err(ZError::Usage(format!("invalid option spec: {}", full)));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            key: format!("-{}", key.unwrap_or(name)),
+            repeat,
+            arg,
+        })
+    }
+}
+
+/// A parser for options described with `zparseopts` specs. See the [module docs](self).
+#[derive(Debug, Clone)]
+pub struct ParseOpts {
+    specs: Vec<Spec>,
+    extract: bool,
+    fail_on_unknown: bool,
+}
+
+impl ParseOpts {
+    /// Creates a parser for the options described by `specs`.
+    ///
+    /// By default, options are parsed until the first argument that isn't one, like with
+    /// `zparseopts` without `-E`, and unknown options also end them, like without `-F`.
+    pub fn new(specs: &[&str]) -> ZResult<Self> {
+        Ok(Self {
+            specs: specs
+                .iter()
+                .map(|spec| Spec::parse(spec))
+                .collect::<ZResult<_>>()?,
+            extract: false,
+            fail_on_unknown: false,
+        })
+    }
+    /// Keeps parsing options after arguments that aren't options, until `-` or `--`, like
+    /// `zparseopts -E`.
+    pub fn extract(mut self) -> Self {
+        self.extract = true;
+        self
+    }
+    /// Fails on options that aren't in the specs instead of treating them as arguments, like
+    /// `zparseopts -F`.
+    pub fn fail_on_unknown(mut self) -> Self {
+        self.fail_on_unknown = true;
+        self
+    }
+    fn spec(&self, name: &str) -> Option<&Spec> {
+        self.specs.iter().find(|spec| spec.name == name)
+    }
+    /// Parses the options out of `args`. Everything else is left in [`Parsed::rest`], in order.
+    pub fn parse<'a>(&self, args: &[&'a str]) -> ZResult<Parsed<'a>> {
+        let mut parsed = Parsed::default();
+        let mut args = args.iter().copied();
+        while let Some(arg) = args.next() {
+            if arg == "-" || arg == "--" {
+                parsed.rest.extend(args);
+                break;
+            }
+            let Some(word) = arg.strip_prefix('-') else {
+                parsed.rest.push(arg);
+                if !self.extract {
+                    parsed.rest.extend(args);
+                    break;
+                }
+                continue;
+            };
+            let found = self.option(word, &mut args, &mut parsed)?;
+            if !found {
+                if self.fail_on_unknown {
+                    return Err(ZError::Usage(format!("bad option: {}", arg)));
+                }
+                parsed.rest.push(arg);
+                if !self.extract {
+                    parsed.rest.extend(args);
+                    break;
+                }
+            }
+        }
+        Ok(parsed)
+    }
+    /// Parses the option in `word` (without its leading `-`). Returns whether it was known.
+    fn option<'a>(
+        &self,
+        word: &'a str,
+        args: &mut impl Iterator<Item = &'a str>,
+        parsed: &mut Parsed<'a>,
+    ) -> ZResult<bool> {
+        if let Some(spec) = self.spec(word) {
+            let value = match spec.arg {
+                ArgKind::Mandatory => Some(args.next().ok_or_else(|| missing(spec))?),
+                ArgKind::SameWord => return Err(missing(spec)),
+                ArgKind::None | ArgKind::Optional => None,
+            };
+            parsed.push(spec, value);
+            return Ok(true);
+        }
+        // An option with its argument in the same word, preferring the longest name
+        let attached = self
+            .specs
+            .iter()
+            .filter(|spec| spec.arg != ArgKind::None && word.starts_with(&spec.name))
+            .max_by_key(|spec| spec.name.len());
+        if let Some(spec) = attached {
+            parsed.push(spec, Some(&word[spec.name.len()..]));
+            return Ok(true);
+        }
+        // Grouped single letter options, like `-abc`. The first one taking an argument gets the
+        // rest of the word, or the next one.
+        let mut found = Vec::new();
+        for (i, c) in word.char_indices() {
+            let Some(spec) = self.spec(&word[i..i + c.len_utf8()]) else {
+                return Ok(false);
+            };
+            if spec.arg == ArgKind::None {
+                found.push((spec, None));
+                continue;
+            }
+            let rest = &word[i + c.len_utf8()..];
+            let value = match spec.arg {
+                _ if !rest.is_empty() => Some(rest),
+                ArgKind::Mandatory => Some(args.next().ok_or_else(|| missing(spec))?),
+                ArgKind::SameWord => return Err(missing(spec)),
+                _ => None,
+            };
+            found.push((spec, value));
+            break;
+        }
+        for (spec, value) in found {
+            parsed.push(spec, value);
+        }
+        Ok(true)
+    }
+}
+
+fn missing(spec: &Spec) -> ZError {
+    ZError::Usage(format!("missing argument for option: -{}", spec.name))
+}
+
+/// The result of [`ParseOpts::parse`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Parsed<'a> {
+    /// The options that were found, in order, as `-key` with their argument.
+    pub opts: Vec<(String, Option<&'a str>)>,
+    /// Every argument that wasn't an option, in order.
+    pub rest: Vec<&'a str>,
+}
+
+impl<'a> Parsed<'a> {
+    fn push(&mut self, spec: &Spec, value: Option<&'a str>) {
+        if !spec.repeat {
+            self.opts.retain(|(key, _)| *key != spec.key);
+        }
+        self.opts.push((spec.key.clone(), value));
+    }
+    /// Whether the option `key` (e.g. `-v`) was given.
+    pub fn contains(&self, key: &str) -> bool {
+        self.opts.iter().any(|(k, _)| k == key)
+    }
+    /// How many times the option `key` was given.
+    pub fn count(&self, key: &str) -> usize {
+        self.opts.iter().filter(|(k, _)| k == key).count()
+    }
+    /// The argument of the last occurrence of the option `key`, if it had one.
+    pub fn get(&self, key: &str) -> Option<&'a str> {
+        self.opts
+            .iter()
+            .rev()
+            .find(|(k, _)| k == key)
+            .and_then(|(_, value)| *value)
+    }
+    /// The argument of the last occurrence of the option `key`, checked by `validator`. Errors
+    /// name the option.
+    ///
+    /// # Example
+    /// ```
+    /// use zsh_module::{parseopts::ParseOpts, validate::as_int};
+    ///
+    /// let parsed = ParseOpts::new(&["n:"]).unwrap().parse(&["-n", "x"]).unwrap();
+    /// let err = parsed.value("-n", as_int::<u32>()).unwrap_err();
+    /// assert_eq!(err.to_string(), "-n: expected an integer (got `x`)");
+    /// ```
+    pub fn value<V: Validator>(&self, key: &str, validator: V) -> ZResult<Option<V::Output>> {
+        self.get(key)
+            .map(|value| validate::arg(key, value, validator))
+            .transpose()
+    }
+    /// The arguments of every occurrence of the option `key`.
+    pub fn all(&self, key: &str) -> impl Iterator<Item = &'a str> + '_ {
+        let key = key.to_string();
+        self.opts
+            .iter()
+            .filter(move |(k, _)| *k == key)
+            .filter_map(|(_, value)| *value)
+    }
+    /// The options as an association, like `zparseopts -A`: options without an argument map to an
+    /// empty string, and repeated options to the argument of their last occurrence.
+    pub fn assoc(&self) -> HashMap<String, String> {
+        self.opts
+            .iter()
+            .map(|(key, value)| (key.clone(), value.unwrap_or_default().to_string()))
+            .collect()
+    }
+}
+
+/// The `OPTIND` last set by [`getopts`], and how far it got into that word. Zsh's `getopts` keeps
+/// the latter internally too, since `OPTIND` only counts words.
+static GETOPTS: Mutex<(i64, usize)> = parking_lot::const_mutex((0, 0));
+
+/// Parses the next option in `args` like zsh's `getopts`, resuming from `OPTIND` and updating it
+/// and `OPTARG`, so a builtin can be called in a loop like `getopts` is:
+/// ```zsh
+/// while mygetopts "ab:" opt; do ...; done
+/// shift $((OPTIND - 1))
+/// ```
+/// `optstring` lists the option letters, where a `:` after a letter means it takes an argument.
+/// Returns the option and its argument, which is also put in `OPTARG`, or [`None`] when there are
+/// no options left. Setting `OPTIND` to 0 starts over, even in the middle of grouped options
+/// like `-abc`.
+///
+/// Unknown options and missing arguments are returned as errors, which the builtin can print or
+/// not, so a leading `:` in `optstring` (`getopts`' silent mode) is ignored.
+///
+/// # Example
+/// ```no_run
+/// use zsh_module::{parseopts, MaybeError, Opts};
+///
+/// fn mygetopts(_: &mut (), _: &str, args: &[&str], _: Opts) -> MaybeError {
+///     match parseopts::getopts("ab:", &args[1..])? {
+///         Some((opt, arg)) => println!("-{} {}", opt, arg.unwrap_or("")),
+///         None => return Err("no more options".into()),
+///     }
+///     Ok(())
+/// }
+/// ```
+pub fn getopts<'a>(optstring: &str, args: &[&'a str]) -> ZResult<Option<(char, Option<&'a str>)>> {
+    let mut state = GETOPTS.lock();
+    let mut optind = Current::get_int_param(c"OPTIND");
+    // Start over when the script changed OPTIND
+    let mut optcind = if optind == state.0 { state.1 } else { 0 };
+    if optind < 1 {
+        optind = 1;
+        optcind = 0;
+    }
+    let result = next_opt(
+        optstring.strip_prefix(':').unwrap_or(optstring),
+        args,
+        &mut optind,
+        &mut optcind,
+    );
+    *state = (optind, optcind);
+    Current::set_int_param(c"OPTIND", optind);
+    let optarg = match &result {
+        Ok(Some((_, Some(arg)))) => arg,
+        _ => "",
+    };
+    Current::set_str_param(c"OPTARG", optarg.as_bytes());
+    result
+}
+
+/// Reads the option at `args[optind - 1][optcind..]`, moving past it (and its argument).
+fn next_opt<'a>(
+    optstring: &str,
+    args: &[&'a str],
+    optind: &mut i64,
+    optcind: &mut usize,
+) -> ZResult<Option<(char, Option<&'a str>)>> {
+    let Some(word) = args.get(*optind as usize - 1) else {
+        return Ok(None);
+    };
+    // Left over from other arguments
+    if word.get(*optcind..).is_none_or(str::is_empty) {
+        *optcind = 0;
+    }
+    if *optcind == 0 {
+        if *word == "--" {
+            *optind += 1;
+            return Ok(None);
+        }
+        if !word.starts_with('-') || *word == "-" {
+            return Ok(None);
+        }
+        *optcind = 1;
+    }
+    let c = word[*optcind..].chars().next().unwrap();
+    *optcind += c.len_utf8();
+    let rest = &word[*optcind..];
+    let next_word = |optind: &mut i64, optcind: &mut usize| {
+        *optind += 1;
+        *optcind = 0;
+    };
+    let takes_arg = match optstring.find(c) {
+        Some(i) if c != ':' => optstring[i + c.len_utf8()..].starts_with(':'),
+        _ => {
+            if rest.is_empty() {
+                next_word(optind, optcind);
+            }
+            return Err(ZError::Usage(format!("bad option: -{}", c)));
+        }
+    };
+    if !takes_arg {
+        if rest.is_empty() {
+            next_word(optind, optcind);
+        }
+        return Ok(Some((c, None)));
+    }
+    next_word(optind, optcind);
+    if !rest.is_empty() {
+        return Ok(Some((c, Some(rest))));
+    }
+    match args.get(*optind as usize - 1) {
+        Some(arg) => {
+            *optind += 1;
+            Ok(Some((c, Some(arg))))
+        }
+        None => Err(ZError::Usage(format!(
+            "argument expected after -{} option",
+            c
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse<'a>(specs: &[&str], args: &[&'a str]) -> ZResult<Parsed<'a>> {
+        ParseOpts::new(specs)?.parse(args)
+    }
+
+    #[test]
+    fn arguments() {
+        let specs = ["o:", "s:-", "p::", "v"];
+        let parsed = parse(&specs, &["-o", "a", "-ob", "-sc", "-p", "-pd"]).unwrap();
+        assert_eq!(
+            parsed.opts,
+            [
+                ("-o".to_string(), Some("b")),
+                ("-s".to_string(), Some("c")),
+                ("-p".to_string(), Some("d")),
+            ]
+        );
+        assert!(parse(&specs, &["-o"]).is_err());
+        assert!(parse(&specs, &["-s", "x"]).is_err());
+        assert_eq!(parse(&specs, &["-p", "x"]).unwrap().rest, ["x"]);
+    }
+
+    #[test]
+    fn repeats_and_groups() {
+        let parsed = parse(&["v+", "q", "o+:"], &["-vqv", "-ox", "-o", "y"]).unwrap();
+        assert_eq!(parsed.count("-v"), 2);
+        assert_eq!(parsed.count("-q"), 1);
+        assert_eq!(parsed.all("-o").collect::<Vec<_>>(), ["x", "y"]);
+        // The first option of a group taking an argument gets the rest
+        assert_eq!(
+            parse(&["o:", "v"], &["-ovx"]).unwrap().get("-o"),
+            Some("vx")
+        );
+        let parsed = parse(&["o:", "v"], &["-vo", "x"]).unwrap();
+        assert!(parsed.contains("-v"));
+        assert_eq!(parsed.get("-o"), Some("x"));
+        assert!(parse(&["o:", "v"], &["-vx"]).unwrap().opts.is_empty());
+    }
+
+    #[test]
+    fn where_options_end() {
+        let specs = ["v", "-long=l"];
+        let parsed = parse(&specs, &["-v", "arg", "-v"]).unwrap();
+        assert_eq!(parsed.rest, ["arg", "-v"]);
+        let parsed = parse(&specs, &["--long", "-x", "-v"]).unwrap();
+        assert_eq!(parsed.opts, [("-l".to_string(), None)]);
+        assert_eq!(parsed.rest, ["-x", "-v"]);
+        let parsed = parse(&specs, &["-v", "--", "-v"]).unwrap();
+        assert_eq!(parsed.rest, ["-v"]);
+
+        let extract = ParseOpts::new(&specs).unwrap().extract();
+        let parsed = extract.parse(&["a", "-v", "-x", "b", "-", "-v"]).unwrap();
+        assert_eq!(parsed.opts, [("-v".to_string(), None)]);
+        assert_eq!(parsed.rest, ["a", "-x", "b", "-v"]);
+
+        let strict = ParseOpts::new(&specs).unwrap().fail_on_unknown();
+        assert_eq!(
+            strict.parse(&["-x"]).unwrap_err().to_string(),
+            "bad option: -x"
+        );
+    }
+
+    #[test]
+    fn assoc() {
+        let parsed = parse(&["v", "o:"], &["-v", "-o", "a", "-o", "b"]).unwrap();
+        let assoc = parsed.assoc();
+        assert_eq!(assoc.len(), 2);
+        assert_eq!(assoc["-v"], "");
+        assert_eq!(assoc["-o"], "b");
+    }
+
+    #[test]
+    fn invalid_specs() {
+        assert!(ParseOpts::new(&[":"]).is_err());
+        assert!(ParseOpts::new(&["a="]).is_err());
+    }
+
+    #[test]
+    fn getopts_resumes_from_optind() {
+        use crate::mock;
+
+        let args = ["-ab", "x", "-cvalue", "-d", "--", "rest"];
+        let next = || getopts(":abc:", &args).map_err(|e| e.to_string());
+        mock::set_param("OPTIND", "1");
+        assert_eq!(next(), Ok(Some(('a', None))));
+        assert_eq!(mock::param("OPTIND").unwrap(), "1");
+        assert_eq!(next(), Ok(Some(('b', None))));
+        assert_eq!(mock::param("OPTIND").unwrap(), "2");
+        assert_eq!(next(), Ok(None));
+
+        mock::set_param("OPTIND", "3");
+        assert_eq!(next(), Ok(Some(('c', Some("value")))));
+        assert_eq!(mock::param("OPTARG").unwrap(), "value");
+        assert_eq!(next(), Err("bad option: -d".into()));
+        assert_eq!(next(), Ok(None));
+        assert_eq!(mock::param("OPTIND").unwrap(), "6");
+
+        // Starting over, even in the middle of a group
+        mock::set_param("OPTIND", "1");
+        assert_eq!(next(), Ok(Some(('a', None))));
+        mock::set_param("OPTIND", "0");
+        assert_eq!(next(), Ok(Some(('a', None))));
+
+        let args = ["-c"];
+        mock::set_param("OPTIND", "0");
+        assert_eq!(
+            getopts("c:", &args).unwrap_err().to_string(),
+            "argument expected after -c option"
+        );
+    }
+}
@@ -0,0 +1,255 @@
+//! Pseudo-terminal support, similar to what the `zsh/zpty` module provides.
+//!
+//! A [`Pty`] runs a command with a pseudo-terminal as its controlling terminal, which makes
+//! interactive programs (REPLs, `ssh`, etc) behave as if a user was typing into them.
+//!
+//! # Example
+//! ```no_run
+//! use std::io::{Read, Write};
+//! use std::process::Command;
+//!
+//! let mut pty = zsh_module::pty::Pty::spawn(&mut Command::new("python3")).unwrap();
+//! pty.write_all(b"print(1 + 1)\n").unwrap();
+//! let mut buf = [0; 128];
+//! let n = pty.read(&mut buf).unwrap();
+//! println!("{}", String::from_utf8_lossy(&buf[..n]));
+//! ```
+use std::{
+    any::Any,
+    collections::HashMap,
+    ffi::c_char,
+    fs::File,
+    io::{self, Read, Write},
+    os::unix::{
+        io::{AsRawFd, FromRawFd, RawFd},
+        process::CommandExt,
+    },
+    process::{Child, Command, ExitStatus, Stdio},
+};
+
+use crate::{AnyError, Cmd, MaybeError, Opts};
+
+/// A command running under a pseudo-terminal.
+///
+/// Reading from a [`Pty`] returns whatever the command wrote to its terminal, and writing to it
+/// is the same as typing into that terminal.
+///
+/// The command is sent a `SIGHUP` when its [`Pty`] is dropped, just like `zpty -d` does.
+pub struct Pty {
+    master: File,
+    child: Child,
+}
+
+fn cvt(ret: libc::c_int) -> io::Result<libc::c_int> {
+    if ret == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ret)
+    }
+}
+
+/// Opens a new pseudo-terminal pair, returning the master and the slave sides.
+fn openpty() -> io::Result<(File, File)> {
+    unsafe {
+        let master = cvt(libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY))?;
+        let master = File::from_raw_fd(master);
+        cvt(libc::grantpt(master.as_raw_fd()))?;
+        cvt(libc::unlockpt(master.as_raw_fd()))?;
+        let name = libc::ptsname(master.as_raw_fd());
+        if name.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        let slave = cvt(libc::open(name, libc::O_RDWR | libc::O_NOCTTY))?;
+        Ok((master, File::from_raw_fd(slave)))
+    }
+}
+
+impl Pty {
+    /// Spawns `command` with a new pseudo-terminal as its standard input, output, error and
+    /// controlling terminal.
+    pub fn spawn(command: &mut Command) -> io::Result<Self> {
+        let (master, slave) = openpty()?;
+        command
+            .stdin(Stdio::from(slave.try_clone()?))
+            .stdout(Stdio::from(slave.try_clone()?))
+            .stderr(Stdio::from(slave));
+        unsafe {
+            command.pre_exec(|| {
+                // Become a session leader, so that the pty can be our controlling terminal.
+                cvt(libc::setsid())?;
+                cvt(libc::ioctl(0, libc::TIOCSCTTY as _, 0))?;
+                Ok(())
+            });
+        }
+        let child = command.spawn()?;
+        Ok(Self { master, child })
+    }
+    /// The process id of the command.
+    pub fn pid(&self) -> u32 {
+        self.child.id()
+    }
+    /// Sets the window size reported by the pseudo-terminal.
+    pub fn resize(&self, cols: u16, rows: u16) -> io::Result<()> {
+        let size = libc::winsize {
+            ws_row: rows,
+            ws_col: cols,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        unsafe { cvt(libc::ioctl(self.as_raw_fd(), libc::TIOCSWINSZ as _, &size)) }?;
+        Ok(())
+    }
+    /// Moves the pseudo-terminal in and out of non-blocking mode.
+    ///
+    /// While in non-blocking mode, reads fail with [`io::ErrorKind::WouldBlock`] if the command
+    /// has no pending output.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        let fd = self.as_raw_fd();
+        unsafe {
+            let flags = cvt(libc::fcntl(fd, libc::F_GETFL))?;
+            let flags = if nonblocking {
+                flags | libc::O_NONBLOCK
+            } else {
+                flags & !libc::O_NONBLOCK
+            };
+            cvt(libc::fcntl(fd, libc::F_SETFL, flags))?;
+        }
+        Ok(())
+    }
+    /// Reads everything the command has written so far, without blocking.
+    pub fn read_available(&mut self) -> io::Result<Vec<u8>> {
+        self.set_nonblocking(true)?;
+        let mut out = Vec::new();
+        let mut buf = [0; 1024];
+        let res = loop {
+            match self.master.read(&mut buf) {
+                Ok(0) => break Ok(()),
+                Ok(n) => out.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break Ok(()),
+                // Linux reports a hung up pty with `EIO`.
+                Err(e) if e.raw_os_error() == Some(libc::EIO) => break Ok(()),
+                Err(e) => break Err(e),
+            }
+        };
+        self.set_nonblocking(false)?;
+        res.map(|_| out)
+    }
+    /// Returns the command's exit status, if it has already exited.
+    pub fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        self.child.try_wait()
+    }
+    /// Waits for the command to exit.
+    pub fn wait(&mut self) -> io::Result<ExitStatus> {
+        self.child.wait()
+    }
+    /// Forcefully kills the command.
+    pub fn kill(&mut self) -> io::Result<()> {
+        self.child.kill()
+    }
+}
+
+impl Read for Pty {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.master.read(buf)
+    }
+}
+
+impl Write for Pty {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.master.write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.master.flush()
+    }
+}
+
+impl AsRawFd for Pty {
+    fn as_raw_fd(&self) -> RawFd {
+        self.master.as_raw_fd()
+    }
+}
+
+impl Drop for Pty {
+    fn drop(&mut self) {
+        unsafe { libc::kill(self.child.id() as libc::pid_t, libc::SIGHUP) };
+        let _ = self.child.try_wait();
+    }
+}
+
+#[derive(Debug)]
+struct PtyError(String);
+
+impl std::fmt::Display for PtyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+impl std::error::Error for PtyError {}
+
+fn flag(opts: &Opts, c: u8) -> bool {
+    opts.is_set(c as c_char)
+}
+
+type PtyTable = HashMap<String, (String, Pty)>;
+
+fn get<'a>(ptys: &'a mut PtyTable, name: Option<&&str>) -> Result<&'a mut Pty, PtyError> {
+    let name = name.ok_or_else(|| PtyError("missing pty name".into()))?;
+    ptys.get_mut(*name)
+        .map(|(_, pty)| pty)
+        .ok_or_else(|| PtyError(format!("no such pty command: {}", name)))
+}
+
+/// Creates a `zpty`-like builtin command that manages named [`Pty`]s.
+///
+/// The builtin must be registered with the `"wrdL"` flags:
+/// ```no_run
+/// use zsh_module::{Builtin, ModuleBuilder};
+///
+/// let module = ModuleBuilder::new(())
+///     .builtin(zsh_module::pty::builtin(), Builtin::new("rzpty").flags("wrdL"))
+///     .build();
+/// ```
+/// Which can then be used from zsh like so:
+/// ```zsh
+/// rzpty name command args... # Starts `command` under a pty named `name`
+/// rzpty -w name strings...   # Writes `strings` (followed by a newline) to `name`
+/// rzpty -r name              # Prints whatever `name` has written so far
+/// rzpty -d names...          # Kills and deletes `names`
+/// rzpty -L                   # Lists running ptys
+/// ```
+pub fn builtin<A: Any + ?Sized>() -> impl Cmd<A, AnyError> {
+    // Maps each pty name to its command line and the pty itself
+    let mut ptys = PtyTable::new();
+    move |_: &mut A, _name: &str, args: &[&str], opts: Opts| -> MaybeError {
+        if flag(&opts, b'L') {
+            for (name, (cmdline, pty)) in &ptys {
+                crate::zprintln!("{} ({}): {}", name, pty.pid(), cmdline);
+            }
+        } else if flag(&opts, b'd') {
+            for name in args {
+                ptys.remove(*name)
+                    .ok_or_else(|| PtyError(format!("no such pty command: {}", name)))?;
+            }
+        } else if flag(&opts, b'w') {
+            let pty = get(&mut ptys, args.first())?;
+            let mut line = args[1..].join(" ");
+            line.push('\n');
+            pty.write_all(line.as_bytes())?;
+        } else if flag(&opts, b'r') {
+            let pty = get(&mut ptys, args.first())?;
+            let output = pty.read_available()?;
+            io::stdout().write_all(&output)?;
+        } else {
+            let (name, argv) = match args {
+                [name, cmd, argv @ ..] => (name, (cmd, argv)),
+                _ => return Err(PtyError("not enough arguments".into()).into()),
+            };
+            if ptys.contains_key(*name) {
+                return Err(PtyError(format!("pty command name already used: {}", name)).into());
+            }
+            let pty = Pty::spawn(Command::new(argv.0).args(argv.1))?;
+            ptys.insert(name.to_string(), (args[1..].join(" "), pty));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,73 @@
+//! Reloading the module when it's rebuilt. See
+//! [`ModuleBuilder::hot_reload`](crate::ModuleBuilder::hot_reload).
+use std::{
+    ffi::{c_void, CStr, OsStr},
+    os::unix::ffi::OsStrExt,
+    path::PathBuf,
+    time::SystemTime,
+};
+
+/// The path of the shared library this crate was linked into.
+pub(crate) fn library_path() -> Option<PathBuf> {
+    let mut info: libc::Dl_info = unsafe { std::mem::zeroed() };
+    let addr = library_path as *const c_void;
+    if unsafe { libc::dladdr(addr, &mut info) } == 0 || info.dli_fname.is_null() {
+        return None;
+    }
+    let path = unsafe { CStr::from_ptr(info.dli_fname) };
+    Some(PathBuf::from(OsStr::from_bytes(path.to_bytes())))
+}
+
+/// Tells when a file was changed.
+pub(crate) struct Watcher {
+    path: Option<PathBuf>,
+    modified: Option<SystemTime>,
+}
+
+impl Watcher {
+    pub(crate) fn new(path: Option<PathBuf>) -> Self {
+        let modified = path
+            .as_deref()
+            .and_then(|path| path.metadata().ok()?.modified().ok());
+        Self { path, modified }
+    }
+    /// Whether the file was changed since the last time this returned true (or the watcher was
+    /// created). A file that was removed, e.g. while it's being rebuilt, doesn't count as changed.
+    pub(crate) fn changed(&mut self) -> bool {
+        let Some(modified) = self
+            .path
+            .as_deref()
+            .and_then(|path| path.metadata().ok()?.modified().ok())
+        else {
+            return false;
+        };
+        if self.modified == Some(modified) {
+            return false;
+        }
+        self.modified = Some(modified);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn changes_are_noticed_once() {
+        let path = std::env::temp_dir().join(format!("zsh-module-reload-{}", std::process::id()));
+        std::fs::write(&path, "a").unwrap();
+        let mut watcher = Watcher::new(Some(path.clone()));
+        assert!(!watcher.changed());
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+        std::fs::remove_file(&path).unwrap();
+        assert!(!watcher.changed());
+        assert!(!Watcher::new(None).changed());
+    }
+}
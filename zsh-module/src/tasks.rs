@@ -0,0 +1,94 @@
+//! Futures run on a background runtime, whose results are handed back to the shell's thread. See
+//! [`Module::spawn`](crate::Module::spawn).
+//!
+//! Zsh can only be used from its own thread, so a finished task pushes its callback to the
+//! module's [`MainThreadQueue`](crate::main_thread::MainThreadQueue).
+use std::{any::Any, collections::HashMap, future::Future, io};
+
+use tokio::runtime::{self, Runtime};
+
+use crate::{extensions::State, main_thread::MainThreadQueue};
+
+/// Called on the shell's thread with the output of a task, which it downcasts.
+type Then = Box<dyn FnOnce(&mut State, Box<dyn Any + Send>)>;
+
+#[derive(Default)]
+pub(crate) struct Tasks {
+    /// Created on the first spawn.
+    runtime: Option<Runtime>,
+    /// The callbacks aren't [`Send`], so they stay here until their task finishes.
+    pending: HashMap<u64, Then>,
+    next_id: u64,
+}
+
+impl Tasks {
+    /// Runs `fut` on the runtime, and `then` with its output once the shell is back on the
+    /// prompt.
+    pub(crate) fn spawn<F>(
+        &mut self,
+        queue: MainThreadQueue<()>,
+        fut: F,
+        then: Then,
+    ) -> io::Result<()>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send,
+    {
+        let runtime = match &mut self.runtime {
+            Some(runtime) => runtime,
+            None => self.runtime.insert(
+                runtime::Builder::new_multi_thread()
+                    .thread_name("zsh-module-tasks")
+                    .enable_all()
+                    .build()?,
+            ),
+        };
+        let id = self.next_id;
+        self.next_id += 1;
+        self.pending.insert(id, then);
+        runtime.spawn(async move {
+            let output: Box<dyn Any + Send> = Box::new(fut.await);
+            queue.push_job(Box::new(move |state| {
+                if let Some(then) = state.tasks.pending.remove(&id) {
+                    then(state, output);
+                }
+            }));
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::main_thread::{self, Queue};
+
+    #[test]
+    fn finished_tasks_run_their_callbacks() {
+        let mut state = State::new(Box::new(Vec::<i32>::new()));
+        let queue = Queue::new().unwrap();
+        let fd = queue.fd();
+        for n in [1, 2] {
+            let then: Then = Box::new(move |state, output| {
+                state
+                    .data::<Vec<i32>>()
+                    .push(*output.downcast::<i32>().unwrap());
+            });
+            let fut = async move { n * 10 };
+            state.tasks.spawn(queue.handle(), fut, then).unwrap();
+        }
+        state.queue = Some(queue);
+        let mut fds = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        while state.data::<Vec<i32>>().len() < 2 {
+            assert_eq!(unsafe { libc::poll(&mut fds, 1, 5000) }, 1);
+            main_thread::run_queued(&mut state);
+        }
+        let mut results = std::mem::take(state.data::<Vec<i32>>());
+        results.sort();
+        assert_eq!(results, [10, 20]);
+    }
+}
@@ -0,0 +1,273 @@
+//! Functions used to write to the user's terminal the way zsh does.
+//!
+//! Modules drawing on the terminal themselves, like status lines or spinners, should write to
+//! [`tty`] rather than to the standard output, which may be redirected, and use [`Control`] to
+//! move the cursor around.
+//!
+//! # Example
+//! ```no_run
+//! use std::io::Write;
+//!
+//! use zsh_module::terminal::{self, Control};
+//!
+//! let mut tty = terminal::tty().expect("not interactive");
+//! // Rewrites the line below the cursor, and goes back
+//! let (save, clear, restore) = (Control::Save, Control::ClearLine, Control::Restore);
+//! write!(tty, "{save}\n{clear}building...{restore}")?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+use std::{
+    fmt,
+    io::{self, Write},
+};
+
+use zsh_sys as zsys;
+
+use crate::{
+    backend::{Backend, Current},
+    Fd,
+};
+
+/// The width of the terminal, as known by zsh (i.e. `$COLUMNS`).
+pub fn columns() -> usize {
+    unsafe { zsys::zterm_columns }.max(1) as usize
+}
+
+/// The height of the terminal, as known by zsh (i.e. `$LINES`).
+pub fn lines() -> usize {
+    unsafe { zsys::zterm_lines }.max(1) as usize
+}
+
+/// The width and height of the terminal, as known by zsh. Zsh updates them when the terminal is
+/// resized, unless the user set `$COLUMNS` or `$LINES` themselves. See [`tty_size`] to ask the
+/// terminal instead.
+pub fn size() -> (usize, usize) {
+    (columns(), lines())
+}
+
+/// The width and height of the terminal, as the terminal itself reports them.
+///
+/// Fails if the shell isn't interactive, like [`tty`].
+pub fn tty_size() -> io::Result<(usize, usize)> {
+    let fd = tty_fd().ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok((size.ws_col as usize, size.ws_row as usize))
+}
+
+/// The terminal zsh reads commands from and draws the line editor on, if the shell is
+/// interactive. Unlike the standard output, it isn't redirected along with the running command.
+pub fn tty() -> Option<Fd> {
+    tty_fd().map(Fd::new)
+}
+
+fn tty_fd() -> Option<i32> {
+    let fd = unsafe { zsys::SHTTY };
+    (fd != -1).then_some(fd)
+}
+
+/// Moves the cursor around or clears parts of the terminal, when written to it.
+///
+/// These are the ANSI escape sequences every terminal zsh runs in nowadays understands, rather
+/// than the ones of the terminal's termcap entry, which zsh keeps to itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Control {
+    /// Moves the cursor up, by that many lines.
+    Up(u16),
+    /// Moves the cursor down, by that many lines.
+    Down(u16),
+    /// Moves the cursor left, by that many columns.
+    Left(u16),
+    /// Moves the cursor right, by that many columns.
+    Right(u16),
+    /// Moves the cursor to a column of the line it's on, starting from 0.
+    Column(u16),
+    /// Clears the line the cursor is on, and moves it to the start of the line.
+    ClearLine,
+    /// Clears the line from the cursor on.
+    ClearToEndOfLine,
+    /// Clears the screen from the cursor on.
+    ClearBelow,
+    /// Remembers where the cursor is, to go back with [`Control::Restore`].
+    Save,
+    /// Moves the cursor back to where [`Control::Save`] left it.
+    Restore,
+    /// Hides the cursor until [`Control::ShowCursor`].
+    HideCursor,
+    /// Shows the cursor again after [`Control::HideCursor`].
+    ShowCursor,
+}
+
+impl fmt::Display for Control {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            // Moving by 0 would move by 1
+            Self::Up(0) | Self::Down(0) | Self::Left(0) | Self::Right(0) => Ok(()),
+            Self::Up(n) => write!(f, "\x1b[{n}A"),
+            Self::Down(n) => write!(f, "\x1b[{n}B"),
+            Self::Right(n) => write!(f, "\x1b[{n}C"),
+            Self::Left(n) => write!(f, "\x1b[{n}D"),
+            Self::Column(n) => write!(f, "\x1b[{}G", n as u32 + 1),
+            Self::ClearLine => f.write_str("\r\x1b[2K"),
+            Self::ClearToEndOfLine => f.write_str("\x1b[K"),
+            Self::ClearBelow => f.write_str("\x1b[J"),
+            Self::Save => f.write_str("\x1b7"),
+            Self::Restore => f.write_str("\x1b8"),
+            Self::HideCursor => f.write_str("\x1b[?25l"),
+            Self::ShowCursor => f.write_str("\x1b[?25h"),
+        }
+    }
+}
+
+/// Surrounds `text` with the escape sequences showing it in `style`, which is in the format of
+/// `$zle_highlight`, e.g. `fg=red,bold` or `fg=#ff8800,bg=238,underline`.
+///
+/// The colors go through zsh's prompt expansion, so they're named and downgraded like the ones of
+/// `%F{...}` are (e.g. to 256 colors, unless `zsh/nearcolor` is loaded or the terminal supports
+/// true colors). Only available with the `prompt` feature.
+///
+/// # Examples
+/// ```no_run
+/// let warning = zsh_module::terminal::style("careful", "fg=red,standout").unwrap();
+/// ```
+#[cfg(feature = "prompt")]
+pub fn style(text: &str, style: &str) -> crate::ZResult<String> {
+    let (start, end) = style_prompts(style)?;
+    let start = crate::zsh::expand_prompt(&start)?;
+    let end = crate::zsh::expand_prompt(&end)?;
+    Ok(format!("{start}{text}{end}"))
+}
+
+/// The prompt escapes starting and ending `style`. The text is left out of the prompts, so it
+/// doesn't need escaping.
+#[cfg(feature = "prompt")]
+fn style_prompts(style: &str) -> crate::ZResult<(String, String)> {
+    let (mut start, mut end) = (String::new(), String::new());
+    for attr in style
+        .split(',')
+        .map(str::trim)
+        .filter(|attr| !attr.is_empty())
+    {
+        let (on, off) = match attr.split_once('=') {
+            Some(("fg", color)) => (format!("%F{{{}}}", color_name(color)?), "%f"),
+            Some(("bg", color)) => (format!("%K{{{}}}", color_name(color)?), "%k"),
+            None if attr == "bold" => ("%B".to_string(), "%b"),
+            None if attr == "underline" => ("%U".to_string(), "%u"),
+            None if attr == "standout" => ("%S".to_string(), "%s"),
+            None if attr == "none" => continue,
+            _ => {
+                return Err(crate::ZError::InvalidArgument {
+                    name: "style".into(),
+                    msg: format!("unknown attribute `{attr}`"),
+                })
+            }
+        };
+        start.push_str(&on);
+        end.push_str(off);
+    }
+    Ok((start, end))
+}
+
+/// `color`, if it can go between the braces of `%F{...}`. Anything closing them or starting
+/// another escape would be expanded as part of the prompt.
+#[cfg(feature = "prompt")]
+fn color_name(color: &str) -> crate::ZResult<&str> {
+    if color.is_empty() || color.contains(['}', '%']) {
+        return Err(crate::ZError::InvalidArgument {
+            name: "style".into(),
+            msg: format!("invalid color `{color}`"),
+        });
+    }
+    Ok(color)
+}
+
+/// The amount of terminal columns taken by `text`, using the same rules zsh uses to lay out
+/// prompts and completion listings.
+pub fn width(text: &str) -> usize {
+    text.chars().map(Current::char_width).sum()
+}
+
+/// Lays out `items` in columns that fit in `columns` terminal cells, like `print -c` does.
+///
+/// Items are sorted down the columns first, and every line ends with a newline.
+pub fn format_columns<S: AsRef<str>>(items: &[S], columns: usize) -> String {
+    if items.is_empty() {
+        return String::new();
+    }
+    let widths: Vec<usize> = items.iter().map(|item| width(item.as_ref())).collect();
+    // Each column is as wide as the widest item, plus a two-space gap.
+    let column_width = widths.iter().copied().max().unwrap_or(0) + 2;
+    let ncols = ((columns + 1) / column_width).max(1);
+    let nrows = items.len().div_ceil(ncols);
+
+    let mut out = String::new();
+    for row in 0..nrows {
+        let mut i = row;
+        while i < items.len() {
+            out.push_str(items[i].as_ref());
+            let next = i + nrows;
+            if next < items.len() {
+                out.extend(std::iter::repeat_n(' ', column_width - widths[i]));
+            }
+            i = next;
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Prints `items` to stdout in columns that fit the terminal, like `print -c` does.
+///
+/// # Examples
+/// ```no_run
+/// zsh_module::terminal::print_columns(&["apple", "banana", "cherry", "durian"]).unwrap();
+/// ```
+pub fn print_columns<S: AsRef<str>>(items: &[S]) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(format_columns(items, columns()).as_bytes())?;
+    stdout.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn controls() {
+        let moves = format!(
+            "{}{}{}",
+            Control::Up(2),
+            Control::Left(0),
+            Control::Column(0)
+        );
+        assert_eq!(moves, "\x1b[2A\x1b[1G");
+        assert_eq!(Control::ClearLine.to_string(), "\r\x1b[2K");
+    }
+
+    #[test]
+    #[cfg(feature = "prompt")]
+    fn styles() {
+        let (start, end) = style_prompts("fg=#ff8800, bg=238,bold,none").unwrap();
+        assert_eq!(start, "%F{#ff8800}%K{238}%B");
+        assert_eq!(end, "%f%k%b");
+        assert_eq!(style_prompts("").unwrap(), (String::new(), String::new()));
+        assert!(style_prompts("blink").is_err());
+        assert!(style_prompts("fg=red}%n").is_err());
+        assert!(style_prompts("bg=%").is_err());
+    }
+
+    #[test]
+    fn columns_are_filled_first() {
+        let items = ["a", "bb", "c", "d", "e"];
+        assert_eq!(format_columns(&items, 8), "a   d\nbb  e\nc\n");
+    }
+
+    #[test]
+    fn narrow_terminal() {
+        assert_eq!(format_columns(&["abc", "d"], 2), "abc\nd\n");
+        assert_eq!(format_columns::<&str>(&[], 80), "");
+    }
+}
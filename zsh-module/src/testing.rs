@@ -0,0 +1,152 @@
+//! Utilities to test code that talks to zsh from `cargo test`, without loading it as a module.
+//!
+//! This module is only available with the `testing` feature, which links your tests against a
+//! shared `libzsh` (e.g. a zsh built with `--enable-dynamic` and `LIBS=-shared`). Use
+//! `ZSH_SYS_LIB_DIR` to tell the linker where it is and `ZSH_SYS_LIB` if it isn't called
+//! `libzsh`. The library must be found at runtime too, e.g. through `LD_LIBRARY_PATH`.
+//!
+//! Since you most likely want this only for tests, enable it on your dev-dependencies:
+//! ```toml
+//! [dev-dependencies]
+//! zsh-module = { version = "0.3", features = ["testing"] }
+//! ```
+//!
+//! # Example
+//! ```
+//! let _zsh = zsh_module::testing::init_embedded_zsh();
+//! zsh_module::zsh::eval_simple("answer=42").unwrap();
+//! ```
+use std::{
+    ffi::{c_char, c_int},
+    ptr,
+    sync::Once,
+};
+
+use parking_lot::{Mutex, MutexGuard};
+use zsh_sys as zsys;
+
+use crate::{to_cstr, Module, ZError, ZResult};
+
+#[cfg(not(target_os = "macos"))]
+unsafe fn environ() -> *mut *mut c_char {
+    extern "C" {
+        static mut environ: *mut *mut c_char;
+    }
+    environ
+}
+
+// Libraries can't refer to `environ` directly on macOS.
+#[cfg(target_os = "macos")]
+unsafe fn environ() -> *mut *mut c_char {
+    extern "C" {
+        fn _NSGetEnviron() -> *mut *mut *mut c_char;
+    }
+    *_NSGetEnviron()
+}
+
+/// Exclusive access to the embedded shell. See [`init_embedded_zsh`].
+pub type ZshGuard = MutexGuard<'static, ()>;
+
+static INIT: Once = Once::new();
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// Initializes zsh inside the current process, as if it was started with `zsh -f`, and locks it
+/// for the current test.
+///
+/// Zsh is only initialized once, so later calls only take the lock. Since zsh isn't thread safe
+/// and `cargo test` runs tests in parallel, keep the returned guard alive for as long as the test
+/// uses zsh.
+///
+/// Only as much as needed to run code is set up: no startup files (not even `/etc/zshenv`) are
+/// read, there's no terminal and no job control, and the shell never reads input on its own.
+///
+/// Keep in mind that zsh installs its own signal handlers. In particular, its `SIGCHLD` handler
+/// reaps every child process, so don't use [`std::process`] in tests that share a binary with
+/// these.
+pub fn init_embedded_zsh() -> ZshGuard {
+    let guard = LOCK.lock();
+    INIT.call_once(|| unsafe { init() });
+    guard
+}
+
+/// Mirrors `zsh_main`, minus the parts dealing with the command line, startup files and input.
+unsafe fn init() {
+    libc::setlocale(libc::LC_ALL, c"".as_ptr());
+    let name = zsys::ztrdup(c"zsh".as_ptr());
+    let mut argv = [name, ptr::null_mut()];
+    zsys::init_jobs(argv.as_mut_ptr(), environ());
+
+    zsys::fdtable_size = zsys::zopenmax() as c_int;
+    zsys::fdtable = zsys::zshcalloc(zsys::fdtable_size as usize) as *mut _;
+    for fd in 0..3 {
+        *zsys::fdtable.add(fd) = zsys::FDT_EXTERNAL as _;
+    }
+
+    zsys::createoptiontable();
+    zsys::emulate(
+        name,
+        1,
+        ptr::addr_of_mut!(zsys::emulation),
+        ptr::addr_of_mut!(zsys::opts) as *mut c_char,
+    );
+    // What `zsh -f` would decide when not attached to a terminal.
+    for opt in [
+        zsys::INTERACTIVE,
+        zsys::MONITOR,
+        zsys::SHINSTDIN,
+        zsys::USEZLE,
+        zsys::RCS,
+        zsys::GLOBALRCS,
+    ] {
+        zsys::opts[opt as usize] = 0;
+    }
+    zsys::argzero = zsys::ztrdup(name);
+    zsys::posixzero = zsys::ztrdup(name);
+    zsys::pparams = zsys::zshcalloc(std::mem::size_of::<*mut c_char>()) as *mut _;
+
+    zsys::SHTTY = -1;
+    zsys::init_io(ptr::null_mut());
+    zsys::setupvals(ptr::null_mut(), ptr::null_mut(), name);
+    zsys::init_signals();
+    zsys::init_bltinmods();
+    zsys::init_builtins();
+}
+
+/// Makes the builtins of `module` available to the embedded shell, as if it was loaded with
+/// `zmodload`.
+///
+/// Only one module can be loaded per process, and [`init_embedded_zsh`] must have been called
+/// before.
+///
+/// # Example
+/// ```
+/// use zsh_module::{Builtin, MaybeError, ModuleBuilder, Opts};
+///
+/// fn hello(_: &mut (), _: &str, _: &[&str], _: Opts) -> MaybeError {
+///     Ok(())
+/// }
+///
+/// let _zsh = zsh_module::testing::init_embedded_zsh();
+/// let module = ModuleBuilder::new(())
+///     .builtin(hello, Builtin::new("hello"))
+///     .build();
+/// zsh_module::testing::load_module("hello", module).unwrap();
+/// zsh_module::zsh::eval_simple("hello").unwrap();
+/// ```
+pub fn load_module(name: &'static str, module: Module) -> ZResult<()> {
+    crate::export_module::set_mod(module, name);
+    let module = crate::export_module::get_mod();
+    let name = to_cstr(name);
+    let failed = unsafe {
+        zsys::addbuiltins(
+            name.as_ptr(),
+            module.features.bn_list,
+            module.features.bn_size,
+        )
+    };
+    if failed != 0 {
+        Err(ZError::Zsh)
+    } else {
+        Ok(())
+    }
+}
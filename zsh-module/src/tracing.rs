@@ -0,0 +1,104 @@
+//! A [`tracing_subscriber`] layer printing events like [`log::log`](crate::log::log), for
+//! modules (or the libraries they use) instrumented with `tracing`. Requires the `tracing`
+//! feature.
+//!
+//! Events are printed as zsh warnings, which zsh prints above the line being edited, so they
+//! don't garble the prompt even when they happen while it's redrawn. Which ones are printed
+//! follows the [log level](crate::log::level).
+//!
+//! # Example
+//! ```no_run
+//! use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+//! use zsh_module::{log, tracing::ZshLayer};
+//!
+//! log::level_param("MYMOD_DEBUG");
+//! tracing_subscriber::registry().with(ZshLayer::new()).init();
+//! ```
+use std::{
+    fmt::{self, Write},
+    thread::ThreadId,
+};
+
+use tracing_core::{field::Visit, Event, Field, Subscriber};
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+
+use crate::log::{self, Level};
+
+/// A layer printing events as zsh warnings, like `span:inner span: message key=value`.
+pub struct ZshLayer {
+    /// The thread of the shell, the only one that may print.
+    shell_thread: ThreadId,
+}
+
+impl ZshLayer {
+    /// Creates the layer. It must be called from the shell's thread, e.g. in the module's setup:
+    /// events from other threads are dropped, since zsh can't print them.
+    pub fn new() -> Self {
+        Self {
+            shell_thread: std::thread::current().id(),
+        }
+    }
+}
+
+impl Default for ZshLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn level(level: &tracing_core::Level) -> Level {
+    match *level {
+        tracing_core::Level::ERROR => Level::Error,
+        tracing_core::Level::WARN => Level::Warn,
+        tracing_core::Level::INFO => Level::Info,
+        tracing_core::Level::DEBUG => Level::Debug,
+        tracing_core::Level::TRACE => Level::Trace,
+    }
+}
+
+/// Formats the fields of an event, its message first.
+#[derive(Default)]
+struct Fields {
+    message: String,
+    others: String,
+}
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{:?}", value);
+        } else {
+            let _ = write!(self.others, " {}={:?}", field.name(), value);
+        }
+    }
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.push_str(value);
+        } else {
+            let _ = write!(self.others, " {}={}", field.name(), value);
+        }
+    }
+}
+
+impl<S> Layer<S> for ZshLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let level = level(event.metadata().level());
+        if std::thread::current().id() != self.shell_thread || !log::enabled(level) {
+            return;
+        }
+        let mut text = String::new();
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope.from_root() {
+                let _ = write!(text, "{}: ", span.name());
+            }
+        }
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        text += fields.message.as_str();
+        text += fields.others.as_str();
+        log::log(level, text);
+    }
+}
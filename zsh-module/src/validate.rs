@@ -0,0 +1,158 @@
+//! Validation of builtin arguments.
+//!
+//! A [`Validator`] checks an argument and converts it to the type the builtin wants. Checking an
+//! argument with [`arg`] (or an option with [`Parsed::value`]) turns failures into a
+//! [`ZError::InvalidArgument`] naming the argument, so every builtin reports them the same way.
+//!
+//! # Example
+//! ```
+//! use zsh_module::validate::{self, as_int, one_of, Validator};
+//!
+//! let count: u32 = validate::arg("count", "3", as_int().in_range(1..=10)).unwrap();
+//! assert_eq!(count, 3);
+//!
+//! let err = validate::arg("mode", "maybe", one_of(["on", "off"])).unwrap_err();
+//! assert_eq!(err.to_string(), "mode: must be one of on, off (got `maybe`)");
+//! ```
+//!
+//! [`Parsed::value`]: crate::parseopts::Parsed::value
+use std::{fmt::Debug, ops::RangeBounds, path::PathBuf, str::FromStr};
+
+use crate::{ZError, ZResult};
+
+/// Checks an argument, converting it to [`Validator::Output`]. On failure, returns a message
+/// saying what's wrong with it.
+///
+/// Any `Fn(&str) -> Result<T, String>` is a validator.
+pub trait Validator {
+    type Output;
+    fn check(&self, value: &str) -> Result<Self::Output, String>;
+    /// Also checks that the value is in `range`.
+    fn in_range<R>(self, range: R) -> InRange<Self, R>
+    where
+        Self: Sized,
+        Self::Output: PartialOrd + Debug,
+        R: RangeBounds<Self::Output> + Debug,
+    {
+        InRange {
+            validator: self,
+            range,
+        }
+    }
+}
+
+impl<T, F> Validator for F
+where
+    F: Fn(&str) -> Result<T, String>,
+{
+    type Output = T;
+    fn check(&self, value: &str) -> Result<T, String> {
+        self(value)
+    }
+}
+
+/// See [`Validator::in_range`].
+#[derive(Debug, Clone)]
+pub struct InRange<V, R> {
+    validator: V,
+    range: R,
+}
+
+impl<V, R> Validator for InRange<V, R>
+where
+    V: Validator,
+    V::Output: PartialOrd + Debug,
+    R: RangeBounds<V::Output> + Debug,
+{
+    type Output = V::Output;
+    fn check(&self, value: &str) -> Result<V::Output, String> {
+        let checked = self.validator.check(value)?;
+        if self.range.contains(&checked) {
+            Ok(checked)
+        } else {
+            Err(format!("must be in {:?} (got {:?})", self.range, checked))
+        }
+    }
+}
+
+/// Checks `value`, naming the argument as `name` on failure.
+pub fn arg<V: Validator>(name: &str, value: &str, validator: V) -> ZResult<V::Output> {
+    validator
+        .check(value)
+        .map_err(|msg| ZError::InvalidArgument {
+            name: name.to_string(),
+            msg,
+        })
+}
+
+/// An integer, in any of Rust's integer types.
+pub fn as_int<T: FromStr>() -> impl Fn(&str) -> Result<T, String> {
+    |value| {
+        value
+            .parse()
+            .map_err(|_| format!("expected an integer (got `{}`)", value))
+    }
+}
+
+/// A path to a file or directory that exists.
+pub fn as_existing_path() -> impl Fn(&str) -> Result<PathBuf, String> {
+    |value| {
+        let path = PathBuf::from(value);
+        if path.exists() {
+            Ok(path)
+        } else {
+            Err(format!("no such file or directory: {}", value))
+        }
+    }
+}
+
+/// One of `choices`.
+pub fn one_of<const N: usize>(
+    choices: [&'static str; N],
+) -> impl Fn(&str) -> Result<&'static str, String> {
+    move |value| {
+        choices
+            .iter()
+            .find(|choice| **choice == value)
+            .copied()
+            .ok_or_else(|| format!("must be one of {} (got `{}`)", choices.join(", "), value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn msg<T: Debug>(result: ZResult<T>) -> String {
+        result.unwrap_err().to_string()
+    }
+
+    #[test]
+    fn ints_and_ranges() {
+        assert_eq!(arg("n", "-4", as_int::<i64>()).unwrap(), -4);
+        assert_eq!(
+            msg(arg("n", "four", as_int::<i64>())),
+            "n: expected an integer (got `four`)"
+        );
+        assert_eq!(
+            msg(arg("n", "-4", as_int::<u8>())),
+            "n: expected an integer (got `-4`)"
+        );
+        let percent = || as_int::<u8>().in_range(0..=100);
+        assert_eq!(arg("pct", "100", percent()).unwrap(), 100);
+        assert_eq!(
+            msg(arg("pct", "101", percent())),
+            "pct: must be in 0..=100 (got 101)"
+        );
+    }
+
+    #[test]
+    fn choices_and_paths() {
+        assert_eq!(arg("mode", "off", one_of(["on", "off"])).unwrap(), "off");
+        assert!(arg("dir", "/", as_existing_path()).is_ok());
+        assert_eq!(
+            msg(arg("dir", "/no/such/dir", as_existing_path())),
+            "dir: no such file or directory: /no/such/dir"
+        );
+    }
+}
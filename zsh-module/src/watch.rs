@@ -0,0 +1,124 @@
+//! Callbacks run when a file descriptor becomes readable, like handlers added with `zle -F`.
+//! See [`Module::watch_fd`](crate::Module::watch_fd). Requires the `watch` feature.
+//!
+//! Zsh only watches fds while the line editor waits for input, so callbacks run at the prompt,
+//! as widgets: they may use [`zle`](crate::zle), e.g. to redraw it. The shell doesn't watch them
+//! in scripts, or while a command runs.
+//!
+//! The internals of `zle -F` aren't exported, so the module uses it from the shell: every fd is
+//! handed to a `_<module>_fd` widget, which calls the `<module>-fd` builtin.
+use std::os::fd::RawFd;
+
+use crate::{extensions::State, MaybeError, ZError};
+
+/// Runs on the shell's thread when a watched fd is ready. Returns whether to keep watching it.
+pub(crate) type WatchFn = Box<dyn FnMut(&mut State, RawFd, FdEvent) -> bool>;
+
+/// Why a watched fd woke the shell up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FdEvent {
+    /// There's something to read.
+    Readable,
+    /// The other end was closed. What's left may still be read, but the fd isn't watched anymore.
+    Closed,
+    /// The fd is invalid, or failed. It isn't watched anymore.
+    Error,
+}
+
+impl FdEvent {
+    /// Parses the error `zle -F` passes to widgets, if any.
+    fn parse(error: Option<&str>) -> Self {
+        match error {
+            None => Self::Readable,
+            Some("hup") => Self::Closed,
+            Some(_) => Self::Error,
+        }
+    }
+}
+
+impl State {
+    /// Watches `fd` with `cb`, once the running builtin returns.
+    pub(crate) fn watch(&mut self, fd: RawFd, cb: WatchFn) {
+        self.watchers.insert(fd, cb);
+        self.watch_changes.push((fd, true));
+    }
+    pub(crate) fn unwatch(&mut self, fd: RawFd) -> bool {
+        let watched = self.watchers.remove(&fd).is_some();
+        if watched {
+            self.watch_changes.push((fd, false));
+        }
+        watched
+    }
+}
+
+/// The `<module>-fd` builtin, which zle runs with a fd and its error, if any.
+pub(crate) fn dispatch(state: &mut State, args: &[&str]) -> MaybeError {
+    let Some(fd) = args.first().and_then(|fd| fd.parse().ok()) else {
+        return Err(ZError::Usage("usage: fd [error]".into()).into());
+    };
+    // The callback is taken out, since it's given the state it's in
+    let Some(mut cb) = state.watchers.remove(&fd) else {
+        return Ok(());
+    };
+    let event = FdEvent::parse(args.get(1).copied());
+    let keep = cb(state, fd, event) && event == FdEvent::Readable;
+    // Unless the callback watched the fd again
+    if state.watchers.contains_key(&fd) {
+        return Ok(());
+    }
+    if keep {
+        state.watchers.insert(fd, cb);
+    } else {
+        state.watch_changes.push((fd, false));
+    }
+    Ok(())
+}
+
+/// The script applying `changes` to the fds zle watches.
+pub(crate) fn script(module_name: &str, changes: &[(RawFd, bool)]) -> String {
+    let lines: String = changes
+        .iter()
+        .map(|&(fd, watch)| {
+            if watch {
+                format!("zle -Fw {fd} _{module_name}_fd\n")
+            } else {
+                format!("zle -F {fd}\n")
+            }
+        })
+        .collect();
+    if lines.is_empty() {
+        return lines;
+    }
+    format!("if [[ -o zle ]]; then\n{lines}fi\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn watchers_stop_on_errors() {
+        let mut state = State::new(Box::new(Vec::<FdEvent>::new()));
+        state.watch(
+            12,
+            Box::new(|state, _, event| {
+                state.data::<Vec<FdEvent>>().push(event);
+                true
+            }),
+        );
+        dispatch(&mut state, &["12"]).unwrap();
+        dispatch(&mut state, &["12", "hup"]).unwrap();
+        dispatch(&mut state, &["12"]).unwrap();
+        assert_eq!(
+            *state.data::<Vec<FdEvent>>(),
+            [FdEvent::Readable, FdEvent::Closed]
+        );
+        assert_eq!(state.watch_changes, [(12, true), (12, false)]);
+        assert!(dispatch(&mut state, &["x"]).is_err());
+        assert_eq!(
+            script("mymod", &state.watch_changes),
+            "if [[ -o zle ]]; then\nzle -Fw 12 _mymod_fd\nzle -F 12\nfi\n"
+        );
+    }
+}
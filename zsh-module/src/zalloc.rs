@@ -0,0 +1,201 @@
+//! Statistics about the memory this crate allocates with zsh's allocators.
+//!
+//! Memory allocated with zsh's allocators isn't managed by Rust, so leaking it is easy. Comparing
+//! [`stats`] before and after running a builtin many times helps spotting leaks in long-lived
+//! shells.
+//!
+//! # Example
+//! ```no_run
+//! let before = zsh_module::zalloc::stats();
+//! zsh_module::zsh::eval_simple("mybuiltin").unwrap();
+//! let after = zsh_module::zalloc::stats();
+//! println!("mybuiltin allocated {} bytes", after.bytes - before.bytes);
+//! ```
+//!
+//! The same numbers can be printed from the shell by a builtin, see [`builtin`].
+use std::{
+    any::Any,
+    ffi::{c_char, c_void, CStr},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use zsh_sys as zsys;
+
+use crate::{AnyError, Cmd, MaybeError, Opts};
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static BYTES: AtomicUsize = AtomicUsize::new(0);
+static LIVE_ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static LIVE_BYTES: AtomicUsize = AtomicUsize::new(0);
+static HEAP_ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static HEAP_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// Totals since the module was loaded, and what's still allocated.
+///
+/// The totals only ever grow and there is no way to reset them, since other code running in the
+/// same shell may be comparing its own snapshots. To measure a piece of code, take a snapshot
+/// before and after it and diff the two. A leak shows as `live_allocations` growing each time the
+/// code runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Stats {
+    /// Allocations with `zalloc` and friends, which are owned by whatever they're handed to
+    /// (usually a parameter) and must be freed explicitly otherwise.
+    pub allocations: usize,
+    pub bytes: usize,
+    /// The part of `allocations` that's neither freed nor handed over to zsh yet, which then frees
+    /// it itself.
+    pub live_allocations: usize,
+    pub live_bytes: usize,
+    /// Allocations on zsh's heap, which are freed when zsh pops it, e.g. after a builtin returns.
+    pub heap_allocations: usize,
+    pub heap_bytes: usize,
+}
+
+/// The memory allocated since the module was loaded, and the part of it still owned by the
+/// module. See [`Stats`].
+pub fn stats() -> Stats {
+    Stats {
+        allocations: ALLOCATIONS.load(Ordering::Relaxed),
+        bytes: BYTES.load(Ordering::Relaxed),
+        live_allocations: LIVE_ALLOCATIONS.load(Ordering::Relaxed),
+        live_bytes: LIVE_BYTES.load(Ordering::Relaxed),
+        heap_allocations: HEAP_ALLOCATIONS.load(Ordering::Relaxed),
+        heap_bytes: HEAP_BYTES.load(Ordering::Relaxed),
+    }
+}
+
+/// Creates a builtin command that prints [`stats`], one `name value` pair per line, to look for
+/// leaks from the shell.
+/// ```no_run
+/// use zsh_module::{Builtin, ModuleBuilder};
+///
+/// let module = ModuleBuilder::new(())
+///     .builtin(zsh_module::zalloc::builtin(), Builtin::new("mymodule-zalloc").maxargs(Some(0)))
+///     .build();
+/// ```
+pub fn builtin<A: Any + ?Sized>() -> impl Cmd<A, AnyError> {
+    |_: &mut A, _name: &str, _args: &[&str], _opts: Opts| -> MaybeError {
+        let stats = stats();
+        for (name, value) in [
+            ("allocations", stats.allocations),
+            ("bytes", stats.bytes),
+            ("live_allocations", stats.live_allocations),
+            ("live_bytes", stats.live_bytes),
+            ("heap_allocations", stats.heap_allocations),
+            ("heap_bytes", stats.heap_bytes),
+        ] {
+            crate::zprintln!("{} {}", name, value);
+        }
+        Ok(())
+    }
+}
+
+fn record(bytes: usize) {
+    ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    BYTES.fetch_add(bytes, Ordering::Relaxed);
+    LIVE_ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    LIVE_BYTES.fetch_add(bytes, Ordering::Relaxed);
+}
+
+fn release(bytes: usize) {
+    LIVE_ALLOCATIONS.fetch_sub(1, Ordering::Relaxed);
+    LIVE_BYTES.fetch_sub(bytes, Ordering::Relaxed);
+}
+
+pub(crate) fn record_heap(bytes: usize) {
+    HEAP_ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    HEAP_BYTES.fetch_add(bytes, Ordering::Relaxed);
+}
+
+/// [`zsys::zalloc`], counted.
+#[cfg_attr(any(test, feature = "mock"), allow(dead_code))]
+pub(crate) unsafe fn zalloc(size: usize) -> *mut c_void {
+    record(size);
+    zsys::zalloc(size)
+}
+
+/// [`zsys::ztrdup`], counted.
+pub(crate) unsafe fn ztrdup(s: *const c_char) -> *mut c_char {
+    let dup = zsys::ztrdup(s);
+    if !dup.is_null() {
+        record(CStr::from_ptr(dup).to_bytes_with_nul().len());
+    }
+    dup
+}
+
+/// [`zsys::ztrdup_metafy`], counted.
+#[cfg_attr(not(feature = "zle"), allow(dead_code))]
+pub(crate) unsafe fn ztrdup_metafy(s: *const c_char) -> *mut c_char {
+    let dup = zsys::ztrdup_metafy(s);
+    if !dup.is_null() {
+        record(CStr::from_ptr(dup).to_bytes_with_nul().len());
+    }
+    dup
+}
+
+/// Stops counting the string `s`, made by [`ztrdup`] or [`ztrdup_metafy`], as live, since zsh
+/// takes it over (e.g. as the value of `setsparam`).
+pub(crate) unsafe fn hand_over(s: *mut c_char) -> *mut c_char {
+    if !s.is_null() {
+        release(CStr::from_ptr(s).to_bytes_with_nul().len());
+    }
+    s
+}
+
+/// Stops counting the array `raw` and its strings, all made by [`zalloc`] and [`ztrdup`] (see
+/// [`CStrArrayBuf`](crate::CStrArrayBuf)), as live, since zsh takes it over.
+pub(crate) unsafe fn hand_over_array(raw: *mut *mut c_char) -> *mut *mut c_char {
+    for size in allocation_sizes(raw) {
+        release(size);
+    }
+    raw
+}
+
+/// The size of each allocation making up the array `raw`: its strings, then itself.
+unsafe fn allocation_sizes(raw: *mut *mut c_char) -> Vec<usize> {
+    let mut sizes = Vec::new();
+    while !(*raw.add(sizes.len())).is_null() {
+        sizes.push(
+            CStr::from_ptr(*raw.add(sizes.len()))
+                .to_bytes_with_nul()
+                .len(),
+        );
+    }
+    sizes.push((sizes.len() + 1) * std::mem::size_of::<*mut c_char>());
+    sizes
+}
+
+/// [`zsys::freearray`], for an array counted like in [`hand_over_array`].
+pub(crate) unsafe fn freearray(raw: *mut *mut c_char) {
+    zsys::freearray(hand_over_array(raw));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn totals_only_grow() {
+        let before = stats();
+        record(16);
+        record_heap(4);
+        release(16);
+        let after = stats();
+        // Other tests may allocate concurrently
+        assert!(after.allocations > before.allocations && after.bytes >= before.bytes + 16);
+        assert!(after.heap_allocations > before.heap_allocations);
+        assert!(after.heap_bytes >= before.heap_bytes + 4);
+    }
+
+    #[test]
+    fn array_allocations() {
+        let mut array = [
+            c"a".as_ptr() as *mut c_char,
+            c"bc".as_ptr() as *mut c_char,
+            std::ptr::null_mut(),
+        ];
+        let sizes = unsafe { allocation_sizes(array.as_mut_ptr()) };
+        assert_eq!(sizes, [2, 3, 3 * std::mem::size_of::<*mut c_char>()]);
+    }
+}
@@ -0,0 +1,357 @@
+//! Functions used to interact with the Zsh Line Editor (ZLE).
+//!
+//! # Widget parameters
+//! While a widget runs, ZLE sets some special parameters describing how it was invoked, which
+//! [`widget`], [`keys`], [`numeric`], [`keymap`] and [`last_widget`] read. They all return
+//! [`None`] outside of widgets.
+//!
+//! ```no_run
+//! use zsh_module::zle;
+//!
+//! // Like most native widgets, repeat as many times as the prefix argument says
+//! for _ in 0..zle::numeric().unwrap_or(1) {
+//!     // ...
+//! }
+//! ```
+use std::{
+    ffi::{c_char, c_int, CStr},
+    ops::Range,
+};
+
+use crate::{
+    backend::{Backend, Current},
+    to_cstr, unmetafy, zalloc, ZError, ZResult,
+};
+
+use zsh_sys as zsys;
+
+/// Lets the user interactively edit `initial` using ZLE, just like the `vared` builtin does, and
+/// returns the edited string.
+///
+/// Fails if ZLE isn't usable (e.g. the shell is not interactive or ZLE is already active) or if
+/// the user aborted the edit.
+///
+/// # Examples
+/// ```no_run
+/// let editor = zsh_module::zle::edit_string("vim").unwrap();
+/// println!("Your editor is now {}", editor);
+/// ```
+pub fn edit_string(initial: &str) -> ZResult<String> {
+    crate::zsh::check_thread();
+    unsafe {
+        if zsys::SHTTY == -1 || zsys::opts[zsys::USEZLE as usize] == 0 {
+            return Err(ZError::Unavailable("ZLE not enabled"));
+        }
+        if zsys::zleactive != 0 {
+            return Err(ZError::Unavailable("ZLE cannot be used recursively"));
+        }
+        // Zle reads its initial buffer from the top of the buffer stack, just like `vared` does.
+        let initial = to_cstr(initial);
+        zsys::zinsertlinknode(
+            zsys::bufstack,
+            std::ptr::addr_of_mut!((*zsys::bufstack).node),
+            zalloc::hand_over(zalloc::ztrdup_metafy(initial.as_ptr())) as *mut _,
+        );
+
+        let mut lprompt: *mut c_char = std::ptr::null_mut();
+        let mut rprompt: *mut c_char = std::ptr::null_mut();
+        let line = zsys::zleentry(
+            zsys::ZLE_CMD_READ as c_int,
+            &mut lprompt as *mut *mut c_char,
+            &mut rprompt as *mut *mut c_char,
+            zsys::ZLRF_HISTORY as c_int,
+            zsys::ZLCON_VARED as c_int,
+        );
+        if line.is_null() || zsys::errflag != 0 {
+            zsys::errflag &= !(zsys::ERRFLAG_ERROR as c_int);
+            if !line.is_null() {
+                zsys::zsfree(line);
+            }
+            return Err(ZError::Unavailable("edit aborted"));
+        }
+
+        let mut bytes = unmetafy(CStr::from_ptr(line).to_bytes());
+        zsys::zsfree(line);
+        if bytes.last() == Some(&b'\n') {
+            bytes.pop();
+        }
+        Ok(String::from_utf8(bytes)?)
+    }
+}
+
+/// Whether the line editor is active, i.e. the user is editing a line. Widgets, including the
+/// callbacks of [`Module::watch_fd`](crate::Module::watch_fd) and of the
+/// [`main_thread`](crate::main_thread) queue, run while it is.
+pub fn is_active() -> bool {
+    crate::zsh::check_thread();
+    unsafe { zsys::zleactive != 0 }
+}
+
+/// Expands the prompts again and redraws them along with the line, like `zle reset-prompt`, e.g.
+/// once the information they show is out of date.
+///
+/// Does nothing, returning `false`, when the line editor isn't active: the prompts will be
+/// expanded anew when it is. Otherwise, it has to be called from a widget, like the callbacks of
+/// [`Module::watch_fd`](crate::Module::watch_fd) and of the [`main_thread`](crate::main_thread)
+/// queue, and not from a completion function.
+///
+/// # Examples
+/// ```no_run
+/// use zsh_module::{zle, zsh::ParamBuilder};
+///
+/// // Once a background job found out the new git status
+/// ParamBuilder::new("GIT_STATUS").scalar("dirty").build()?;
+/// zle::reset_prompt()?;
+/// # Ok::<(), zsh_module::ZError>(())
+/// ```
+pub fn reset_prompt() -> ZResult<bool> {
+    crate::zsh::check_thread();
+    call_zle(&["reset-prompt"])
+}
+
+/// Redraws the line, like `zle -R`, e.g. after printing something above it. Does nothing,
+/// returning `false`, when the line editor isn't active.
+pub fn redisplay() -> ZResult<bool> {
+    crate::zsh::check_thread();
+    call_zle(&["-R"])
+}
+
+/// Shows `text` below the line being edited, like `zle -M`, until the next key is pressed. Does
+/// nothing, returning `false`, when the line editor isn't active.
+///
+/// # Examples
+/// ```no_run
+/// zsh_module::zle::message("build finished").unwrap();
+/// ```
+pub fn message(text: &str) -> ZResult<bool> {
+    crate::zsh::check_thread();
+    call_zle(&["-M", text])
+}
+
+/// Runs the `zle` builtin with `args` if the line editor is active. The functions behind it are
+/// internal to the `zsh/zle` module.
+fn call_zle(args: &[&str]) -> ZResult<bool> {
+    if !is_active() {
+        return Ok(false);
+    }
+    let argv: Vec<&str> = ["zle"].iter().chain(args).copied().collect();
+    match crate::zsh::run(&argv)? {
+        0 => Ok(true),
+        status => Err(ZError::Status(status)),
+    }
+}
+
+/// Whether a line is accepted. See [`ModuleBuilder::accept_line`](crate::ModuleBuilder::accept_line).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AcceptAction {
+    /// Run the line.
+    #[default]
+    Accept,
+    /// Don't run the line, and let the user keep editing it.
+    KeepEditing,
+}
+
+/// Runs `cb` on `$BUFFER`, writing it back (and moving the cursor to its end) if it was changed.
+pub(crate) fn filter_buffer<T>(cb: impl FnOnce(&mut String) -> T) -> T {
+    let old = get_string(c"BUFFER").unwrap_or_default();
+    let mut buffer = old.clone();
+    let ret = cb(&mut buffer);
+    if buffer != old {
+        Current::set_str_param(c"BUFFER", buffer.as_bytes());
+        // Zle counts characters, not bytes
+        Current::set_int_param(c"CURSOR", buffer.chars().count() as i64);
+    }
+    ret
+}
+
+/// A part of the edit buffer to show in some style.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    /// The bytes of the buffer it covers.
+    pub range: Range<usize>,
+    /// How it looks, in the format of `$zle_highlight`, e.g. `fg=red,bold`.
+    pub style: String,
+}
+
+impl Span {
+    pub fn new(range: Range<usize>, style: impl Into<String>) -> Self {
+        Self {
+            range,
+            style: style.into(),
+        }
+    }
+}
+
+/// Highlights the edit buffer, e.g. to color commands depending on whether they exist. See
+/// [`ModuleBuilder::highlighter`](crate::ModuleBuilder::highlighter).
+///
+/// Any `FnMut(&str) -> Vec<Span>` is a highlighter.
+pub trait Highlighter {
+    /// The spans to highlight in `buffer`. Only called when it changes.
+    fn highlight(&mut self, buffer: &str) -> Vec<Span>;
+}
+
+impl<F> Highlighter for F
+where
+    F: FnMut(&str) -> Vec<Span>,
+{
+    fn highlight(&mut self, buffer: &str) -> Vec<Span> {
+        self(buffer)
+    }
+}
+
+/// Keeps the entries of a [`Highlighter`] in `$region_highlight`, telling them apart from the
+/// entries of others with a `memo` (or, before zsh 5.9, by remembering them).
+pub(crate) struct Highlighting {
+    highlighter: Box<dyn Highlighter>,
+    /// The buffer and entries of the last refresh.
+    last: Option<(String, Vec<String>)>,
+}
+
+impl Highlighting {
+    pub(crate) fn new(highlighter: impl Highlighter + 'static) -> Self {
+        Self {
+            highlighter: Box::new(highlighter),
+            last: None,
+        }
+    }
+    pub(crate) fn refresh(&mut self, memo: &str) {
+        let buffer = get_string(c"BUFFER").unwrap_or_default();
+        // Entries are told apart with a memo, which needs zsh 5.9. Before, ours are the ones
+        // added last time.
+        let tag = if cfg!(zsh_5_9) {
+            format!(",memo={}", memo)
+        } else {
+            String::new()
+        };
+        let last_ours = self.last.as_ref().map_or(&[][..], |(_, ours)| ours);
+        let (ours, mut entries): (Vec<String>, Vec<String>) =
+            Current::get_array_param(c"region_highlight")
+                .unwrap_or_default()
+                .into_iter()
+                .map(|entry| String::from_utf8_lossy(&entry).into_owned())
+                .partition(|entry| {
+                    if cfg!(zsh_5_9) {
+                        entry.ends_with(&tag)
+                    } else {
+                        last_ours.contains(entry)
+                    }
+                });
+        // Zle clears `$region_highlight` between lines, which may hold the same buffer
+        if self.last.as_ref() == Some(&(buffer.clone(), ours)) {
+            return;
+        }
+        // Offsets are in characters, and relative to the buffer, since they aren't prefixed with `P`
+        let chars_before =
+            |byte: usize| buffer.char_indices().take_while(|(i, _)| *i < byte).count();
+        let ours: Vec<String> = self
+            .highlighter
+            .highlight(&buffer)
+            .into_iter()
+            .map(|span| {
+                format!(
+                    "{} {} {}{}",
+                    chars_before(span.range.start),
+                    chars_before(span.range.end),
+                    span.style,
+                    tag
+                )
+            })
+            .collect();
+        entries.extend(ours.iter().cloned());
+        let entries: Vec<&[u8]> = entries.iter().map(|entry| entry.as_bytes()).collect();
+        Current::set_array_param(c"region_highlight", &entries);
+        self.last = Some((buffer, ours));
+    }
+}
+
+fn get_string(name: &CStr) -> Option<String> {
+    Current::get_str_param(name).map(|value| String::from_utf8_lossy(&value).into_owned())
+}
+
+/// The name of the widget being run, `$WIDGET`.
+pub fn widget() -> Option<String> {
+    crate::zsh::check_thread();
+    get_string(c"WIDGET")
+}
+
+/// The keys typed to invoke the widget being run, `$KEYS`. These are raw bytes, as escape
+/// sequences aren't necessarily valid UTF-8.
+pub fn keys() -> Option<Vec<u8>> {
+    crate::zsh::check_thread();
+    Current::get_str_param(c"KEYS")
+}
+
+/// The prefix argument given to the widget being run, `$NUMERIC`, if there was one.
+pub fn numeric() -> Option<i64> {
+    crate::zsh::check_thread();
+    get_string(c"NUMERIC")?.parse().ok()
+}
+
+/// The name of the keymap the widget being run was invoked from, `$KEYMAP`.
+pub fn keymap() -> Option<String> {
+    crate::zsh::check_thread();
+    get_string(c"KEYMAP")
+}
+
+/// The name of the last widget that ran before the current one, `$LASTWIDGET`.
+pub fn last_widget() -> Option<String> {
+    crate::zsh::check_thread();
+    get_string(c"LASTWIDGET")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock;
+
+    #[test]
+    fn widget_parameters() {
+        assert_eq!(numeric(), None);
+        assert_eq!(widget(), None);
+        mock::set_param("WIDGET", "my-widget");
+        mock::set_param("KEYS", "\x1bx");
+        mock::set_param("NUMERIC", "-3");
+        assert_eq!(widget().as_deref(), Some("my-widget"));
+        assert_eq!(keys().as_deref(), Some(&b"\x1bx"[..]));
+        assert_eq!(numeric(), Some(-3));
+    }
+
+    #[test]
+    fn buffer_is_only_written_when_changed() {
+        mock::set_param("BUFFER", "ls");
+        mock::set_param("CURSOR", "1");
+        filter_buffer(|buffer| buffer.push_str(""));
+        assert_eq!(mock::param("CURSOR").unwrap(), "1");
+        filter_buffer(|buffer| *buffer = "echo é".into());
+        assert_eq!(mock::param("BUFFER").unwrap(), "echo é");
+        assert_eq!(mock::param("CURSOR").unwrap(), "6");
+    }
+
+    #[test]
+    fn highlighting_keeps_other_entries() {
+        let mut calls = 0;
+        let mut highlighting = Highlighting::new(move |buffer: &str| {
+            calls += 1;
+            assert!(calls <= 2, "highlighted an unchanged buffer");
+            let end = buffer.find(' ').unwrap_or(buffer.len());
+            vec![Span::new(0..end, "fg=green")]
+        });
+        mock::set_array_param("region_highlight", &["0 1 standout"]);
+        mock::set_param("BUFFER", "éa b");
+        let memo = if cfg!(zsh_5_9) { ",memo=m" } else { "" };
+        highlighting.refresh("m");
+        highlighting.refresh("m");
+        assert_eq!(
+            mock::array_param("region_highlight").unwrap(),
+            ["0 1 standout".to_string(), format!("0 2 fg=green{memo}")]
+        );
+        mock::set_param("BUFFER", "ls");
+        mock::set_param("PREDISPLAY", "> ");
+        highlighting.refresh("m");
+        assert_eq!(
+            mock::array_param("region_highlight").unwrap(),
+            ["0 1 standout".to_string(), format!("0 2 fg=green{memo}")]
+        );
+    }
+}
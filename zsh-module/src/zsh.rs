@@ -1,10 +1,71 @@
 //! A collection of functions used to interact directly with Zsh
-use std::{io::Read, path::Path};
+use std::{
+    ffi::{c_int, CStr},
+    fs::File,
+    io::{Read, Seek},
+    os::fd::AsRawFd,
+    path::Path,
+    sync::OnceLock,
+    thread::ThreadId,
+};
 
-use crate::{to_cstr, MaybeError, ToCString};
+use crate::{
+    backend::{Backend, Current},
+    ConversionError, MaybeError, MetaCString, ToCString, ZError, ZResult,
+};
 
 use zsh_sys as zsys;
 
+pub mod commands;
+pub mod coproc;
+pub mod env;
+mod flock;
+mod glob;
+pub mod history;
+pub mod io;
+pub mod jobs;
+pub mod options;
+pub mod param;
+mod path;
+mod pattern;
+pub mod print;
+pub mod special;
+mod stat;
+mod time;
+pub mod timers;
+
+pub use flock::{flock, FileLock, LockOpts};
+pub use glob::{glob, GlobFlags};
+pub use param::{tie, unset, unset_matching, Param, ParamBuilder, ParamFlag, ParamValue};
+pub use path::{path, SearchPath};
+pub use pattern::Pattern;
+#[cfg(feature = "prompt")]
+pub use print::{expand_prompt, expand_prompt_width};
+pub use print::{quote, quote_vec, quote_with, Quoting};
+pub use stat::{lstat, stat, stat_builtin, FileInfo};
+pub use time::{strftime, strptime};
+
+/// The thread running the shell, once the module is booted.
+static SHELL_THREAD: OnceLock<ThreadId> = OnceLock::new();
+
+/// Records the calling thread as the shell's. See [`check_thread`].
+#[cfg_attr(not(feature = "export_module"), allow(dead_code))]
+pub(crate) fn set_shell_thread() {
+    let _ = SHELL_THREAD.set(std::thread::current().id());
+}
+
+/// Panics unless called from the shell's thread, since zsh isn't thread safe. Every function of
+/// this module checks it. Before the module is booted (e.g. in tests), anything goes.
+#[track_caller]
+pub(crate) fn check_thread() {
+    if let Some(&shell) = SHELL_THREAD.get() {
+        assert!(
+            shell == std::thread::current().id(),
+            "zsh can only be used from the shell's thread, see `zsh_module::main_thread`"
+        );
+    }
+}
+
 #[derive(Debug)]
 pub struct InternalError;
 
@@ -23,9 +84,10 @@ impl std::error::Error for InternalError {}
 /// ```
 ///
 pub fn eval_simple(cmd: &str) -> MaybeError<InternalError> {
+    crate::zsh::check_thread();
     static ZSH_CONTEXT_STRING: &[u8] = b"zsh-module-rs-eval\0";
     unsafe {
-        let cmd = to_cstr(cmd);
+        let cmd = MetaCString::new(cmd);
         zsys::execstring(
             cmd.as_ptr() as *mut _,
             1,
@@ -40,12 +102,218 @@ pub fn eval_simple(cmd: &str) -> MaybeError<InternalError> {
     }
 }
 
+/// How evaluating a script went. See [`eval`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EvalOutcome {
+    /// The exit status of the script, i.e. `$?` after it ran.
+    pub status: i32,
+    /// Whether zsh reported an error, e.g. because the script couldn't be parsed. The status is
+    /// meaningless then.
+    pub errflag: bool,
+}
+
+/// Evaluates a zsh script string, telling how it went.
+///
+/// Unlike with [`eval_simple`], an error reported by the script is cleared once it ran, so it
+/// doesn't abort whatever called the module. An error reported before is left alone.
+///
+/// # Examples
+/// ```no_run
+/// use zsh_module::zsh;
+///
+/// let outcome = zsh::eval("grep -q foo file");
+/// if !outcome.errflag && outcome.status == 1 {
+///     println!("no foo");
+/// }
+/// ```
+pub fn eval(cmd: &str) -> EvalOutcome {
+    crate::zsh::check_thread();
+    const ERROR: c_int = zsys::ERRFLAG_ERROR as c_int;
+    unsafe {
+        let before = zsys::errflag & ERROR;
+        zsys::errflag &= !ERROR;
+        let _ = eval_simple(cmd);
+        let errflag = zsys::errflag & ERROR != 0;
+        zsys::errflag = (zsys::errflag & !ERROR) | before;
+        EvalOutcome {
+            status: zsys::lastval as i32,
+            errflag,
+        }
+    }
+}
+
+/// Evaluates a zsh script string, returning its exit status, or [`ZError::Zsh`] if zsh reported an
+/// error. See [`eval`].
+pub fn eval_with_status(cmd: &str) -> ZResult<i32> {
+    crate::zsh::check_thread();
+    match eval(cmd) {
+        EvalOutcome { errflag: true, .. } => Err(ZError::Zsh),
+        EvalOutcome { status, .. } => Ok(status),
+    }
+}
+
+/// Runs a single command, given its name and arguments, and returns its exit status, or
+/// [`ZError::Zsh`] if zsh reported an error. Like typing it, the command may be a function, a
+/// builtin, or an external command found in the command hash table or `$PATH`.
+///
+/// Unlike building a string for [`eval`], the arguments are passed as they are, even when they
+/// contain quotes, spaces or newlines.
+///
+/// # Examples
+/// ```no_run
+/// let file = "notes from \"today\".txt";
+/// let status = zsh_module::zsh::run(&["touch", "--", file]).unwrap();
+/// ```
+pub fn run(argv: &[&str]) -> ZResult<i32> {
+    crate::zsh::check_thread();
+    const ARGV: &CStr = c"_zsh_module_argv";
+    if argv.is_empty() {
+        return Err(ZError::InvalidArgument {
+            name: "argv".into(),
+            msg: "no command to run".into(),
+        });
+    }
+    let values: Vec<&[u8]> = argv.iter().map(|arg| arg.as_bytes()).collect();
+    if !Current::set_array_param(ARGV, &values) {
+        return Err(ZError::Zsh);
+    }
+    // Not through a function, so commands like `typeset` act on the caller's scope
+    let status = eval_with_status("\"${_zsh_module_argv[@]}\"");
+    let _ = eval_simple("unset _zsh_module_argv");
+    status
+}
+
+/// Evaluates a zsh script string like [`eval_simple`], returning what it printed to its standard
+/// output. Like with `$(...)`, trailing newlines are removed, but the script runs in the current
+/// shell instead of a subshell, so it can change its state.
+///
+/// # Examples
+/// ```no_run
+/// let branch = zsh_module::zsh::eval_capture("git branch --show-current").unwrap();
+/// ```
+pub fn eval_capture(cmd: &str) -> ZResult<String> {
+    crate::zsh::check_thread();
+    // A file rather than a pipe, which would block once full since nothing reads it meanwhile
+    let mut output =
+        capture_file().map_err(|_| ZError::Unavailable("can't create a temporary file"))?;
+    let status = unsafe {
+        libc::fflush(std::ptr::null_mut());
+        let stdout = libc::dup(1);
+        if stdout < 0 {
+            return Err(ZError::Io(std::io::Error::last_os_error()));
+        }
+        let status = if libc::dup2(output.as_raw_fd(), 1) < 0 {
+            Err(ZError::Io(std::io::Error::last_os_error()))
+        } else {
+            let status = eval_simple(cmd).map_err(|_| ZError::Zsh);
+            libc::fflush(std::ptr::null_mut());
+            status
+        };
+        // Put back whatever the outcome, even if stdout was never redirected
+        let restored = match libc::dup2(stdout, 1) {
+            -1 => Err(ZError::Io(std::io::Error::last_os_error())),
+            _ => Ok(()),
+        };
+        libc::close(stdout);
+        restored.and(status)
+    };
+    status?;
+    let mut bytes = Vec::new();
+    output
+        .rewind()
+        .and_then(|_| output.read_to_end(&mut bytes))
+        .map_err(|_| ZError::Unavailable("can't read the output back"))?;
+    while bytes.last() == Some(&b'\n') {
+        bytes.pop();
+    }
+    String::from_utf8(bytes).map_err(|_| {
+        ConversionError::InvalidUtf8 {
+            what: "output".into(),
+        }
+        .into()
+    })
+}
+
+/// An anonymous temporary file.
+fn capture_file() -> std::io::Result<File> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let path = std::env::temp_dir().join(format!(
+        "zsh-module-capture-{}-{}",
+        std::process::id(),
+        COUNT.fetch_add(1, Ordering::Relaxed)
+    ));
+    let file = File::options()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    std::fs::remove_file(&path)?;
+    Ok(file)
+}
+
+/// Expands `words` like the arguments of a command, without globbing: parameter expansion
+/// (including flags like `(s.:.)`), command substitution, arithmetic expansion, brace expansion,
+/// `~` and quote removal. Returns the resulting words.
+///
+/// `words` is shell code, so a command substitution in it runs: don't give it text from an
+/// untrusted source. Use [`expand_glob`] to also expand glob patterns.
+///
+/// # Examples
+/// ```no_run
+/// let dirs = zsh_module::zsh::expand("${(s.:.)PATH}").unwrap();
+/// let args = zsh_module::zsh::expand("'a b' ~/c{1,2}").unwrap();
+/// assert_eq!(args.len(), 3);
+/// ```
+pub fn expand(words: &str) -> ZResult<Vec<String>> {
+    crate::zsh::check_thread();
+    expand_words(words, false)
+}
+
+/// Expands `words` like [`expand`], then expands the glob patterns in the result like the shell
+/// would, honoring options like `EXTENDED_GLOB` or `NULL_GLOB`.
+///
+/// # Examples
+/// ```no_run
+/// let sources = zsh_module::zsh::expand_glob("src/**/*.rs").unwrap();
+/// ```
+pub fn expand_glob(words: &str) -> ZResult<Vec<String>> {
+    crate::zsh::check_thread();
+    expand_words(words, true)
+}
+
+fn expand_words(words: &str, glob: bool) -> ZResult<Vec<String>> {
+    let expanded = options::with_options(&[(options::Opt::Glob, glob)], || {
+        expand_array(&format!("_zsh_module_expanded=( {}\n)", words))
+    })?;
+    expanded
+        .into_iter()
+        .map(|word| {
+            String::from_utf8(word).map_err(|_| {
+                ConversionError::InvalidUtf8 {
+                    what: "expansion".into(),
+                }
+                .into()
+            })
+        })
+        .collect()
+}
+
+/// Runs `script`, which assigns the words it expands to `$_zsh_module_expanded`, and returns them.
+fn expand_array(script: &str) -> ZResult<Vec<Vec<u8>>> {
+    const EXPANDED: &CStr = c"_zsh_module_expanded";
+    let status = eval_with_status(script);
+    let expanded = Current::get_array_param(EXPANDED);
+    let _ = eval_simple("unset _zsh_module_expanded");
+    match (status?, expanded) {
+        (0, Some(expanded)) => Ok(expanded),
+        _ => Err(ZError::Zsh),
+    }
+}
+
 // for some shell globals, take a look at Src/init.c:source
 
-// !TODO: implement zsh's stdin
-/* pub fn stdin() -> impl Read {
-    std::os::unix::io::FromRawFd::from_raw_fd(zsys::SHIN)
-} */
+pub use io::stdin;
 
 #[derive(Debug)]
 #[repr(u32)]
@@ -65,7 +333,8 @@ impl std::fmt::Display for SourceError {
 impl std::error::Error for SourceError {}
 
 pub fn source_file(path: impl ToCString) -> MaybeError<SourceError> {
-    let path = path.into_cstr();
+    crate::zsh::check_thread();
+    let path = MetaCString::new(path.into_cstr().to_bytes());
     let result = unsafe { zsys::source(path.as_ptr() as *mut _) };
     if result == zsys::source_return_SOURCE_OK {
         Ok(())
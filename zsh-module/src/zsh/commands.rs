@@ -0,0 +1,117 @@
+//! The commands zsh knows of, like `$builtins` and `$commands` from `zsh/parameter`.
+use std::{ffi::CStr, path::PathBuf};
+
+use zsh_sys as zsys;
+
+use crate::HashTable;
+
+/// What provides a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CommandKind {
+    /// A builtin that's ready to run.
+    Builtin,
+    /// A builtin of a module that will be loaded the first time it runs.
+    AutoloadBuiltin,
+    /// An external command found in `$path`.
+    External,
+    /// An external command given a path with `hash name=path`.
+    Hashed,
+}
+
+/// A command zsh knows of.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Command {
+    pub name: String,
+    pub kind: CommandKind,
+    /// Where the command is, for external ones.
+    pub path: Option<PathBuf>,
+}
+
+fn node_name(node: zsys::HashNode) -> String {
+    unsafe { CStr::from_ptr((*node).nam) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Every enabled builtin, sorted by name.
+pub fn builtins() -> impl Iterator<Item = Command> {
+    crate::zsh::check_thread();
+    let table = unsafe { HashTable::from_raw(zsys::builtintab) };
+    let mut builtins: Vec<_> = table
+        .nodes()
+        .filter(|&node| unsafe { (*node).flags } as u32 & zsys::DISABLED == 0)
+        .map(|node| Command {
+            name: node_name(node),
+            kind: if unsafe { (*node).flags } as u32 & zsys::BINF_ADDED != 0 {
+                CommandKind::Builtin
+            } else {
+                CommandKind::AutoloadBuiltin
+            },
+            path: None,
+        })
+        .collect();
+    builtins.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+    builtins.into_iter()
+}
+
+/// Every external command in zsh's command hash table, sorted by name.
+///
+/// The table is only complete if zsh has hashed all of `$path`, which it does first here when the
+/// `HASH_LIST_ALL` option is set (the default), like `$commands` does.
+pub fn externals() -> impl Iterator<Item = Command> {
+    crate::zsh::check_thread();
+    unsafe {
+        if zsys::opts[zsys::HASHLISTALL as usize] != 0 {
+            if let Some(fill) = (*zsys::cmdnamtab).filltable {
+                fill(zsys::cmdnamtab);
+            }
+        }
+    }
+    let table = unsafe { HashTable::from_raw(zsys::cmdnamtab) };
+    let mut commands: Vec<_> = table
+        .nodes()
+        .map(|node| unsafe {
+            let cmd = node as zsys::Cmdnam;
+            let name = node_name(node);
+            if (*node).flags as u32 & zsys::HASHED != 0 {
+                let path = CStr::from_ptr((*cmd).u.cmd).to_string_lossy().into_owned();
+                Command {
+                    name,
+                    kind: CommandKind::Hashed,
+                    path: Some(path.into()),
+                }
+            } else {
+                // Points into `$path`
+                let dir = CStr::from_ptr(*(*cmd).u.name)
+                    .to_string_lossy()
+                    .into_owned();
+                Command {
+                    path: Some(PathBuf::from(dir).join(&name)),
+                    name,
+                    kind: CommandKind::External,
+                }
+            }
+        })
+        .collect();
+    commands.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+    commands.into_iter()
+}
+
+/// Every builtin and external command called `name`, in the order zsh would pick them (builtins
+/// first). Aliases and functions aren't included.
+///
+/// # Example
+/// ```no_run
+/// for cmd in zsh_module::zsh::commands::providers("echo") {
+///     println!("{:?} {:?}", cmd.kind, cmd.path);
+/// }
+/// ```
+pub fn providers(name: &str) -> Vec<Command> {
+    crate::zsh::check_thread();
+    builtins()
+        .filter(|cmd| cmd.name == name)
+        .chain(externals().filter(|cmd| cmd.name == name))
+        .collect()
+}
@@ -0,0 +1,130 @@
+//! Access to the shell's coprocess, the one started with `coproc` and used by `print -p` and
+//! `read -p`.
+//!
+//! # Example
+//! ```no_run
+//! use std::io::{BufRead, BufReader, Write};
+//! use std::process::Command;
+//!
+//! use zsh_module::zsh::coproc;
+//!
+//! coproc::spawn(&mut Command::new("bc")).unwrap();
+//! coproc::writer().unwrap().write_all(b"2 + 2\n").unwrap();
+//! let mut answer = String::new();
+//! BufReader::new(coproc::reader().unwrap()).read_line(&mut answer).unwrap();
+//! ```
+//! The user can then keep talking to `bc` with `print -p` and `read -p`.
+use std::{
+    io::{self, Read, Write},
+    os::unix::io::{IntoRawFd, RawFd},
+    process::{Command, Stdio},
+};
+
+use zsh_sys as zsys;
+
+/// Reads what the coprocess writes, like `read -p`. See [`reader`].
+pub struct CoprocReader {
+    fd: RawFd,
+}
+
+/// Writes to the coprocess' input, like `print -p`. See [`writer`].
+pub struct CoprocWriter {
+    fd: RawFd,
+}
+
+impl Read for CoprocReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = unsafe { libc::read(self.fd, buf.as_mut_ptr() as *mut _, buf.len()) };
+        if n < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(n as usize)
+        }
+    }
+}
+
+impl Write for CoprocWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = unsafe { libc::write(self.fd, buf.as_ptr() as *const _, buf.len()) };
+        if n < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(n as usize)
+        }
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Returns the file descriptors used to talk to the coprocess as `(read_fd, write_fd)`, or
+/// [`None`] if there's no coprocess.
+pub fn fds() -> Option<(RawFd, RawFd)> {
+    crate::zsh::check_thread();
+    let (input, output) = unsafe { (zsys::coprocin, zsys::coprocout) };
+    if input < 0 || output < 0 {
+        None
+    } else {
+        Some((input, output))
+    }
+}
+
+/// Returns a reader over the coprocess' output, or [`None`] if there's no coprocess.
+///
+/// The reader borrows the shell's fd, so the coprocess stays available to the user.
+pub fn reader() -> Option<CoprocReader> {
+    crate::zsh::check_thread();
+    fds().map(|(fd, _)| CoprocReader { fd })
+}
+
+/// Returns a writer to the coprocess' input, or [`None`] if there's no coprocess.
+///
+/// The writer borrows the shell's fd, so the coprocess stays available to the user.
+pub fn writer() -> Option<CoprocWriter> {
+    crate::zsh::check_thread();
+    fds().map(|(_, fd)| CoprocWriter { fd })
+}
+
+/// Closes the coprocess' fds, which usually makes it exit, like `coproc :` does.
+pub fn close() {
+    crate::zsh::check_thread();
+    unsafe {
+        if zsys::coprocin >= 0 {
+            zsys::zclose(zsys::coprocin);
+            zsys::coprocin = -1;
+        }
+        if zsys::coprocout >= 0 {
+            zsys::zclose(zsys::coprocout);
+            zsys::coprocout = -1;
+        }
+    }
+}
+
+/// Hands an fd over to zsh, the same way it does for coprocess pipes.
+unsafe fn install_fd(fd: RawFd) -> io::Result<RawFd> {
+    let fd = zsys::movefd(fd);
+    if fd == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    // Mirror what zsh does with the pipes it creates for `coproc`.
+    *zsys::fdtable.add(fd as usize) = zsys::FDT_UNUSED as _;
+    Ok(fd)
+}
+
+/// Spawns `command` as the shell's coprocess, replacing the current one (if any), and returns
+/// its pid.
+pub fn spawn(command: &mut Command) -> io::Result<u32> {
+    crate::zsh::check_thread();
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let stdin = child.stdin.take().expect("stdin should be piped");
+    let stdout = child.stdout.take().expect("stdout should be piped");
+    close();
+    unsafe {
+        zsys::coprocin = install_fd(stdout.into_raw_fd())?;
+        zsys::coprocout = install_fd(stdin.into_raw_fd())?;
+    }
+    Ok(child.id())
+}
@@ -0,0 +1,101 @@
+//! Control over how parameters and the environment mix.
+//!
+//! When it starts, zsh imports the environment it was given as parameters, except for the ones
+//! marked as not importable (like some special parameters). Modules that sanitize the environment
+//! can scrub variables, or re-import ones changed behind zsh's back (e.g. by a C library calling
+//! `setenv`).
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+
+use zsh_sys as zsys;
+
+use crate::{metafy_heap, to_cstr, zalloc, HashTable, ZError, ZResult};
+
+fn param(name: &str) -> Option<zsys::HashNode> {
+    let node = unsafe { HashTable::from_raw(zsys::paramtab) }.get(name);
+    (!node.is_null()).then_some(node)
+}
+
+fn set_flag(name: &str, flag: u32, value: bool) -> ZResult<()> {
+    let node = param(name).ok_or(ZError::Unavailable("no such parameter"))?;
+    unsafe {
+        if value {
+            (*node).flags |= flag as i32;
+        } else {
+            (*node).flags &= !(flag as i32);
+        }
+    }
+    Ok(())
+}
+
+fn has_flag(name: &str, flag: u32) -> Option<bool> {
+    param(name).map(|node| unsafe { (*node).flags } as u32 & flag != 0)
+}
+
+/// Whether the parameter `name` is exported to the environment of commands, or [`None`] if it
+/// isn't set.
+pub fn is_exported(name: &str) -> Option<bool> {
+    crate::zsh::check_thread();
+    has_flag(name, zsys::PM_EXPORTED)
+}
+
+/// Whether the parameter `name` can be imported from the environment, or [`None`] if it isn't set.
+pub fn is_importable(name: &str) -> Option<bool> {
+    crate::zsh::check_thread();
+    has_flag(name, zsys::PM_DONTIMPORT).map(|dont| !dont)
+}
+
+/// Marks the parameter `name` as (not) importable from the environment. Parameters that aren't
+/// importable are skipped by [`reimport`].
+pub fn set_importable(name: &str, importable: bool) -> ZResult<()> {
+    crate::zsh::check_thread();
+    set_flag(name, zsys::PM_DONTIMPORT, !importable)
+}
+
+/// Marks the parameter `name` as (not) changeable when the shell is restricted (see the
+/// `RESTRICTED` option).
+pub fn set_restricted(name: &str, restricted: bool) -> ZResult<()> {
+    crate::zsh::check_thread();
+    set_flag(name, zsys::PM_RESTRICTED, restricted)
+}
+
+/// Sets the parameter `name` to the value of the environment variable `name`. Returns whether
+/// there was such a variable, unless the parameter isn't importable, in which case nothing is
+/// done and `false` is returned.
+pub fn reimport(name: &str) -> ZResult<bool> {
+    crate::zsh::check_thread();
+    if is_importable(name) == Some(false) {
+        return Ok(false);
+    }
+    let Some(value) = std::env::var_os(name) else {
+        return Ok(false);
+    };
+    let name = to_cstr(name);
+    let value = unsafe { zalloc::hand_over(zalloc::ztrdup(metafy_heap(OsStr::as_bytes(&value)))) };
+    let param = unsafe { zsys::setsparam(name.as_ptr() as *mut _, value) };
+    if param.is_null() {
+        return Err(ZError::Zsh);
+    }
+    Ok(true)
+}
+
+/// Unsets the parameter `name`, which also removes it from the environment of any command run
+/// afterwards.
+///
+/// # Example
+/// ```no_run
+/// use zsh_module::zsh::env;
+///
+/// for name in ["LD_PRELOAD", "LD_LIBRARY_PATH"] {
+///     env::scrub(name);
+/// }
+/// ```
+pub fn scrub(name: &str) {
+    crate::zsh::check_thread();
+    let cname = to_cstr(name);
+    unsafe { zsys::unsetparam(cname.as_ptr() as *mut _) };
+    // In case it wasn't imported in the first place
+    if !name.is_empty() && !name.contains('=') {
+        std::env::remove_var(name);
+    }
+}
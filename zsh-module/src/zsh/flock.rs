@@ -0,0 +1,129 @@
+use std::{
+    io,
+    os::unix::io::{AsRawFd, RawFd},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use zsh_sys as zsys;
+
+use crate::ToCString;
+
+/// Options for [`flock`].
+#[derive(Debug, Clone)]
+pub struct LockOpts {
+    /// How long to wait for the lock. [`None`] waits forever.
+    pub timeout: Option<Duration>,
+    /// Whether to take a shared (read) lock instead of an exclusive one, like `zsystem flock -r`.
+    pub shared: bool,
+    /// How often to retry while waiting for a lock with a timeout, like `zsystem flock -i`.
+    pub interval: Duration,
+}
+
+impl Default for LockOpts {
+    fn default() -> Self {
+        Self {
+            timeout: None,
+            shared: false,
+            interval: Duration::from_secs(1),
+        }
+    }
+}
+
+/// A lock held on a file. The lock is released when this is dropped.
+#[derive(Debug)]
+pub struct FileLock {
+    fd: RawFd,
+}
+
+impl AsRawFd for FileLock {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        unsafe { zsys::zcloselockfd(self.fd) };
+    }
+}
+
+fn try_lock(fd: RawFd, lock: &libc::flock, wait: bool) -> io::Result<bool> {
+    let cmd = if wait { libc::F_SETLKW } else { libc::F_SETLK };
+    if unsafe { libc::fcntl(fd, cmd, lock) } != -1 {
+        return Ok(true);
+    }
+    let err = io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(libc::EACCES | libc::EAGAIN) if !wait => Ok(false),
+        _ => Err(err),
+    }
+}
+
+/// Locks the file at `path`, with the same semantics as `zsystem flock`.
+///
+/// The file must already exist. Like zsh, this uses `fcntl` locks, so they're compatible with
+/// locks taken by `zsystem flock` in other shells.
+///
+/// Fails with [`io::ErrorKind::TimedOut`] if `opts.timeout` elapses before the lock is acquired.
+///
+/// # Examples
+/// ```no_run
+/// use std::time::Duration;
+/// use zsh_module::zsh::{flock, LockOpts};
+///
+/// let _lock = flock(
+///     "/tmp/cache.db",
+///     LockOpts { timeout: Some(Duration::from_secs(5)), ..Default::default() },
+/// ).unwrap();
+/// // The file is locked until `_lock` gets dropped.
+/// ```
+pub fn flock(path: impl AsRef<Path>, opts: LockOpts) -> io::Result<FileLock> {
+    crate::zsh::check_thread();
+    use std::os::unix::ffi::OsStrExt;
+    let path = path.as_ref().as_os_str().as_bytes().into_cstr();
+    let mode = if opts.shared {
+        libc::O_RDONLY
+    } else {
+        libc::O_RDWR
+    };
+    let fd = unsafe { libc::open(path.as_ptr(), mode | libc::O_NOCTTY) };
+    if fd == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    let fd = unsafe { zsys::movefd(fd) };
+    if fd == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    unsafe { zsys::addlockfd(fd, 1) };
+    // From now on, dropping the guard releases the fd.
+    let guard = FileLock { fd };
+
+    let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+    lock.l_type = if opts.shared {
+        libc::F_RDLCK
+    } else {
+        libc::F_WRLCK
+    } as _;
+    lock.l_whence = libc::SEEK_SET as _;
+
+    match opts.timeout {
+        None => {
+            try_lock(fd, &lock, true)?;
+        }
+        Some(timeout) => {
+            let start = Instant::now();
+            while !try_lock(fd, &lock, false)? {
+                let elapsed = start.elapsed();
+                if elapsed >= timeout {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "timeout reached while waiting for lock",
+                    ));
+                }
+                std::thread::sleep(opts.interval.min(timeout - elapsed));
+            }
+        }
+    }
+    Ok(guard)
+}
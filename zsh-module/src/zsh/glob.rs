@@ -0,0 +1,65 @@
+use std::{ffi::CStr, os::unix::ffi::OsStringExt, path::PathBuf};
+
+use crate::{
+    backend::{Backend, Current},
+    ZResult,
+};
+
+use super::{
+    eval_simple, expand_array,
+    options::{with_options, Opt},
+};
+
+/// Options for [`glob`]. Each one turns a shell option on for the expansion; when it's `false`
+/// the user's setting is used.
+#[derive(Debug, Clone, Default)]
+pub struct GlobFlags {
+    /// Turn `EXTENDED_GLOB` on, for `^`, `~` and `#` in patterns.
+    pub extended: bool,
+    /// Turn `NULL_GLOB` on, so that nothing matching isn't an error.
+    pub null: bool,
+    /// Turn `GLOB_DOTS` on, so that files starting with `.` match without an explicit `.`.
+    pub dots: bool,
+}
+
+/// Expands the glob pattern `pattern` like the shell would, including glob qualifiers like
+/// `*(.om[1])`, and returns the paths it matches.
+///
+/// Only globbing is done: parameters and such in `pattern` aren't expanded. Unless `NULL_GLOB`
+/// is set, nothing matching is an error, like it would be in the shell.
+///
+/// # Examples
+/// ```no_run
+/// use zsh_module::zsh::{glob, GlobFlags};
+///
+/// // The most recently modified file
+/// let newest = glob("*(.om[1])", GlobFlags::default()).unwrap();
+/// let flags = GlobFlags {
+///     null: true,
+///     ..Default::default()
+/// };
+/// let backups = glob("**/*~", flags).unwrap();
+/// ```
+pub fn glob(pattern: &str, flags: GlobFlags) -> ZResult<Vec<PathBuf>> {
+    crate::zsh::check_thread();
+    const PATTERN: &CStr = c"_zsh_module_glob";
+    let options: Vec<_> = [
+        (Opt::Glob, true),
+        (Opt::ExtendedGlob, flags.extended),
+        (Opt::NullGlob, flags.null),
+        (Opt::GlobDots, flags.dots),
+    ]
+    .into_iter()
+    .filter(|&(_, set)| set)
+    .collect();
+    Current::set_str_param(PATTERN, pattern.as_bytes());
+    // `~` makes the value a pattern, and `==` keeps it a single word under SH_WORD_SPLIT
+    let expanded = with_options(&options, || {
+        expand_array("_zsh_module_expanded=( ${~==_zsh_module_glob} )")
+    });
+    let _ = eval_simple("unset _zsh_module_glob");
+    Ok(expanded?
+        .into_iter()
+        .map(|path| std::ffi::OsString::from_vec(path).into())
+        .collect())
+}
@@ -0,0 +1,139 @@
+//! The shell's history list, as shown by `fc -l`.
+//!
+//! # Example
+//! ```no_run
+//! use zsh_module::zsh::history;
+//!
+//! // Report commands run more than once
+//! let mut seen = std::collections::HashSet::new();
+//! for entry in history::entries() {
+//!     if !seen.insert(entry.text.clone()) {
+//!         println!("{} is a duplicate", entry.number);
+//!     }
+//! }
+//! history::append("echo added from rust")?;
+//! # Ok::<(), zsh_module::ZError>(())
+//! ```
+use std::{
+    ffi::{c_int, CStr},
+    time::{Duration, SystemTime},
+};
+
+use zsh_sys as zsys;
+
+use crate::{
+    backend::{Backend, Current},
+    unmetafy,
+    zsh::{eval_simple, eval_with_status},
+    ZError, ZResult,
+};
+
+/// An entry of the history list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Entry {
+    /// The history event number, as used by `!number` or `fc`.
+    pub number: i64,
+    /// The command line.
+    pub text: String,
+    /// When the command started, if known.
+    pub started: Option<SystemTime>,
+    /// How long the command ran, if known.
+    pub duration: Option<Duration>,
+}
+
+impl Entry {
+    /// # Safety
+    /// `raw` must point to a valid history entry.
+    unsafe fn from_raw(raw: zsys::Histent) -> Self {
+        let raw = &*raw;
+        let text = unmetafy(CStr::from_ptr(raw.node.nam).to_bytes());
+        // Both times are 0 for entries read from a file without timestamps
+        let time = |t: zsys::time_t| {
+            u64::try_from(t)
+                .ok()
+                .filter(|&t| t != 0)
+                .map(|t| SystemTime::UNIX_EPOCH + Duration::from_secs(t))
+        };
+        let started = time(raw.stim);
+        let duration = started
+            .zip(time(raw.ftim))
+            .and_then(|(start, end)| end.duration_since(start).ok());
+        Self {
+            number: raw.histnum,
+            text: String::from_utf8_lossy(&text).into_owned(),
+            started,
+            duration,
+        }
+    }
+}
+
+/// The entries of the history list, oldest first.
+///
+/// They're copied out all at once, since running any command may add or drop entries.
+pub fn entries() -> Vec<Entry> {
+    crate::zsh::check_thread();
+    let ring = unsafe { zsys::hist_ring };
+    // The ring goes from the newest entry up to the oldest, and then back down to the newest
+    let mut next = (!ring.is_null()).then(|| unsafe { (*ring).down });
+    std::iter::from_fn(|| {
+        let current = next?;
+        next = Some(unsafe { zsys::down_histent(current) }).filter(|he| !he.is_null());
+        Some(unsafe { Entry::from_raw(current) })
+    })
+    .collect()
+}
+
+/// The entry with the event number `number`, if it's still in the history list.
+pub fn get(number: i64) -> Option<Entry> {
+    crate::zsh::check_thread();
+    let raw = unsafe { zsys::gethistent(number, zsys::GETHIST_EXACT as c_int) };
+    if raw.is_null() || unsafe { (*raw).histnum } != number {
+        return None;
+    }
+    Some(unsafe { Entry::from_raw(raw) })
+}
+
+/// Adds `text` to the end of the history list, like `print -s`.
+pub fn append(text: &str) -> ZResult<()> {
+    crate::zsh::check_thread();
+    const PARAM: &CStr = c"_zsh_module_history_text";
+    Current::set_str_param(PARAM, text.as_bytes());
+    let status = eval_with_status("print -rs -- \"$_zsh_module_history_text\"");
+    let _ = eval_simple("unset _zsh_module_history_text");
+    match status? {
+        0 => Ok(()),
+        _ => Err(ZError::Unavailable("the history can't be changed")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entry_times() {
+        let mut raw = zsys::histent {
+            node: zsys::hashnode {
+                next: std::ptr::null_mut(),
+                nam: c"make \xc5\x83\xb1".as_ptr() as *mut _,
+                flags: 0,
+            },
+            up: std::ptr::null_mut(),
+            down: std::ptr::null_mut(),
+            zle_text: std::ptr::null_mut(),
+            stim: 100,
+            ftim: 103,
+            words: std::ptr::null_mut(),
+            nwords: 0,
+            histnum: 7,
+        };
+        let entry = unsafe { Entry::from_raw(&mut raw) };
+        assert_eq!(entry.number, 7);
+        assert_eq!(entry.text, "make ő");
+        assert_eq!(entry.duration, Some(Duration::from_secs(3)));
+        (raw.stim, raw.ftim) = (0, 0);
+        let entry = unsafe { Entry::from_raw(&mut raw) };
+        assert_eq!((entry.started, entry.duration), (None, None));
+    }
+}
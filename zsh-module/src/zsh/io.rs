@@ -0,0 +1,76 @@
+//! The standard input, output and error of the running builtin, as zsh set them up.
+//!
+//! Zsh applies a builtin's redirections (e.g. `mybuiltin > file`, `mybuiltin | less` or multios
+//! like `mybuiltin > a > b`) to its file descriptors for just as long as it runs. Output buffered
+//! by [`std::io::stdout`] (e.g. from [`println!`]) can outlive the builtin and end up wherever the
+//! next command writes to, while these writers aren't buffered.
+//!
+//! # Example
+//! ```no_run
+//! use std::io::{Read, Write};
+//!
+//! use zsh_module::{zprintln, zsh::io};
+//!
+//! let mut input = String::new();
+//! io::stdin().read_to_string(&mut input)?;
+//! zprintln!("{} lines", input.lines().count());
+//! writeln!(io::stderr(), "couldn't read foo")?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+use crate::Fd;
+
+/// Flushes the C library's buffers, where zsh's own output waits, so it's written before
+/// ours.
+fn flush_stdio() {
+    unsafe { libc::fflush(std::ptr::null_mut()) };
+}
+
+/// The standard input of the builtin, like the output of `echo foo` in `echo foo | mybuiltin`.
+///
+/// This isn't zsh's `SHIN`, which is where the shell reads its commands from: in a script, that's
+/// the script itself.
+pub fn stdin() -> Fd {
+    crate::zsh::check_thread();
+    Fd::new(0)
+}
+
+/// The standard output of the builtin.
+pub fn stdout() -> Fd {
+    crate::zsh::check_thread();
+    flush_stdio();
+    Fd::new(1)
+}
+
+/// The standard error of the builtin.
+pub fn stderr() -> Fd {
+    crate::zsh::check_thread();
+    flush_stdio();
+    Fd::new(2)
+}
+
+#[macro_export]
+/// Prints to [`zsh::io::stdout`](crate::zsh::io::stdout), like [`print!`].
+///
+/// Unlike with [`print!`], a failure to write (e.g. to a closed pipe) is ignored, like the end of
+/// the output was.
+macro_rules! zprint {
+    ($($arg:tt)*) => {{
+        use ::std::io::Write as _;
+        let _ = ::std::write!($crate::zsh::io::stdout(), $($arg)*);
+    }};
+}
+
+#[macro_export]
+/// Prints to [`zsh::io::stdout`](crate::zsh::io::stdout) with a newline, like [`println!`].
+///
+/// Unlike with [`println!`], a failure to write (e.g. to a closed pipe) is ignored, like the end
+/// of the output was.
+macro_rules! zprintln {
+    () => {
+        $crate::zprint!("\n")
+    };
+    ($($arg:tt)*) => {{
+        use ::std::io::Write as _;
+        let _ = ::std::writeln!($crate::zsh::io::stdout(), $($arg)*);
+    }};
+}
@@ -0,0 +1,159 @@
+//! The shell's job table, as shown by `jobs`.
+//!
+//! # Example
+//! ```no_run
+//! use zsh_module::zsh::jobs::{self, JobState};
+//!
+//! // Resume every suspended job in the background
+//! for job in jobs::list() {
+//!     if job.state == JobState::Suspended {
+//!         job.kill(libc::SIGCONT)?;
+//!     }
+//! }
+//! # Ok::<(), zsh_module::ZError>(())
+//! ```
+use std::ffi::{c_int, CStr};
+
+use zsh_sys as zsys;
+
+use crate::{unmetafy, zsh::eval_with_status, ZError, ZResult};
+
+/// Whether a job is still running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum JobState {
+    Running,
+    /// Stopped by a signal, like after `^Z`.
+    Suspended,
+    /// Finished, but not reported yet.
+    Done,
+}
+
+/// A job of the job table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Job {
+    /// The job number, as used by `%number`.
+    pub number: usize,
+    /// The process group leader of the job.
+    pub pid: i32,
+    pub state: JobState,
+    /// The command line of the job, as shown by `jobs`.
+    pub text: String,
+}
+
+impl Job {
+    /// # Safety
+    /// `raw` must point to a valid job.
+    unsafe fn from_raw(number: usize, raw: &zsys::job) -> Self {
+        let stat = raw.stat as u32;
+        let state = if stat & zsys::STAT_DONE != 0 {
+            JobState::Done
+        } else if stat & zsys::STAT_STOPPED != 0 {
+            JobState::Suspended
+        } else {
+            JobState::Running
+        };
+        // Every process of a pipeline has its own part of the command line
+        let mut parts = Vec::new();
+        let mut process = raw.procs;
+        while let Some(proc) = process.as_ref() {
+            let text = unmetafy(CStr::from_ptr(proc.text.as_ptr()).to_bytes());
+            parts.push(String::from_utf8_lossy(&text).into_owned());
+            process = proc.next;
+        }
+        Self {
+            number,
+            pid: raw.gleader,
+            state,
+            text: parts.join(" | "),
+        }
+    }
+    /// Runs a job control builtin on this job, failing if it returns a non-zero status.
+    fn builtin(&self, cmd: &str) -> ZResult<()> {
+        match eval_with_status(&format!("{} %{}", cmd, self.number))? {
+            0 => Ok(()),
+            _ => Err(ZError::Unavailable("the job no longer exists")),
+        }
+    }
+    /// Waits for the job to finish, returning its exit status.
+    ///
+    /// A suspended job must be resumed first, or this blocks until it is.
+    pub fn wait(&self) -> ZResult<i32> {
+        if get(self.number).is_none() {
+            return Err(ZError::Unavailable("the job no longer exists"));
+        }
+        eval_with_status(&format!("wait %{}", self.number))
+    }
+    /// Sends `signal` to every process of the job.
+    pub fn kill(&self, signal: c_int) -> ZResult<()> {
+        self.builtin(&format!("kill -n {}", signal))
+    }
+    /// Removes the job from the job table, so the shell no longer reports or waits for it.
+    pub fn disown(self) -> ZResult<()> {
+        self.builtin("disown")
+    }
+}
+
+/// Whether an entry of the job table is a job `jobs` would list.
+fn is_listed(raw: &zsys::job) -> bool {
+    let stat = raw.stat as u32;
+    // Subjobs are part of their superjob
+    stat & zsys::STAT_INUSE != 0
+        && stat & (zsys::STAT_SUBJOB | zsys::STAT_NOPRINT) == 0
+        && !raw.procs.is_null()
+}
+
+/// The jobs of the job table, by number.
+pub fn list() -> Vec<Job> {
+    crate::zsh::check_thread();
+    (1..=unsafe { zsys::maxjob }.max(0) as usize)
+        .filter_map(get)
+        .collect()
+}
+
+/// The job with the number `number`, if there's one.
+pub fn get(number: usize) -> Option<Job> {
+    crate::zsh::check_thread();
+    let table = unsafe { zsys::jobtab };
+    // The current job is the one running this code
+    let number_c = number as c_int;
+    if table.is_null()
+        || number == 0
+        || number_c > unsafe { zsys::maxjob }
+        || number_c == unsafe { zsys::thisjob }
+    {
+        return None;
+    }
+    let raw = unsafe { &*table.add(number) };
+    is_listed(raw).then(|| unsafe { Job::from_raw(number, raw) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(text: &CStr, next: *mut zsys::process) -> zsys::process {
+        let mut raw: zsys::process = unsafe { std::mem::zeroed() };
+        for (dst, &src) in raw.text.iter_mut().zip(text.to_bytes()) {
+            *dst = src as _;
+        }
+        raw.next = next;
+        raw
+    }
+
+    #[test]
+    fn pipeline_text_and_state() {
+        let mut tail = process(c"grep \x83\x30", std::ptr::null_mut());
+        let mut head = process(c"tail -f log", &mut tail);
+        let mut raw: zsys::job = unsafe { std::mem::zeroed() };
+        raw.gleader = 42;
+        raw.stat = (zsys::STAT_INUSE | zsys::STAT_STOPPED) as c_int;
+        raw.procs = &mut head;
+        let job = unsafe { Job::from_raw(2, &raw) };
+        assert_eq!(job.text, "tail -f log | grep \x10");
+        assert_eq!((job.pid, job.state), (42, JobState::Suspended));
+        raw.stat |= zsys::STAT_SUBJOB as c_int;
+        assert!(!is_listed(&raw));
+    }
+}
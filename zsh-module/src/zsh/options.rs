@@ -0,0 +1,384 @@
+//! Zsh's shell options, as `setopt` and `$options` (from `zsh/parameter`) see them.
+use std::{
+    ffi::{c_char, c_int, CStr},
+    marker::PhantomData,
+};
+
+use zsh_sys as zsys;
+
+use crate::{to_cstr, HashTable, ZError, ZResult};
+
+macro_rules! options {
+    ($($(#[$attr:meta])* $variant:ident = $optno:ident,)*) => {
+        /// A shell option, named like in zsh's documentation (e.g. `EXTENDED_GLOB` is
+        /// [`Opt::ExtendedGlob`]).
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        #[non_exhaustive]
+        pub enum Opt {
+            $($(#[$attr])* $variant,)*
+        }
+
+        impl Opt {
+            /// The index of the option in zsh's `opts`.
+            fn optno(self) -> usize {
+                match self {
+                    $($(#[$attr])* Self::$variant => zsys::$optno as usize,)*
+                }
+            }
+        }
+    };
+}
+
+options! {
+    Aliases = ALIASESOPT,
+    AliasFuncDef = ALIASFUNCDEF,
+    AllExport = ALLEXPORT,
+    AlwaysLastPrompt = ALWAYSLASTPROMPT,
+    AlwaysToEnd = ALWAYSTOEND,
+    AppendHistory = APPENDHISTORY,
+    AutoCd = AUTOCD,
+    AutoContinue = AUTOCONTINUE,
+    AutoList = AUTOLIST,
+    AutoMenu = AUTOMENU,
+    AutoNameDirs = AUTONAMEDIRS,
+    AutoParamKeys = AUTOPARAMKEYS,
+    AutoParamSlash = AUTOPARAMSLASH,
+    AutoPushd = AUTOPUSHD,
+    AutoRemoveSlash = AUTOREMOVESLASH,
+    AutoResume = AUTORESUME,
+    BadPattern = BADPATTERN,
+    BangHist = BANGHIST,
+    BareGlobQual = BAREGLOBQUAL,
+    BashAutoList = BASHAUTOLIST,
+    BashRematch = BASHREMATCH,
+    Beep = BEEP,
+    BgNice = BGNICE,
+    BraceCcl = BRACECCL,
+    BsdEcho = BSDECHO,
+    CaseGlob = CASEGLOB,
+    CaseMatch = CASEMATCH,
+    #[cfg(zsh_5_9)]
+    CasePaths = CASEPATHS,
+    CBases = CBASES,
+    CdableVars = CDABLEVARS,
+    CdSilent = CDSILENT,
+    ChaseDots = CHASEDOTS,
+    ChaseLinks = CHASELINKS,
+    CheckJobs = CHECKJOBS,
+    CheckRunningJobs = CHECKRUNNINGJOBS,
+    Clobber = CLOBBER,
+    #[cfg(zsh_5_9)]
+    ClobberEmpty = CLOBBEREMPTY,
+    AppendCreate = APPENDCREATE,
+    CombiningChars = COMBININGCHARS,
+    CompleteAliases = COMPLETEALIASES,
+    CompleteInWord = COMPLETEINWORD,
+    Correct = CORRECT,
+    CorrectAll = CORRECTALL,
+    ContinueOnError = CONTINUEONERROR,
+    CPrecedences = CPRECEDENCES,
+    CshJunkieHistory = CSHJUNKIEHISTORY,
+    CshJunkieLoops = CSHJUNKIELOOPS,
+    CshJunkieQuotes = CSHJUNKIEQUOTES,
+    CshNullCmd = CSHNULLCMD,
+    CshNullGlob = CSHNULLGLOB,
+    DebugBeforeCmd = DEBUGBEFORECMD,
+    Emacs = EMACSMODE,
+    Equals = EQUALS,
+    ErrExit = ERREXIT,
+    ErrReturn = ERRRETURN,
+    Exec = EXECOPT,
+    ExtendedGlob = EXTENDEDGLOB,
+    ExtendedHistory = EXTENDEDHISTORY,
+    EvalLineno = EVALLINENO,
+    FlowControl = FLOWCONTROL,
+    ForceFloat = FORCEFLOAT,
+    FunctionArgZero = FUNCTIONARGZERO,
+    Glob = GLOBOPT,
+    GlobalExport = GLOBALEXPORT,
+    GlobalRcs = GLOBALRCS,
+    GlobAssign = GLOBASSIGN,
+    GlobComplete = GLOBCOMPLETE,
+    GlobDots = GLOBDOTS,
+    GlobStarShort = GLOBSTARSHORT,
+    GlobSubst = GLOBSUBST,
+    HashCmds = HASHCMDS,
+    HashDirs = HASHDIRS,
+    HashExecutablesOnly = HASHEXECUTABLESONLY,
+    HashListAll = HASHLISTALL,
+    HistAllowClobber = HISTALLOWCLOBBER,
+    HistBeep = HISTBEEP,
+    HistExpireDupsFirst = HISTEXPIREDUPSFIRST,
+    HistFcntlLock = HISTFCNTLLOCK,
+    HistFindNoDups = HISTFINDNODUPS,
+    HistIgnoreAllDups = HISTIGNOREALLDUPS,
+    HistIgnoreDups = HISTIGNOREDUPS,
+    HistIgnoreSpace = HISTIGNORESPACE,
+    HistLexWords = HISTLEXWORDS,
+    HistNoFunctions = HISTNOFUNCTIONS,
+    HistNoStore = HISTNOSTORE,
+    HistReduceBlanks = HISTREDUCEBLANKS,
+    HistSaveByCopy = HISTSAVEBYCOPY,
+    HistSaveNoDups = HISTSAVENODUPS,
+    HistSubstPattern = HISTSUBSTPATTERN,
+    HistVerify = HISTVERIFY,
+    Hup = HUP,
+    IgnoreBraces = IGNOREBRACES,
+    IgnoreCloseBraces = IGNORECLOSEBRACES,
+    IgnoreEof = IGNOREEOF,
+    IncAppendHistory = INCAPPENDHISTORY,
+    IncAppendHistoryTime = INCAPPENDHISTORYTIME,
+    Interactive = INTERACTIVE,
+    InteractiveComments = INTERACTIVECOMMENTS,
+    KshArrays = KSHARRAYS,
+    KshAutoload = KSHAUTOLOAD,
+    KshGlob = KSHGLOB,
+    KshOptionPrint = KSHOPTIONPRINT,
+    KshTypeset = KSHTYPESET,
+    KshZeroSubscript = KSHZEROSUBSCRIPT,
+    ListAmbiguous = LISTAMBIGUOUS,
+    ListBeep = LISTBEEP,
+    ListPacked = LISTPACKED,
+    ListRowsFirst = LISTROWSFIRST,
+    ListTypes = LISTTYPES,
+    LocalLoops = LOCALLOOPS,
+    LocalOptions = LOCALOPTIONS,
+    LocalPatterns = LOCALPATTERNS,
+    LocalTraps = LOCALTRAPS,
+    Login = LOGINSHELL,
+    LongListJobs = LONGLISTJOBS,
+    MagicEqualSubst = MAGICEQUALSUBST,
+    MailWarning = MAILWARNING,
+    MarkDirs = MARKDIRS,
+    MenuComplete = MENUCOMPLETE,
+    Monitor = MONITOR,
+    Multibyte = MULTIBYTE,
+    MultiFuncDef = MULTIFUNCDEF,
+    Multios = MULTIOS,
+    Nomatch = NOMATCH,
+    Notify = NOTIFY,
+    NullGlob = NULLGLOB,
+    NumericGlobSort = NUMERICGLOBSORT,
+    OctalZeroes = OCTALZEROES,
+    Overstrike = OVERSTRIKE,
+    PathDirs = PATHDIRS,
+    PathScript = PATHSCRIPT,
+    PipeFail = PIPEFAIL,
+    PosixAliases = POSIXALIASES,
+    PosixArgZero = POSIXARGZERO,
+    PosixBuiltins = POSIXBUILTINS,
+    PosixCd = POSIXCD,
+    PosixIdentifiers = POSIXIDENTIFIERS,
+    PosixJobs = POSIXJOBS,
+    PosixStrings = POSIXSTRINGS,
+    PosixTraps = POSIXTRAPS,
+    PrintEightBit = PRINTEIGHTBIT,
+    PrintExitValue = PRINTEXITVALUE,
+    Privileged = PRIVILEGED,
+    PromptBang = PROMPTBANG,
+    PromptCr = PROMPTCR,
+    PromptPercent = PROMPTPERCENT,
+    PromptSp = PROMPTSP,
+    PromptSubst = PROMPTSUBST,
+    PushdIgnoreDups = PUSHDIGNOREDUPS,
+    PushdMinus = PUSHDMINUS,
+    PushdSilent = PUSHDSILENT,
+    PushdToHome = PUSHDTOHOME,
+    RcExpandParam = RCEXPANDPARAM,
+    RcQuotes = RCQUOTES,
+    Rcs = RCS,
+    RecExact = RECEXACT,
+    RematchPcre = REMATCHPCRE,
+    Restricted = RESTRICTED,
+    RmStarSilent = RMSTARSILENT,
+    RmStarWait = RMSTARWAIT,
+    ShareHistory = SHAREHISTORY,
+    ShFileExpansion = SHFILEEXPANSION,
+    ShGlob = SHGLOB,
+    ShinStdin = SHINSTDIN,
+    ShNullCmd = SHNULLCMD,
+    ShOptionLetters = SHOPTIONLETTERS,
+    ShortLoops = SHORTLOOPS,
+    ShortRepeat = SHORTREPEAT,
+    ShWordSplit = SHWORDSPLIT,
+    SingleCommand = SINGLECOMMAND,
+    SingleLineZle = SINGLELINEZLE,
+    SourceTrace = SOURCETRACE,
+    SunKeyboardHack = SUNKEYBOARDHACK,
+    TransientRprompt = TRANSIENTRPROMPT,
+    TrapsAsync = TRAPSASYNC,
+    TypesetSilent = TYPESETSILENT,
+    #[cfg(zsh_5_9)]
+    TypesetToUnset = TYPESETTOUNSET,
+    Unset = UNSET,
+    Verbose = VERBOSE,
+    Vi = VIMODE,
+    WarnCreateGlobal = WARNCREATEGLOBAL,
+    WarnNestedVar = WARNNESTEDVAR,
+    Xtrace = XTRACE,
+    Zle = USEZLE,
+    Dvorak = DVORAK,
+}
+
+/// Every shell option with whether it's set, sorted by name.
+///
+/// Names are zsh's canonical ones, lowercase and without underscores, like the keys of `$options`.
+///
+/// # Example
+/// ```no_run
+/// for (name, set) in zsh_module::zsh::options::all() {
+///     println!("{:<25} {}", name, if set { "on" } else { "off" });
+/// }
+/// ```
+pub fn all() -> impl Iterator<Item = (String, bool)> {
+    crate::zsh::check_thread();
+    let table = unsafe { HashTable::from_raw(zsys::optiontab) };
+    let mut options: Vec<_> = table
+        .nodes()
+        .map(|node| unsafe {
+            let option = node as zsys::Optname;
+            let name = CStr::from_ptr((*option).node.nam)
+                .to_string_lossy()
+                .into_owned();
+            (name, optno_is_set((*option).optno))
+        })
+        .collect();
+    options.sort_unstable();
+    options.into_iter()
+}
+
+/// Whether `option` is set.
+///
+/// # Example
+/// ```no_run
+/// use zsh_module::zsh::options::{self, Opt};
+///
+/// if !options::is_on(Opt::ExtendedGlob) {
+///     options::set(Opt::ExtendedGlob).unwrap();
+/// }
+/// ```
+pub fn is_on(option: Opt) -> bool {
+    crate::zsh::check_thread();
+    unsafe { zsys::opts[option.optno()] != 0 }
+}
+
+/// Sets `option`, like `setopt`.
+pub fn set(option: Opt) -> ZResult<()> {
+    crate::zsh::check_thread();
+    set_to(option, true)
+}
+
+/// Unsets `option`, like `unsetopt`.
+pub fn unset(option: Opt) -> ZResult<()> {
+    crate::zsh::check_thread();
+    set_to(option, false)
+}
+
+fn set_to(option: Opt, value: bool) -> ZResult<()> {
+    set_optno(option.optno(), value)
+}
+
+fn set_optno(optno: usize, value: bool) -> ZResult<()> {
+    // dosetopt also does what changing the option implies, like starting job control for `monitor`
+    let result = unsafe {
+        zsys::dosetopt(
+            optno as c_int,
+            value.into(),
+            0,
+            std::ptr::addr_of_mut!(zsys::opts) as *mut _,
+        )
+    };
+    match result {
+        0 => Ok(()),
+        _ => Err(ZError::Unavailable("the option can't be changed")),
+    }
+}
+
+/// Sets every option of `options`, until the returned guard is dropped. Then, every option is
+/// restored to the value it had when this was called, including the ones changed with [`set`] and
+/// [`unset`] meanwhile, like a function with `LOCAL_OPTIONS` does when it returns.
+///
+/// # Example
+/// ```no_run
+/// use zsh_module::zsh::{self, options::{self, Opt}};
+///
+/// {
+///     let _guard = options::scoped(&[Opt::ExtendedGlob, Opt::NullGlob]).unwrap();
+///     zsh::eval_simple("files=( ^*.o )").unwrap();
+/// }
+/// // EXTENDED_GLOB and NULL_GLOB are back to what the user set
+/// ```
+pub fn scoped(options: &[Opt]) -> ZResult<OptionGuard> {
+    crate::zsh::check_thread();
+    let guard = OptionGuard {
+        saved: unsafe { zsys::opts }.to_vec(),
+        _not_send: PhantomData,
+    };
+    for &option in options {
+        set(option)?;
+    }
+    Ok(guard)
+}
+
+/// Restores the shell options when dropped. See [`scoped`].
+#[must_use = "the options are restored as soon as the guard is dropped"]
+#[derive(Debug)]
+pub struct OptionGuard {
+    saved: Vec<c_char>,
+    /// The options can only be restored from the shell's thread.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for OptionGuard {
+    fn drop(&mut self) {
+        // Index 0 isn't an option
+        for (optno, &saved) in self.saved.iter().enumerate().skip(1) {
+            if unsafe { zsys::opts[optno] } != saved {
+                let _ = set_optno(optno, saved != 0);
+            }
+        }
+    }
+}
+
+/// Whether the option `name` is set, or [`None`] if there's no such option.
+///
+/// Like `setopt`, `name` is case insensitive, ignores underscores and may start with `no` to ask
+/// whether the option is unset.
+pub fn is_set_by_name(name: &str) -> Option<bool> {
+    crate::zsh::check_thread();
+    let name = to_cstr(name);
+    match unsafe { zsys::optlookup(name.as_ptr()) } {
+        0 => None,
+        optno => Some(optno_is_set(optno)),
+    }
+}
+
+/// Whether the option numbered `optno` is set. Negative numbers are the options named with a
+/// `no` (e.g. `braceexpand` is `-IGNOREBRACES`), which are set when the option isn't.
+fn optno_is_set(optno: c_int) -> bool {
+    match optno {
+        optno if optno >= 0 => unsafe { zsys::opts[optno as usize] != 0 },
+        optno => unsafe { zsys::opts[optno.unsigned_abs() as usize] == 0 },
+    }
+}
+
+/// Runs `f` with each option of `options` set or unset, restoring them afterwards.
+///
+/// Only for options that are just read by the shell, unlike e.g. `monitor`, which `setopt`
+/// has to act upon.
+pub(crate) fn with_options<T>(options: &[(Opt, bool)], f: impl FnOnce() -> T) -> T {
+    let saved: Vec<_> = options
+        .iter()
+        .map(|(option, set)| unsafe {
+            let optno = option.optno();
+            let saved = zsys::opts[optno];
+            zsys::opts[optno] = (*set).into();
+            (optno, saved)
+        })
+        .collect();
+    let result = f();
+    for (optno, value) in saved {
+        unsafe { zsys::opts[optno] = value };
+    }
+    result
+}
@@ -0,0 +1,548 @@
+//! Direct access to shell parameters, without going through `eval`.
+//!
+//! # Example
+//! ```no_run
+//! use zsh_module::zsh::Param;
+//!
+//! if let Some(mut prompt) = Param::find("PROMPT") {
+//!     let value = prompt.get_value()?;
+//!     prompt.set(&format!("[rs] {value}"))?;
+//! }
+//! # Ok::<(), zsh_module::ZError>(())
+//! ```
+//!
+//! Arrays and associative arrays are (re)created as a whole with [`set_array`] and [`set_assoc`].
+use std::{
+    collections::HashMap,
+    ffi::{c_char, c_int, CStr},
+};
+
+use zsh_sys as zsys;
+
+use super::{eval_simple, eval_with_status, Pattern};
+use crate::{
+    backend::{Backend, Current},
+    math::Number,
+    to_cstr, zalloc, CStrArray, CStrArrayBuf, ConversionError, HashTable, MetaCString, ToCString,
+    ZError, ZResult,
+};
+
+/// The flags telling the type of a parameter (`PM_TYPE` in zsh).
+const PM_TYPE: u32 = zsys::PM_SCALAR
+    | zsys::PM_ARRAY
+    | zsys::PM_INTEGER
+    | zsys::PM_EFLOAT
+    | zsys::PM_FFLOAT
+    | zsys::PM_HASHED;
+
+/// The value of a [`Param`], depending on its type.
+pub enum ParamValue {
+    Scalar(String),
+    Integer(i64),
+    Float(f64),
+    Array(Vec<String>),
+    /// An associative array, e.g. `$commands`. Its entries are read with
+    /// [`HashTable::entries`]. It's only valid until the parameter is changed or unset.
+    HashTable(HashTable),
+}
+
+/// A shell parameter, as found in zsh's parameter table.
+///
+/// Values are read and written through the parameter's own get/set functions, so special
+/// parameters (like `PS1` or `HISTSIZE`) behave as when assigned from the shell.
+pub struct Param {
+    raw: zsys::Param,
+}
+
+impl Param {
+    /// The parameter `name` visible in the current scope, or [`None`] if it isn't set.
+    pub fn find(name: &str) -> Option<Self> {
+        crate::zsh::check_thread();
+        let node = unsafe { HashTable::from_raw(zsys::paramtab) }.get(name);
+        if node.is_null() || unsafe { (*node).flags } as u32 & zsys::PM_UNSET != 0 {
+            return None;
+        }
+        Some(Self {
+            raw: node as zsys::Param,
+        })
+    }
+    /// # Safety
+    /// `raw` must point to a valid parameter, which outlives the returned value.
+    pub unsafe fn from_raw(raw: zsys::Param) -> Self {
+        Self { raw }
+    }
+    /// The raw parameter, to pass to functions of [`zsh_sys`] this crate doesn't wrap.
+    pub fn as_raw(&self) -> zsys::Param {
+        self.raw
+    }
+    pub fn name(&self) -> &str {
+        let name = unsafe { CStr::from_ptr((*self.raw).node.nam) };
+        name.to_str().unwrap_or_default()
+    }
+    fn flags(&self) -> u32 {
+        unsafe { (*self.raw).node.flags as u32 }
+    }
+    pub fn is_scalar(&self) -> bool {
+        self.flags() & PM_TYPE == zsys::PM_SCALAR
+    }
+    pub fn is_readonly(&self) -> bool {
+        self.flags() & zsys::PM_READONLY != 0
+    }
+    fn expect_scalar(&self) -> ZResult<()> {
+        if self.is_scalar() {
+            Ok(())
+        } else {
+            Err(ConversionError::WrongType {
+                name: self.name().into(),
+                expected: "scalar",
+            }
+            .into())
+        }
+    }
+    fn decode(&self, value: &CStr) -> ZResult<String> {
+        MetaCString::from_metafied(value)
+            .into_string()
+            .map_err(|_| {
+                ConversionError::InvalidUtf8 {
+                    what: self.name().into(),
+                }
+                .into()
+            })
+    }
+    /// The value of a scalar parameter.
+    pub fn get_value(&self) -> ZResult<String> {
+        self.expect_scalar()?;
+        let value = unsafe {
+            let getfn = (*(*self.raw).gsu.s).getfn.unwrap();
+            getfn(self.raw)
+        };
+        if value.is_null() {
+            return Ok(String::new());
+        }
+        self.decode(unsafe { CStr::from_ptr(value) })
+    }
+    /// The value of the parameter, whatever its type.
+    pub fn value(&self) -> ZResult<ParamValue> {
+        let raw = self.raw;
+        let flags = self.flags();
+        Ok(unsafe {
+            if flags & zsys::PM_INTEGER != 0 {
+                ParamValue::Integer((*(*raw).gsu.i).getfn.unwrap()(raw))
+            } else if flags & (zsys::PM_EFLOAT | zsys::PM_FFLOAT) != 0 {
+                ParamValue::Float((*(*raw).gsu.f).getfn.unwrap()(raw))
+            } else if flags & zsys::PM_ARRAY != 0 {
+                let array = (*(*raw).gsu.a).getfn.unwrap()(raw);
+                if array.is_null() {
+                    ParamValue::Array(Vec::new())
+                } else {
+                    let array = CStrArray::from_raw(array);
+                    ParamValue::Array(
+                        array
+                            .iter()
+                            .map(|value| self.decode(value))
+                            .collect::<ZResult<_>>()?,
+                    )
+                }
+            } else if flags & zsys::PM_HASHED != 0 {
+                let table = (*(*raw).gsu.h).getfn.unwrap()(raw);
+                if table.is_null() {
+                    return Err(ZError::Unavailable("the parameter has no table"));
+                }
+                ParamValue::HashTable(HashTable::from_raw(table))
+            } else {
+                ParamValue::Scalar(self.get_value()?)
+            }
+        })
+    }
+    /// Sets the value of a scalar parameter.
+    ///
+    /// Fails if the parameter is read-only or isn't a scalar, or if zsh rejected the value.
+    pub fn set(&mut self, value: &str) -> ZResult<()> {
+        if self.is_readonly() {
+            return Err(ZError::Unavailable("the parameter is read-only"));
+        }
+        self.expect_scalar()?;
+        // Assigning by name, rather than with the parameter's own setter, exports the value and
+        // applies the other attributes. Zsh may write into the name while parsing it
+        let mut name = unsafe { CStr::from_ptr((*self.raw).node.nam) }
+            .to_bytes_with_nul()
+            .to_vec();
+        // Zsh takes ownership of the value
+        let param = assigned(unsafe {
+            zsys::setsparam(
+                name.as_mut_ptr() as *mut _,
+                zalloc::hand_over(zalloc::ztrdup(MetaCString::new(value).as_ptr())),
+            )
+        })?;
+        // The assignment may have replaced the parameter
+        self.raw = param.raw;
+        Ok(())
+    }
+    /// Gives the parameter the attributes `flags`, like `typeset -x name`.
+    ///
+    /// Fails if the parameter is read-only, or if the attributes can't be combined with its type
+    /// or other attributes, e.g. [`ParamFlag::Unique`] on a scalar.
+    pub fn set_flags(&mut self, flags: &[ParamFlag]) -> ZResult<()> {
+        let bits = flags.iter().fold(0, |bits, flag| bits | flag.bits());
+        if self.is_readonly() && bits & !zsys::PM_READONLY != 0 {
+            return Err(ZError::Unavailable("the parameter is read-only"));
+        }
+        check_flags(self.name(), self.flags() & PM_TYPE, self.flags() | bits)?;
+        self.typeset('-', flags)
+    }
+    /// Removes the attributes `flags` from the parameter, like `typeset +x name`.
+    ///
+    /// Fails if the parameter is read-only: that can't be undone.
+    pub fn clear_flags(&mut self, flags: &[ParamFlag]) -> ZResult<()> {
+        if self.is_readonly() {
+            return Err(ZError::Unavailable("the parameter is read-only"));
+        }
+        self.typeset('+', flags)
+    }
+    /// Runs `typeset` to turn attributes on (with `-`) or off (with `+`).
+    fn typeset(&mut self, sign: char, flags: &[ParamFlag]) -> ZResult<()> {
+        if flags.is_empty() {
+            return Ok(());
+        }
+        let name = self.name().to_string();
+        if !is_identifier(&name) {
+            return Err(ZError::Unavailable("the parameter can't be changed"));
+        }
+        // Exporting and unexporting update the environment, which zsh keeps private
+        let letters: String = flags.iter().map(|flag| flag.letter()).collect();
+        let status = eval_with_status(&format!("typeset -g {}{} {}", sign, letters, name))?;
+        // Changing attributes may have replaced the parameter
+        match Param::find(&name) {
+            Some(param) if status == 0 => {
+                self.raw = param.raw;
+                Ok(())
+            }
+            _ => Err(ZError::Unavailable("the parameter can't be changed")),
+        }
+    }
+    /// Unsets the parameter, like `unset`. Special parameters may stay defined, with their own
+    /// idea of being unset.
+    ///
+    /// Fails if the parameter is read-only.
+    pub fn unset(self) -> ZResult<()> {
+        if self.is_readonly() {
+            return Err(ZError::Unavailable("the parameter is read-only"));
+        }
+        // Calls the parameter's own unset function, and restores the parameter it hid, if any
+        match unsafe { zsys::unsetparam_pm(self.raw, 0, 1) } {
+            0 => Ok(()),
+            _ => Err(ZError::Zsh),
+        }
+    }
+}
+
+/// Unsets the parameter `name` visible in the current scope, like `unset name`. Unsetting a
+/// parameter which isn't set does nothing.
+pub fn unset(name: &str) -> ZResult<()> {
+    crate::zsh::check_thread();
+    match Param::find(name) {
+        Some(param) => param.unset(),
+        None => Ok(()),
+    }
+}
+
+/// Unsets every parameter whose name matches the glob pattern `pattern`, like
+/// `unset -m pattern`, returning how many were.
+///
+/// # Example
+/// ```no_run
+/// // Clean up after the module
+/// zsh_module::zsh::unset_matching("_mymod_*")?;
+/// # Ok::<(), zsh_module::ZError>(())
+/// ```
+pub fn unset_matching(pattern: &str) -> ZResult<usize> {
+    crate::zsh::check_thread();
+    let pattern = Pattern::compile(pattern)?;
+    // Unsetting changes the table, so it isn't walked meanwhile
+    let names: Vec<String> = unsafe { HashTable::from_raw(zsys::paramtab) }
+        .nodes()
+        .filter(|&node| unsafe { (*node).flags } as u32 & zsys::PM_UNSET == 0)
+        .filter_map(|node| unsafe { CStr::from_ptr((*node).nam) }.to_str().ok())
+        .filter(|name| pattern.matches(name))
+        .map(str::to_string)
+        .collect();
+    for name in &names {
+        unset(name)?;
+    }
+    Ok(names.len())
+}
+
+/// An attribute of a parameter, as given to `typeset`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ParamFlag {
+    /// `-x`: the parameter is in the environment of commands.
+    Export,
+    /// `-r`: the parameter can't be changed or unset.
+    Readonly,
+    /// `-U`: an array only keeps the first of duplicate elements.
+    Unique,
+    /// `-l`: a scalar is converted to lowercase.
+    Lower,
+    /// `-u`: a scalar is converted to uppercase.
+    Upper,
+}
+
+impl ParamFlag {
+    fn bits(self) -> u32 {
+        match self {
+            Self::Export => zsys::PM_EXPORTED,
+            Self::Readonly => zsys::PM_READONLY,
+            Self::Unique => zsys::PM_UNIQUE,
+            Self::Lower => zsys::PM_LOWER,
+            Self::Upper => zsys::PM_UPPER,
+        }
+    }
+    /// The option of `typeset` giving the attribute.
+    fn letter(self) -> char {
+        match self {
+            Self::Export => 'x',
+            Self::Readonly => 'r',
+            Self::Unique => 'U',
+            Self::Lower => 'l',
+            Self::Upper => 'u',
+        }
+    }
+}
+
+/// Checks that the attributes `flags` can be given to the parameter `name` of type `ty` (one of
+/// the `PM_TYPE` flags), like `typeset` does.
+fn check_flags(name: &str, ty: u32, flags: u32) -> ZResult<()> {
+    let invalid = |msg: &str| {
+        Err(ZError::InvalidArgument {
+            name: name.to_string(),
+            msg: msg.to_string(),
+        })
+    };
+    if flags & zsys::PM_LOWER != 0 && flags & zsys::PM_UPPER != 0 {
+        invalid("can't be both lowercase and uppercase")
+    } else if flags & (zsys::PM_LOWER | zsys::PM_UPPER) != 0 && ty != zsys::PM_SCALAR {
+        invalid("only scalars can be converted to lowercase or uppercase")
+    } else if flags & zsys::PM_UNIQUE != 0 && ty != zsys::PM_ARRAY {
+        invalid("only arrays can have unique elements")
+    } else {
+        Ok(())
+    }
+}
+
+/// The value of a parameter created by [`ParamBuilder`].
+enum Init {
+    Scalar(String),
+    Array(Vec<String>),
+    Number(Number),
+}
+
+/// Creates a parameter with a type and attributes, like `typeset`.
+///
+/// # Example
+/// ```no_run
+/// use zsh_module::zsh::{ParamBuilder, ParamFlag};
+///
+/// // typeset -rx MYMOD_VERSION=1.0
+/// ParamBuilder::new("MYMOD_VERSION")
+///     .scalar("1.0")
+///     .flag(ParamFlag::Export)
+///     .flag(ParamFlag::Readonly)
+///     .build()?;
+/// // typeset -U mymod_dirs=(...)
+/// ParamBuilder::new("mymod_dirs")
+///     .array(&["/usr/share", "/usr/local/share"])
+///     .flag(ParamFlag::Unique)
+///     .build()?;
+/// # Ok::<(), zsh_module::ZError>(())
+/// ```
+pub struct ParamBuilder {
+    name: String,
+    init: Init,
+    flags: u32,
+}
+
+impl ParamBuilder {
+    /// Starts building the parameter `name`, an empty scalar unless given another value.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            init: Init::Scalar(String::new()),
+            flags: 0,
+        }
+    }
+    /// Makes the parameter a scalar with the value `value`.
+    pub fn scalar(mut self, value: &str) -> Self {
+        self.init = Init::Scalar(value.to_string());
+        self
+    }
+    /// Makes the parameter an array with the elements `values`.
+    pub fn array<T: AsRef<str>>(mut self, values: &[T]) -> Self {
+        self.init = Init::Array(values.iter().map(|v| v.as_ref().to_string()).collect());
+        self
+    }
+    /// Makes the parameter an integer (`typeset -i`) with the value `value`.
+    pub fn integer(mut self, value: i64) -> Self {
+        self.init = Init::Number(Number::Integer(value));
+        self
+    }
+    /// Makes the parameter a float (`typeset -F`) with the value `value`.
+    pub fn float(mut self, value: f64) -> Self {
+        self.init = Init::Number(Number::Float(value));
+        self
+    }
+    /// Gives the parameter the attribute `flag`.
+    pub fn flag(mut self, flag: ParamFlag) -> Self {
+        self.flags |= flag.bits();
+        self
+    }
+    fn type_flag(&self) -> u32 {
+        match self.init {
+            Init::Scalar(_) => zsys::PM_SCALAR,
+            Init::Array(_) => zsys::PM_ARRAY,
+            Init::Number(Number::Integer(_)) => zsys::PM_INTEGER,
+            Init::Number(Number::Float(_)) => zsys::PM_FFLOAT,
+        }
+    }
+    /// Creates the parameter in the current scope, replacing any parameter named the same.
+    ///
+    /// Fails if the attributes can't be combined, or if the parameter it would replace is
+    /// read-only or special, like `PATH`.
+    pub fn build(self) -> ZResult<Param> {
+        crate::zsh::check_thread();
+        let ty = self.type_flag();
+        check_flags(&self.name, ty, self.flags)?;
+        unset(&self.name)?;
+        let mut name = to_cstr(self.name.as_str()).into_bytes_with_nul();
+        let name = name.as_mut_ptr() as *mut c_char;
+        // Read-only parameters can't be assigned, so that's set last
+        let flags = ty | (self.flags & !zsys::PM_READONLY);
+        if unsafe { zsys::createparam(name, flags as c_int) }.is_null() {
+            return Err(ZError::Unavailable("the parameter can't be replaced"));
+        }
+        // Assigning through zsh exports the value and applies the other attributes
+        let param = assigned(unsafe {
+            match &self.init {
+                Init::Scalar(value) => zsys::setsparam(
+                    name,
+                    zalloc::hand_over(zalloc::ztrdup(MetaCString::new(value.as_str()).as_ptr())),
+                ),
+                Init::Array(values) => {
+                    zsys::setaparam(name, CStrArrayBuf::from_iter(values).into_raw())
+                }
+                Init::Number(value) => zsys::setnparam(name, value.into_raw()),
+            }
+        })?;
+        unsafe { (*param.raw).node.flags |= (self.flags & zsys::PM_READONLY) as c_int };
+        Ok(param)
+    }
+}
+
+/// Whether `name` can be the name of a parameter created from Rust.
+fn is_identifier(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Ties the scalar `scalar` to the array `array`, like `typeset -T scalar array sep`: the scalar
+/// is the elements of the array joined with `sep`, and assigning either one changes both, the way
+/// `PATH` and `path` are.
+///
+/// An existing `scalar` keeps its value, split into the array. Returns the scalar and the array.
+///
+/// # Example
+/// ```no_run
+/// let (_, dirs) = zsh_module::zsh::tie("MYMOD_PATH", "mymod_path", ':')?;
+/// # Ok::<(), zsh_module::ZError>(())
+/// ```
+pub fn tie(scalar: &str, array: &str, sep: char) -> ZResult<(Param, Param)> {
+    crate::zsh::check_thread();
+    const SEP: &CStr = c"_zsh_module_sep";
+    for name in [scalar, array] {
+        if !is_identifier(name) || scalar == array {
+            return Err(ZError::InvalidArgument {
+                name: name.to_string(),
+                msg: "can't be tied".to_string(),
+            });
+        }
+    }
+    // Tied parameters keep their join and split functions private to zsh, so `typeset` creates
+    // them. The names were checked, and the separator goes through a parameter
+    Current::set_str_param(SEP, sep.to_string().as_bytes());
+    let status = eval_with_status(&format!(
+        "typeset -gT {} {} \"$_zsh_module_sep\"",
+        scalar, array
+    ));
+    let _ = eval_simple("unset _zsh_module_sep");
+    if status? != 0 {
+        return Err(ZError::Unavailable("the parameters can't be tied"));
+    }
+    match (Param::find(scalar), Param::find(array)) {
+        (Some(scalar), Some(array)) => Ok((scalar, array)),
+        _ => Err(ZError::Unavailable("the parameters can't be tied")),
+    }
+}
+
+/// Turns a returned parameter into a [`ZResult`]. Zsh returns null (and reports why) when it
+/// rejects an assignment, e.g. to a read-only parameter.
+fn assigned(param: zsys::Param) -> ZResult<Param> {
+    if param.is_null() || unsafe { zsys::errflag } != 0 {
+        Err(ZError::Zsh)
+    } else {
+        Ok(Param { raw: param })
+    }
+}
+
+/// Sets the parameter `name` to an array of `values`, like `name=(values...)`.
+pub fn set_array<T: ToCString + Clone>(name: &str, values: &[T]) -> ZResult<Param> {
+    crate::zsh::check_thread();
+    let values: Vec<_> = values.iter().map(|v| v.clone().into_cstr()).collect();
+    // Zsh may write into the name while parsing it
+    let mut name = to_cstr(name).into_bytes_with_nul();
+    // Zsh takes ownership of the array and its elements
+    let array = CStrArrayBuf::from_iter(values.iter().map(|v| v.to_bytes())).into_raw();
+    assigned(unsafe { zsys::assignaparam(name.as_mut_ptr() as *mut _, array, 0) })
+}
+
+/// Sets the parameter `name` to an associative array with the entries of `values`, like
+/// `typeset -A name=(key value ...)`.
+pub fn set_assoc<K: ToCString, V: ToCString>(name: &str, values: HashMap<K, V>) -> ZResult<Param> {
+    crate::zsh::check_thread();
+    let values: Vec<_> = values
+        .into_iter()
+        .flat_map(|(key, value)| [key.into_cstr(), value.into_cstr()])
+        .collect();
+    let mut name = to_cstr(name).into_bytes_with_nul();
+    // Zsh takes ownership of the array (of keys followed by their values) and its elements
+    let array = CStrArrayBuf::from_iter(values.iter().map(|v| v.to_bytes())).into_raw();
+    assigned(unsafe { zsys::sethparam(name.as_mut_ptr() as *mut _, array) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identifiers() {
+        assert!(is_identifier("MYMOD_PATH"));
+        assert!(is_identifier("_dirs2"));
+        assert!(!is_identifier("2dirs"));
+        assert!(!is_identifier("a b"));
+        assert!(!is_identifier(""));
+    }
+
+    #[test]
+    fn flag_combinations() {
+        let unique = ParamFlag::Unique.bits();
+        let case = ParamFlag::Lower.bits() | ParamFlag::Upper.bits();
+        assert!(check_flags("dirs", zsys::PM_ARRAY, unique).is_ok());
+        assert!(check_flags("DIR", zsys::PM_SCALAR, unique).is_err());
+        assert!(check_flags("n", zsys::PM_INTEGER, ParamFlag::Lower.bits()).is_err());
+        assert_eq!(
+            check_flags("NAME", zsys::PM_SCALAR, case)
+                .unwrap_err()
+                .to_string(),
+            "NAME: can't be both lowercase and uppercase"
+        );
+    }
+}
@@ -0,0 +1,112 @@
+//! The command search path, `$path` (and its tied scalar, `$PATH`).
+use std::{
+    ffi::OsStr,
+    ops::Deref,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    backend::{Backend, Current},
+    MaybeZError, ZError,
+};
+
+/// The directories in `$path`. See [`path`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchPath {
+    dirs: Vec<PathBuf>,
+}
+
+/// Reads `$path`. Changes made through the returned [`SearchPath`] are written back to `$path`
+/// right away, and zsh keeps `$PATH` up to date with it.
+///
+/// # Example
+/// ```no_run
+/// let mut path = zsh_module::zsh::path();
+/// path.prepend_unique("/opt/mytool/bin").unwrap();
+/// path.dedup().unwrap();
+/// assert_eq!(path[0], std::path::Path::new("/opt/mytool/bin"));
+/// ```
+pub fn path() -> SearchPath {
+    crate::zsh::check_thread();
+    let dirs = Current::get_array_param(c"path")
+        .unwrap_or_default()
+        .into_iter()
+        .map(|dir| PathBuf::from(OsStr::from_bytes(&dir)))
+        .collect();
+    SearchPath { dirs }
+}
+
+impl SearchPath {
+    fn store(&self) -> MaybeZError {
+        let dirs: Vec<&[u8]> = self
+            .dirs
+            .iter()
+            .map(|dir| dir.as_os_str().as_bytes())
+            .collect();
+        if Current::set_array_param(c"path", &dirs) {
+            Ok(())
+        } else {
+            Err(ZError::Zsh)
+        }
+    }
+    /// Puts `dir` first, removing it from anywhere else in the path.
+    pub fn prepend_unique(&mut self, dir: impl Into<PathBuf>) -> MaybeZError {
+        let dir = dir.into();
+        self.dirs.retain(|d| *d != dir);
+        self.dirs.insert(0, dir);
+        self.store()
+    }
+    /// Puts `dir` last, unless it's already in the path.
+    pub fn append_unique(&mut self, dir: impl Into<PathBuf>) -> MaybeZError {
+        let dir = dir.into();
+        if !self.dirs.contains(&dir) {
+            self.dirs.push(dir);
+        }
+        self.store()
+    }
+    /// Removes every occurrence of `dir`.
+    pub fn remove(&mut self, dir: impl AsRef<Path>) -> MaybeZError {
+        self.dirs.retain(|d| d != dir.as_ref());
+        self.store()
+    }
+    /// Removes repeated directories, keeping the first occurrence of each.
+    pub fn dedup(&mut self) -> MaybeZError {
+        let mut seen = std::collections::HashSet::new();
+        self.dirs.retain(|dir| seen.insert(dir.clone()));
+        self.store()
+    }
+}
+
+impl Deref for SearchPath {
+    type Target = [PathBuf];
+    fn deref(&self) -> &[PathBuf] {
+        &self.dirs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::path;
+    use crate::mock;
+
+    #[test]
+    fn changes_are_written_back() {
+        mock::set_array_param("path", &["/usr/bin", "/bin", "/usr/bin", "/opt/bin"]);
+        let mut path = path();
+        path.dedup().unwrap();
+        assert_eq!(
+            mock::array_param("path").unwrap(),
+            ["/usr/bin", "/bin", "/opt/bin"]
+        );
+        path.prepend_unique("/opt/bin").unwrap();
+        path.append_unique("/usr/bin").unwrap();
+        path.append_unique("/sbin").unwrap();
+        path.remove("/bin").unwrap();
+        assert_eq!(
+            mock::array_param("path").unwrap(),
+            ["/opt/bin", "/usr/bin", "/sbin"]
+        );
+        assert_eq!(path.len(), 3);
+    }
+}
@@ -0,0 +1,56 @@
+use std::ffi::c_int;
+
+use zsh_sys as zsys;
+
+use crate::{MetaCString, ZError, ZResult};
+
+/// A compiled zsh pattern, matching strings like `[[ string = pattern ]]` does.
+///
+/// The pattern syntax depends on the options set when it's compiled, like `EXTENDED_GLOB` for
+/// `##` or `^`.
+///
+/// # Examples
+/// ```no_run
+/// use zsh_module::zsh::Pattern;
+///
+/// let pattern = Pattern::compile("*.(rs|toml)").unwrap();
+/// assert!(pattern.matches("Cargo.toml"));
+/// assert!(!pattern.matches("README.md"));
+/// ```
+#[derive(Debug)]
+pub struct Pattern {
+    prog: zsys::Patprog,
+}
+
+impl Pattern {
+    /// Compiles `pattern`, failing if it isn't a valid pattern.
+    pub fn compile(pattern: &str) -> ZResult<Self> {
+        crate::zsh::check_thread();
+        let mut bytes = MetaCString::new(pattern).to_bytes_with_nul().to_vec();
+        let prog = unsafe {
+            let ptr = bytes.as_mut_ptr() as *mut _;
+            zsys::tokenize(ptr);
+            zsys::remnulargs(ptr);
+            // Copied to real memory, since the heap is freed once the builtin returns
+            zsys::patcompile(ptr, zsys::PAT_ZDUP as c_int, std::ptr::null_mut())
+        };
+        if prog.is_null() {
+            return Err(ZError::InvalidArgument {
+                name: "pattern".into(),
+                msg: format!("bad pattern: {}", pattern),
+            });
+        }
+        Ok(Self { prog })
+    }
+    /// Whether the whole of `string` matches the pattern.
+    pub fn matches(&self, string: &str) -> bool {
+        let string = MetaCString::new(string);
+        unsafe { zsys::pattry(self.prog, string.as_ptr() as *mut _) != 0 }
+    }
+}
+
+impl Drop for Pattern {
+    fn drop(&mut self) {
+        unsafe { zsys::freepatprog(self.prog) }
+    }
+}
@@ -0,0 +1,224 @@
+//! Formatting utilities that replicate the behaviour of zsh's `print` and `printf` builtins.
+//!
+//! Output meant to be read back by the shell (e.g. code given to `eval`) should go through these
+//! functions, since they use zsh's own implementation and are therefore always round-trip safe.
+use std::ffi::{c_int, CStr};
+
+use zsh_sys as zsys;
+
+use crate::{metafy_heap, unmetafy, ZResult};
+
+/// The ways zsh quotes words, like the `q` parameter expansion flag does. See [`quote_with`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Quoting {
+    /// Backslashes before special characters, like `${(q)var}`. Newlines and other unprintable
+    /// characters are quoted like `$'\n'`.
+    #[default]
+    Backslash,
+    /// Single quotes, like `${(qq)var}`.
+    Single,
+    /// Double quotes, like `${(qqq)var}`.
+    Double,
+    /// `$'...'`, like `${(qqqq)var}`.
+    Dollars,
+    /// Single quotes, only where needed, like `${(q-)var}`. The most readable.
+    Minimal,
+}
+
+/// Quotes `text` so that the shell reads it back as a single word, like `printf %q` does.
+///
+/// # Examples
+/// ```no_run
+/// use zsh_module::zsh::print;
+///
+/// let file = "it's a file.txt";
+/// zsh_module::zsh::eval_simple(&format!("rm -- {}", print::quote(file).unwrap())).unwrap();
+/// ```
+pub fn quote(text: &str) -> ZResult<String> {
+    crate::zsh::check_thread();
+    quote_with(text, Quoting::Backslash)
+}
+
+/// Quotes `text` so that the shell reads it back as a single word, in the given style. Even an
+/// empty `text` is quoted, as `''`.
+///
+/// # Examples
+/// ```no_run
+/// use zsh_module::zsh::print::{quote_with, Quoting};
+///
+/// let alias = format!("alias today={}", quote_with("ls ~/notes", Quoting::Single).unwrap());
+/// zsh_module::zsh::eval_simple(&alias).unwrap();
+/// ```
+pub fn quote_with(text: &str, quoting: Quoting) -> ZResult<String> {
+    crate::zsh::check_thread();
+    let quoting = match quoting {
+        // Unlike `QT_BACKSLASH`, it doesn't leave empty strings empty
+        Quoting::Backslash => zsys::QT_BACKSLASH_SHOWNULL,
+        Quoting::Single => zsys::QT_SINGLE,
+        Quoting::Double => zsys::QT_DOUBLE,
+        Quoting::Dollars => zsys::QT_DOLLARS,
+        Quoting::Minimal => zsys::QT_SINGLE_OPTIONAL,
+    };
+    let quoted = unsafe {
+        CStr::from_ptr(zsys::quotestring(
+            metafy_heap(text.as_bytes()),
+            quoting as c_int,
+        ))
+    };
+    let quoted = String::from_utf8(unmetafy(quoted.to_bytes()))?;
+    if quoted.is_empty() {
+        return Ok("''".to_string());
+    }
+    Ok(quoted)
+}
+
+/// Quotes every word of `words` like [`quote`], separated by spaces, e.g. to build a command line
+/// for [`eval`](crate::zsh::eval) or an alias.
+///
+/// # Examples
+/// ```no_run
+/// use zsh_module::zsh;
+///
+/// let command = zsh::quote_vec(&["grep", "-e", "it's", "notes today.txt"]).unwrap();
+/// zsh::eval_simple(&format!("alias search={}", zsh::quote(&command).unwrap())).unwrap();
+/// ```
+pub fn quote_vec<T: AsRef<str>>(words: &[T]) -> ZResult<String> {
+    crate::zsh::check_thread();
+    let quoted = words
+        .iter()
+        .map(|word| quote(word.as_ref()))
+        .collect::<ZResult<Vec<_>>>()?;
+    Ok(quoted.join(" "))
+}
+
+/// Replaces the beginning of `path` by a named directory (e.g. `~` or `~user`) whenever
+/// possible, like `print -D` does.
+///
+/// # Examples
+/// ```no_run
+/// // Prints `~/Documents` if $HOME is `/home/user`
+/// println!("{}", zsh_module::zsh::print::contract_path("/home/user/Documents").unwrap());
+/// ```
+pub fn contract_path(path: &str) -> ZResult<String> {
+    crate::zsh::check_thread();
+    let meta = metafy_heap(path.as_bytes());
+    let dir = unsafe { zsys::finddir(meta) };
+    if dir.is_null() {
+        return Ok(path.to_string());
+    }
+    let (name, dir_len) = unsafe {
+        (
+            CStr::from_ptr((*dir).node.nam).to_bytes(),
+            CStr::from_ptr((*dir).dir).to_bytes().len(),
+        )
+    };
+    let meta = unsafe { CStr::from_ptr(meta) }.to_bytes();
+    let mut contracted = Vec::with_capacity(meta.len());
+    contracted.push(b'~');
+    contracted.extend_from_slice(name);
+    contracted.extend_from_slice(&meta[dir_len..]);
+    Ok(String::from_utf8(unmetafy(&contracted))?)
+}
+
+/// Expands prompt escape sequences (e.g. `%~`, `%n` or `%F{red}`) in `prompt`, like `print -P`
+/// does.
+///
+/// Only available with the `prompt` feature.
+///
+/// # Examples
+/// ```no_run
+/// let dir = zsh_module::zsh::print::expand_prompt("%B%~%b").unwrap();
+/// ```
+#[cfg(feature = "prompt")]
+pub fn expand_prompt(prompt: &str) -> ZResult<String> {
+    crate::zsh::check_thread();
+    expand_prompt_width(prompt).map(|(expanded, _)| expanded)
+}
+
+/// Expands `prompt` like [`expand_prompt`], also returning the amount of columns its last line
+/// takes on the terminal. Escape sequences, like the ones of `%F{red}` or inside `%{...%}`, don't
+/// count.
+///
+/// Only available with the `prompt` feature.
+///
+/// # Examples
+/// ```no_run
+/// let (_, width) = zsh_module::zsh::print::expand_prompt_width("%F{red}>%f ").unwrap();
+/// assert_eq!(width, 2);
+/// ```
+#[cfg(feature = "prompt")]
+pub fn expand_prompt_width(prompt: &str) -> ZResult<(String, usize)> {
+    crate::zsh::check_thread();
+    unsafe {
+        let expanded = zsys::promptexpand(
+            metafy_heap(prompt.as_bytes()),
+            0,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        );
+        let (mut width, mut height) = (0, 0);
+        // -1 counts the whole line, instead of wrapping at the terminal's width
+        zsys::countprompt(expanded, &mut width, &mut height, -1);
+        let bytes = unmetafy(&strip_width_markers(CStr::from_ptr(expanded).to_bytes()));
+        zsys::zsfree(expanded);
+        Ok((String::from_utf8(bytes)?, width.max(0) as usize))
+    }
+}
+
+/// Removes the tokens `promptexpand` puts around `%{...%}` for `countprompt`.
+#[cfg(feature = "prompt")]
+fn strip_width_markers(expanded: &[u8]) -> Vec<u8> {
+    const INPAR: u8 = 0x88;
+    const OUTPAR: u8 = 0x8a;
+    let mut stripped = Vec::with_capacity(expanded.len());
+    let mut escaped = false;
+    for &byte in expanded {
+        if escaped || !matches!(byte, INPAR | OUTPAR) {
+            stripped.push(byte);
+        }
+        escaped = !escaped && byte == crate::META;
+    }
+    stripped
+}
+
+/// Interprets backslash escapes in `text` (e.g. `\n`, `\e`, `\x41` or `\0101`), like `print`
+/// does without the `-r` flag.
+///
+/// Just like with `print`, `\c` ends the text.
+///
+/// # Examples
+/// ```no_run
+/// let text = zsh_module::zsh::print::escapes(r"\x68\151\n").unwrap();
+/// assert_eq!(text, "hi\n");
+/// ```
+pub fn escapes(text: &str) -> ZResult<String> {
+    crate::zsh::check_thread();
+    let how = zsys::GETKEY_OCTAL_ESC | zsys::GETKEY_BACKSLASH_C | zsys::GETKEY_EMACS;
+    let mut len = 0;
+    let mut misc = 0;
+    let bytes = unsafe {
+        let escaped = zsys::getkeystring(
+            metafy_heap(text.as_bytes()),
+            &mut len,
+            how as c_int,
+            &mut misc,
+        );
+        std::slice::from_raw_parts(escaped as *const u8, len as usize)
+    };
+    Ok(String::from_utf8(bytes.to_vec())?)
+}
+
+#[cfg(all(test, feature = "prompt"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn width_markers_are_stripped() {
+        // The second Inpar is an escaped byte, not a token
+        assert_eq!(
+            strip_width_markers(b"\x88\x1b[1m\x8a> \x83\x88"),
+            b"\x1b[1m> \x83\x88"
+        );
+    }
+}
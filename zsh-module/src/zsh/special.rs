@@ -0,0 +1,133 @@
+//! Typed access to commonly used special parameters.
+//!
+//! # Example
+//! ```no_run
+//! use zsh_module::zsh::special;
+//!
+//! let width = special::columns().min(80);
+//! println!("{}", "-".repeat(width));
+//! println!("in {} for {}s", special::pwd().display(), special::seconds());
+//! ```
+use std::{
+    ffi::{CStr, OsStr},
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use crate::backend::{Backend, Current};
+
+fn get_path(name: &CStr) -> Option<PathBuf> {
+    Current::get_str_param(name).map(|value| PathBuf::from(OsStr::from_bytes(&value)))
+}
+
+fn get_usize(name: &CStr) -> usize {
+    Current::get_int_param(name).max(0) as usize
+}
+
+/// The current directory, `$PWD`.
+pub fn pwd() -> PathBuf {
+    crate::zsh::check_thread();
+    get_path(c"PWD").unwrap_or_default()
+}
+
+/// The previous directory, `$OLDPWD`.
+pub fn oldpwd() -> PathBuf {
+    crate::zsh::check_thread();
+    get_path(c"OLDPWD").unwrap_or_default()
+}
+
+/// The characters that separate words when splitting, `$IFS`.
+pub fn ifs() -> String {
+    crate::zsh::check_thread();
+    Current::get_str_param(c"IFS")
+        .map(|value| String::from_utf8_lossy(&value).into_owned())
+        .unwrap_or_default()
+}
+
+/// Sets `$IFS`.
+pub fn set_ifs(value: &str) {
+    crate::zsh::check_thread();
+    Current::set_str_param(c"IFS", value.as_bytes())
+}
+
+/// Where history is saved, `$HISTFILE`, if it is.
+pub fn histfile() -> Option<PathBuf> {
+    crate::zsh::check_thread();
+    get_path(c"HISTFILE").filter(|path| !path.as_os_str().is_empty())
+}
+
+/// Sets `$HISTFILE`.
+pub fn set_histfile(path: impl AsRef<Path>) {
+    crate::zsh::check_thread();
+    Current::set_str_param(c"HISTFILE", path.as_ref().as_os_str().as_bytes())
+}
+
+/// How many lines of history are kept in memory, `$HISTSIZE`.
+pub fn histsize() -> usize {
+    crate::zsh::check_thread();
+    get_usize(c"HISTSIZE")
+}
+
+/// Sets `$HISTSIZE`.
+pub fn set_histsize(value: usize) {
+    crate::zsh::check_thread();
+    Current::set_int_param(c"HISTSIZE", value as i64)
+}
+
+/// How many seconds have passed since the shell started (or `$SECONDS` was last set).
+pub fn seconds() -> i64 {
+    crate::zsh::check_thread();
+    Current::get_int_param(c"SECONDS")
+}
+
+/// Sets `$SECONDS`, which keeps counting from `value`.
+pub fn set_seconds(value: i64) {
+    crate::zsh::check_thread();
+    Current::set_int_param(c"SECONDS", value)
+}
+
+/// The width of the terminal, `$COLUMNS`.
+pub fn columns() -> usize {
+    crate::zsh::check_thread();
+    get_usize(c"COLUMNS")
+}
+
+/// Sets `$COLUMNS`.
+pub fn set_columns(value: usize) {
+    crate::zsh::check_thread();
+    Current::set_int_param(c"COLUMNS", value as i64)
+}
+
+/// The height of the terminal, `$LINES`.
+pub fn lines() -> usize {
+    crate::zsh::check_thread();
+    get_usize(c"LINES")
+}
+
+/// Sets `$LINES`.
+pub fn set_lines(value: usize) {
+    crate::zsh::check_thread();
+    Current::set_int_param(c"LINES", value as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock;
+
+    #[test]
+    fn typed_values() {
+        mock::set_param("PWD", "/home/me");
+        mock::set_param("COLUMNS", "120");
+        mock::set_param("LINES", "-1");
+        mock::set_param("HISTFILE", "");
+        assert_eq!(pwd(), Path::new("/home/me"));
+        assert_eq!(columns(), 120);
+        assert_eq!(lines(), 0);
+        assert_eq!(histfile(), None);
+        set_histfile("/tmp/history");
+        assert_eq!(histfile(), Some(PathBuf::from("/tmp/history")));
+        set_seconds(5);
+        assert_eq!(mock::param("SECONDS").unwrap(), "5");
+    }
+}
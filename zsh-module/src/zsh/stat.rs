@@ -0,0 +1,233 @@
+use std::{
+    any::Any,
+    ffi::{c_char, CStr},
+    fs::{self, Metadata},
+    io,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{AnyError, Cmd, MaybeError, Opts};
+
+/// Metadata about a file, with the same fields as the `zstat` builtin from `zsh/stat`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileInfo {
+    pub device: u64,
+    pub inode: u64,
+    /// The file's type and permission bits. See [`FileInfo::mode_string`].
+    pub mode: u32,
+    pub nlink: u64,
+    pub uid: u32,
+    pub gid: u32,
+    pub rdev: u64,
+    pub size: u64,
+    pub atime: SystemTime,
+    pub mtime: SystemTime,
+    pub ctime: SystemTime,
+    pub blksize: u64,
+    pub blocks: u64,
+    /// Where the file points to, if it is a symbolic link. Only filled in by [`lstat`].
+    pub link: Option<PathBuf>,
+}
+
+fn to_time(secs: i64, nsecs: i64) -> SystemTime {
+    if secs >= 0 {
+        UNIX_EPOCH + Duration::new(secs as u64, nsecs as u32)
+    } else {
+        UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs())
+    }
+}
+
+fn user_name(uid: u32) -> Option<String> {
+    let pwd = unsafe { libc::getpwuid(uid) };
+    if pwd.is_null() {
+        return None;
+    }
+    Some(
+        unsafe { CStr::from_ptr((*pwd).pw_name) }
+            .to_string_lossy()
+            .into_owned(),
+    )
+}
+
+fn group_name(gid: u32) -> Option<String> {
+    let grp = unsafe { libc::getgrgid(gid) };
+    if grp.is_null() {
+        return None;
+    }
+    Some(
+        unsafe { CStr::from_ptr((*grp).gr_name) }
+            .to_string_lossy()
+            .into_owned(),
+    )
+}
+
+impl FileInfo {
+    fn new(meta: Metadata, link: Option<PathBuf>) -> Self {
+        Self {
+            device: meta.dev(),
+            inode: meta.ino(),
+            mode: meta.mode(),
+            nlink: meta.nlink(),
+            uid: meta.uid(),
+            gid: meta.gid(),
+            rdev: meta.rdev(),
+            size: meta.size(),
+            atime: to_time(meta.atime(), meta.atime_nsec()),
+            mtime: to_time(meta.mtime(), meta.mtime_nsec()),
+            ctime: to_time(meta.ctime(), meta.ctime_nsec()),
+            blksize: meta.blksize(),
+            blocks: meta.blocks(),
+            link,
+        }
+    }
+    /// The name of the file's owner, if it has one.
+    pub fn owner(&self) -> Option<String> {
+        user_name(self.uid)
+    }
+    /// The name of the file's group, if it has one.
+    pub fn group(&self) -> Option<String> {
+        group_name(self.gid)
+    }
+    /// The mode formatted like `ls -l` does (e.g. `-rw-r--r--`), as shown by `zstat -s`.
+    // `mode_t` isn't `u32` on every platform
+    #[allow(clippy::unnecessary_cast)]
+    pub fn mode_string(&self) -> String {
+        let kind = [
+            (libc::S_IFDIR, 'd'),
+            (libc::S_IFLNK, 'l'),
+            (libc::S_IFCHR, 'c'),
+            (libc::S_IFBLK, 'b'),
+            (libc::S_IFIFO, 'p'),
+            (libc::S_IFSOCK, 's'),
+        ]
+        .into_iter()
+        .find(|(kind, _)| self.mode & libc::S_IFMT as u32 == *kind as u32)
+        .map_or('-', |(_, c)| c);
+        let mut out = String::with_capacity(10);
+        out.push(kind);
+        for (i, special, special_char) in [
+            (6, libc::S_ISUID, 's'),
+            (3, libc::S_ISGID, 's'),
+            (0, libc::S_ISVTX, 't'),
+        ] {
+            let bits = (self.mode >> i) & 0o7;
+            out.push(if bits & 4 != 0 { 'r' } else { '-' });
+            out.push(if bits & 2 != 0 { 'w' } else { '-' });
+            out.push(match (bits & 1 != 0, self.mode & special as u32 != 0) {
+                (true, true) => special_char,
+                (false, true) => special_char.to_ascii_uppercase(),
+                (true, false) => 'x',
+                (false, false) => '-',
+            });
+        }
+        out
+    }
+    /// Returns every field as a `(name, value)` pair, using the same names as `zstat`.
+    ///
+    /// If `strings` is `true`, the mode, uid and gid are shown in human readable form, like
+    /// `zstat -s` does.
+    pub fn fields(&self, strings: bool) -> Vec<(&'static str, String)> {
+        let secs = |time: SystemTime| match time.duration_since(UNIX_EPOCH) {
+            Ok(dur) => dur.as_secs().to_string(),
+            Err(e) => format!("-{}", e.duration().as_secs()),
+        };
+        let mut fields = vec![
+            ("device", self.device.to_string()),
+            ("inode", self.inode.to_string()),
+        ];
+        if strings {
+            fields.push(("mode", self.mode_string()));
+        } else {
+            fields.push(("mode", self.mode.to_string()));
+        }
+        fields.push(("nlink", self.nlink.to_string()));
+        match (strings, self.owner(), self.group()) {
+            (true, Some(owner), Some(group)) => {
+                fields.push(("uid", owner));
+                fields.push(("gid", group));
+            }
+            _ => {
+                fields.push(("uid", self.uid.to_string()));
+                fields.push(("gid", self.gid.to_string()));
+            }
+        }
+        fields.extend([
+            ("rdev", self.rdev.to_string()),
+            ("size", self.size.to_string()),
+            ("atime", secs(self.atime)),
+            ("mtime", secs(self.mtime)),
+            ("ctime", secs(self.ctime)),
+            ("blksize", self.blksize.to_string()),
+            ("blocks", self.blocks.to_string()),
+            (
+                "link",
+                self.link
+                    .as_ref()
+                    .map(|link| link.display().to_string())
+                    .unwrap_or_default(),
+            ),
+        ]);
+        fields
+    }
+}
+
+/// Returns metadata about the file at `path`, following symbolic links.
+///
+/// # Examples
+/// ```no_run
+/// let info = zsh_module::zsh::stat("/etc/passwd").unwrap();
+/// println!("{} is owned by {:?}", info.mode_string(), info.owner());
+/// ```
+pub fn stat(path: impl AsRef<Path>) -> io::Result<FileInfo> {
+    crate::zsh::check_thread();
+    Ok(FileInfo::new(fs::metadata(path)?, None))
+}
+
+/// Returns metadata about the file at `path` without following symbolic links, like `zstat -L`.
+/// If the file is a symbolic link, [`FileInfo::link`] is filled in.
+pub fn lstat(path: impl AsRef<Path>) -> io::Result<FileInfo> {
+    crate::zsh::check_thread();
+    let path = path.as_ref();
+    let meta = fs::symlink_metadata(path)?;
+    let link = if meta.file_type().is_symlink() {
+        Some(fs::read_link(path)?)
+    } else {
+        None
+    };
+    Ok(FileInfo::new(meta, link))
+}
+
+/// Creates a `zstat`-like builtin command that prints the metadata of each of its arguments.
+///
+/// The builtin must be registered with the `"Ls"` flags, which behave like their `zstat`
+/// counterparts:
+/// ```no_run
+/// use zsh_module::{Builtin, ModuleBuilder};
+///
+/// let module = ModuleBuilder::new(())
+///     .builtin(zsh_module::zsh::stat_builtin(), Builtin::new("rzstat").flags("Ls"))
+///     .build();
+/// ```
+pub fn stat_builtin<A: Any + ?Sized>() -> impl Cmd<A, AnyError> {
+    |_: &mut A, _name: &str, args: &[&str], opts: Opts| -> MaybeError {
+        let strings = opts.is_set(b's' as c_char);
+        for file in args {
+            let info = if opts.is_set(b'L' as c_char) {
+                lstat(file)
+            } else {
+                stat(file)
+            };
+            let info = info.map_err(|e| format!("{}: {}", file, e))?;
+            if args.len() > 1 {
+                crate::zprintln!("{}:", file);
+            }
+            for (name, value) in info.fields(strings) {
+                crate::zprintln!("{} {}", name, value);
+            }
+        }
+        Ok(())
+    }
+}
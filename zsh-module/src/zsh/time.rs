@@ -0,0 +1,106 @@
+use std::{
+    ffi::{c_char, c_int, CStr},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use zsh_sys as zsys;
+
+use crate::{metafy_heap, to_cstr, ConversionError, ZResult};
+
+#[cfg(not(target_vendor = "apple"))]
+use libc::strptime as c_strptime;
+
+// The `libc` crate doesn't declare `strptime` for Apple platforms, even though it's there.
+#[cfg(target_vendor = "apple")]
+extern "C" {
+    #[link_name = "strptime"]
+    fn c_strptime(s: *const c_char, format: *const c_char, tm: *mut libc::tm) -> *mut c_char;
+}
+
+/// Formats `time` (in the local timezone) according to `format`, exactly like the `strftime`
+/// builtin from `zsh/datetime` does.
+///
+/// This uses zsh's own implementation, so its extensions (like `%.` and `%3.` for fractions of a
+/// second, or `%f`, `%K` and `%L`) are supported.
+///
+/// # Examples
+/// ```no_run
+/// use std::time::SystemTime;
+///
+/// let now = zsh_module::zsh::strftime("%Y-%m-%d %H:%M:%6.", SystemTime::now()).unwrap();
+/// println!("It is now {}", now);
+/// ```
+pub fn strftime(format: &str, time: SystemTime) -> ZResult<String> {
+    crate::zsh::check_thread();
+    let since_epoch = time
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| ConversionError::OutOfRange {
+            what: "time before the unix epoch".into(),
+        })?;
+    let secs = since_epoch.as_secs() as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&secs, &mut tm) }.is_null() {
+        return Err(ConversionError::OutOfRange {
+            what: "time".into(),
+        }
+        .into());
+    }
+
+    // Zsh expects the format to be metafied
+    let format = metafy_heap(format.as_bytes());
+    // Same heuristic as `zsh/datetime`: start with a generous buffer and grow it if needed.
+    let mut buffer = vec![0 as c_char; unsafe { CStr::from_ptr(format) }.to_bytes().len() * 8 + 1];
+    loop {
+        let len = unsafe {
+            zsys::ztrftime(
+                buffer.as_mut_ptr(),
+                buffer.len() as c_int,
+                format,
+                &mut tm as *mut libc::tm as *mut zsys::tm,
+                since_epoch.subsec_nanos() as _,
+            )
+        };
+        // Unlike the format, the output isn't metafied
+        if len >= 0 {
+            let output: Vec<u8> = buffer[..len as usize].iter().map(|&c| c as u8).collect();
+            break Ok(String::from_utf8(output)?);
+        }
+        let new_len = buffer.len() * 2;
+        buffer.resize(new_len, 0);
+    }
+}
+
+/// Parses `input` according to `format` (interpreted in the local timezone), just like
+/// `strftime -r` does.
+///
+/// # Examples
+/// ```no_run
+/// let time = zsh_module::zsh::strptime("%Y-%m-%d", "2023-02-14").unwrap();
+/// ```
+pub fn strptime(format: &str, input: &str) -> ZResult<SystemTime> {
+    crate::zsh::check_thread();
+    let format = to_cstr(format);
+    let input_c = to_cstr(input);
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    tm.tm_isdst = -1;
+    let end = unsafe { c_strptime(input_c.as_ptr(), format.as_ptr(), &mut tm) };
+    if end.is_null() {
+        return Err(ConversionError::NoMatch {
+            input: input.into(),
+        }
+        .into());
+    }
+    // Like zsh, allow trailing whitespace but nothing else.
+    let rest = unsafe { CStr::from_ptr(end) }.to_bytes();
+    if !rest.iter().all(u8::is_ascii_whitespace) {
+        return Err(ConversionError::NoMatch {
+            input: input.into(),
+        }
+        .into());
+    }
+    let secs = unsafe { libc::mktime(&mut tm) };
+    if secs < 0 {
+        return Err(ConversionError::OutOfRange { what: input.into() }.into());
+    }
+    Ok(UNIX_EPOCH + Duration::from_secs(secs as u64))
+}
@@ -0,0 +1,219 @@
+//! Callbacks run after a delay, or periodically, like `sched` entries but without a visible
+//! entry or shell function.
+//!
+//! They use the same machinery as `sched`: zsh runs them before showing a prompt, and while the
+//! line editor waits for input. They don't run while a command does, and only to the second,
+//! since that's what zsh keeps track of.
+//!
+//! # Example
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use zsh_module::zsh::timers;
+//!
+//! // Refreshes the cached git status every 5 seconds
+//! timers::repeat(Duration::from_secs(5), || {
+//!     // ...
+//!     true
+//! });
+//! ```
+use std::{
+    cell::RefCell,
+    marker::PhantomData,
+    panic::{self, AssertUnwindSafe},
+    time::{Duration, Instant},
+};
+
+use zsh_sys as zsys;
+
+thread_local! {
+    static TIMERS: RefCell<Timers> = RefCell::new(Timers::default());
+}
+
+/// Runs on the shell's thread. Returns whether to run again.
+type TimerFn = Box<dyn FnMut() -> bool>;
+
+struct Entry {
+    id: u64,
+    due: Instant,
+    /// For timers that repeat.
+    period: Option<Duration>,
+    cb: TimerFn,
+}
+
+#[derive(Default)]
+struct Timers {
+    entries: Vec<Entry>,
+    next_id: u64,
+    /// Whether [`run_due`] runs before prompts. It's only removed when the module is unloaded,
+    /// since zsh doesn't expect it to be removed while it runs.
+    preprompt: bool,
+    /// Whether zsh has [`run_timed`] in its list of timed functions. Removing it when it was never
+    /// added would crash, since zsh only creates the list when something is added.
+    armed: bool,
+}
+
+impl Timers {
+    fn add(&mut self, due: Instant, period: Option<Duration>, cb: TimerFn) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.entries.push(Entry {
+            id,
+            due,
+            period,
+            cb,
+        });
+        id
+    }
+    /// Takes the timers due at `now`. They're taken out, so their callbacks may add timers.
+    fn take_due(&mut self, now: Instant) -> Vec<Entry> {
+        let (due, later) = std::mem::take(&mut self.entries)
+            .into_iter()
+            .partition(|entry| entry.due <= now);
+        self.entries = later;
+        due
+    }
+    fn next_due(&self) -> Option<Instant> {
+        self.entries.iter().map(|entry| entry.due).min()
+    }
+}
+
+/// A scheduled callback, which can be cancelled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Timer {
+    id: u64,
+    /// Timers are kept by the shell's thread.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Timer {
+    /// Stops the timer. Returns whether it was still scheduled.
+    pub fn cancel(self) -> bool {
+        let cancelled = TIMERS.with_borrow_mut(|timers| {
+            let len = timers.entries.len();
+            timers.entries.retain(|entry| entry.id != self.id);
+            timers.entries.len() != len
+        });
+        rearm();
+        cancelled
+    }
+}
+
+/// Runs `cb` once, after `delay`.
+pub fn schedule(delay: Duration, cb: impl FnOnce() + 'static) -> Timer {
+    crate::zsh::check_thread();
+    let mut cb = Some(cb);
+    add(delay, None, move || {
+        if let Some(cb) = cb.take() {
+            cb();
+        }
+        false
+    })
+}
+
+/// Runs `cb` every `period`, until it returns `false`.
+pub fn repeat(period: Duration, cb: impl FnMut() -> bool + 'static) -> Timer {
+    crate::zsh::check_thread();
+    add(period, Some(period), cb)
+}
+
+fn add(delay: Duration, period: Option<Duration>, cb: impl FnMut() -> bool + 'static) -> Timer {
+    let due = Instant::now() + delay;
+    let (id, preprompt) = TIMERS.with_borrow_mut(|timers| {
+        let preprompt = std::mem::replace(&mut timers.preprompt, true);
+        (timers.add(due, period, Box::new(cb)), preprompt)
+    });
+    if !preprompt {
+        unsafe { zsys::addprepromptfn(Some(run_due)) };
+    }
+    rearm();
+    Timer {
+        id,
+        _not_send: PhantomData,
+    }
+}
+
+/// Called by zsh from its timed functions, when the earliest timer is due.
+extern "C" fn run_timed() {
+    // Like `sched` does, the function removes itself, so that it runs once
+    let armed = TIMERS.with_borrow_mut(|timers| std::mem::take(&mut timers.armed));
+    if armed {
+        unsafe { zsys::deltimedfn(Some(run_timed)) };
+    }
+    run_due();
+}
+
+/// Called by zsh before prompts, and by [`run_timed`], when the earliest timer might be due.
+extern "C" fn run_due() {
+    let now = Instant::now();
+    let due = TIMERS.with_borrow_mut(|timers| timers.take_due(now));
+    for mut entry in due {
+        // Unwinding into zsh would abort the shell
+        let again = panic::catch_unwind(AssertUnwindSafe(&mut entry.cb)).unwrap_or_else(|_| {
+            crate::error!("a timer panicked, and was cancelled");
+            false
+        });
+        if let (true, Some(period)) = (again, entry.period) {
+            TIMERS.with_borrow_mut(|timers| {
+                timers.entries.push(Entry {
+                    due: now + period,
+                    ..entry
+                })
+            });
+        }
+    }
+    rearm();
+}
+
+/// Tells zsh when to call [`run_due`] next, if ever.
+fn rearm() {
+    let (next, armed) = TIMERS.with_borrow_mut(|timers| {
+        let next = timers.next_due();
+        (next, std::mem::replace(&mut timers.armed, next.is_some()))
+    });
+    if armed {
+        unsafe { zsys::deltimedfn(Some(run_timed)) };
+    }
+    let Some(next) = next else {
+        return;
+    };
+    let left = next.saturating_duration_since(Instant::now());
+    let secs = left.as_secs() + u64::from(left.subsec_nanos() > 0);
+    unsafe {
+        zsys::addtimedfn(
+            Some(run_timed),
+            libc::time(std::ptr::null_mut()) + secs as libc::time_t,
+        )
+    };
+}
+
+/// Drops every timer, so that zsh doesn't call into the module once it's unloaded.
+#[cfg_attr(not(feature = "export_module"), allow(dead_code))]
+pub(crate) fn clear() {
+    let timers = TIMERS.take();
+    if timers.armed {
+        unsafe { zsys::deltimedfn(Some(run_timed)) };
+    }
+    if timers.preprompt {
+        unsafe { zsys::delprepromptfn(Some(run_due)) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn due_timers() {
+        let mut timers = Timers::default();
+        let now = Instant::now();
+        let later = now + Duration::from_secs(5);
+        timers.add(later, None, Box::new(|| false));
+        let soon = timers.add(now, None, Box::new(|| false));
+        assert_eq!(timers.next_due(), Some(now));
+        let due = timers.take_due(now);
+        assert_eq!(due.iter().map(|entry| entry.id).collect::<Vec<_>>(), [soon]);
+        assert_eq!(timers.next_due(), Some(later));
+        assert!(timers.take_due(now).is_empty());
+    }
+}
@@ -1,22 +1,188 @@
+#[cfg(feature = "bindgen")]
 extern crate bindgen;
 
 use std::env;
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Path, PathBuf};
 
-// If your build is failing, please, take a look at config.h and change its values accordingly to
-// your machine.
+// If your build is failing with the `vendored` headers, please, take a look at config.h and change
+// its values accordingly to your machine, or point `ZSH_SYS_INCLUDE_DIR` to your own zsh build.
 
-fn main() {
-    println!("cargo:rerun-if-changed=headers/wrapper.h");
+/// The header that pulls in all of zsh's internals, as generated by zsh's build system.
+const MAIN_HEADER: &str = "zsh.mdh";
 
-    let bindings = bindgen::Builder::default()
-        .header("headers/wrapper.h")
-        .parse_callbacks(Box::new(bindgen::CargoCallbacks))
-        .generate()
-        .expect("Unable to generate bindings");
+/// Zsh releases that get a `zsh_<major>_<minor>` cfg flag when the headers are at least that
+/// version. The first one is the oldest supported release.
+const VERSIONS: &[(u32, u32)] = &[(5, 8), (5, 9)];
+
+/// Reads an environment variable, preferring its variants for the target being built for (e.g.
+/// `NAME_aarch64-unknown-linux-gnu` or `NAME_aarch64_unknown_linux_gnu`), like bindgen does.
+fn target_env(name: &str) -> Option<String> {
+    let target = env::var("TARGET").unwrap();
+    let names = [
+        format!("{}_{}", name, target),
+        format!("{}_{}", name, target.replace('-', "_")),
+        name.to_string(),
+    ];
+    for name in &names {
+        println!("cargo:rerun-if-env-changed={}", name);
+    }
+    names.iter().find_map(|name| env::var(name).ok())
+}
+
+/// Finds the directory with zsh's headers. It must look like the `Src` directory of a configured
+/// zsh source tree: `zsh.mdh`, `zsh.h` and the `*.epro` files, with `config.h` in its parent.
+fn find_headers() -> PathBuf {
+    if let Some(dir) = target_env("ZSH_SYS_INCLUDE_DIR") {
+        let dir = PathBuf::from(dir);
+        if !dir.join(MAIN_HEADER).is_file() {
+            panic!(
+                "ZSH_SYS_INCLUDE_DIR is set to {}, but it doesn't contain {}",
+                dir.display(),
+                MAIN_HEADER
+            );
+        }
+        return dir;
+    }
+    if let Some(dir) = pkg_config_headers() {
+        return dir;
+    }
+    if env::var_os("CARGO_FEATURE_VENDORED").is_some() {
+        return PathBuf::from("headers");
+    }
+    panic!(
+        "Couldn't find zsh's headers. Set ZSH_SYS_INCLUDE_DIR to the `Src` directory of a \
+         configured zsh source tree, or enable the `vendored` feature to use the headers bundled \
+         with this crate."
+    );
+}
+
+/// Asks pkg-config for zsh's include dirs, which may have the headers directly or in a `zsh`
+/// subdirectory.
+fn pkg_config_headers() -> Option<PathBuf> {
+    let lib = pkg_config::Config::new()
+        .cargo_metadata(false)
+        .env_metadata(true)
+        .probe("zsh")
+        .ok()?;
+    lib.include_paths
+        .iter()
+        .flat_map(|path| [path.clone(), path.join("zsh")])
+        .find(|path| path.join(MAIN_HEADER).is_file())
+}
+
+/// Reads the `major.minor` version of zsh from `version.h`.
+fn detect_version(headers: &Path) -> (u32, u32) {
+    let version_h = headers.join("version.h");
+    println!("cargo:rerun-if-changed={}", version_h.display());
+    let contents = fs::read_to_string(&version_h)
+        .unwrap_or_else(|e| panic!("Couldn't read {}: {}", version_h.display(), e));
+    contents
+        .lines()
+        .find_map(|line| line.strip_prefix("#define ZSH_VERSION "))
+        .and_then(|version| {
+            let mut parts = version.trim().trim_matches('"').split('.');
+            Some((parts.next()?.parse().ok()?, parts.next()?.parse().ok()?))
+        })
+        .unwrap_or_else(|| panic!("Couldn't find ZSH_VERSION in {}", version_h.display()))
+}
+
+/// Emits the version cfg flags. They are also passed to crates depending on this one (as
+/// `DEP_ZSH_CFGS` and `DEP_ZSH_KNOWN_CFGS`), so that they can gate their APIs too.
+fn emit_version_cfgs(version: (u32, u32)) {
+    if version < VERSIONS[0] {
+        panic!(
+            "zsh {}.{} is too old, at least {}.{} is needed",
+            version.0, version.1, VERSIONS[0].0, VERSIONS[0].1
+        );
+    }
+    let mut known = Vec::new();
+    let mut enabled = Vec::new();
+    for &(major, minor) in VERSIONS {
+        let cfg = format!("zsh_{}_{}", major, minor);
+        println!("cargo:rustc-check-cfg=cfg({})", cfg);
+        if version >= (major, minor) {
+            println!("cargo:rustc-cfg={}", cfg);
+            enabled.push(cfg.clone());
+        }
+        known.push(cfg);
+    }
+    println!("cargo:version={}.{}", version.0, version.1);
+    println!("cargo:cfgs={}", enabled.join(","));
+    println!("cargo:known_cfgs={}", known.join(","));
+}
 
-    let out_path = PathBuf::from(env::var("OUT_DIR").unwrap());
-    bindings
-        .write_to_file(out_path.join("bindings.rs"))
+/// Bindings generated beforehand, in the file `ZSH_SYS_BINDINGS` points to, used instead of
+/// running bindgen. They're required without the `bindgen` feature.
+fn pregenerated_bindings() -> Option<PathBuf> {
+    if let Some(path) = target_env("ZSH_SYS_BINDINGS") {
+        return Some(path.into());
+    }
+    if !cfg!(feature = "bindgen") {
+        panic!(
+            "Without the `bindgen` feature, ZSH_SYS_BINDINGS must be set to a file with bindings \
+             generated beforehand."
+        );
+    }
+    None
+}
+
+#[cfg(feature = "bindgen")]
+fn generate_bindings(headers: &Path, out: &Path) {
+    let main_header = headers.join(MAIN_HEADER);
+    println!("cargo:rerun-if-changed={}", main_header.display());
+    // Bindgen already reads these (and targets the right triple), but doesn't tell cargo to rerun
+    // when they change.
+    target_env("BINDGEN_EXTRA_CLANG_ARGS");
+
+    // Clang would take `zsh.mdh` for a linker input, given its extension
+    let mut builder = bindgen::Builder::default()
+        .clang_arg("-xc-header")
+        .header(main_header.to_string_lossy())
+        .clang_arg(format!("-I{}", headers.display()))
+        .parse_callbacks(Box::new(bindgen::CargoCallbacks));
+    if let Some(sysroot) = target_env("ZSH_SYS_SYSROOT") {
+        builder = builder.clang_arg(format!("--sysroot={}", sysroot));
+    }
+    builder
+        .generate()
+        .expect("Unable to generate bindings")
+        .write_to_file(out)
         .expect("Couldn't write bindings!");
 }
+
+#[cfg(not(feature = "bindgen"))]
+fn generate_bindings(_: &Path, _: &Path) {
+    unreachable!("pregenerated bindings are always used without the `bindgen` feature")
+}
+
+fn main() {
+    let headers = find_headers();
+    emit_version_cfgs(detect_version(&headers));
+
+    let out = PathBuf::from(env::var("OUT_DIR").unwrap()).join("bindings.rs");
+    match pregenerated_bindings() {
+        Some(bindings) => {
+            println!("cargo:rerun-if-changed={}", bindings.display());
+            fs::copy(&bindings, &out).unwrap_or_else(|e| {
+                panic!("Couldn't copy bindings from {}: {}", bindings.display(), e)
+            });
+        }
+        None => generate_bindings(&headers, &out),
+    }
+
+    // Normally, zsh's symbols are provided by the shell that loads the module. With the
+    // `embedded` feature, zsh is linked in instead, which needs zsh built as a shared library.
+    if env::var_os("CARGO_FEATURE_EMBEDDED").is_some() {
+        println!("cargo:rerun-if-env-changed=ZSH_SYS_LIB_DIR");
+        println!("cargo:rerun-if-env-changed=ZSH_SYS_LIB");
+        if let Some(dir) = env::var_os("ZSH_SYS_LIB_DIR") {
+            println!(
+                "cargo:rustc-link-search=native={}",
+                PathBuf::from(dir).display()
+            );
+        }
+        let lib = env::var("ZSH_SYS_LIB").unwrap_or_else(|_| "zsh".into());
+        println!("cargo:rustc-link-lib=dylib={}", lib);
+    }
+}